@@ -327,6 +327,7 @@ export interface Holding {
   marketValue: MonetaryValue;
   costBasis?: MonetaryValue | null;
   price?: number | null;
+  quoteDate?: string | null;
   unrealizedGain?: MonetaryValue | null;
   unrealizedGainPct?: number | null;
   realizedGain?: MonetaryValue | null;
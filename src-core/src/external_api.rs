@@ -1,35 +1,292 @@
 use crate::accounts::{Account, AccountServiceTrait};
-use crate::activities::{Activity, ActivityServiceTrait};
-use crate::fx::{ExchangeRate, FxServiceTrait};
-use crate::market_data::market_data_model::{Quote, QuoteSummary};
-use crate::market_data::MarketDataServiceTrait;
-use crate::portfolio::holdings::{Holding, HoldingsServiceTrait};
-use crate::portfolio::performance::{PerformanceMetrics, PerformanceServiceTrait, SimplePerformanceMetrics};
-use crate::settings::SettingsServiceTrait;
-use crate::errors::Result;
+use crate::activities::{self, Activity, ActivityServiceTrait, NewActivity};
+use crate::assets::{Asset, AssetServiceTrait, CASH_ASSET_CLASS};
+use crate::constants::{CASH_ASSET_PREFIX, PORTFOLIO_TOTAL_ACCOUNT_ID};
+use crate::fx::{ExchangeRate, FxServiceTrait, NewExchangeRate};
+use crate::market_data::market_data_model::{
+    DataSource, MarketDataProviderSetting, Quote, QuoteSummary,
+};
+use crate::market_data::{
+    MarketDataServiceTrait, DATA_SOURCE_ALPHA_VANTAGE, DATA_SOURCE_MANUAL, DATA_SOURCE_METAL_PRICE_API,
+    DATA_SOURCE_YAHOO,
+};
+use crate::portfolio::holdings::{Holding, HoldingType, HoldingsServiceTrait, Instrument, MonetaryValue};
+use crate::portfolio::performance::{
+    PerformanceMetrics, PerformanceServiceTrait, ReturnData, SimplePerformanceMetrics,
+};
+use crate::portfolio::snapshot::{Position, SnapshotServiceTrait};
+use crate::portfolio::valuation::{DailyAccountValuation, ValuationServiceTrait};
+use crate::secrets::SecretStore;
+use crate::settings::{PortfolioGroup, SettingsServiceTrait};
+use crate::errors::{DatabaseError, Error, Result, ValidationError};
 use async_trait::async_trait;
-use serde::Deserialize;
+use futures::stream::{self, StreamExt};
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use rust_decimal::{prelude::ToPrimitive, Decimal, MathematicalOps};
+use rust_decimal_macros::dec;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+
+/// Event name carried by alert callbacks, following the `domain:event` naming
+/// used by the server event bus.
+pub const PORTFOLIO_VALUE_ALERT_EVENT: &str = "portfolio:value-alert";
+
+/// How often registered alerts are evaluated against current holdings.
+pub const ALERT_EVALUATION_INTERVAL_SECS: u64 = 60;
+
+/// Default largest request body accepted by write routes (4 MiB).
+pub const DEFAULT_MAX_BODY_BYTES: usize = 4 * 1024 * 1024;
+
+/// Route groups mounted by the external API routers. A disabled group is not
+/// registered at all, so its routes answer 404 (or 405 where a path also serves reads).
+/// By default the API is read-only: write routes are mounted only when enabled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExternalApiFeatures {
+    /// Routes that create, update or delete data; off unless opted in
+    pub writes: bool,
+    /// `/api/market-data/*` routes
+    pub market_data: bool,
+    /// `/api/export`
+    pub exports: bool,
+}
+
+impl Default for ExternalApiFeatures {
+    fn default() -> Self {
+        Self {
+            writes: false,
+            market_data: true,
+            exports: true,
+        }
+    }
+}
+
+/// Default number of accounts whose holdings are fetched concurrently.
+pub const DEFAULT_HOLDINGS_CONCURRENCY: usize = 4;
+
+/// Default number of market data provider calls allowed to run at once.
+pub const DEFAULT_PROVIDER_CONCURRENCY: usize = 4;
+
+/// Default largest page a paginated list returns.
+pub const DEFAULT_MAX_PAGE_SIZE: usize = 500;
+
+/// Decimal places monetary amounts are rounded to when no currency rule matches.
+pub const DEFAULT_CURRENCY_PRECISION: u32 = 2;
+
+/// Most decimal places a currency rule may ask for.
+const MAX_CURRENCY_PRECISION: u32 = 18;
+
+/// Built-in currency rules: ISO 4217 minor units where they differ from the
+/// default, plus common crypto assets.
+const CURRENCY_PRECISION_DEFAULTS: &[(&str, u32)] = &[
+    ("BIF", 0),
+    ("CLP", 0),
+    ("DJF", 0),
+    ("GNF", 0),
+    ("ISK", 0),
+    ("JPY", 0),
+    ("KMF", 0),
+    ("KRW", 0),
+    ("PYG", 0),
+    ("RWF", 0),
+    ("UGX", 0),
+    ("VND", 0),
+    ("VUV", 0),
+    ("XAF", 0),
+    ("XOF", 0),
+    ("XPF", 0),
+    ("BHD", 3),
+    ("IQD", 3),
+    ("JOD", 3),
+    ("KWD", 3),
+    ("LYD", 3),
+    ("OMR", 3),
+    ("TND", 3),
+    ("BTC", 8),
+    ("ETH", 8),
+];
+
+/// Default number of holdings listed by the top holdings endpoint.
+pub const DEFAULT_TOP_HOLDINGS: usize = 10;
+
+/// Default quote age, in days, after which a holding counts as stale.
+pub const DEFAULT_STALE_QUOTE_DAYS: i64 = 3;
 
 #[async_trait]
 pub trait ExternalApiServiceTrait: Send + Sync {
-    async fn get_holdings(&self, account_id: Option<String>) -> Result<Value>;
+    async fn get_holdings(&self, query: HoldingsQuery) -> Result<Value>;
     fn get_accounts(&self) -> Result<Value>;
-    fn get_exchange_rates(&self) -> Result<Value>;
+    fn get_accounts_version(&self) -> Result<Option<ResourceVersion>>;
+    async fn recompute_account(&self, account_id: &str) -> Result<Value>;
+    async fn get_rebalance(&self, request: RebalanceRequest) -> Result<Value>;
+    async fn simulate_trade(&self, request: SimulateTradeRequest) -> Result<Value>;
+    async fn get_allocation_history(
+        &self,
+        group_by: &str,
+        interval: &str,
+        from: Option<String>,
+        to: Option<String>,
+    ) -> Result<Value>;
+    async fn get_account_currency_exposure(&self, account_id: &str) -> Result<Value>;
+    async fn get_lots(&self, symbol: &str, account_id: Option<String>) -> Result<Value>;
+    async fn get_unrealized_by_term(&self, account_id: Option<String>) -> Result<Value>;
+    async fn get_cash_summary(&self) -> Result<Value>;
+    async fn get_asset_classes(&self) -> Result<Value>;
+    async fn get_top_holdings(&self, account_id: Option<String>, limit: usize) -> Result<Value>;
+    async fn get_sector_exposure(&self, account_id: Option<String>, look_through: bool) -> Result<Value>;
+    async fn get_accounts_day_change(&self) -> Result<Value>;
+    async fn get_exchange_rates(&self, query: ExchangeRatesQuery) -> Result<Value>;
+    async fn upsert_exchange_rates(&self, rows: Vec<ExchangeRateRow>) -> Result<Value>;
+    async fn refresh_exchange_rates(&self) -> Result<Value>;
+    fn get_fx_path(&self, from: &str, to: &str) -> Result<Value>;
+    fn get_rates_to_base(&self, currencies: &[String]) -> Result<Value>;
+    fn get_exchange_rate(&self, from: &str, to: &str) -> Result<Value>;
+    fn convert_amount(&self, from: &str, to: &str, amount: Decimal) -> Result<Value>;
     fn get_base_currency(&self) -> Result<Value>;
+    fn get_base_currency_history(&self) -> Result<Value>;
+    fn get_default_account(&self) -> Result<Value>;
+    async fn set_default_account(&self, request: DefaultAccountRequest) -> Result<Value>;
+    async fn get_config_status(&self) -> Result<Value>;
+    async fn export_snapshot(&self) -> Result<Value>;
 
     // Market data methods
-    async fn search_market_data(&self, query: &str) -> Result<Value>;
-    fn get_quote(&self, symbol: &str) -> Result<Value>;
-    fn get_historical_quotes(&self, symbol: &str) -> Result<Value>;
+    async fn search_market_data(&self, query: &str, asset_class: Option<&str>) -> Result<Value>;
+    async fn resolve_symbol(&self, query: &str) -> Result<Value>;
+    async fn get_quote(
+        &self,
+        symbol: &str,
+        time_format: TimeFormat,
+        source: Option<&str>,
+    ) -> Result<Value>;
+    async fn get_historical_quotes(
+        &self,
+        symbol: &str,
+        time_format: TimeFormat,
+        source: Option<&str>,
+        adjusted: bool,
+        normalize: QuoteNormalization,
+    ) -> Result<Value>;
+    async fn add_manual_quote(&self, symbol: &str, request: ManualQuoteRequest) -> Result<Value>;
+    async fn get_data_sources(&self, symbol: &str) -> Result<Value>;
+    async fn ensure_symbol(&self, symbol: &str) -> Result<Value>;
+    async fn set_data_source(&self, symbol: &str, request: DataSourceUpdateRequest) -> Result<Value>;
+    async fn get_missing_quotes(&self) -> Result<Value>;
+    async fn get_held_quotes(&self, time_format: TimeFormat) -> Result<Value>;
+    fn get_quote_stats(&self, symbol: &str, window: &str) -> Result<Value>;
+    fn get_profile_freshness(&self, symbol: &str, stale_days: i64) -> Result<Value>;
+    fn get_quote_gaps(&self, symbol: &str, from: Option<String>, to: Option<String>) -> Result<Value>;
+    fn get_historical_quotes_bulk(
+        &self,
+        symbols: &[String],
+        from: Option<String>,
+        to: Option<String>,
+        normalize: QuoteNormalization,
+    ) -> Result<Value>;
+    async fn get_sync_status(&self) -> Result<Value>;
 
     // Performance methods
     async fn get_account_performance(&self, account_id: &str) -> Result<Value>;
-    fn get_portfolio_performance_summary(&self) -> Result<Value>;
+    async fn get_twr_series(
+        &self,
+        account_id: &str,
+        from: Option<String>,
+        to: Option<String>,
+        interval: &str,
+    ) -> Result<Value>;
+    fn get_valuation_series(&self, account_id: &str, from: Option<String>, to: Option<String>) -> Result<Value>;
+    async fn get_annualized_returns(&self, account_id: &str) -> Result<Value>;
+    async fn compare_accounts(&self, a: &str, b: &str, from: Option<String>, to: Option<String>) -> Result<Value>;
+    async fn get_beta(
+        &self,
+        account_id: Option<String>,
+        benchmark: &str,
+        from: Option<String>,
+        to: Option<String>,
+    ) -> Result<Value>;
+    async fn get_return_contribution(
+        &self,
+        account_id: Option<String>,
+        from: Option<String>,
+        to: Option<String>,
+    ) -> Result<Value>;
+    async fn get_correlation_matrix(
+        &self,
+        account_id: Option<String>,
+        from: Option<String>,
+        to: Option<String>,
+    ) -> Result<Value>;
+    async fn get_sharpe(
+        &self,
+        account_id: Option<String>,
+        from: Option<String>,
+        to: Option<String>,
+        risk_free_rate: Option<f64>,
+    ) -> Result<Value>;
+    async fn get_var(
+        &self,
+        account_id: Option<String>,
+        confidence: f64,
+        horizon_days: u32,
+        from: Option<String>,
+    ) -> Result<Value>;
+    async fn get_portfolio_performance_summary(&self, exclude_accounts: Option<String>) -> Result<Value>;
+    async fn get_yield(&self) -> Result<Value>;
+    fn get_value_changes(&self) -> Result<Value>;
+    fn get_gains_by_period(&self, account_id: Option<String>, interval: &str) -> Result<Value>;
+    fn get_fees_summary(
+        &self,
+        account_id: Option<String>,
+        from: Option<String>,
+        to: Option<String>,
+    ) -> Result<Value>;
 
     // Activities methods
-    fn get_activities(&self, account_id: Option<String>) -> Result<Value>;
+    fn get_activities(
+        &self,
+        account_id: Option<String>,
+        group_by: Option<&str>,
+        time_format: TimeFormat,
+        numbers: NumberFormat,
+        page: Pagination,
+    ) -> Result<Value>;
+
+    fn get_activity_date_range(&self, account_id: Option<String>) -> Result<Value>;
+    fn get_transfers(&self, from: Option<String>, to: Option<String>) -> Result<Value>;
+    fn get_symbol_activities(&self, symbol: &str, from: Option<String>, to: Option<String>) -> Result<Value>;
+    async fn create_activity(&self, payload: Value, idempotency_key: Option<&str>) -> Result<Value>;
+    async fn validate_activity(&self, payload: Value) -> Result<Value>;
+
+    // Alert methods
+    fn register_alert(&self, request: AlertRequest) -> Result<Value>;
+    fn delete_alert(&self, alert_id: &str) -> Result<Value>;
+    fn list_alerts(&self) -> Result<Value>;
+    async fn evaluate_alerts(&self) -> Result<Value>;
+
+    // Watchlist methods
+    fn get_watchlist(&self, include_quotes: bool, time_format: TimeFormat) -> Result<Value>;
+    async fn add_to_watchlist(&self, request: WatchlistRequest) -> Result<Value>;
+    async fn remove_from_watchlist(&self, symbols: &str) -> Result<Value>;
+
+    // Portfolio group methods
+    fn list_portfolio_groups(&self) -> Result<Value>;
+    async fn save_portfolio_group(&self, request: PortfolioGroupRequest) -> Result<Value>;
+    async fn get_portfolio_group_summary(&self, group_id: &str) -> Result<Value>;
+
+    // Instrument tag methods
+    fn get_instrument_tags(&self, symbol: &str) -> Result<Value>;
+    async fn set_instrument_tags(&self, symbol: &str, request: InstrumentTagsRequest) -> Result<Value>;
+}
+
+/// A sale replayed by `realize_lots`, with its gain in position and base currency
+struct RealizedSale {
+    account_id: String,
+    asset_id: String,
+    currency: String,
+    date: NaiveDate,
+    gain: Decimal,
+    gain_base: Decimal,
 }
 
 #[derive(Clone)]
@@ -41,6 +298,22 @@ pub struct ExternalApiService {
     market_data_service: Arc<dyn MarketDataServiceTrait>,
     performance_service: Arc<dyn PerformanceServiceTrait>,
     activity_service: Arc<dyn ActivityServiceTrait>,
+    asset_service: Arc<dyn AssetServiceTrait>,
+    valuation_service: Arc<dyn ValuationServiceTrait>,
+    snapshot_service: Arc<dyn SnapshotServiceTrait>,
+    alerts: Arc<RwLock<HashMap<String, Alert>>>,
+    idempotency: Arc<IdempotencyStore>,
+    http_client: reqwest::Client,
+    holdings_concurrency: usize,
+    max_page_size: usize,
+    currency_precision: Arc<CurrencyPrecision>,
+    /// Shared across clones so every request queues on the same permits
+    provider_permits: Arc<tokio::sync::Semaphore>,
+    /// Held across each read-modify-write of stored settings, so concurrent
+    /// requests cannot overwrite each other's changes
+    settings_writes: Arc<tokio::sync::Mutex<()>>,
+    /// Only consulted for whether a credential exists, never for its value
+    secret_store: Option<Arc<dyn SecretStore>>,
 }
 
 impl ExternalApiService {
@@ -52,6 +325,9 @@ impl ExternalApiService {
         market_data_service: Arc<dyn MarketDataServiceTrait>,
         performance_service: Arc<dyn PerformanceServiceTrait>,
         activity_service: Arc<dyn ActivityServiceTrait>,
+        asset_service: Arc<dyn AssetServiceTrait>,
+        valuation_service: Arc<dyn ValuationServiceTrait>,
+        snapshot_service: Arc<dyn SnapshotServiceTrait>,
     ) -> Self {
         Self {
             account_service,
@@ -61,44 +337,563 @@ impl ExternalApiService {
             market_data_service,
             performance_service,
             activity_service,
+            asset_service,
+            valuation_service,
+            snapshot_service,
+            alerts: Arc::new(RwLock::new(HashMap::new())),
+            idempotency: Arc::new(IdempotencyStore::new(std::time::Duration::from_secs(
+                DEFAULT_IDEMPOTENCY_TTL_SECS,
+            ))),
+            http_client: reqwest::Client::new(),
+            holdings_concurrency: DEFAULT_HOLDINGS_CONCURRENCY,
+            max_page_size: DEFAULT_MAX_PAGE_SIZE,
+            currency_precision: Arc::new(CurrencyPrecision::default()),
+            provider_permits: Arc::new(tokio::sync::Semaphore::new(DEFAULT_PROVIDER_CONCURRENCY)),
+            settings_writes: Arc::new(tokio::sync::Mutex::new(())),
+            secret_store: None,
+        }
+    }
+
+    /// Caps how many accounts are fetched at once when aggregating holdings,
+    /// to avoid exhausting the database connection pool.
+    pub fn with_holdings_concurrency(mut self, limit: usize) -> Self {
+        self.holdings_concurrency = limit.max(1);
+        self
+    }
+
+    /// Caps how many market data provider calls run at once; further calls queue
+    /// until a permit frees up. Database-backed endpoints are not gated.
+    pub fn with_provider_concurrency(mut self, limit: usize) -> Self {
+        self.provider_permits = Arc::new(tokio::sync::Semaphore::new(limit.max(1)));
+        self
+    }
+
+    /// Caps the `limit` a client may request from paginated lists.
+    pub fn with_max_page_size(mut self, max_page_size: usize) -> Self {
+        self.max_page_size = max_page_size.max(1);
+        self
+    }
+
+    /// Sets the decimal places monetary amounts are rounded to, per currency.
+    pub fn with_currency_precision(mut self, precision: CurrencyPrecision) -> Self {
+        self.currency_precision = Arc::new(precision);
+        self
+    }
+
+    /// Lets `GET /api/config/status` report which provider credentials are present.
+    pub fn with_secret_store(mut self, secret_store: Arc<dyn SecretStore>) -> Self {
+        self.secret_store = Some(secret_store);
+        self
+    }
+
+    /// Sets how long an `Idempotency-Key` is remembered after its request completes.
+    pub fn with_idempotency_ttl(mut self, ttl: std::time::Duration) -> Self {
+        self.idempotency = Arc::new(IdempotencyStore::new(ttl));
+        self
+    }
+
+    async fn fetch_account_holdings(
+        &self,
+        account_id: &str,
+        base_currency: &str,
+        as_of: Option<NaiveDate>,
+    ) -> Result<Vec<Holding>> {
+        match as_of {
+            Some(date) => {
+                self.holdings_service
+                    .get_holdings_as_of(account_id, base_currency, date)
+                    .await
+            }
+            None => self.holdings_service.get_holdings(account_id, base_currency).await,
+        }
+    }
+
+    /// Collects holdings for one account, or for all accounts when `account_id` is `None`.
+    /// Holdings are current unless `as_of` asks for a past date.
+    /// Accounts that fail are skipped and reported in the returned partial errors.
+    async fn collect_holdings(
+        &self,
+        account_id: Option<String>,
+        base_currency: &str,
+        as_of: Option<NaiveDate>,
+    ) -> Result<(Vec<Holding>, Vec<Value>)> {
+        self.collect_holdings_excluding(account_id, base_currency, as_of, &[]).await
+    }
+
+    /// Like `collect_holdings`, but leaves the `excluded` accounts out. Excluding the
+    /// requested account, or every account, yields no holdings rather than an error.
+    async fn collect_holdings_excluding(
+        &self,
+        account_id: Option<String>,
+        base_currency: &str,
+        as_of: Option<NaiveDate>,
+        excluded: &[String],
+    ) -> Result<(Vec<Holding>, Vec<Value>)> {
+        if let Some(account_id) = account_id {
+            if excluded.contains(&account_id) {
+                return Ok((Vec::new(), Vec::new()));
+            }
+            // Get holdings for specific account
+            let holdings = self.fetch_account_holdings(&account_id, base_currency, as_of).await?;
+            return Ok((holdings, Vec::new()));
+        }
+
+        // Get holdings for all accounts
+        let accounts = self.accounts_excluding(excluded)?;
+        Ok(self.holdings_for_accounts(accounts, base_currency, as_of).await)
+    }
+
+    /// Holdings for the listed accounts, leaving out the `excluded` ones. Ids that
+    /// match no account are reported in the returned partial errors.
+    async fn holdings_for_account_ids(
+        &self,
+        account_ids: &[String],
+        base_currency: &str,
+        as_of: Option<NaiveDate>,
+        excluded: &[String],
+    ) -> Result<(Vec<Holding>, Vec<Value>)> {
+        let mut accounts = self.account_service.get_all_accounts()?;
+        let mut partial_errors: Vec<Value> = account_ids
+            .iter()
+            .filter(|id| !accounts.iter().any(|account| &account.id == *id))
+            .map(|id| json!({
+                "accountId": id,
+                "error": "Account not found"
+            }))
+            .collect();
+        accounts.retain(|account| account_ids.contains(&account.id) && !excluded.contains(&account.id));
+
+        let (holdings, mut errors) = self.holdings_for_accounts(accounts, base_currency, as_of).await;
+        partial_errors.append(&mut errors);
+        Ok((holdings, partial_errors))
+    }
+
+    /// All accounts except the `excluded` ids.
+    fn accounts_excluding(&self, excluded: &[String]) -> Result<Vec<Account>> {
+        let mut accounts = self.account_service.get_all_accounts()?;
+        accounts.retain(|account| !excluded.contains(&account.id));
+        Ok(accounts)
+    }
+
+    /// Fetches holdings for several accounts concurrently. Accounts that fail are
+    /// skipped and reported in the returned partial errors.
+    async fn holdings_for_accounts(
+        &self,
+        accounts: Vec<Account>,
+        base_currency: &str,
+        as_of: Option<NaiveDate>,
+    ) -> (Vec<Holding>, Vec<Value>) {
+        // Accounts that failed during aggregation, so clients know the result is incomplete
+        let mut partial_errors: Vec<Value> = Vec::new();
+        let results: Vec<(Account, Result<Vec<Holding>>)> = stream::iter(accounts)
+            .map(|account| async move {
+                let result = self.fetch_account_holdings(&account.id, base_currency, as_of).await;
+                (account, result)
+            })
+            .buffered(self.holdings_concurrency)
+            .collect()
+            .await;
+
+        let mut all_holdings = Vec::new();
+        for (account, result) in results {
+            match result {
+                Ok(mut holdings) => all_holdings.append(&mut holdings),
+                Err(e) => {
+                    tracing::warn!(
+                        account_id = %account.id,
+                        error = %e,
+                        "Failed to get holdings for account"
+                    );
+                    partial_errors.push(json!({
+                        "accountId": account.id,
+                        "error": e.to_string()
+                    }));
+                }
+            }
+        }
+        (all_holdings, partial_errors)
+    }
+
+    /// Performance, currency exposure and gains across `accounts`, the body of the
+    /// portfolio summary.
+    async fn summarize_accounts(&self, accounts: Vec<Account>) -> Result<Value> {
+        let account_ids: Vec<String> = accounts.iter().map(|a| a.id.clone()).collect();
+        let performances = self.performance_service.calculate_accounts_simple_performance(&account_ids)?;
+        let performances_data = simple_performances_to_json(performances);
+
+        // Native-currency exposure, before everything is collapsed to base currency
+        let base_currency = self.settings_service.get_base_currency()?;
+        let mut by_currency = None;
+        let mut realized_gain = None;
+        let mut unrealized_gain = None;
+        let mut partial_errors = Vec::new();
+        if let Some(base) = &base_currency {
+            let (holdings, errors) = self.holdings_for_accounts(accounts, base, None).await;
+            by_currency = Some(market_value_by_currency(&holdings));
+            // Paper gains on what is still held
+            unrealized_gain = Some(
+                holdings
+                    .iter()
+                    .filter_map(|h| h.unrealized_gain.as_ref().map(|gain| gain.base))
+                    .sum::<Decimal>(),
+            );
+            // Locked-in gains from every sale since the first activity
+            let mut history = self.trade_history(None)?;
+            history.retain(|a| account_ids.contains(&a.account_id));
+            let (gains, mut realized_errors) = self.realized_gains(&history, base);
+            realized_gain = Some(gains.into_iter().map(|(_, gain)| gain).sum::<Decimal>());
+            partial_errors = errors;
+            partial_errors.append(&mut realized_errors);
+        }
+
+        Ok(json!({
+            "performances": performances_data,
+            "baseCurrency": base_currency,
+            "byCurrency": by_currency,
+            "realizedGain": realized_gain,
+            "realizedGainScope": "allTime",
+            "unrealizedGain": unrealized_gain,
+            "partialErrors": partial_errors
+        }))
+    }
+
+    /// Symbol search against the market data providers, gated by the provider permits.
+    async fn provider_search(&self, query: &str) -> Result<Vec<QuoteSummary>> {
+        let _permit = self
+            .provider_permits
+            .acquire()
+            .await
+            .map_err(|e| Error::Unexpected(format!("Provider limiter closed: {}", e)))?;
+        self.market_data_service.search_symbol(query).await
+    }
+
+    /// Live quote history with provider failover, gated by the provider permits.
+    async fn provider_history(
+        &self,
+        symbol: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+        order: Option<&[String]>,
+    ) -> Result<(String, Vec<Quote>)> {
+        let _permit = self
+            .provider_permits
+            .acquire()
+            .await
+            .map_err(|e| Error::Unexpected(format!("Provider limiter closed: {}", e)))?;
+        self.market_data_service
+            .get_historical_quotes_with_failover(symbol, start, end, order)
+            .await
+    }
+
+    /// Falls back to the configured default account when no account is given.
+    /// `None` still means all accounts when no default is set.
+    fn account_or_default(&self, account_id: Option<String>) -> Result<Option<String>> {
+        match account_id {
+            Some(id) => Ok(Some(id)),
+            None => self.settings_service.get_default_account_id(),
+        }
+    }
+
+    /// Provider order for a `source` override: `auto` keeps the configured priority
+    /// order, otherwise a comma-separated list of provider ids tried left to right.
+    async fn provider_order(&self, source: &str) -> Result<Option<Vec<String>>> {
+        if source.trim().eq_ignore_ascii_case(AUTO_SOURCE) {
+            return Ok(None);
+        }
+        let providers = self.market_data_service.get_market_data_providers_settings().await?;
+        let mut order = Vec::new();
+        for name in source.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let provider = providers
+                .iter()
+                .find(|p| p.id.eq_ignore_ascii_case(name))
+                .ok_or_else(|| {
+                    Error::Validation(ValidationError::InvalidInput(format!(
+                        "Unknown source '{}', expected one of: {}",
+                        name,
+                        providers.iter().map(|p| p.id.as_str()).collect::<Vec<_>>().join(", ")
+                    )))
+                })?;
+            if !order.contains(&provider.id) {
+                order.push(provider.id.clone());
+            }
+        }
+        if order.is_empty() {
+            return Err(Error::Validation(ValidationError::InvalidInput(
+                "source must name at least one provider".to_string(),
+            )));
+        }
+        Ok(Some(order))
+    }
+
+    /// Creates an activity from a validated payload and refreshes the account's holdings
+    /// and valuations so the next read reflects it.
+    async fn insert_activity(&self, mut payload: Value) -> Result<Value> {
+        if payload.get("isDraft").map_or(true, Value::is_null) {
+            payload["isDraft"] = json!(false);
+        }
+        let new_activity: NewActivity = serde_json::from_value(payload).map_err(|e| {
+            Error::Validation(ValidationError::InvalidInput(format!("Invalid activity: {}", e)))
+        })?;
+        let created = self.activity_service.create_activity(new_activity).await?;
+
+        // The activity is stored either way, so a failed refresh only leaves data stale
+        if let Err(e) = self.recompute_account(&created.account_id).await {
+            tracing::warn!(
+                account_id = %created.account_id,
+                error = %e,
+                "Failed to recompute account after creating activity"
+            );
+        }
+
+        Ok(json!({
+            "activity": activities_to_json(
+                vec![created],
+                TimeFormat::default(),
+                NumberFormat::default(),
+                &self.currency_precision,
+            )
+            .pop()
+        }))
+    }
+
+    /// Non-draft, non-cash activities for one account or all accounts, oldest first.
+    fn trade_history(&self, account_id: Option<&str>) -> Result<Vec<Activity>> {
+        let mut history: Vec<Activity> = match account_id {
+            Some(id) => self.activity_service.get_activities_by_account_id(id)?,
+            None => self.activity_service.get_activities()?,
+        };
+        history.retain(|a| !a.is_draft && !a.asset_id.starts_with(CASH_ASSET_PREFIX));
+        history.sort_by_key(|a| a.activity_date);
+        Ok(history)
+    }
+
+    /// Realized gain in base currency for each sale in `history` (as returned by
+    /// `trade_history`), relieving lots FIFO per account and asset, with the
+    /// activities that had to be left out.
+    fn realized_gains(&self, history: &[Activity], base_currency: &str) -> (Vec<(NaiveDate, Decimal)>, Vec<Value>) {
+        let (sales, _, partial_errors) = self.realized_sales(history, base_currency);
+        let gains = sales.into_iter().map(|sale| (sale.date, sale.gain_base)).collect();
+        (gains, partial_errors)
+    }
+
+    /// Replays `history` through the core lot engine (see `realize_lots`), converting
+    /// with the exchange rate of each activity date. Returns every sale with its
+    /// realized gain, the quantity left in each position at the end, and the
+    /// activities that could not be replayed, such as sales without an exchange rate.
+    fn realized_sales(
+        &self,
+        history: &[Activity],
+        base_currency: &str,
+    ) -> (Vec<RealizedSale>, HashMap<(String, String), Decimal>, Vec<Value>) {
+        realize_lots(history, base_currency, |amount, from, to, date| {
+            self.fx_service.convert_currency_for_date(amount, from, to, date)
+        })
+    }
+
+    /// Positions in `history` that have been sold down to zero, as zero-quantity
+    /// holdings carrying the gain realized on them, with the activities left out.
+    fn closed_holdings(&self, history: &[Activity], base_currency: &str) -> (Vec<Holding>, Vec<Value>) {
+        let (sales, remaining, partial_errors) = self.realized_sales(history, base_currency);
+        let mut closed: BTreeMap<(String, String), (String, NaiveDate, MonetaryValue)> = BTreeMap::new();
+        for sale in sales {
+            let key = (sale.account_id, sale.asset_id);
+            if !remaining.get(&key).is_some_and(|quantity| quantity.is_zero()) {
+                continue;
+            }
+            let entry = closed
+                .entry(key)
+                .or_insert_with(|| (sale.currency, sale.date, MonetaryValue::zero()));
+            entry.1 = entry.1.max(sale.date);
+            entry.2.local += sale.gain;
+            entry.2.base += sale.gain_base;
         }
+
+        let holdings = closed
+            .into_iter()
+            .map(|((account_id, asset_id), (currency, closed_on, gain))| {
+                let instrument = match self.asset_service.get_asset_by_id(&asset_id) {
+                    Ok(asset) => Instrument {
+                        id: asset.id,
+                        symbol: asset.symbol,
+                        name: asset.name,
+                        currency: asset.currency,
+                        notes: asset.notes,
+                        data_source: Some(asset.data_source),
+                        asset_class: asset.asset_class,
+                        asset_subclass: asset.asset_sub_class,
+                        countries: None,
+                        sectors: None,
+                        profile_updated_at: Some(asset.updated_at),
+                    },
+                    Err(_) => Instrument {
+                        id: asset_id.clone(),
+                        symbol: asset_id.clone(),
+                        name: None,
+                        currency: currency.clone(),
+                        notes: None,
+                        data_source: None,
+                        asset_class: None,
+                        asset_subclass: None,
+                        countries: None,
+                        sectors: None,
+                        profile_updated_at: None,
+                    },
+                };
+                Holding {
+                    id: format!("SEC-{}-{}", account_id, asset_id),
+                    account_id,
+                    holding_type: HoldingType::Security,
+                    instrument: Some(instrument),
+                    quantity: Decimal::ZERO,
+                    open_date: None,
+                    lots: None,
+                    local_currency: currency,
+                    base_currency: base_currency.to_string(),
+                    fx_rate: None,
+                    market_value: MonetaryValue::zero(),
+                    cost_basis: Some(MonetaryValue::zero()),
+                    price: None,
+                    quote_date: None,
+                    unrealized_gain: Some(MonetaryValue::zero()),
+                    unrealized_gain_pct: None,
+                    realized_gain: Some(gain.clone()),
+                    realized_gain_pct: None,
+                    total_gain: Some(gain),
+                    total_gain_pct: None,
+                    day_change: None,
+                    day_change_pct: None,
+                    prev_close_value: None,
+                    weight: Decimal::ZERO,
+                    as_of_date: closed_on,
+                }
+            })
+            .collect();
+        (holdings, partial_errors)
+    }
+
+    /// Posts a triggered alert to its callback URL.
+    async fn send_alert_callback(&self, alert: &Alert, value: Decimal, base_currency: &str) -> Result<()> {
+        let body = json!({
+            "name": PORTFOLIO_VALUE_ALERT_EVENT,
+            "payload": {
+                "alertId": alert.id,
+                "accountId": alert.account_id,
+                "condition": alert.condition,
+                "threshold": alert.threshold,
+                "value": value,
+                "baseCurrency": base_currency,
+                "triggeredAt": Utc::now().to_rfc3339()
+            }
+        });
+
+        self.http_client
+            .post(&alert.callback_url)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| Error::Unexpected(format!("Alert callback failed: {}", e)))?;
+        Ok(())
     }
 }
 
 #[async_trait]
 impl ExternalApiServiceTrait for ExternalApiService {
-    async fn get_holdings(&self, account_id: Option<String>) -> Result<Value> {
+    async fn get_holdings(&self, query: HoldingsQuery) -> Result<Value> {
         // Get base currency
         let base_currency = match self.settings_service.get_base_currency()? {
             Some(currency) => currency,
             None => return Ok(json!({"error": "Base currency not set"})),
         };
 
-        let holdings_result: Result<Vec<crate::portfolio::holdings::Holding>> = if let Some(account_id) = account_id {
-            // Get holdings for specific account
-            self.holdings_service.get_holdings(&account_id, &base_currency).await
-        } else {
-            // Get holdings for all accounts
-            let accounts = self.account_service.get_all_accounts()?;
-
-            let mut all_holdings = Vec::new();
-            for account in accounts {
-                match self.holdings_service.get_holdings(&account.id, &base_currency).await {
-                    Ok(mut holdings) => all_holdings.append(&mut holdings),
-                    Err(e) => {
-                        eprintln!("Failed to get holdings for account {}: {}", account.id, e);
-                    }
-                }
+        let excluded = query.exclude_accounts.as_deref().map(split_account_ids).unwrap_or_default();
+        // An explicit account list replaces the single-account and default-account selection
+        let account_id = match &query.account_ids {
+            Some(_) => None,
+            None => self.account_or_default(query.account_id.clone())?,
+        };
+        let collected = match &query.account_ids {
+            Some(ids) => {
+                self.holdings_for_account_ids(ids, &base_currency, query.as_of, &excluded)
+                    .await
+            }
+            None => {
+                self.collect_holdings_excluding(account_id.clone(), &base_currency, query.as_of, &excluded)
+                    .await
             }
-            Ok(all_holdings)
         };
+        match collected {
+            Ok((holdings, mut partial_errors)) => {
+                // Drop dust positions, keeping their value so totals still reconcile
+                let (mut holdings, dust): (Vec<Holding>, Vec<Holding>) =
+                    holdings.into_iter().partition(|holding| !query.is_dust(holding));
+                let dust_value: Decimal = dust.iter().map(|holding| holding.market_value.base).sum();
+
+                holdings.retain(|holding| query.positions.matches(holding));
+
+                let tags = self.settings_service.get_instrument_tags()?;
+                let tag_filter = query.tags.as_deref().map(split_tags).unwrap_or_default();
+                if !tag_filter.is_empty() {
+                    holdings.retain(|holding| holding_has_any_tag(holding, &tags, &tag_filter));
+                }
+
+                if query.stale_only.unwrap_or(false) {
+                    let threshold = query.stale_days.unwrap_or(DEFAULT_STALE_QUOTE_DAYS);
+                    let today = Utc::now().date_naive();
+                    // A security without any quote is as stale as it gets
+                    holdings.retain(|holding| {
+                        holding.holding_type == HoldingType::Security
+                            && quote_age_days(holding, today).map_or(true, |age| age > threshold)
+                    });
+                }
+
+                if query.rebase_weights.unwrap_or(false) {
+                    rebase_holding_weights(&mut holdings);
+                }
 
-        match holdings_result {
-            Ok(holdings) => {
-                let holdings_data = holdings_to_json(holdings);
+                sort_holdings(&mut holdings);
+                if let Some(sort) = query.sort {
+                    sort.apply(&mut holdings);
+                }
+                let numbers = NumberFormat::from_exact(query.exact);
+                let mut holdings_data = holdings_to_json(holdings, &tags, numbers, &self.currency_precision);
+                if query.include_closed.unwrap_or(false) {
+                    let mut history = self.trade_history(account_id.as_deref())?;
+                    history.retain(|a| {
+                        !excluded.contains(&a.account_id)
+                            && query.account_ids.as_ref().map_or(true, |ids| ids.contains(&a.account_id))
+                    });
+                    if let Some(as_of) = query.as_of {
+                        history.retain(|a| a.activity_date.date_naive() <= as_of);
+                    }
+                    for holding in holdings_data.iter_mut() {
+                        holding["closed"] = json!(false);
+                    }
+                    let (mut closed_holdings, mut closed_errors) = self.closed_holdings(&history, &base_currency);
+                    partial_errors.append(&mut closed_errors);
+                    sort_holdings(&mut closed_holdings);
+                    let mut closed = holdings_to_json(closed_holdings, &tags, numbers, &self.currency_precision);
+                    for holding in closed.iter_mut() {
+                        holding["closed"] = json!(true);
+                    }
+                    holdings_data.append(&mut closed);
+                }
+                let page_info = Pagination::new(query.limit, query.offset)
+                    .clamp(self.max_page_size)
+                    .apply(&mut holdings_data);
+                if let Some(fields) = query.fields.as_deref() {
+                    select_fields(&mut holdings_data, fields);
+                }
                 Ok(json!({
                     "holdings": holdings_data,
-                    "baseCurrency": base_currency
+                    "baseCurrency": base_currency,
+                    "asOfDate": query.as_of,
+                    "dustValue": numbers.render_amount(dust_value, &base_currency, &self.currency_precision),
+                    "total": page_info.total,
+                    "limit": page_info.limit,
+                    "offset": page_info.offset,
+                    "hasMore": page_info.has_more,
+                    "partialErrors": partial_errors
                 }))
             }
             Err(error) => Ok(json!({"error": error.to_string()})),
@@ -113,145 +908,2986 @@ impl ExternalApiServiceTrait for ExternalApiService {
         }))
     }
 
-    fn get_exchange_rates(&self) -> Result<Value> {
-        let rates = self.fx_service.get_latest_exchange_rates()?;
-        let rates_data = exchange_rates_to_json(rates);
+    fn get_accounts_version(&self) -> Result<Option<ResourceVersion>> {
+        let accounts = self.account_service.get_all_accounts()?;
+        Ok(accounts_version(&accounts))
+    }
+
+    async fn recompute_account(&self, account_id: &str) -> Result<Value> {
+        // Fail fast on unknown accounts before touching snapshots
+        let account = self.account_service.get_account(account_id)?;
+        let started = std::time::Instant::now();
+
+        let account_ids = [account.id.clone()];
+        self.snapshot_service
+            .force_recalculate_holdings_snapshots(Some(&account_ids))
+            .await?;
+        self.valuation_service
+            .calculate_valuation_history(&account.id, true)
+            .await?;
+
+        // The TOTAL portfolio aggregates account snapshots, so bring it up to date too
+        self.snapshot_service.calculate_total_portfolio_snapshots().await?;
+        self.valuation_service
+            .calculate_valuation_history(PORTFOLIO_TOTAL_ACCOUNT_ID, false)
+            .await?;
+
+        let base_currency = self.settings_service.get_base_currency()?.unwrap_or_default();
+        let holdings = self.holdings_service.get_holdings(&account.id, &base_currency).await?;
+        let positions = holdings
+            .iter()
+            .filter(|h| h.holding_type == HoldingType::Security)
+            .count();
+        let total_value: Decimal = holdings.iter().map(|h| h.market_value.base).sum();
+
         Ok(json!({
-            "exchangeRates": rates_data
+            "accountId": account.id,
+            "positions": positions,
+            "cashBalances": holdings.len() - positions,
+            "totalValue": total_value,
+            "baseCurrency": base_currency,
+            "durationMs": started.elapsed().as_millis() as u64
         }))
     }
 
-    fn get_base_currency(&self) -> Result<Value> {
-        match self.settings_service.get_base_currency()? {
-            Some(currency) => Ok(json!({
-                "baseCurrency": currency
-            })),
-            None => Ok(json!({
-                "error": "Base currency not set"
-            })),
+    async fn get_rebalance(&self, request: RebalanceRequest) -> Result<Value> {
+        if let Err(details) = request.validate() {
+            return Ok(validation_error_response(&details));
         }
-    }
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+
+        let account_id = self.account_or_default(request.account_id.clone())?;
+        let (holdings, partial_errors) = self
+            .collect_holdings(account_id.clone(), &base_currency, None)
+            .await?;
+        let allocation = allocation_by(&holdings, request.group_by);
+
+        let mut bucket_names: Vec<&String> = allocation.values.keys().collect();
+        for name in request.targets.keys() {
+            if !allocation.values.contains_key(name) {
+                bucket_names.push(name);
+            }
+        }
+        bucket_names.sort();
+
+        let buckets: Vec<Value> = bucket_names
+            .into_iter()
+            .map(|name| {
+                let current_value = allocation.values.get(name).copied().unwrap_or(Decimal::ZERO);
+                let current_weight = allocation.weight(name);
+                let target_weight = request.targets.get(name).copied().unwrap_or(Decimal::ZERO);
+                json!({
+                    "bucket": name,
+                    "currentValue": current_value,
+                    "currentWeight": current_weight.round_dp(4),
+                    "targetWeight": target_weight,
+                    "drift": (current_weight - target_weight).round_dp(4),
+                    // Positive amounts are purchases, negative amounts are sales
+                    "tradeAmount": (target_weight * allocation.total - current_value).round_dp(2)
+                })
+            })
+            .collect();
 
-    // Market data methods
-    async fn search_market_data(&self, query: &str) -> Result<Value> {
-        let results = self.market_data_service.search_symbol(query).await?;
-        let results_data = quote_summaries_to_json(results);
         Ok(json!({
-            "results": results_data
+            "accountId": account_id,
+            "groupBy": request.group_by,
+            "baseCurrency": base_currency,
+            "totalValue": allocation.total,
+            "buckets": buckets,
+            "partialErrors": partial_errors
         }))
     }
 
-    fn get_quote(&self, symbol: &str) -> Result<Value> {
-        let quote = self.market_data_service.get_latest_quote_for_symbol(symbol)?;
-        let quote_data = quote_to_json(quote);
+    async fn simulate_trade(&self, request: SimulateTradeRequest) -> Result<Value> {
+        if let Err(details) = request.validate() {
+            return Ok(validation_error_response(&details));
+        }
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+
+        let account_id = self.account_or_default(request.account_id.clone())?;
+        let (holdings, partial_errors) = self
+            .collect_holdings(account_id.clone(), &base_currency, None)
+            .await?;
+        let symbol = request.symbol.trim();
+        let position: Vec<&Holding> = holdings
+            .iter()
+            .filter(|h| {
+                h.instrument
+                    .as_ref()
+                    .is_some_and(|i| i.symbol.eq_ignore_ascii_case(symbol))
+            })
+            .collect();
+        let held_quantity: Decimal = position.iter().map(|h| h.quantity).sum();
+        if request.side == TradeSide::Sell && request.quantity > held_quantity {
+            return Ok(validation_error_response(&[FieldError::new(
+                "quantity",
+                format!("cannot sell more than the {} held", held_quantity),
+            )]));
+        }
+
+        // Price the trade in the position's currency, falling back to the asset's
+        let asset = self.asset_service.get_asset_by_id(symbol).ok();
+        let currency = request
+            .currency
+            .clone()
+            .or_else(|| position.first().map(|h| h.local_currency.clone()))
+            .or_else(|| asset.as_ref().map(|a| a.currency.clone()))
+            .unwrap_or_else(|| base_currency.clone());
+        let trade_value = request.quantity * request.price;
+        let fee = request.fee.unwrap_or(Decimal::ZERO);
+        let (security_change, cash_change) = match request.side {
+            TradeSide::Buy => (trade_value, -(trade_value + fee)),
+            TradeSide::Sell => (-trade_value, trade_value - fee),
+        };
+        let to_base =
+            |amount: Decimal| self.fx_service.convert_currency(amount, &currency, &base_currency);
+        let security_change_base = to_base(security_change)?;
+        let cash_change_base = to_base(cash_change)?;
+
+        let class_bucket = match position.first() {
+            Some(holding) => allocation_bucket(holding, AllocationGroup::AssetClass),
+            None => asset
+                .as_ref()
+                .and_then(|a| a.asset_class.clone())
+                .filter(|class| !class.trim().is_empty())
+                .unwrap_or_else(|| "UNCLASSIFIED".to_string()),
+        };
+        let symbol_bucket = position
+            .first()
+            .and_then(|h| h.instrument.as_ref().map(|i| i.symbol.clone()))
+            .unwrap_or_else(|| symbol.to_string());
+        let (security_bucket, cash_bucket) = match request.group_by {
+            AllocationGroup::AssetClass => (class_bucket, CASH_ASSET_CLASS.to_string()),
+            AllocationGroup::Symbol => (
+                symbol_bucket.clone(),
+                format!("{}-{}", CASH_ASSET_PREFIX, currency),
+            ),
+        };
+
+        // Reuse the allocation grouping, then move the trade's value between buckets
+        let before = allocation_by(&holdings, request.group_by);
+        let after = apply_trade(
+            &before,
+            (&security_bucket, security_change_base),
+            (&cash_bucket, cash_change_base),
+        );
+        let by_symbol = allocation_by(&holdings, AllocationGroup::Symbol);
+        let by_symbol_after = apply_trade(
+            &by_symbol,
+            (&symbol_bucket, security_change_base),
+            (&format!("{}-{}", CASH_ASSET_PREFIX, currency), cash_change_base),
+        );
+
+        let cash_before: Decimal = holdings
+            .iter()
+            .filter(|h| h.holding_type == HoldingType::Cash && h.local_currency == currency)
+            .map(|h| h.market_value.local)
+            .sum();
+        let cash_after = cash_before + cash_change;
+
+        let mut buckets: Vec<&String> = before.values.keys().chain(after.values.keys()).collect();
+        buckets.sort();
+        buckets.dedup();
+        let allocation: Vec<Value> = buckets
+            .into_iter()
+            .map(|bucket| {
+                let (weight_before, weight_after) = (before.weight(bucket), after.weight(bucket));
+                json!({
+                    "bucket": bucket,
+                    "weightBefore": weight_before.round_dp(4),
+                    "weightAfter": weight_after.round_dp(4),
+                    "drift": (weight_after - weight_before).round_dp(4)
+                })
+            })
+            .collect();
+        let holdings_after: Vec<Value> = by_symbol_after
+            .values
+            .iter()
+            .map(|(bucket, value)| {
+                json!({
+                    "symbol": bucket,
+                    "marketValue": value.round_dp(2),
+                    "weightBefore": by_symbol.weight(bucket).round_dp(4),
+                    "weight": by_symbol_after.weight(bucket).round_dp(4)
+                })
+            })
+            .collect();
+
         Ok(json!({
-            "quote": quote_data
+            "accountId": account_id,
+            "symbol": symbol_bucket,
+            "side": request.side,
+            "quantity": request.quantity,
+            "price": request.price,
+            "fee": fee,
+            "currency": currency,
+            "tradeValue": trade_value,
+            "tradeValueBase": security_change_base.abs(),
+            "baseCurrency": base_currency,
+            "cash": {
+                "currency": currency,
+                "before": cash_before,
+                "after": cash_after,
+                "shortfall": cash_after < Decimal::ZERO
+            },
+            "groupBy": request.group_by,
+            "totalValueBefore": before.total,
+            "totalValueAfter": after.total,
+            "holdings": holdings_after,
+            "allocation": allocation,
+            "partialErrors": partial_errors
         }))
     }
 
-    fn get_historical_quotes(&self, symbol: &str) -> Result<Value> {
-        let quotes = self.market_data_service.get_historical_quotes_for_symbol(symbol)?;
-        let quotes_data = quotes_to_json(quotes);
+    async fn get_allocation_history(
+        &self,
+        group_by: &str,
+        interval: &str,
+        from: Option<String>,
+        to: Option<String>,
+    ) -> Result<Value> {
+        let group_by = AllocationGroup::parse(group_by)?;
+        let interval = GainsInterval::parse(interval)?;
+        let to = parse_date_param("to", to.as_deref())?.unwrap_or_else(|| Utc::now().date_naive());
+        let from = match parse_date_param("from", from.as_deref())? {
+            Some(date) => date,
+            None => to.checked_sub_months(Months::new(12)).unwrap_or(to),
+        };
+        if from > to {
+            return Err(Error::Validation(ValidationError::InvalidInput(
+                "from must not be after to".to_string(),
+            )));
+        }
+        let dates = interval_boundaries(from, to, interval);
+        if dates.len() > MAX_ALLOCATION_POINTS {
+            return Err(Error::Validation(ValidationError::InvalidInput(format!(
+                "Range covers {} {} points, at most {} are allowed; narrow the range or use a longer interval",
+                dates.len(),
+                interval.as_str(),
+                MAX_ALLOCATION_POINTS
+            ))));
+        }
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+
+        let mut points = Vec::with_capacity(dates.len());
+        let mut partial_errors = Vec::new();
+        for date in dates {
+            let (holdings, errors) = self
+                .collect_holdings(None, &base_currency, Some(date))
+                .await?;
+            partial_errors.extend(errors.into_iter().map(|mut error| {
+                error["date"] = json!(date.to_string());
+                error
+            }));
+            let allocation = allocation_by(&holdings, group_by);
+            let buckets: BTreeMap<&String, Decimal> = allocation
+                .values
+                .keys()
+                .map(|bucket| (bucket, allocation.weight(bucket).round_dp(4)))
+                .collect();
+            points.push(json!({
+                "date": date.to_string(),
+                "totalValue": allocation.total,
+                "buckets": buckets
+            }));
+        }
+
         Ok(json!({
-            "symbol": symbol,
-            "quotes": quotes_data
+            "groupBy": group_by,
+            "interval": interval.as_str(),
+            "baseCurrency": base_currency,
+            "from": from.to_string(),
+            "to": to.to_string(),
+            "points": points,
+            "partialErrors": partial_errors
         }))
     }
 
-    // Performance methods
-    async fn get_account_performance(&self, account_id: &str) -> Result<Value> {
-        let performance = self.performance_service.calculate_performance_summary(
-            "account",
-            account_id,
-            None,
-            None,
-        ).await?;
-        let performance_data = performance_to_json(performance);
+    async fn get_lots(&self, symbol: &str, account_id: Option<String>) -> Result<Value> {
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+
+        let account_id = self.account_or_default(account_id)?;
+        let (mut holdings, mut partial_errors) = self
+            .collect_holdings(account_id.clone(), &base_currency, None)
+            .await?;
+        holdings.retain(|h| {
+            h.instrument.as_ref().is_some_and(|i| {
+                i.symbol.eq_ignore_ascii_case(symbol) || i.id.eq_ignore_ascii_case(symbol)
+            })
+        });
+        let (positions, mut lot_errors) =
+            holdings_with_lots(self.holdings_service.as_ref(), &holdings, &base_currency).await;
+        partial_errors.append(&mut lot_errors);
+        if positions.is_empty() {
+            return Ok(json!({
+                "error": format!("No open position in {}", symbol)
+            }));
+        }
+
+        let today = Utc::now().date_naive();
+        let mut lots: Vec<(DateTime<Utc>, Value)> = Vec::new();
+        for holding in &positions {
+            let price = holding.price.unwrap_or_default();
+            let fx_rate = holding.fx_rate.unwrap_or(Decimal::ONE);
+            for lot in holding.lots.iter().flatten() {
+                let market_value = lot.quantity * price;
+                let unrealized_gain = market_value - lot.cost_basis;
+                let unrealized_gain_pct = (!lot.cost_basis.is_zero())
+                    .then(|| (unrealized_gain / lot.cost_basis).round_dp(6));
+                lots.push((
+                    lot.acquisition_date,
+                    json!({
+                        "lotId": lot.id,
+                        "accountId": holding.account_id,
+                        "acquisitionDate": lot.acquisition_date.date_naive(),
+                        "holdingDays": (today - lot.acquisition_date.date_naive()).num_days(),
+                        "quantity": lot.quantity,
+                        "acquisitionPrice": lot.acquisition_price,
+                        "acquisitionFees": lot.acquisition_fees,
+                        "costBasis": lot.cost_basis,
+                        "marketValue": market_value,
+                        "unrealizedGain": {
+                            "local": unrealized_gain,
+                            "base": unrealized_gain * fx_rate
+                        },
+                        "unrealizedGainPct": unrealized_gain_pct
+                    }),
+                ));
+            }
+        }
+        // Oldest first, the order FIFO disposals consume lots in
+        lots.sort_by_key(|(acquired, _)| *acquired);
+
+        let first = &positions[0];
         Ok(json!({
+            "symbol": first.instrument.as_ref().map(|i| i.symbol.clone()),
             "accountId": account_id,
-            "performance": performance_data
+            "currency": first.local_currency,
+            "baseCurrency": base_currency,
+            "price": first.price,
+            "quantity": positions.iter().map(|h| h.quantity).sum::<Decimal>(),
+            "lots": lots.into_iter().map(|(_, lot)| lot).collect::<Vec<Value>>(),
+            "partialErrors": partial_errors
         }))
     }
 
-    fn get_portfolio_performance_summary(&self) -> Result<Value> {
-        let accounts = self.account_service.get_all_accounts()?;
-        let account_ids: Vec<String> = accounts.iter().map(|a| a.id.clone()).collect();
-        let performances = self.performance_service.calculate_accounts_simple_performance(&account_ids)?;
-        let performances_data = simple_performances_to_json(performances);
+    async fn get_unrealized_by_term(&self, account_id: Option<String>) -> Result<Value> {
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+
+        let account_id = self.account_or_default(account_id)?;
+        let (holdings, mut partial_errors) = self
+            .collect_holdings(account_id.clone(), &base_currency, None)
+            .await?;
+        let (holdings, mut lot_errors) =
+            holdings_with_lots(self.holdings_service.as_ref(), &holdings, &base_currency).await;
+        partial_errors.append(&mut lot_errors);
+
+        #[derive(Default)]
+        struct TermBucket {
+            lots: usize,
+            cost_basis: Decimal,
+            market_value: Decimal,
+            unrealized_gain: Decimal,
+        }
+
+        // Each lot is classified on its own acquisition date, so a position
+        // bought in several tranches can land in both buckets
+        let today = Utc::now().date_naive();
+        let mut short_term = TermBucket::default();
+        let mut long_term = TermBucket::default();
+        for holding in &holdings {
+            let price = holding.price.unwrap_or_default();
+            let fx_rate = holding.fx_rate.unwrap_or(Decimal::ONE);
+            for lot in holding.lots.iter().flatten() {
+                let bucket = if is_long_term(lot.acquisition_date.date_naive(), today) {
+                    &mut long_term
+                } else {
+                    &mut short_term
+                };
+                let market_value = lot.quantity * price;
+                bucket.lots += 1;
+                bucket.cost_basis += lot.cost_basis * fx_rate;
+                bucket.market_value += market_value * fx_rate;
+                bucket.unrealized_gain += (market_value - lot.cost_basis) * fx_rate;
+            }
+        }
+
+        let bucket_json = |bucket: &TermBucket| {
+            json!({
+                "lots": bucket.lots,
+                "costBasis": bucket.cost_basis,
+                "marketValue": bucket.market_value,
+                "unrealizedGain": bucket.unrealized_gain
+            })
+        };
         Ok(json!({
-            "performances": performances_data
+            "accountId": account_id,
+            "baseCurrency": base_currency,
+            "asOf": today.to_string(),
+            "shortTerm": bucket_json(&short_term),
+            "longTerm": bucket_json(&long_term),
+            "totalUnrealizedGain": short_term.unrealized_gain + long_term.unrealized_gain,
+            "partialErrors": partial_errors
         }))
     }
 
-    // Activities methods
-    fn get_activities(&self, account_id: Option<String>) -> Result<Value> {
-        let activities = match account_id {
-            Some(account_id) => self.activity_service.get_activities_by_account_id(&account_id)?,
-            None => self.activity_service.get_activities()?,
+    async fn get_account_currency_exposure(&self, account_id: &str) -> Result<Value> {
+        let account = self.account_service.get_account(account_id)?;
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
         };
-        let activities_data = activities_to_json(activities);
+
+        let holdings = self
+            .fetch_account_holdings(&account.id, &base_currency, None)
+            .await?;
+        let by_currency = market_value_by_currency(&holdings);
+        let total_base: Decimal = by_currency.values().map(|value| value.base).sum();
+        let currencies: Vec<Value> = by_currency
+            .into_iter()
+            .map(|(currency, value)| {
+                let weight = if total_base.is_zero() {
+                    Decimal::ZERO
+                } else {
+                    (value.base / total_base).round_dp(4)
+                };
+                json!({
+                    "currency": currency,
+                    "local": value.local,
+                    "base": value.base,
+                    "weight": weight
+                })
+            })
+            .collect();
+
         Ok(json!({
-            "activities": activities_data
+            "accountId": account.id,
+            "accountCurrency": account.currency,
+            "baseCurrency": base_currency,
+            "totalBase": total_base,
+            "currencies": currencies
         }))
     }
-}
 
-/// Convert holdings to JSON format for external API
-pub fn holdings_to_json(holdings: Vec<Holding>) -> Vec<Value> {
-    holdings.into_iter()
-        .map(|h| json!({
-            "id": h.id,
-            "accountId": h.account_id,
-            "holdingType": h.holding_type,
-            "instrument": h.instrument.map(|inst| json!({
-                "id": inst.id,
-                "symbol": inst.symbol,
-                "name": inst.name,
-                "currency": inst.currency,
-                "assetClass": inst.asset_class,
-                "assetSubclass": inst.asset_subclass,
-                "countries": inst.countries,
-                "sectors": inst.sectors
-            })),
-            "quantity": h.quantity,
-            "openDate": h.open_date.map(|dt| dt.to_rfc3339()),
-            "localCurrency": h.local_currency,
-            "baseCurrency": h.base_currency,
-            "fxRate": h.fx_rate,
-            "marketValue": {
-                "local": h.market_value.local,
-                "base": h.market_value.base
-            },
-            "costBasis": h.cost_basis.map(|cb| json!({
-                "local": cb.local,
-                "base": cb.base
-            })),
-            "price": h.price,
-            "unrealizedGain": h.unrealized_gain.map(|ug| json!({
-                "local": ug.local,
-                "base": ug.base
-            })),
-            "unrealizedGainPct": h.unrealized_gain_pct,
-            "realizedGain": h.realized_gain.map(|rg| json!({
-                "local": rg.local,
-                "base": rg.base
-            })),
-            "realizedGainPct": h.realized_gain_pct,
-            "totalGain": h.total_gain.map(|tg| json!({
-                "local": tg.local,
-                "base": tg.base
-            })),
-            "totalGainPct": h.total_gain_pct,
-            "dayChange": h.day_change.map(|dc| json!({
-                "local": dc.local,
-                "base": dc.base
-            })),
-            "dayChangePct": h.day_change_pct,
-            "weight": h.weight,
-            "asOfDate": h.as_of_date.to_string()
+    async fn get_asset_classes(&self) -> Result<Value> {
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+
+        let (holdings, partial_errors) = self.collect_holdings(None, &base_currency, None).await?;
+        Ok(json!({
+            "assetClasses": asset_classes_to_json(&holdings),
+            "partialErrors": partial_errors
         }))
-        .collect()
-}
+    }
+
+    async fn get_top_holdings(&self, account_id: Option<String>, limit: usize) -> Result<Value> {
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+
+        let account_id = self.account_or_default(account_id)?;
+        let (holdings, partial_errors) = self
+            .collect_holdings(account_id.clone(), &base_currency, None)
+            .await?;
+        // Positions held in several accounts are combined before ranking
+        let allocation = allocation_by(&holdings, AllocationGroup::Symbol);
+        let (top, rest) = top_buckets(&allocation, limit);
+        let other_value: Decimal = rest.iter().map(|(_, value)| *value).sum();
+        let other_weight = if allocation.total > Decimal::ZERO {
+            other_value / allocation.total
+        } else {
+            Decimal::ZERO
+        };
+
+        Ok(json!({
+            "accountId": account_id,
+            "baseCurrency": base_currency,
+            "totalValue": allocation.total,
+            "holdings": top
+                .iter()
+                .map(|(symbol, value)| json!({
+                    "symbol": symbol,
+                    "marketValue": value,
+                    "weight": allocation.weight(symbol).round_dp(4)
+                }))
+                .collect::<Vec<_>>(),
+            "other": {
+                "count": rest.len(),
+                "marketValue": other_value,
+                "weight": other_weight.round_dp(4)
+            },
+            "partialErrors": partial_errors
+        }))
+    }
+
+    async fn get_sector_exposure(&self, account_id: Option<String>, look_through: bool) -> Result<Value> {
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+
+        let account_id = self.account_or_default(account_id)?;
+        let (holdings, partial_errors) = self
+            .collect_holdings(account_id.clone(), &base_currency, None)
+            .await?;
+        let (exposure, looked_through) = sector_exposure(&holdings, look_through);
+        let mut sectors: Vec<(&String, &Decimal)> = exposure.values.iter().collect();
+        sectors.sort_by(|a, b| b.1.cmp(a.1));
+
+        Ok(json!({
+            "accountId": account_id,
+            "baseCurrency": base_currency,
+            "lookThrough": look_through,
+            "totalValue": exposure.total,
+            "sectors": sectors
+                .into_iter()
+                .map(|(sector, value)| json!({
+                    "sector": sector,
+                    "marketValue": value.round_dp(2),
+                    "weight": exposure.weight(sector).round_dp(4)
+                }))
+                .collect::<Vec<_>>(),
+            "lookedThrough": looked_through,
+            "partialErrors": partial_errors
+        }))
+    }
+
+    async fn get_accounts_day_change(&self) -> Result<Value> {
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+
+        let accounts = self.account_service.get_all_accounts()?;
+        let (holdings, partial_errors) = self
+            .holdings_for_accounts(accounts.clone(), &base_currency, None)
+            .await;
+        let failed: HashSet<&str> = partial_errors
+            .iter()
+            .filter_map(|error| error["accountId"].as_str())
+            .collect();
+
+        let mut total = DayChange::default();
+        let mut accounts_data = Vec::new();
+        for account in accounts.iter().filter(|a| !failed.contains(a.id.as_str())) {
+            let mut in_base = DayChange::default();
+            let mut in_account = DayChange::default();
+            for holding in holdings.iter().filter(|h| h.account_id == account.id) {
+                // Holdings without a previous close have no day change to report
+                let (Some(change), Some(previous)) = (&holding.day_change, &holding.prev_close_value) else {
+                    continue;
+                };
+                let to_account = |amount: Decimal| {
+                    self.fx_service
+                        .convert_currency(amount, &holding.local_currency, &account.currency)
+                };
+                in_base.add(change.base, previous.base);
+                in_account.add(to_account(change.local)?, to_account(previous.local)?);
+            }
+            total.add(in_base.amount, in_base.previous_value);
+            accounts_data.push(json!({
+                "accountId": account.id,
+                "name": account.name,
+                "currency": account.currency,
+                "dayGainLossAmount": {
+                    "base": in_base.amount,
+                    "account": in_account.amount
+                },
+                "dayReturnPercent": {
+                    "base": in_base.return_pct(),
+                    "account": in_account.return_pct()
+                }
+            }));
+        }
+
+        Ok(json!({
+            "baseCurrency": base_currency,
+            "dayGainLossAmount": total.amount,
+            "dayReturnPercent": total.return_pct(),
+            "accounts": accounts_data,
+            "partialErrors": partial_errors
+        }))
+    }
+
+    async fn get_cash_summary(&self) -> Result<Value> {
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+
+        let (holdings, partial_errors) = self.collect_holdings(None, &base_currency, None).await?;
+
+        // Cash balances per currency, with the FX rate the holdings were converted at
+        let mut currencies: BTreeMap<String, (Decimal, Decimal, Option<Decimal>)> = BTreeMap::new();
+        let mut accounts: Vec<String> = Vec::new();
+        for holding in holdings.iter().filter(|h| h.holding_type == HoldingType::Cash) {
+            let entry = currencies
+                .entry(holding.local_currency.clone())
+                .or_insert((Decimal::ZERO, Decimal::ZERO, None));
+            entry.0 += holding.market_value.local;
+            entry.1 += holding.market_value.base;
+            if entry.2.is_none() {
+                entry.2 = holding.fx_rate;
+            }
+            if !accounts.contains(&holding.account_id) {
+                accounts.push(holding.account_id.clone());
+            }
+        }
+
+        let total_base: Decimal = currencies.values().map(|(_, base, _)| *base).sum();
+        let currencies_data: Vec<Value> = currencies
+            .into_iter()
+            .map(|(currency, (balance, balance_base, fx_rate))| json!({
+                "currency": currency,
+                "balance": balance,
+                "fxRate": fx_rate,
+                "balanceBase": balance_base
+            }))
+            .collect();
+
+        Ok(json!({
+            "baseCurrency": base_currency,
+            "totalBase": total_base,
+            "currencies": currencies_data,
+            "accountIds": accounts,
+            "partialErrors": partial_errors
+        }))
+    }
+
+    async fn get_exchange_rates(&self, query: ExchangeRatesQuery) -> Result<Value> {
+        let rates = self.fx_service.get_latest_exchange_rates()?;
+        if query.account_id.is_none() && !query.relevant_only.unwrap_or(false) {
+            return Ok(json!({
+                "exchangeRates": exchange_rates_to_json(rates, query.time_format)
+            }));
+        }
+
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+        let account_id = self.account_or_default(query.account_id)?;
+        let (holdings, partial_errors) = self
+            .collect_holdings(account_id.clone(), &base_currency, None)
+            .await?;
+        let currencies: BTreeSet<String> = holdings
+            .iter()
+            .map(|h| h.local_currency.to_uppercase())
+            .collect();
+        let (rates, unresolved) = relevant_exchange_rates(rates, &currencies, &base_currency);
+
+        let mut response = json!({
+            "accountId": account_id,
+            "baseCurrency": base_currency,
+            "exchangeRates": exchange_rates_to_json(rates, query.time_format),
+            "unresolvedCurrencies": unresolved
+        });
+        if !partial_errors.is_empty() {
+            response["partialErrors"] = json!(partial_errors);
+        }
+        Ok(response)
+    }
+
+    async fn upsert_exchange_rates(&self, rows: Vec<ExchangeRateRow>) -> Result<Value> {
+        if let Err(details) = validate_exchange_rate_rows(&rows) {
+            return Ok(validation_error_response(&details));
+        }
+
+        let now = Utc::now();
+        let mut rates = Vec::with_capacity(rows.len());
+        for row in rows {
+            let new_rate = NewExchangeRate {
+                from_currency: row.from.trim().to_uppercase(),
+                to_currency: row.to.trim().to_uppercase(),
+                rate: row.rate,
+                source: DataSource::Manual,
+            };
+            let saved = self
+                .fx_service
+                .add_exchange_rate_at(new_rate, row.timestamp.unwrap_or(now))
+                .await?;
+            rates.push(saved);
+        }
+        // Rebuild the conversion cache so the new rates apply immediately
+        self.fx_service.initialize()?;
+
+        Ok(json!({
+            "upserted": rates.len(),
+            "exchangeRates": exchange_rates_to_json(rates, TimeFormat::Rfc3339)
+        }))
+    }
+
+    async fn refresh_exchange_rates(&self) -> Result<Value> {
+        let before = self.fx_service.get_latest_exchange_rates()?;
+        // Manual rates have no provider to refresh from
+        let symbols: Vec<String> = before
+            .iter()
+            .filter(|rate| rate.source != DataSource::Manual)
+            .map(|rate| ExchangeRate::make_fx_symbol(&rate.from_currency, &rate.to_currency))
+            .collect();
+        if symbols.is_empty() {
+            return Ok(json!({
+                "updated": 0,
+                "refreshedAt": Utc::now().to_rfc3339(),
+                "exchangeRates": [],
+                "failed": []
+            }));
+        }
+
+        let (_, failures) = {
+            let _permit = self
+                .provider_permits
+                .acquire()
+                .await
+                .map_err(|e| Error::Unexpected(format!("Provider limiter closed: {}", e)))?;
+            self.market_data_service.resync_market_data(Some(symbols)).await?
+        };
+        // Rebuild the conversion cache so refreshed rates apply immediately
+        self.fx_service.initialize()?;
+        let after = self.fx_service.get_latest_exchange_rates()?;
+        let refreshed = refreshed_exchange_rates(&before, after);
+
+        Ok(json!({
+            "updated": refreshed.len(),
+            "refreshedAt": refreshed.iter().map(|rate| rate.timestamp).max().map(|t| t.to_rfc3339()),
+            "exchangeRates": exchange_rates_to_json(refreshed, TimeFormat::Rfc3339),
+            "failed": failures
+                .into_iter()
+                .map(|(symbol, error)| {
+                    let (from, to) = ExchangeRate::parse_fx_symbol(&symbol);
+                    json!({ "from": from, "to": to, "error": error })
+                })
+                .collect::<Vec<_>>()
+        }))
+    }
+
+    fn get_fx_path(&self, from: &str, to: &str) -> Result<Value> {
+        let from = from.trim().to_uppercase();
+        let to = to.trim().to_uppercase();
+        let rates = self.fx_service.get_latest_exchange_rates()?;
+
+        let Some(hops) = find_fx_path(&rates, &from, &to) else {
+            return Ok(rate_not_found_response(&from, &to));
+        };
+        let rate: Decimal = hops.iter().map(|hop| hop.rate).product();
+
+        Ok(json!({
+            "from": from,
+            "to": to,
+            "rate": rate,
+            "hops": hops.iter().map(fx_hop_to_json).collect::<Vec<_>>()
+        }))
+    }
+
+    fn get_rates_to_base(&self, currencies: &[String]) -> Result<Value> {
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency.to_uppercase(),
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+        let rates = self.fx_service.get_latest_exchange_rates()?;
+
+        let mut seen = HashSet::new();
+        let mut converted = Vec::new();
+        let mut missing = Vec::new();
+        for currency in currencies.iter().map(|c| c.trim().to_uppercase()) {
+            if currency.is_empty() || !seen.insert(currency.clone()) {
+                continue;
+            }
+            match find_fx_path(&rates, &currency, &base_currency) {
+                Some(hops) => {
+                    let rate: Decimal = hops.iter().map(|hop| hop.rate).product();
+                    converted.push(json!({
+                        "currency": currency,
+                        "rate": rate,
+                        "method": fx_path_method(&hops),
+                        "hops": hops.iter().map(fx_hop_to_json).collect::<Vec<_>>()
+                    }));
+                }
+                None => missing.push(json!({
+                    "currency": currency,
+                    "code": RATE_NOT_FOUND_CODE
+                })),
+            }
+        }
+
+        Ok(json!({
+            "baseCurrency": base_currency,
+            "rates": converted,
+            "missing": missing
+        }))
+    }
+
+    fn get_exchange_rate(&self, from: &str, to: &str) -> Result<Value> {
+        let from = from.trim().to_uppercase();
+        let to = to.trim().to_uppercase();
+        if from == to {
+            return Ok(json!({
+                "from": from,
+                "to": to,
+                "rate": Decimal::ONE,
+                "synthesized": false,
+                "dataSource": null,
+                "timestamp": null
+            }));
+        }
+
+        let rates = self.fx_service.get_latest_exchange_rates()?;
+        match find_pair_rate(&rates, &from, &to) {
+            Some(pair) => Ok(pair_rate_to_json(&pair)),
+            None => Ok(rate_not_found_response(&from, &to)),
+        }
+    }
+
+    fn convert_amount(&self, from: &str, to: &str, amount: Decimal) -> Result<Value> {
+        let rate = self.get_exchange_rate(from, to)?;
+        if rate.get("error").is_some() {
+            return Ok(rate);
+        }
+        let multiplier: Decimal = serde_json::from_value(rate["rate"].clone())
+            .map_err(|e| Error::Unexpected(format!("Invalid exchange rate: {}", e)))?;
+
+        Ok(json!({
+            "from": rate["from"],
+            "to": rate["to"],
+            "amount": amount,
+            "converted": amount * multiplier,
+            "rate": rate["rate"],
+            "synthesized": rate["synthesized"],
+            "timestamp": rate["timestamp"]
+        }))
+    }
+
+    fn get_base_currency(&self) -> Result<Value> {
+        match self.settings_service.get_base_currency()? {
+            Some(currency) => Ok(json!({
+                "baseCurrency": currency
+            })),
+            None => Ok(json!({
+                "error": "Base currency not set"
+            })),
+        }
+    }
+
+    fn get_base_currency_history(&self) -> Result<Value> {
+        let current = self.settings_service.get_base_currency()?;
+        let history = self.settings_service.get_base_currency_history()?;
+        // Changes made before history was tracked leave no entry for the current currency
+        let last_set_at = history
+            .last()
+            .filter(|change| current.as_deref() == Some(change.currency.as_str()))
+            .map(|change| change.effective_at);
+        Ok(json!({
+            "baseCurrency": current,
+            "lastSetAt": last_set_at,
+            "history": history
+        }))
+    }
+
+    fn get_default_account(&self) -> Result<Value> {
+        Ok(json!({
+            "defaultAccountId": self.settings_service.get_default_account_id()?
+        }))
+    }
+
+    async fn set_default_account(&self, request: DefaultAccountRequest) -> Result<Value> {
+        let account_id = request
+            .account_id
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty());
+        if let Some(id) = &account_id {
+            // Fails with a not-found error for unknown accounts
+            self.account_service.get_account(id)?;
+        }
+        self.settings_service
+            .update_default_account_id(account_id.as_deref())
+            .await?;
+        Ok(json!({
+            "defaultAccountId": account_id
+        }))
+    }
+
+    async fn get_config_status(&self) -> Result<Value> {
+        let providers = self.market_data_service.get_market_data_providers_settings().await?;
+        let has_credential = |id: &str| {
+            self.secret_store.as_ref().map(|store| match store.get_secret(id) {
+                Ok(secret) => secret.is_some_and(|value| !value.trim().is_empty()),
+                // Unreadable secrets leave the provider unconfigured, as the registry sees it
+                Err(_) => false,
+            })
+        };
+        Ok(config_status_to_json(&providers, has_credential, self.secret_store.is_some()))
+    }
+
+    async fn export_snapshot(&self) -> Result<Value> {
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+
+        let accounts = self.account_service.get_all_accounts()?;
+        let (holdings, partial_errors) = self.collect_holdings(None, &base_currency, None).await?;
+        let activities = self.activity_service.get_activities()?;
+        let rates = self.fx_service.get_latest_exchange_rates()?;
+        let tags = self.settings_service.get_instrument_tags()?;
+
+        let mut settings = serde_json::to_value(self.settings_service.get_settings()?)
+            .map_err(|e| Error::Unexpected(format!("Failed to serialize settings: {}", e)))?;
+        if let Value::Object(map) = &mut settings {
+            map.insert(
+                "defaultAccountId".to_string(),
+                json!(self.settings_service.get_default_account_id()?),
+            );
+            map.insert("watchlist".to_string(), json!(self.settings_service.get_watchlist()?));
+            map.insert("instrumentTags".to_string(), json!(tags));
+        }
+
+        Ok(json!({
+            "version": EXPORT_SCHEMA_VERSION,
+            "generatedAt": Utc::now().to_rfc3339(),
+            "baseCurrency": base_currency,
+            "settings": settings,
+            "accounts": accounts_to_json(accounts),
+            "holdings": holdings_to_json(holdings, &tags, NumberFormat::default(), &self.currency_precision),
+            "activities": activities_to_json(
+                activities,
+                TimeFormat::Rfc3339,
+                NumberFormat::default(),
+                &self.currency_precision,
+            ),
+            "exchangeRates": exchange_rates_to_json(rates, TimeFormat::Rfc3339),
+            "partialErrors": partial_errors
+        }))
+    }
+
+    // Market data methods
+    async fn search_market_data(&self, query: &str, asset_class: Option<&str>) -> Result<Value> {
+        let classes = asset_class.map(parse_search_asset_classes).transpose()?;
+        let mut results = self.provider_search(query).await?;
+        if let Some(classes) = classes {
+            results.retain(|r| classes.iter().any(|class| r.quote_type.eq_ignore_ascii_case(class)));
+        }
+        let results_data = quote_summaries_to_json(results);
+        Ok(json!({
+            "results": results_data
+        }))
+    }
+
+    async fn resolve_symbol(&self, query: &str) -> Result<Value> {
+        let results = self.provider_search(query).await?;
+
+        match best_symbol_match(query, &results) {
+            Some((index, confidence)) => Ok(json!({
+                "query": query,
+                "ambiguous": false,
+                "symbol": results[index].symbol,
+                "confidence": confidence,
+                "match": quote_summaries_to_json(vec![results[index].clone()]).pop(),
+                "candidates": []
+            })),
+            None => {
+                let candidates: Vec<QuoteSummary> =
+                    results.into_iter().take(MAX_RESOLVE_CANDIDATES).collect();
+                Ok(json!({
+                    "query": query,
+                    "ambiguous": !candidates.is_empty(),
+                    "symbol": null,
+                    "confidence": 0.0,
+                    "match": null,
+                    "candidates": quote_summaries_to_json(candidates)
+                }))
+            }
+        }
+    }
+
+    async fn get_quote(
+        &self,
+        symbol: &str,
+        time_format: TimeFormat,
+        source: Option<&str>,
+    ) -> Result<Value> {
+        let order = match source {
+            Some(source) => self.provider_order(source).await?,
+            None => None,
+        };
+        let today = Utc::now().date_naive();
+        let live = self
+            .provider_history(
+                symbol,
+                today - chrono::Duration::days(LIVE_QUOTE_LOOKBACK_DAYS),
+                today,
+                order.as_deref(),
+            )
+            .await
+            .and_then(|(provider_id, quotes)| {
+                let quote = quotes
+                    .into_iter()
+                    .max_by_key(|q| q.timestamp)
+                    .ok_or_else(|| Error::Unexpected(format!("No quote found for {}", symbol)))?;
+                Ok((provider_id, quote))
+            });
+        let (data_source, quote) = match live {
+            Ok(answer) => answer,
+            // Without a `source` override, symbols no provider covers keep their saved quote
+            Err(e) if source.is_none() => {
+                tracing::warn!(symbol = %symbol, error = %e, "No provider returned a quote, using the saved one");
+                let quote = self.market_data_service.get_latest_quote_for_symbol(symbol)?;
+                (quote.data_source.as_str().to_string(), quote)
+            }
+            Err(e) => return Err(e),
+        };
+        let quote_data = quote_to_json(quote, time_format);
+        Ok(json!({
+            "dataSource": data_source,
+            "quote": quote_data
+        }))
+    }
+
+    async fn get_historical_quotes(
+        &self,
+        symbol: &str,
+        time_format: TimeFormat,
+        source: Option<&str>,
+        adjusted: bool,
+        normalize: QuoteNormalization,
+    ) -> Result<Value> {
+        let order = match source {
+            Some(source) => self.provider_order(source).await?,
+            None => None,
+        };
+        let today = Utc::now().date_naive();
+        let live = self
+            .provider_history(
+                symbol,
+                today - chrono::Duration::days(LIVE_HISTORY_DAYS),
+                today,
+                order.as_deref(),
+            )
+            .await;
+        let (data_source, mut quotes) = match live {
+            Ok((provider_id, mut quotes)) => {
+                quotes.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+                (Some(provider_id), quotes)
+            }
+            // Without a `source` override, symbols no provider covers keep their saved history
+            Err(e) if source.is_none() => {
+                tracing::warn!(symbol = %symbol, error = %e, "No provider returned quotes, using the saved history");
+                let quotes = self.market_data_service.get_historical_quotes_for_symbol(symbol)?;
+                let data_source = quotes.last().map(|q| q.data_source.as_str().to_string());
+                (data_source, quotes)
+            }
+            Err(e) => return Err(e),
+        };
+        let adjustment_applied = adjusted && adjust_quotes(&mut quotes);
+        let quotes_data = normalize.render(quotes, time_format);
+        Ok(json!({
+            "symbol": symbol,
+            "dataSource": data_source,
+            "adjusted": adjustment_applied,
+            "normalize": normalize,
+            "quotes": quotes_data
+        }))
+    }
+
+    async fn add_manual_quote(&self, symbol: &str, request: ManualQuoteRequest) -> Result<Value> {
+        request.validate()?;
+
+        let date = request.date.unwrap_or_else(|| Utc::now().date_naive());
+        let timestamp = date
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+            .and_local_timezone(Utc)
+            .unwrap();
+
+        // Same id scheme as provider quotes, so the manual price replaces that day's quote
+        let quote = Quote {
+            id: format!("{}_{}", date.format("%Y%m%d"), symbol),
+            symbol: symbol.to_string(),
+            timestamp,
+            open: request.price,
+            high: request.price,
+            low: request.price,
+            close: request.price,
+            adjclose: request.price,
+            volume: Decimal::ZERO,
+            currency: request.currency.trim().to_uppercase(),
+            data_source: DataSource::Manual,
+            created_at: Utc::now(),
+        };
+
+        let stored = self.market_data_service.add_quote(&quote).await?;
+        let mut quote_data = quote_to_json(stored, TimeFormat::default());
+        quote_data["dataSource"] = json!(DataSource::Manual.as_str());
+        Ok(json!({
+            "quote": quote_data
+        }))
+    }
+
+    async fn get_data_sources(&self, symbol: &str) -> Result<Value> {
+        let asset = self.asset_service.get_asset_by_id(symbol)?;
+        let providers = self.market_data_service.get_market_data_providers_settings().await?;
+        let latest_quote_source = self
+            .market_data_service
+            .get_latest_quote_for_symbol(symbol)
+            .ok()
+            .map(|quote| quote.data_source.as_str());
+
+        Ok(json!({
+            "symbol": symbol,
+            "activeSource": asset.data_source,
+            "latestQuoteSource": latest_quote_source,
+            "sources": data_sources_to_json(&providers, &asset.data_source)
+        }))
+    }
+
+    async fn ensure_symbol(&self, symbol: &str) -> Result<Value> {
+        let symbol = symbol.trim();
+        if symbol.is_empty() {
+            return Err(Error::Validation(ValidationError::InvalidInput(
+                "symbol must not be empty".to_string(),
+            )));
+        }
+
+        let (asset, created) = match self.asset_service.get_asset_by_id(symbol) {
+            Ok(asset) => (asset, false),
+            Err(Error::Database(DatabaseError::QueryFailed(diesel::result::Error::NotFound))) => {
+                // Creating the asset fetches its profile from the provider
+                let _permit = self
+                    .provider_permits
+                    .acquire()
+                    .await
+                    .map_err(|e| Error::Unexpected(format!("Provider limiter closed: {}", e)))?;
+                (self.asset_service.get_or_create_asset(symbol, None).await?, true)
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(json!({
+            "symbol": symbol,
+            "created": created,
+            "asset": {
+                "id": asset.id,
+                "symbol": asset.symbol,
+                "name": asset.name,
+                "currency": asset.currency,
+                "assetClass": asset.asset_class,
+                "assetSubclass": asset.asset_sub_class,
+                "dataSource": asset.data_source
+            }
+        }))
+    }
+
+    async fn set_data_source(&self, symbol: &str, request: DataSourceUpdateRequest) -> Result<Value> {
+        let source = request.data_source.trim().to_uppercase();
+        let providers = self.market_data_service.get_market_data_providers_settings().await?;
+        if source != DataSource::Manual.as_str() && !providers.iter().any(|p| p.id == source) {
+            return Err(Error::Validation(ValidationError::InvalidInput(format!(
+                "Unknown data source '{}'",
+                request.data_source
+            ))));
+        }
+
+        let asset = self
+            .asset_service
+            .update_asset_data_source(symbol, source)
+            .await?;
+
+        Ok(json!({
+            "symbol": symbol,
+            "activeSource": asset.data_source,
+            "sources": data_sources_to_json(&providers, &asset.data_source)
+        }))
+    }
+
+    async fn get_missing_quotes(&self) -> Result<Value> {
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+
+        let (holdings, partial_errors) = self.collect_holdings(None, &base_currency, None).await?;
+
+        // Held quantity per symbol, broken down by account
+        let mut positions: BTreeMap<String, (Option<String>, BTreeMap<String, Decimal>)> =
+            BTreeMap::new();
+        for holding in holdings.iter().filter(|h| h.holding_type == HoldingType::Security) {
+            let Some(instrument) = &holding.instrument else {
+                continue;
+            };
+            let entry = positions
+                .entry(instrument.id.clone())
+                .or_insert_with(|| (instrument.name.clone(), BTreeMap::new()));
+            *entry.1.entry(holding.account_id.clone()).or_default() += holding.quantity;
+        }
+
+        let symbols: Vec<String> = positions.keys().cloned().collect();
+        let quotes = self.market_data_service.get_latest_quotes_for_symbols(&symbols)?;
+        let today = Utc::now().date_naive();
+
+        let missing: Vec<Value> = positions
+            .into_iter()
+            .filter_map(|(symbol, (name, accounts))| {
+                let last_quote_date = quotes.get(&symbol).map(|q| q.timestamp.date_naive());
+                let age = last_quote_date.map(|date| (today - date).num_days());
+                let reason = match age {
+                    None => "missing",
+                    Some(age) if age > DEFAULT_STALE_QUOTE_DAYS => "stale",
+                    Some(_) => return None,
+                };
+                let quantity: Decimal = accounts.values().copied().sum();
+                Some(json!({
+                    "symbol": symbol,
+                    "name": name,
+                    "reason": reason,
+                    "lastQuoteDate": last_quote_date,
+                    "quoteAgeDays": age,
+                    "quantity": quantity,
+                    "accounts": accounts
+                        .into_iter()
+                        .map(|(account_id, quantity)| json!({
+                            "accountId": account_id,
+                            "quantity": quantity
+                        }))
+                        .collect::<Vec<Value>>()
+                }))
+            })
+            .collect();
+
+        Ok(json!({
+            "missing": missing,
+            "staleAfterDays": DEFAULT_STALE_QUOTE_DAYS,
+            "partialErrors": partial_errors
+        }))
+    }
+
+    async fn get_held_quotes(&self, time_format: TimeFormat) -> Result<Value> {
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+
+        let (holdings, partial_errors) = self.collect_holdings(None, &base_currency, None).await?;
+        let mut symbols: Vec<String> = holdings
+            .iter()
+            .filter(|h| h.holding_type == HoldingType::Security)
+            .filter_map(|h| h.instrument.as_ref().map(|i| i.id.clone()))
+            .collect();
+        symbols.sort();
+        symbols.dedup();
+
+        let mut latest = self.market_data_service.get_latest_quotes_for_symbols(&symbols)?;
+        let mut quotes = serde_json::Map::new();
+        let mut missing = Vec::new();
+        for symbol in &symbols {
+            match latest.remove(symbol) {
+                Some(quote) => {
+                    quotes.insert(symbol.clone(), quote_to_json(quote, time_format));
+                }
+                None => missing.push(symbol.clone()),
+            }
+        }
+
+        Ok(json!({
+            "symbols": symbols,
+            "quotes": quotes,
+            "missing": missing,
+            "partialErrors": partial_errors
+        }))
+    }
+
+    fn get_profile_freshness(&self, symbol: &str, stale_days: i64) -> Result<Value> {
+        match self.asset_service.get_asset_by_id(symbol.trim()) {
+            Ok(asset) => Ok(profile_freshness_to_json(&asset, Utc::now(), stale_days)),
+            Err(Error::Database(DatabaseError::QueryFailed(diesel::result::Error::NotFound))) => Ok(json!({
+                "error": format!("No stored profile for {}", symbol)
+            })),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn get_quote_stats(&self, symbol: &str, window: &str) -> Result<Value> {
+        let window = StatsWindow::parse(window)?;
+        let to = Utc::now().date_naive();
+        let from = to.checked_sub_months(Months::new(window.months())).unwrap_or(to);
+        let quotes: Vec<Quote> = self
+            .market_data_service
+            .get_historical_quotes_for_symbol(symbol)?
+            .into_iter()
+            .filter(|q| q.timestamp.date_naive() >= from)
+            .collect();
+
+        let Some(stats) = quote_stats(&quotes) else {
+            return Ok(json!({
+                "error": format!("No quotes for {} since {}", symbol, from)
+            }));
+        };
+        Ok(json!({
+            "symbol": symbol,
+            "window": window.as_str(),
+            "from": from.to_string(),
+            "to": to.to_string(),
+            "currency": quotes.last().map(|q| q.currency.clone()),
+            "count": stats.count,
+            "min": {"close": stats.min.0, "date": stats.min.1.to_string()},
+            "max": {"close": stats.max.0, "date": stats.max.1.to_string()},
+            "average": stats.average
+        }))
+    }
+
+    fn get_quote_gaps(&self, symbol: &str, from: Option<String>, to: Option<String>) -> Result<Value> {
+        let from = parse_date_param("from", from.as_deref())?;
+        let to = parse_date_param("to", to.as_deref())?.unwrap_or_else(|| Utc::now().date_naive());
+        let dates: BTreeSet<NaiveDate> = self
+            .market_data_service
+            .get_historical_quotes_for_symbol(symbol)?
+            .iter()
+            .map(|q| q.timestamp.date_naive())
+            .collect();
+
+        // Without a start date the scan begins at the first stored quote
+        let Some(from) = from.or_else(|| dates.first().copied()) else {
+            return Ok(json!({
+                "error": format!("No quotes stored for {}", symbol)
+            }));
+        };
+        if from > to {
+            return Err(Error::Validation(ValidationError::InvalidInput(
+                "from must not be after to".to_string(),
+            )));
+        }
+
+        let gaps = quote_gaps(&dates, from, to);
+        let trading_days = from.iter_days().take_while(|d| *d <= to).filter(|d| is_weekday(*d)).count();
+        let missing_days: usize = gaps.iter().map(|gap| gap.days).sum();
+        Ok(json!({
+            "symbol": symbol,
+            "from": from.to_string(),
+            "to": to.to_string(),
+            "tradingDays": trading_days,
+            "missingDays": missing_days,
+            "gaps": gaps
+                .iter()
+                .map(|gap| json!({
+                    "from": gap.from.to_string(),
+                    "to": gap.to.to_string(),
+                    "days": gap.days
+                }))
+                .collect::<Vec<_>>()
+        }))
+    }
+
+    fn get_historical_quotes_bulk(
+        &self,
+        symbols: &[String],
+        from: Option<String>,
+        to: Option<String>,
+        normalize: QuoteNormalization,
+    ) -> Result<Value> {
+        if symbols.is_empty() {
+            return Err(Error::Validation(ValidationError::InvalidInput(
+                "symbols must list at least one symbol".to_string(),
+            )));
+        }
+        let to = parse_date_param("to", to.as_deref())?.unwrap_or_else(|| Utc::now().date_naive());
+        let from = parse_date_param("from", from.as_deref())?
+            .unwrap_or(to - chrono::Duration::days(LIVE_HISTORY_DAYS));
+        if from > to {
+            return Err(Error::Validation(ValidationError::InvalidInput(
+                "from must not be after to".to_string(),
+            )));
+        }
+
+        let mut quotes = Vec::new();
+        for symbol in symbols {
+            quotes.extend(self.market_data_service.get_historical_quotes_for_symbol(symbol)?);
+        }
+
+        let history = quote_history_by_symbol(symbols, quotes, from, to);
+        let series: serde_json::Map<String, Value> = history
+            .into_iter()
+            .map(|(symbol, quotes)| (symbol, Value::Array(normalize.render(quotes, TimeFormat::default()))))
+            .collect();
+        Ok(json!({
+            "from": from.to_string(),
+            "to": to.to_string(),
+            "normalize": normalize,
+            "quotes": series
+        }))
+    }
+
+    async fn get_sync_status(&self) -> Result<Value> {
+        let providers = self.market_data_service.get_market_data_providers_settings().await?;
+        let mut assets = self.asset_service.get_assets()?;
+        assets.retain(|asset| !asset.id.starts_with(CASH_ASSET_PREFIX));
+        let symbols: Vec<String> = assets.iter().map(|asset| asset.id.clone()).collect();
+        let quotes = self.market_data_service.get_latest_quotes_for_symbols(&symbols)?;
+
+        Ok(json!({
+            "sources": sync_status_to_json(&providers, &assets, &quotes)
+        }))
+    }
+
+    // Performance methods
+    async fn get_account_performance(&self, account_id: &str) -> Result<Value> {
+        let performance = self.performance_service.calculate_performance_summary(
+            "account",
+            account_id,
+            None,
+            None,
+        ).await?;
+        let performance_data = performance_to_json(performance);
+        Ok(json!({
+            "accountId": account_id,
+            "performance": performance_data
+        }))
+    }
+
+    async fn get_twr_series(
+        &self,
+        account_id: &str,
+        from: Option<String>,
+        to: Option<String>,
+        interval: &str,
+    ) -> Result<Value> {
+        let interval = GainsInterval::parse(interval)?;
+        let from = parse_date_param("from", from.as_deref())?;
+        let to = parse_date_param("to", to.as_deref())?;
+
+        // The daily history already nets out cash flows day by day, so sub-periods
+        // are read off its cumulative curve and chain back to the headline TWR
+        let performance = self
+            .performance_service
+            .calculate_performance_history("account", account_id, from, to)
+            .await?;
+
+        let series: Vec<Value> = twr_series(&performance.returns, interval)
+            .into_iter()
+            .map(|(date, period_return, cumulative_return)| json!({
+                "date": date.to_string(),
+                "periodReturn": period_return,
+                "cumulativeReturn": cumulative_return
+            }))
+            .collect();
+
+        Ok(json!({
+            "accountId": account_id,
+            "interval": interval.as_str(),
+            "from": performance.period_start_date.map(|d| d.to_string()),
+            "to": performance.period_end_date.map(|d| d.to_string()),
+            "cumulativeTwr": performance.cumulative_twr,
+            "series": series
+        }))
+    }
+
+    fn get_valuation_series(&self, account_id: &str, from: Option<String>, to: Option<String>) -> Result<Value> {
+        let from = parse_date_param("from", from.as_deref())?;
+        let to = parse_date_param("to", to.as_deref())?;
+
+        // Same rows the TWR calculation walks, so day-over-day changes in
+        // netContribution are exactly the cash flows it nets out
+        let valuations = self
+            .valuation_service
+            .get_historical_valuations(account_id, from, to)?;
+        let series = valuation_series_to_json(&valuations);
+        let (account_currency, base_currency) = match valuations.first() {
+            Some(v) => (Some(v.account_currency.clone()), Some(v.base_currency.clone())),
+            None => (None, None),
+        };
+
+        Ok(json!({
+            "accountId": account_id,
+            "accountCurrency": account_currency,
+            "baseCurrency": base_currency,
+            "series": series
+        }))
+    }
+
+    async fn get_annualized_returns(&self, account_id: &str) -> Result<Value> {
+        let since_inception = self
+            .performance_service
+            .calculate_performance_history("account", account_id, None, None)
+            .await?;
+        let (Some(inception_date), Some(as_of)) =
+            (since_inception.period_start_date, since_inception.period_end_date)
+        else {
+            return Ok(json!({
+                "accountId": account_id,
+                "inceptionDate": null,
+                "asOf": null,
+                "windows": []
+            }));
+        };
+
+        let window_json = |window: &str, performance: &PerformanceMetrics, from_inception: bool| {
+            json!({
+                "window": window,
+                "startDate": performance.period_start_date.map(|d| d.to_string()),
+                "endDate": performance.period_end_date.map(|d| d.to_string()),
+                "annualizedReturn": performance.annualized_twr,
+                "cumulativeReturn": performance.cumulative_twr,
+                "sinceInception": from_inception
+            })
+        };
+        let mut windows = Vec::new();
+        for (window, years) in ANNUALIZED_WINDOWS {
+            match annualized_window_start(as_of, *years, inception_date) {
+                Some(start) => {
+                    let performance = self
+                        .performance_service
+                        .calculate_performance_history("account", account_id, Some(start), Some(as_of))
+                        .await?;
+                    windows.push(window_json(window, &performance, false));
+                }
+                // Not enough history for the full window
+                None => windows.push(window_json(window, &since_inception, true)),
+            }
+        }
+        windows.push(window_json("ALL", &since_inception, true));
+
+        Ok(json!({
+            "accountId": account_id,
+            "currency": since_inception.currency,
+            "inceptionDate": inception_date.to_string(),
+            "asOf": as_of.to_string(),
+            "windows": windows
+        }))
+    }
+
+    async fn compare_accounts(&self, a: &str, b: &str, from: Option<String>, to: Option<String>) -> Result<Value> {
+        if a == b {
+            return Err(Error::Validation(ValidationError::InvalidInput(
+                "a and b must be different accounts".to_string(),
+            )));
+        }
+        let from = parse_date_param("from", from.as_deref())?;
+        let to = parse_date_param("to", to.as_deref())?;
+
+        let mut spans = Vec::new();
+        for account_id in [a, b] {
+            let history = self
+                .performance_service
+                .calculate_performance_history("account", account_id, None, None)
+                .await?;
+            match (history.period_start_date, history.period_end_date) {
+                (Some(start), Some(end)) => spans.push((start, end)),
+                _ => {
+                    return Ok(json!({
+                        "error": format!("No performance history for account {}", account_id)
+                    }))
+                }
+            }
+        }
+        let (span_a, span_b) = (spans[0], spans[1]);
+        let Some((start, end)) = comparison_window(from, to, span_a, span_b) else {
+            return Err(Error::Validation(ValidationError::InvalidInput(
+                "The accounts have no history in common within the requested window".to_string(),
+            )));
+        };
+
+        // A window reaching back before the younger account's inception starts there instead
+        let (younger, younger_inception) = if span_a.0 >= span_b.0 { (a, span_a.0) } else { (b, span_b.0) };
+        let alignment = (span_a.0 != span_b.0 && start == younger_inception && from.is_none_or(|f| f < start))
+            .then(|| json!({
+                "accountId": younger,
+                "inceptionDate": younger_inception.to_string(),
+                "note": format!("Window starts at {}'s inception on {}", younger, younger_inception)
+            }));
+
+        let mut accounts = Vec::new();
+        let mut cumulative = Vec::new();
+        for account_id in [a, b] {
+            let performance = self
+                .performance_service
+                .calculate_performance_history("account", account_id, Some(start), Some(end))
+                .await?;
+            cumulative.push(performance.cumulative_twr);
+            accounts.push(json!({
+                "accountId": account_id,
+                "currency": performance.currency,
+                "cumulativeReturn": performance.cumulative_twr,
+                "annualizedReturn": performance.annualized_twr,
+                "volatility": performance.volatility,
+                "maxDrawdown": performance.max_drawdown
+            }));
+        }
+        let leader = match cumulative[0].cmp(&cumulative[1]) {
+            std::cmp::Ordering::Greater => Some(a),
+            std::cmp::Ordering::Less => Some(b),
+            std::cmp::Ordering::Equal => None,
+        };
+
+        Ok(json!({
+            "from": start.to_string(),
+            "to": end.to_string(),
+            "alignment": alignment,
+            "accounts": accounts,
+            "leader": leader
+        }))
+    }
+
+    async fn get_beta(
+        &self,
+        account_id: Option<String>,
+        benchmark: &str,
+        from: Option<String>,
+        to: Option<String>,
+    ) -> Result<Value> {
+        let from = parse_date_param("from", from.as_deref())?;
+        let to = parse_date_param("to", to.as_deref())?;
+        let account_id = self
+            .account_or_default(account_id)?
+            .unwrap_or_else(|| PORTFOLIO_TOTAL_ACCOUNT_ID.to_string());
+
+        let portfolio = self
+            .performance_service
+            .calculate_performance_history("account", &account_id, from, to)
+            .await?;
+        let benchmark_performance = self
+            .performance_service
+            .calculate_performance_history("symbol", benchmark, from, to)
+            .await?;
+        let (beta, alpha, r_squared, samples) =
+            beta_regression(&portfolio.returns, &benchmark_performance.returns)?;
+
+        Ok(json!({
+            "accountId": account_id,
+            "benchmark": benchmark,
+            "from": portfolio.period_start_date.map(|d| d.to_string()),
+            "to": portfolio.period_end_date.map(|d| d.to_string()),
+            "beta": beta,
+            "alpha": alpha,
+            "rSquared": r_squared,
+            "samples": samples
+        }))
+    }
+
+    async fn get_sharpe(
+        &self,
+        account_id: Option<String>,
+        from: Option<String>,
+        to: Option<String>,
+        risk_free_rate: Option<f64>,
+    ) -> Result<Value> {
+        let from = parse_date_param("from", from.as_deref())?;
+        let to = parse_date_param("to", to.as_deref())?;
+        let risk_free_rate = match risk_free_rate {
+            Some(rate) => Decimal::try_from(rate).map_err(|_| {
+                Error::Validation(ValidationError::InvalidInput(format!(
+                    "Invalid risk_free_rate '{}'",
+                    rate
+                )))
+            })?,
+            None => Decimal::ZERO,
+        };
+        let account_id = self
+            .account_or_default(account_id)?
+            .unwrap_or_else(|| PORTFOLIO_TOTAL_ACCOUNT_ID.to_string());
+
+        // Both figures are annualized by the performance service from daily TWR returns
+        let performance = self
+            .performance_service
+            .calculate_performance_history("account", &account_id, from, to)
+            .await?;
+        let sharpe = sharpe_ratio(performance.annualized_twr, performance.volatility, risk_free_rate);
+
+        Ok(json!({
+            "accountId": account_id,
+            "from": performance.period_start_date.map(|d| d.to_string()),
+            "to": performance.period_end_date.map(|d| d.to_string()),
+            "riskFreeRate": risk_free_rate,
+            "annualizedReturn": performance.annualized_twr,
+            "volatility": performance.volatility,
+            "sharpe": sharpe,
+            "samples": performance.returns.len()
+        }))
+    }
+
+    async fn get_var(
+        &self,
+        account_id: Option<String>,
+        confidence: f64,
+        horizon_days: u32,
+        from: Option<String>,
+    ) -> Result<Value> {
+        let confidence = Decimal::try_from(confidence)
+            .ok()
+            .filter(|c| *c > Decimal::ZERO && *c < Decimal::ONE)
+            .ok_or_else(|| {
+                Error::Validation(ValidationError::InvalidInput(format!(
+                    "confidence must be between 0 and 1 (exclusive), got {}",
+                    confidence
+                )))
+            })?;
+        if horizon_days == 0 {
+            return Err(Error::Validation(ValidationError::InvalidInput(
+                "horizon must be at least 1 day".to_string(),
+            )));
+        }
+        let from = parse_date_param("from", from.as_deref())?;
+        let account_id = self
+            .account_or_default(account_id)?
+            .unwrap_or_else(|| PORTFOLIO_TOTAL_ACCOUNT_ID.to_string());
+
+        // Daily returns come from the TWR curve, so deposits and withdrawals are not losses
+        let performance = self
+            .performance_service
+            .calculate_performance_history("account", &account_id, from, None)
+            .await?;
+        let growth: BTreeMap<NaiveDate, Decimal> = performance
+            .returns
+            .iter()
+            .map(|point| (point.date, Decimal::ONE + point.value))
+            .collect();
+        let returns: Vec<Decimal> = daily_returns(&growth).into_values().collect();
+        if returns.len() < MIN_VAR_SAMPLES {
+            return Err(Error::Validation(ValidationError::InvalidInput(format!(
+                "Need at least {} daily returns to compute VaR, found {}",
+                MIN_VAR_SAMPLES,
+                returns.len()
+            ))));
+        }
+        let var_pct = historical_var(&returns, confidence, horizon_days);
+
+        let latest = self
+            .valuation_service
+            .get_latest_valuations(std::slice::from_ref(&account_id))?
+            .into_iter()
+            .next();
+        let current_value = latest.as_ref().map(|v| v.total_value * v.fx_rate_to_base);
+
+        Ok(json!({
+            "accountId": account_id,
+            "method": "historical",
+            "from": performance.period_start_date.map(|d| d.to_string()),
+            "to": performance.period_end_date.map(|d| d.to_string()),
+            "confidence": confidence,
+            "horizonDays": horizon_days,
+            "samples": returns.len(),
+            "varPct": var_pct,
+            "baseCurrency": latest.map(|v| v.base_currency),
+            "currentValue": current_value,
+            "varAmount": current_value.map(|value| (value * var_pct).round_dp(2))
+        }))
+    }
+
+    async fn get_correlation_matrix(
+        &self,
+        account_id: Option<String>,
+        from: Option<String>,
+        to: Option<String>,
+    ) -> Result<Value> {
+        let to = parse_date_param("to", to.as_deref())?.unwrap_or_else(|| Utc::now().date_naive());
+        let from = match parse_date_param("from", from.as_deref())? {
+            Some(date) => date,
+            None => to.checked_sub_months(Months::new(12)).unwrap_or(to),
+        };
+        if from >= to {
+            return Err(Error::Validation(ValidationError::InvalidInput(
+                "from must be before to".to_string(),
+            )));
+        }
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+        let account_id = self.account_or_default(account_id)?;
+
+        let (holdings, partial_errors) = self
+            .collect_holdings(account_id.clone(), &base_currency, None)
+            .await?;
+        let symbols: HashSet<String> = holdings
+            .iter()
+            .filter(|h| h.holding_type == HoldingType::Security)
+            .filter_map(|h| h.instrument.as_ref().map(|i| i.id.clone()))
+            .collect();
+
+        let mut closes: BTreeMap<String, BTreeMap<NaiveDate, Decimal>> =
+            symbols.iter().map(|symbol| (symbol.clone(), BTreeMap::new())).collect();
+        for quote in self
+            .market_data_service
+            .get_historical_quotes_for_symbols_in_range(&symbols, from, to)?
+        {
+            if let Some(series) = closes.get_mut(&quote.symbol) {
+                series.insert(quote.timestamp.date_naive(), quote.close);
+            }
+        }
+        let correlation = correlation_matrix(&closes);
+
+        Ok(json!({
+            "accountId": account_id,
+            "from": from.to_string(),
+            "to": to.to_string(),
+            "symbols": correlation.symbols,
+            "matrix": correlation.matrix,
+            "samples": correlation.samples,
+            "minSamples": MIN_CORRELATION_SAMPLES,
+            "dropped": correlation
+                .dropped
+                .iter()
+                .map(|(symbol, samples)| json!({ "symbol": symbol, "samples": samples }))
+                .collect::<Vec<_>>(),
+            "partialErrors": partial_errors
+        }))
+    }
+
+    async fn get_return_contribution(
+        &self,
+        account_id: Option<String>,
+        from: Option<String>,
+        to: Option<String>,
+    ) -> Result<Value> {
+        let today = Utc::now().date_naive();
+        let to = parse_date_param("to", to.as_deref())?.unwrap_or(today);
+        let from = match parse_date_param("from", from.as_deref())? {
+            Some(date) => date,
+            None => NaiveDate::from_ymd_opt(to.year(), 1, 1).unwrap_or(to),
+        };
+        if from >= to {
+            return Err(Error::Validation(ValidationError::InvalidInput(
+                "from must be before to".to_string(),
+            )));
+        }
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+        let account_id = self.account_or_default(account_id)?;
+
+        let (start_holdings, mut partial_errors) = self
+            .collect_holdings(account_id.clone(), &base_currency, Some(from))
+            .await?;
+        let end_as_of = (to < today).then_some(to);
+        let (end_holdings, end_errors) = self
+            .collect_holdings(account_id.clone(), &base_currency, end_as_of)
+            .await?;
+        partial_errors.extend(end_errors);
+
+        let mut positions: BTreeMap<String, ContributionPosition> = BTreeMap::new();
+        let mut cash_start = Decimal::ZERO;
+        for (holding, at_start) in start_holdings
+            .iter()
+            .map(|h| (h, true))
+            .chain(end_holdings.iter().map(|h| (h, false)))
+        {
+            let Some(instrument) = &holding.instrument else {
+                if at_start {
+                    cash_start += holding.market_value.base;
+                }
+                continue;
+            };
+            let position = positions
+                .entry(instrument.id.clone())
+                .or_insert_with(|| ContributionPosition::new(&instrument.symbol));
+            if at_start {
+                position.start_value += holding.market_value.base;
+            } else {
+                position.end_value += holding.market_value.base;
+            }
+        }
+
+        // Money put into (positive) or taken out of (negative) each position during the period
+        for activity in self.trade_history(account_id.as_deref())? {
+            let date = activity.activity_date.date_naive();
+            if date <= from || date > to {
+                continue;
+            }
+            let trade_value = activity.quantity * activity.unit_price;
+            let flow = match activity.activity_type.as_str() {
+                activities::ACTIVITY_TYPE_BUY => trade_value + activity.fee,
+                activities::ACTIVITY_TYPE_ADD_HOLDING | activities::ACTIVITY_TYPE_TRANSFER_IN => trade_value,
+                activities::ACTIVITY_TYPE_SELL => activity.fee - trade_value,
+                activities::ACTIVITY_TYPE_REMOVE_HOLDING | activities::ACTIVITY_TYPE_TRANSFER_OUT => -trade_value,
+                activities::ACTIVITY_TYPE_DIVIDEND => -activity.amount.unwrap_or(trade_value),
+                _ => continue,
+            };
+            let flow_base = match self
+                .fx_service
+                .convert_currency_for_date(flow, &activity.currency, &base_currency, date)
+            {
+                Ok(flow_base) => flow_base,
+                Err(e) => {
+                    tracing::warn!(
+                        activity_id = %activity.id,
+                        error = %e,
+                        "Failed to convert activity amount to base currency"
+                    );
+                    partial_errors.push(json!({
+                        "accountId": activity.account_id,
+                        "activityId": activity.id,
+                        "error": e.to_string()
+                    }));
+                    continue;
+                }
+            };
+            let position = positions
+                .entry(activity.asset_id.clone())
+                .or_insert_with(|| ContributionPosition::new(&activity.asset_id));
+            position.net_flow += flow_base;
+            if flow_base > Decimal::ZERO {
+                position.purchases += flow_base;
+            }
+        }
+
+        let (contributions, total_return) =
+            return_contributions(positions.into_values().collect(), cash_start);
+        Ok(json!({
+            "accountId": account_id,
+            "baseCurrency": base_currency,
+            "from": from.to_string(),
+            "to": to.to_string(),
+            "totalReturn": total_return,
+            "contributions": contributions,
+            "partialErrors": partial_errors
+        }))
+    }
+
+    async fn get_portfolio_performance_summary(&self, exclude_accounts: Option<String>) -> Result<Value> {
+        let excluded = exclude_accounts.as_deref().map(split_account_ids).unwrap_or_default();
+        let accounts = self.accounts_excluding(&excluded)?;
+        self.summarize_accounts(accounts).await
+    }
+
+    async fn get_yield(&self) -> Result<Value> {
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+        let today = Utc::now().date_naive();
+        let window_start = today - chrono::Duration::days(YIELD_WINDOW_DAYS);
+
+        let (holdings, mut partial_errors) = self.collect_holdings(None, &base_currency, None).await?;
+        let total_value: Decimal = holdings.iter().map(|h| h.market_value.base).sum();
+        let mut values: HashMap<String, Decimal> = HashMap::new();
+        for holding in &holdings {
+            if let Some(instrument) = &holding.instrument {
+                *values.entry(instrument.id.clone()).or_default() += holding.market_value.base;
+            }
+        }
+
+        // Trailing income per asset, in base currency
+        let mut income: BTreeMap<String, Decimal> = BTreeMap::new();
+        for activity in self.activity_service.get_activities()? {
+            let date = activity.activity_date.date_naive();
+            if activity.is_draft
+                || date <= window_start
+                || date > today
+                || !activities::INCOME_ACTIVITY_TYPES.contains(&activity.activity_type.as_str())
+            {
+                continue;
+            }
+            let amount = activity
+                .amount
+                .unwrap_or(activity.quantity * activity.unit_price);
+            let amount_base = match self
+                .fx_service
+                .convert_currency_for_date(amount, &activity.currency, &base_currency, date)
+            {
+                Ok(amount_base) => amount_base,
+                Err(e) => {
+                    tracing::warn!(
+                        activity_id = %activity.id,
+                        error = %e,
+                        "Failed to convert income to base currency"
+                    );
+                    partial_errors.push(json!({
+                        "accountId": activity.account_id,
+                        "activityId": activity.id,
+                        "error": e.to_string()
+                    }));
+                    continue;
+                }
+            };
+            *income.entry(activity.asset_id.clone()).or_default() += amount_base;
+        }
+
+        // First acquisition per asset, to spot positions held for less than the window
+        let mut first_held: HashMap<String, NaiveDate> = HashMap::new();
+        for activity in self.trade_history(None)? {
+            if matches!(
+                activity.activity_type.as_str(),
+                activities::ACTIVITY_TYPE_BUY
+                    | activities::ACTIVITY_TYPE_ADD_HOLDING
+                    | activities::ACTIVITY_TYPE_TRANSFER_IN
+            ) {
+                first_held
+                    .entry(activity.asset_id.clone())
+                    .or_insert_with(|| activity.activity_date.date_naive());
+            }
+        }
+
+        let symbols: Vec<Value> = income
+            .iter()
+            .map(|(symbol, income)| {
+                let value = values.get(symbol).copied().unwrap_or_default();
+                let held_days = first_held
+                    .get(symbol)
+                    .map(|date| (today - *date).num_days())
+                    .filter(|days| *days < YIELD_WINDOW_DAYS);
+                let (yield_pct, annualized) = trailing_yield(*income, value, held_days);
+                json!({
+                    "symbol": symbol,
+                    "income": income,
+                    "marketValue": value,
+                    "yield": yield_pct,
+                    "annualized": annualized,
+                    "heldDays": held_days
+                })
+            })
+            .collect();
+        let total_income: Decimal = income.values().copied().sum();
+        let (portfolio_yield, _) = trailing_yield(total_income, total_value, None);
+
+        Ok(json!({
+            "baseCurrency": base_currency,
+            "from": window_start,
+            "to": today,
+            "income": total_income,
+            "marketValue": total_value,
+            "yield": portfolio_yield,
+            "annualized": false,
+            "symbols": symbols,
+            "partialErrors": partial_errors
+        }))
+    }
+
+    fn get_value_changes(&self) -> Result<Value> {
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+
+        let mut values: Vec<(NaiveDate, Decimal)> = self
+            .valuation_service
+            .get_historical_valuations(PORTFOLIO_TOTAL_ACCOUNT_ID, None, None)?
+            .into_iter()
+            .map(|v| (v.valuation_date, v.total_value * v.fx_rate_to_base))
+            .collect();
+        values.sort_by_key(|(date, _)| *date);
+        let (Some(&(inception_date, _)), Some(&(as_of, current_value))) = (values.first(), values.last()) else {
+            return Ok(json!({
+                "baseCurrency": base_currency,
+                "asOf": null,
+                "currentValue": null,
+                "inceptionDate": null,
+                "horizons": []
+            }));
+        };
+
+        let horizons: Vec<Value> = VALUE_HORIZONS
+            .iter()
+            .map(|horizon| {
+                let target = horizon_start(horizon, as_of).unwrap_or(inception_date);
+                let (date, value, before_inception) = value_at_horizon(&values, target);
+                let change = current_value - value;
+                let change_pct = (!value.is_zero()).then(|| (change / value).round_dp(6));
+                json!({
+                    "horizon": horizon,
+                    "date": date,
+                    "value": value,
+                    "change": change,
+                    "changePct": change_pct,
+                    "beforeInception": before_inception
+                })
+            })
+            .collect();
+
+        Ok(json!({
+            "baseCurrency": base_currency,
+            "asOf": as_of,
+            "currentValue": current_value,
+            "inceptionDate": inception_date,
+            "horizons": horizons
+        }))
+    }
+
+    fn get_gains_by_period(&self, account_id: Option<String>, interval: &str) -> Result<Value> {
+        let interval = GainsInterval::parse(interval)?;
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+
+        let account_id = self.account_or_default(account_id)?;
+        let history = self.trade_history(account_id.as_deref())?;
+
+        let Some(first_date) = history.first().map(|a| a.activity_date.date_naive()) else {
+            return Ok(json!({
+                "accountId": account_id,
+                "interval": interval.as_str(),
+                "baseCurrency": base_currency,
+                "unrealizedGainType": "pointInTime",
+                "periods": [],
+                "partialErrors": []
+            }));
+        };
+
+        // Realized gains per period start
+        let mut realized: BTreeMap<NaiveDate, Decimal> = BTreeMap::new();
+        let (gains, partial_errors) = self.realized_gains(&history, &base_currency);
+        for (date, gain) in gains {
+            *realized.entry(interval.period_start(date)).or_insert(Decimal::ZERO) += gain;
+        }
+
+        let valuations = self.valuation_service.get_historical_valuations(
+            account_id.as_deref().unwrap_or(PORTFOLIO_TOTAL_ACCOUNT_ID),
+            Some(first_date),
+            None,
+        )?;
+
+        let today = Utc::now().date_naive();
+        let mut periods = Vec::new();
+        let mut previous_unrealized: Option<Decimal> = None;
+        let mut period_start = interval.period_start(first_date);
+        while period_start <= today {
+            let next_start = interval.next_period_start(period_start);
+            let period_end = next_start.pred_opt().unwrap_or(next_start);
+
+            let unrealized = unrealized_gain_at(&valuations, period_end.min(today));
+            let unrealized_change = match (unrealized, previous_unrealized) {
+                (Some(current), Some(previous)) => Some(current - previous),
+                (Some(current), None) => Some(current),
+                _ => None,
+            };
+            if unrealized.is_some() {
+                previous_unrealized = unrealized;
+            }
+
+            periods.push(json!({
+                "period": interval.label(period_start),
+                "startDate": period_start.to_string(),
+                "endDate": period_end.to_string(),
+                "realizedGain": realized.get(&period_start).copied().unwrap_or(Decimal::ZERO),
+                "unrealizedGain": unrealized,
+                "unrealizedGainChange": unrealized_change
+            }));
+            period_start = next_start;
+        }
+
+        Ok(json!({
+            "accountId": account_id,
+            "interval": interval.as_str(),
+            "baseCurrency": base_currency,
+            "unrealizedGainType": "pointInTime",
+            "periods": periods,
+            "partialErrors": partial_errors
+        }))
+    }
+
+    fn get_fees_summary(
+        &self,
+        account_id: Option<String>,
+        from: Option<String>,
+        to: Option<String>,
+    ) -> Result<Value> {
+        let from = parse_date_param("from", from.as_deref())?;
+        let to = parse_date_param("to", to.as_deref())?;
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+
+        let account_id = self.account_or_default(account_id)?;
+        let activities = match &account_id {
+            Some(id) => self.activity_service.get_activities_by_account_id(id)?,
+            None => self.activity_service.get_activities()?,
+        };
+
+        let interval = GainsInterval::Monthly;
+        let mut accounts: BTreeMap<String, FeeTotals> = BTreeMap::new();
+        let mut total_base = Decimal::ZERO;
+        // Fees without an exchange rate into base currency, left out of every total
+        let mut partial_errors = Vec::new();
+        for activity in activities.iter().filter(|a| !a.is_draft) {
+            let date = activity.activity_date.date_naive();
+            if from.is_some_and(|from| date < from) || to.is_some_and(|to| date > to) {
+                continue;
+            }
+
+            // Stand-alone fee activities may carry the charge in `amount` instead of `fee`
+            let fee = if activity.activity_type == activities::ACTIVITY_TYPE_FEE && activity.fee.is_zero() {
+                activity.amount.unwrap_or(Decimal::ZERO)
+            } else {
+                activity.fee
+            };
+            if fee.is_zero() {
+                continue;
+            }
+
+            let fee_base = match self
+                .fx_service
+                .convert_currency_for_date(fee, &activity.currency, &base_currency, date)
+            {
+                Ok(fee_base) => fee_base,
+                Err(e) => {
+                    tracing::warn!(
+                        activity_id = %activity.id,
+                        error = %e,
+                        "Failed to convert fee to base currency"
+                    );
+                    partial_errors.push(json!({
+                        "accountId": activity.account_id,
+                        "activityId": activity.id,
+                        "error": e.to_string()
+                    }));
+                    continue;
+                }
+            };
+
+            total_base += fee_base;
+            let account = accounts.entry(activity.account_id.clone()).or_default();
+            account.add(&activity.currency, fee, fee_base);
+            account
+                .periods
+                .entry(interval.period_start(date))
+                .or_default()
+                .add(&activity.currency, fee, fee_base);
+        }
+
+        let accounts_data: Vec<Value> = accounts
+            .into_iter()
+            .map(|(account_id, totals)| {
+                let periods: Vec<Value> = totals
+                    .periods
+                    .iter()
+                    .map(|(start, period)| json!({
+                        "period": interval.label(*start),
+                        "base": period.base,
+                        "local": period.local
+                    }))
+                    .collect();
+                json!({
+                    "accountId": account_id,
+                    "base": totals.base,
+                    "local": totals.local,
+                    "periods": periods
+                })
+            })
+            .collect();
+
+        Ok(json!({
+            "baseCurrency": base_currency,
+            "from": from.map(|d| d.to_string()),
+            "to": to.map(|d| d.to_string()),
+            "interval": interval.as_str(),
+            "totalBase": total_base,
+            "accounts": accounts_data,
+            "partialErrors": partial_errors
+        }))
+    }
+
+    // Activities methods
+    fn get_activities(
+        &self,
+        account_id: Option<String>,
+        group_by: Option<&str>,
+        time_format: TimeFormat,
+        numbers: NumberFormat,
+        page: Pagination,
+    ) -> Result<Value> {
+        let mut activities = match self.account_or_default(account_id)? {
+            Some(account_id) => self.activity_service.get_activities_by_account_id(&account_id)?,
+            None => self.activity_service.get_activities()?,
+        };
+        sort_activities(&mut activities);
+        let page = page.clamp(self.max_page_size);
+        match group_by {
+            None => {
+                let mut activities_data = activities_to_json(activities, time_format, numbers, &self.currency_precision);
+                let page_info = page.apply(&mut activities_data);
+                Ok(json!({
+                    "activities": activities_data,
+                    "total": page_info.total,
+                    "limit": page_info.limit,
+                    "offset": page_info.offset,
+                    "hasMore": page_info.has_more
+                }))
+            }
+            Some("symbol") => {
+                let mut groups = activities_by_symbol_to_json(activities, time_format, numbers, &self.currency_precision);
+                let page_info = page.apply(&mut groups);
+                Ok(json!({
+                    "groupBy": "symbol",
+                    "groups": groups,
+                    "total": page_info.total,
+                    "limit": page_info.limit,
+                    "offset": page_info.offset,
+                    "hasMore": page_info.has_more
+                }))
+            }
+            Some(other) => Err(Error::Validation(ValidationError::InvalidInput(format!(
+                "Unsupported group_by '{}', expected 'symbol'",
+                other
+            )))),
+        }
+    }
+
+    fn get_symbol_activities(&self, symbol: &str, from: Option<String>, to: Option<String>) -> Result<Value> {
+        let from = parse_date_param("from", from.as_deref())?;
+        let to = parse_date_param("to", to.as_deref())?;
+        let symbol = symbol.trim();
+
+        let mut history: Vec<Activity> = self
+            .activity_service
+            .get_activities()?
+            .into_iter()
+            .filter(|a| !a.is_draft && a.asset_id.eq_ignore_ascii_case(symbol))
+            .collect();
+        history.sort_by_key(|a| a.activity_date);
+
+        // Activities before `from` still count towards the running quantity
+        let mut running_quantity = Decimal::ZERO;
+        let mut opening_quantity = Decimal::ZERO;
+        let mut activities_data = Vec::new();
+        for activity in history {
+            let date = activity.activity_date.date_naive();
+            if to.is_some_and(|to| date > to) {
+                break;
+            }
+            running_quantity = net_quantity_after(running_quantity, &activity);
+            if from.is_some_and(|from| date < from) {
+                opening_quantity = running_quantity;
+                continue;
+            }
+            let mut entry = activity_to_json(
+                activity,
+                TimeFormat::default(),
+                NumberFormat::default(),
+                &self.currency_precision,
+            );
+            entry["runningQuantity"] = json!(running_quantity);
+            activities_data.push(entry);
+        }
+
+        Ok(json!({
+            "symbol": symbol,
+            "from": from.map(|d| d.to_string()),
+            "to": to.map(|d| d.to_string()),
+            "openingQuantity": opening_quantity,
+            "closingQuantity": running_quantity,
+            "activities": activities_data
+        }))
+    }
+
+    fn get_transfers(&self, from: Option<String>, to: Option<String>) -> Result<Value> {
+        let from = parse_date_param("from", from.as_deref())?;
+        let to = parse_date_param("to", to.as_deref())?;
+
+        let mut outflows = Vec::new();
+        let mut inflows = Vec::new();
+        for activity in self.activity_service.get_activities()?.into_iter().filter(|a| !a.is_draft) {
+            let date = activity.activity_date.date_naive();
+            if from.is_some_and(|from| date < from) || to.is_some_and(|to| date > to) {
+                continue;
+            }
+            let is_cash = activity.asset_id.starts_with(CASH_ASSET_PREFIX);
+            let legs = match activity.activity_type.as_str() {
+                activities::ACTIVITY_TYPE_WITHDRAWAL => &mut outflows,
+                activities::ACTIVITY_TYPE_TRANSFER_OUT if is_cash => &mut outflows,
+                activities::ACTIVITY_TYPE_DEPOSIT => &mut inflows,
+                activities::ACTIVITY_TYPE_TRANSFER_IN if is_cash => &mut inflows,
+                _ => continue,
+            };
+            let amount = activity
+                .amount
+                .unwrap_or(activity.quantity * activity.unit_price)
+                .abs();
+            legs.push(TransferLeg {
+                activity_id: activity.id,
+                account_id: activity.account_id,
+                activity_type: activity.activity_type,
+                date,
+                currency: activity.currency,
+                amount,
+            });
+        }
+        outflows.sort_by_key(|leg| leg.date);
+        inflows.sort_by_key(|leg| leg.date);
+
+        let pairs = match_transfers(&outflows, &inflows);
+        let matched: Vec<Value> = pairs
+            .iter()
+            .map(|&(out, into)| {
+                let (outflow, inflow) = (&outflows[out], &inflows[into]);
+                json!({
+                    "currency": outflow.currency,
+                    "amount": outflow.amount,
+                    "daysApart": (inflow.date - outflow.date).num_days().abs(),
+                    "from": outflow.to_json(),
+                    "to": inflow.to_json()
+                })
+            })
+            .collect();
+        let unmatched = |legs: &[TransferLeg], paired: Vec<usize>| -> Vec<Value> {
+            legs.iter()
+                .enumerate()
+                .filter(|(i, _)| !paired.contains(i))
+                .map(|(_, leg)| leg.to_json())
+                .collect()
+        };
+
+        Ok(json!({
+            "from": from.map(|d| d.to_string()),
+            "to": to.map(|d| d.to_string()),
+            "matchWindowDays": TRANSFER_MATCH_DAYS,
+            "matched": matched,
+            "unmatchedOutflows": unmatched(&outflows, pairs.iter().map(|p| p.0).collect()),
+            "unmatchedInflows": unmatched(&inflows, pairs.iter().map(|p| p.1).collect())
+        }))
+    }
+
+    fn get_activity_date_range(&self, account_id: Option<String>) -> Result<Value> {
+        let accounts = match self.account_or_default(account_id)? {
+            Some(id) => vec![self.account_service.get_account(&id)?],
+            None => self.account_service.get_all_accounts()?,
+        };
+        let mut ranges: HashMap<String, (DateTime<Utc>, DateTime<Utc>)> = HashMap::new();
+        for activity in self.activity_service.get_activities()? {
+            if activity.is_draft {
+                continue;
+            }
+            let date = activity.activity_date;
+            ranges
+                .entry(activity.account_id)
+                .and_modify(|(earliest, latest)| {
+                    *earliest = (*earliest).min(date);
+                    *latest = (*latest).max(date);
+                })
+                .or_insert((date, date));
+        }
+
+        let accounts_data: Vec<Value> = accounts
+            .iter()
+            .map(|account| {
+                let range = ranges.get(&account.id);
+                json!({
+                    "accountId": account.id,
+                    "earliest": range.map(|(earliest, _)| earliest.date_naive().to_string()),
+                    "latest": range.map(|(_, latest)| latest.date_naive().to_string())
+                })
+            })
+            .collect();
+        Ok(json!({
+            "accounts": accounts_data
+        }))
+    }
+
+    async fn create_activity(&self, payload: Value, idempotency_key: Option<&str>) -> Result<Value> {
+        if let Err(details) = validate_activity_payload(&payload) {
+            return Ok(validation_error_response(&details));
+        }
+        let key = idempotency_key.map(str::trim).filter(|key| !key.is_empty());
+        if let Some(key) = key {
+            if let Some(response) = self.idempotency.claim(key, &payload)? {
+                return Ok(response);
+            }
+        }
+
+        let result = self.insert_activity(payload).await;
+        if let Some(key) = key {
+            match &result {
+                Ok(response) => self.idempotency.complete(key, response.clone())?,
+                // Let the client retry a failed request with the same key
+                Err(_) => self.idempotency.release(key)?,
+            }
+        }
+        result
+    }
+
+    async fn validate_activity(&self, payload: Value) -> Result<Value> {
+        let mut errors = validate_activity_payload(&payload).err().unwrap_or_default();
+        let text = |name: &str| {
+            payload
+                .get(name)
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+
+        if let Some(account_id) = text("accountId") {
+            match self.account_service.get_account(account_id) {
+                Ok(_) => {}
+                Err(Error::Database(DatabaseError::QueryFailed(diesel::result::Error::NotFound))) => {
+                    errors.push(FieldError::new("accountId", format!("unknown account '{}'", account_id)));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        // Creation fetches unknown symbols' profiles from the provider, so do the same
+        // lookup here without storing the result
+        if let Some(asset_id) = text("assetId").filter(|id| !id.starts_with(CASH_ASSET_PREFIX)) {
+            match self.asset_service.get_asset_by_id(asset_id) {
+                Ok(_) => {}
+                Err(Error::Database(DatabaseError::QueryFailed(diesel::result::Error::NotFound))) => {
+                    let _permit = self
+                        .provider_permits
+                        .acquire()
+                        .await
+                        .map_err(|e| Error::Unexpected(format!("Provider limiter closed: {}", e)))?;
+                    if let Err(e) = self.market_data_service.get_asset_profile(asset_id).await {
+                        errors.push(FieldError::new("assetId", format!("unknown symbol '{}': {}", asset_id, e)));
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(json!({"valid": true}))
+        } else {
+            Ok(json!({"valid": false, "errors": errors}))
+        }
+    }
+
+    // Alert methods
+    fn register_alert(&self, request: AlertRequest) -> Result<Value> {
+        request.validate()?;
+
+        let alert = Alert {
+            id: uuid::Uuid::new_v4().to_string(),
+            callback_url: request.callback_url,
+            condition: request.condition,
+            threshold: request.threshold,
+            account_id: request.account_id,
+            created_at: Utc::now(),
+            last_value: None,
+            last_evaluated_at: None,
+            last_triggered_at: None,
+            is_met: false,
+        };
+
+        let alert_data = alert_to_json(&alert);
+        self.alerts
+            .write()
+            .map_err(|e| Error::Unexpected(format!("Alert registry lock poisoned: {}", e)))?
+            .insert(alert.id.clone(), alert);
+
+        Ok(json!({
+            "alert": alert_data
+        }))
+    }
+
+    fn delete_alert(&self, alert_id: &str) -> Result<Value> {
+        let removed = self
+            .alerts
+            .write()
+            .map_err(|e| Error::Unexpected(format!("Alert registry lock poisoned: {}", e)))?
+            .remove(alert_id);
+
+        match removed {
+            Some(alert) => Ok(json!({
+                "deleted": true,
+                "alert": alert_to_json(&alert)
+            })),
+            None => Ok(json!({
+                "error": format!("Alert {} not found", alert_id)
+            })),
+        }
+    }
+
+    fn list_alerts(&self) -> Result<Value> {
+        let alerts = self
+            .alerts
+            .read()
+            .map_err(|e| Error::Unexpected(format!("Alert registry lock poisoned: {}", e)))?;
+        let mut alerts_data: Vec<&Alert> = alerts.values().collect();
+        alerts_data.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        Ok(json!({
+            "alerts": alerts_data.into_iter().map(alert_to_json).collect::<Vec<Value>>()
+        }))
+    }
+
+    async fn evaluate_alerts(&self) -> Result<Value> {
+        // Snapshot the registry so no lock is held across provider and callback awaits
+        let alerts: Vec<Alert> = self
+            .alerts
+            .read()
+            .map_err(|e| Error::Unexpected(format!("Alert registry lock poisoned: {}", e)))?
+            .values()
+            .cloned()
+            .collect();
+
+        if alerts.is_empty() {
+            return Ok(json!({ "evaluated": 0, "triggered": 0 }));
+        }
+
+        let base_currency = match self.settings_service.get_base_currency()? {
+            Some(currency) => currency,
+            None => return Ok(json!({"error": "Base currency not set"})),
+        };
+
+        let base = &base_currency;
+        let (valued, partial_errors) = alert_scope_values(&alerts, move |account_id| async move {
+            let (holdings, _) = self.collect_holdings(account_id, base, None).await?;
+            Ok(holdings.iter().map(|h| h.market_value.base).sum())
+        })
+        .await;
+        let mut triggered = 0;
+
+        for (alert, value) in valued {
+            let is_met = alert.condition.is_met(value, alert.threshold);
+            // Only fire when the threshold is crossed, not on every evaluation while it holds
+            let should_fire = is_met && !alert.is_met;
+            let mut fired = false;
+            if should_fire {
+                match self.send_alert_callback(alert, value, &base_currency).await {
+                    Ok(()) => {
+                        fired = true;
+                        triggered += 1;
+                    }
+                    Err(e) => {
+                        tracing::warn!(alert_id = %alert.id, error = %e, "Failed to deliver alert callback");
+                    }
+                }
+            }
+
+            if let Ok(mut registry) = self.alerts.write() {
+                // The alert may have been deleted while we were evaluating
+                if let Some(stored) = registry.get_mut(&alert.id) {
+                    stored.last_value = Some(value);
+                    stored.last_evaluated_at = Some(Utc::now());
+                    // Keep the alert armed when delivery failed so the next round retries
+                    stored.is_met = if should_fire { fired } else { is_met };
+                    if fired {
+                        stored.last_triggered_at = Some(Utc::now());
+                    }
+                }
+            }
+        }
+
+        Ok(json!({
+            "evaluated": alerts.len() - partial_errors.len(),
+            "triggered": triggered,
+            "partialErrors": partial_errors
+        }))
+    }
+
+    // Watchlist methods
+    fn get_watchlist(&self, include_quotes: bool, time_format: TimeFormat) -> Result<Value> {
+        let symbols = self.settings_service.get_watchlist()?;
+        if !include_quotes {
+            return Ok(json!({ "symbols": symbols }));
+        }
+
+        let mut quotes = self.market_data_service.get_latest_quotes_for_symbols(&symbols)?;
+        let items: Vec<Value> = symbols
+            .iter()
+            .map(|symbol| {
+                json!({
+                    "symbol": symbol,
+                    "quote": quotes.remove(symbol).map(|q| quote_to_json(q, time_format))
+                })
+            })
+            .collect();
+        Ok(json!({
+            "symbols": symbols,
+            "items": items
+        }))
+    }
+
+    async fn add_to_watchlist(&self, request: WatchlistRequest) -> Result<Value> {
+        let _write = self.settings_writes.lock().await;
+        let mut symbols = self.settings_service.get_watchlist()?;
+        let before = symbols.len();
+        merge_watchlist_symbols(&mut symbols, &request.symbols);
+        if symbols.len() != before {
+            self.settings_service.update_watchlist(&symbols).await?;
+        }
+        Ok(json!({
+            "added": symbols.len() - before,
+            "symbols": symbols
+        }))
+    }
+
+    async fn remove_from_watchlist(&self, symbols: &str) -> Result<Value> {
+        let removing: Vec<&str> = symbols
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .collect();
+        if removing.is_empty() {
+            return Err(Error::Validation(ValidationError::InvalidInput(
+                "symbols must list at least one symbol".to_string(),
+            )));
+        }
+
+        let _write = self.settings_writes.lock().await;
+        let mut watchlist = self.settings_service.get_watchlist()?;
+        let before = watchlist.len();
+        watchlist.retain(|s| !removing.iter().any(|r| r.eq_ignore_ascii_case(s)));
+        if watchlist.len() != before {
+            self.settings_service.update_watchlist(&watchlist).await?;
+        }
+        Ok(json!({
+            "removed": before - watchlist.len(),
+            "symbols": watchlist
+        }))
+    }
+
+    // Portfolio group methods
+    fn list_portfolio_groups(&self) -> Result<Value> {
+        Ok(json!({
+            "portfolios": self.settings_service.get_portfolio_groups()?
+        }))
+    }
+
+    async fn save_portfolio_group(&self, request: PortfolioGroupRequest) -> Result<Value> {
+        if let Err(details) = request.validate() {
+            return Ok(validation_error_response(&details));
+        }
+        for account_id in &request.account_ids {
+            // Fails with a not-found error for unknown accounts
+            self.account_service.get_account(account_id)?;
+        }
+
+        let group = PortfolioGroup {
+            id: request
+                .id
+                .map(|id| id.trim().to_string())
+                .filter(|id| !id.is_empty())
+                .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            name: request.name.trim().to_string(),
+            account_ids: request.account_ids,
+        };
+
+        // Saving an existing id replaces that group
+        let _write = self.settings_writes.lock().await;
+        let mut groups = self.settings_service.get_portfolio_groups()?;
+        match groups.iter_mut().find(|g| g.id == group.id) {
+            Some(existing) => *existing = group.clone(),
+            None => groups.push(group.clone()),
+        }
+        self.settings_service.update_portfolio_groups(&groups).await?;
+
+        Ok(json!({
+            "portfolio": group
+        }))
+    }
+
+    async fn get_portfolio_group_summary(&self, group_id: &str) -> Result<Value> {
+        let Some(group) = self
+            .settings_service
+            .get_portfolio_groups()?
+            .into_iter()
+            .find(|g| g.id == group_id)
+        else {
+            return Ok(json!({
+                "error": format!("Portfolio {} not found", group_id)
+            }));
+        };
+
+        let accounts: Vec<Account> = self
+            .account_service
+            .get_all_accounts()?
+            .into_iter()
+            .filter(|a| group.account_ids.contains(&a.id))
+            .collect();
+        let mut summary = self.summarize_accounts(accounts).await?;
+        summary["portfolio"] = json!(group);
+        Ok(summary)
+    }
+
+    // Instrument tag methods
+    fn get_instrument_tags(&self, symbol: &str) -> Result<Value> {
+        let key = tag_key(symbol);
+        let tags = self.settings_service.get_instrument_tags()?.remove(&key).unwrap_or_default();
+        Ok(json!({
+            "symbol": key,
+            "tags": tags
+        }))
+    }
+
+    async fn set_instrument_tags(&self, symbol: &str, request: InstrumentTagsRequest) -> Result<Value> {
+        let key = tag_key(symbol);
+        if key.is_empty() {
+            return Err(Error::Validation(ValidationError::InvalidInput(
+                "symbol is required".to_string(),
+            )));
+        }
+
+        let tags = normalize_tags(&request.tags);
+        let _write = self.settings_writes.lock().await;
+        let mut stored = self.settings_service.get_instrument_tags()?;
+        // An empty list removes the symbol's entry
+        if tags.is_empty() {
+            stored.remove(&key);
+        } else {
+            stored.insert(key.clone(), tags.clone());
+        }
+        self.settings_service.update_instrument_tags(&stored).await?;
+
+        Ok(json!({
+            "symbol": key,
+            "tags": tags
+        }))
+    }
+}
+
+/// Days between `today` and the date of the quote a security holding was valued at.
+/// `None` for cash, and for securities valued without any quote.
+pub fn quote_age_days(holding: &Holding, today: NaiveDate) -> Option<i64> {
+    match holding.holding_type {
+        HoldingType::Security => holding.quote_date.map(|date| (today - date).num_days()),
+        HoldingType::Cash => None,
+    }
+}
+
+/// Tags stored for a holding's instrument; cash holdings have none
+pub fn holding_tags<'a>(holding: &Holding, tags: &'a BTreeMap<String, Vec<String>>) -> &'a [String] {
+    holding
+        .instrument
+        .as_ref()
+        .and_then(|inst| tags.get(&tag_key(&inst.symbol)))
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+/// Whether a holding carries at least one of `wanted`, compared case-insensitively
+pub fn holding_has_any_tag(holding: &Holding, tags: &BTreeMap<String, Vec<String>>, wanted: &[String]) -> bool {
+    holding_tags(holding, tags)
+        .iter()
+        .any(|tag| wanted.iter().any(|w| w.eq_ignore_ascii_case(tag)))
+}
+
+/// Convert holdings to JSON format for external API. Instruments with stored tags
+/// carry a `tags` array.
+pub fn holdings_to_json(
+    holdings: Vec<Holding>,
+    tags: &BTreeMap<String, Vec<String>>,
+    numbers: NumberFormat,
+    precision: &CurrencyPrecision,
+) -> Vec<Value> {
+    let today = Utc::now().date_naive();
+    holdings.into_iter()
+        .map(|h| {
+            let quote_age = quote_age_days(&h, today);
+            let instrument_tags = holding_tags(&h, tags).to_vec();
+            let (local_currency, base_currency) = (h.local_currency.clone(), h.base_currency.clone());
+            let money = |value: &MonetaryValue| {
+                numbers.render_money(value, &local_currency, &base_currency, precision)
+            };
+            let mut value = json!({
+                "id": h.id,
+                "accountId": h.account_id,
+                "holdingType": h.holding_type,
+                "instrument": h.instrument.map(|inst| json!({
+                    "id": inst.id,
+                    "symbol": inst.symbol,
+                    "name": inst.name,
+                    "currency": inst.currency,
+                    "assetClass": inst.asset_class,
+                    "assetSubclass": inst.asset_subclass,
+                    "countries": inst.countries,
+                    "sectors": inst.sectors,
+                    "profileUpdatedAt": inst.profile_updated_at.map(|at| at.and_utc().to_rfc3339())
+                })),
+                "quantity": h.quantity,
+                "openDate": h.open_date.map(|dt| dt.to_rfc3339()),
+                "localCurrency": h.local_currency,
+                "baseCurrency": h.base_currency,
+                "fxRate": h.fx_rate.map(|rate| numbers.render(rate)),
+                "marketValue": money(&h.market_value),
+                "costBasis": h.cost_basis.map(|cb| money(&cb)),
+                "price": h.price.map(|price| numbers.render(price)),
+                "unrealizedGain": h.unrealized_gain.map(|ug| money(&ug)),
+                "unrealizedGainPct": h.unrealized_gain_pct,
+                "realizedGain": h.realized_gain.map(|rg| money(&rg)),
+                "realizedGainPct": h.realized_gain_pct,
+                "totalGain": h.total_gain.map(|tg| money(&tg)),
+                "totalGainPct": h.total_gain_pct,
+                "dayChange": h.day_change.map(|dc| money(&dc)),
+                "dayChangePct": h.day_change_pct,
+                "weight": h.weight,
+                "asOfDate": h.as_of_date.to_string(),
+                "quoteDate": h.quote_date,
+                "quoteAgeDays": quote_age,
+                "isShort": h.quantity.is_sign_negative()
+            });
+            if !instrument_tags.is_empty() {
+                value["tags"] = json!(instrument_tags);
+            }
+            value
+        })
+        .collect()
+}
+
+/// Default holdings order, so repeated requests list and page identically: by symbol
+/// (cash as `$CASH-<currency>`), then account, then holding id.
+pub fn sort_holdings(holdings: &mut [Holding]) {
+    holdings.sort_by_cached_key(|h| {
+        (
+            allocation_bucket(h, AllocationGroup::Symbol),
+            h.account_id.clone(),
+            h.id.clone(),
+        )
+    });
+}
+
+/// Default activities order: oldest first, with same-day activities ordered by
+/// account, asset and id so ties are stable.
+pub fn sort_activities(activities: &mut [Activity]) {
+    activities.sort_by(|a, b| {
+        a.activity_date
+            .cmp(&b.activity_date)
+            .then_with(|| a.account_id.cmp(&b.account_id))
+            .then_with(|| a.asset_id.cmp(&b.asset_id))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+}
+
+/// Sums holding market values per native currency, keeping the base-currency equivalent.
+pub fn market_value_by_currency(holdings: &[Holding]) -> BTreeMap<String, MonetaryValue> {
+    let mut totals: BTreeMap<String, MonetaryValue> = BTreeMap::new();
+    for holding in holdings {
+        let total = totals
+            .entry(holding.local_currency.clone())
+            .or_insert_with(MonetaryValue::zero);
+        total.local += holding.market_value.local;
+        total.base += holding.market_value.base;
+    }
+    totals
+}
+
+/// Keys kept on every object regardless of the requested field list.
+const ALWAYS_SELECTED_FIELDS: [&str; 1] = ["baseCurrency"];
+
+/// Trims each object down to the comma-separated top-level keys in `fields`.
+/// Unknown names are ignored; `baseCurrency` is always kept.
+pub fn select_fields(items: &mut [Value], fields: &str) {
+    let wanted: Vec<&str> = fields
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+    if wanted.is_empty() {
+        return;
+    }
+
+    for item in items.iter_mut() {
+        if let Value::Object(map) = item {
+            map.retain(|key, _| {
+                wanted.contains(&key.as_str()) || ALWAYS_SELECTED_FIELDS.contains(&key.as_str())
+            });
+        }
+    }
+}
 
 /// Convert accounts to JSON format for external API
 pub fn accounts_to_json(accounts: Vec<Account>) -> Vec<Value> {
@@ -266,277 +3902,5772 @@ pub fn accounts_to_json(accounts: Vec<Account>) -> Vec<Value> {
         .collect()
 }
 
-/// Convert exchange rates to JSON format for external API
-pub fn exchange_rates_to_json(rates: Vec<ExchangeRate>) -> Vec<Value> {
-    rates.into_iter()
-        .map(|r| json!({
-            "from": r.from_currency,
-            "to": r.to_currency,
-            "rate": r.rate,
-            "timestamp": r.timestamp.to_rfc3339()
-        }))
-        .collect()
-}
+/// Cache validators for a resource, used to answer conditional GET requests
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResourceVersion {
+    pub last_modified: DateTime<Utc>,
+    pub etag: String,
+}
+
+impl ResourceVersion {
+    /// `Last-Modified` header value in HTTP-date format
+    pub fn last_modified_header(&self) -> String {
+        self.last_modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+    }
+
+    /// Whether the client's cached copy is still current. `If-None-Match` takes
+    /// precedence over `If-Modified-Since`, as in RFC 9110.
+    pub fn is_fresh(&self, if_none_match: Option<&str>, if_modified_since: Option<&str>) -> bool {
+        if let Some(tags) = if_none_match {
+            return tags
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag == self.etag);
+        }
+        if_modified_since
+            .and_then(|value| DateTime::parse_from_rfc2822(value.trim()).ok())
+            .is_some_and(|since| self.last_modified.timestamp() <= since.timestamp())
+    }
+}
+
+/// Validators for the accounts list: the latest account update, plus an ETag over
+/// ids and update times so that deletions are noticed too.
+pub fn accounts_version(accounts: &[Account]) -> Option<ResourceVersion> {
+    use std::hash::{Hash, Hasher};
+
+    let last_modified = accounts.iter().map(|a| a.updated_at).max()?.and_utc();
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    for account in accounts {
+        account.id.hash(&mut hasher);
+        account.updated_at.hash(&mut hasher);
+    }
+    Some(ResourceVersion {
+        last_modified,
+        etag: format!("W/\"{:016x}\"", hasher.finish()),
+    })
+}
+
+/// How timestamps are rendered in quote, activity and exchange-rate responses
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeFormat {
+    #[default]
+    Rfc3339,
+    EpochMs,
+}
+
+impl TimeFormat {
+    pub fn render(&self, timestamp: DateTime<Utc>) -> Value {
+        match self {
+            TimeFormat::Rfc3339 => json!(timestamp.to_rfc3339()),
+            TimeFormat::EpochMs => json!(timestamp.timestamp_millis()),
+        }
+    }
+}
+
+/// How quote history series are returned
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteNormalization {
+    /// Stored prices
+    #[default]
+    Raw,
+    /// Change since the first quote of each series, as `{date, pctChange}`
+    Pct,
+}
+
+impl QuoteNormalization {
+    pub fn render(&self, quotes: Vec<Quote>, time_format: TimeFormat) -> Vec<Value> {
+        match self {
+            QuoteNormalization::Raw => quotes_to_json(quotes, time_format),
+            QuoteNormalization::Pct => pct_change_series(&quotes),
+        }
+    }
+}
+
+/// How monetary amounts are rendered in holding and activity responses
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NumberFormat {
+    /// JSON numbers
+    #[default]
+    Float,
+    /// Decimal strings keeping the stored precision, e.g. `"1234.5600"`
+    Exact,
+}
+
+impl NumberFormat {
+    pub fn from_exact(exact: Option<bool>) -> Self {
+        if exact.unwrap_or(false) {
+            NumberFormat::Exact
+        } else {
+            NumberFormat::Float
+        }
+    }
+
+    pub fn render(&self, value: Decimal) -> Value {
+        match self {
+            NumberFormat::Float => json!(value),
+            NumberFormat::Exact => json!(value.to_string()),
+        }
+    }
+
+    /// Renders an amount in `currency`. Numbers are rounded to the currency's
+    /// precision; exact strings keep the stored scale.
+    pub fn render_amount(&self, value: Decimal, currency: &str, precision: &CurrencyPrecision) -> Value {
+        match self {
+            NumberFormat::Float => json!(precision.round(value, currency)),
+            NumberFormat::Exact => json!(value.to_string()),
+        }
+    }
+
+    pub fn render_money(
+        &self,
+        value: &MonetaryValue,
+        local_currency: &str,
+        base_currency: &str,
+        precision: &CurrencyPrecision,
+    ) -> Value {
+        json!({
+            "local": self.render_amount(value.local, local_currency, precision),
+            "base": self.render_amount(value.base, base_currency, precision)
+        })
+    }
+}
+
+/// Decimal places monetary amounts are rounded to in responses, per currency code.
+/// Currencies without a rule use the default precision.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CurrencyPrecision {
+    rules: HashMap<String, u32>,
+    default_places: u32,
+}
+
+impl Default for CurrencyPrecision {
+    fn default() -> Self {
+        Self {
+            rules: CURRENCY_PRECISION_DEFAULTS
+                .iter()
+                .map(|(currency, places)| (currency.to_string(), *places))
+                .collect(),
+            default_places: DEFAULT_CURRENCY_PRECISION,
+        }
+    }
+}
+
+impl CurrencyPrecision {
+    /// Adds or replaces rules from comma-separated `CODE=places` pairs, e.g.
+    /// `JPY=0,BTC=8`. Malformed pairs are skipped.
+    pub fn with_rules(mut self, rules: &str) -> Self {
+        for rule in rules.split(',') {
+            let Some((currency, places)) = rule.split_once('=') else {
+                continue;
+            };
+            let currency = currency.trim().to_uppercase();
+            if let (false, Ok(places)) = (currency.is_empty(), places.trim().parse::<u32>()) {
+                self.rules.insert(currency, places.min(MAX_CURRENCY_PRECISION));
+            }
+        }
+        self
+    }
+
+    /// Sets the precision for currencies without a rule.
+    pub fn with_default_places(mut self, places: u32) -> Self {
+        self.default_places = places.min(MAX_CURRENCY_PRECISION);
+        self
+    }
+
+    pub fn places(&self, currency: &str) -> u32 {
+        self.rules
+            .get(&currency.trim().to_uppercase())
+            .copied()
+            .unwrap_or(self.default_places)
+    }
+
+    pub fn round(&self, amount: Decimal, currency: &str) -> Decimal {
+        amount.round_dp(self.places(currency))
+    }
+}
+
+/// `source` value that fetches from providers in their configured priority order.
+const AUTO_SOURCE: &str = "auto";
+
+/// Days of provider history searched for a live quote.
+const LIVE_QUOTE_LOOKBACK_DAYS: i64 = 7;
+
+/// Response header telling pollers how many seconds to wait before fresh data is expected
+pub const REFRESH_HINT_HEADER: &str = "refresh-hint";
+
+/// Refresh cadence during the session for providers with intraday prices
+pub const INTRADAY_REFRESH_SECS: u64 = 60;
+
+/// Refresh cadence during the session for providers that publish daily closes only
+pub const DAILY_REFRESH_SECS: u64 = 3600;
+
+/// Manual prices only change when someone enters one
+pub const MANUAL_REFRESH_SECS: u64 = 86_400;
+
+/// Trading session in UTC, wide enough to cover the US regular session on both sides
+/// of daylight saving time (13:30-20:00 in summer, 14:30-21:00 in winter)
+const SESSION_OPEN_UTC: (u32, u32) = (13, 30);
+const SESSION_CLOSE_UTC: (u32, u32) = (21, 0);
+
+/// Seconds a client should wait before polling quotes from `data_source` again at `now`.
+/// During the weekday session this is the source's update cadence; outside it, the time
+/// until the next session opens. Unknown or mixed sources use the intraday cadence.
+pub fn refresh_after_secs(now: DateTime<Utc>, data_source: Option<&str>) -> u64 {
+    let cadence = match data_source {
+        Some(source) if source == DATA_SOURCE_MANUAL => return MANUAL_REFRESH_SECS,
+        Some(source) if source == DATA_SOURCE_ALPHA_VANTAGE || source == DATA_SOURCE_METAL_PRICE_API => {
+            DAILY_REFRESH_SECS
+        }
+        _ => INTRADAY_REFRESH_SECS,
+    };
+
+    let at = |date: NaiveDate, (hour, minute): (u32, u32)| {
+        date.and_hms_opt(hour, minute, 0).map(|time| time.and_utc())
+    };
+    let today = now.date_naive();
+    if is_weekday(today)
+        && at(today, SESSION_OPEN_UTC).is_some_and(|open| now >= open)
+        && at(today, SESSION_CLOSE_UTC).is_some_and(|close| now < close)
+    {
+        return cadence;
+    }
+
+    today
+        .iter_days()
+        .take(8)
+        .filter(|date| is_weekday(*date))
+        .filter_map(|date| at(date, SESSION_OPEN_UTC))
+        .find(|open| *open > now)
+        .map_or(cadence, |open| (open - now).num_seconds().max(cadence as i64) as u64)
+}
+
+/// Days of provider history returned for a live historical request.
+const LIVE_HISTORY_DAYS: i64 = 365;
+
+/// Query parameters for quote routes
+#[derive(Deserialize)]
+pub struct QuoteQuery {
+    /// `rfc3339` (default) or `epoch_ms`
+    #[serde(default)]
+    time_format: TimeFormat,
+    /// Fetch live from providers: `auto` or a comma-separated provider order
+    source: Option<String>,
+    /// Split- and dividend-adjusted prices for historical quotes
+    adjusted: Option<bool>,
+    /// `raw` (default) or `pct` to rebase historical quotes to the first one
+    #[serde(default)]
+    normalize: QuoteNormalization,
+}
+
+/// Query parameters for profile freshness
+#[derive(Deserialize)]
+pub struct ProfileFreshnessQuery {
+    /// Profile age in days beyond which it is stale (default 90)
+    stale_days: Option<i64>,
+}
+
+/// Query parameters for quote statistics
+#[derive(Deserialize)]
+pub struct QuoteStatsQuery {
+    /// `1m`, `3m` or `1y` (default)
+    window: Option<String>,
+}
+
+/// Query parameter selecting the timestamp format
+#[derive(Deserialize)]
+pub struct TimeFormatQuery {
+    /// `rfc3339` (default) or `epoch_ms`
+    #[serde(default)]
+    time_format: TimeFormat,
+}
+
+/// Exchange rates query parameters
+#[derive(Debug, Deserialize)]
+pub struct ExchangeRatesQuery {
+    /// `rfc3339` (default) or `epoch_ms`
+    #[serde(default)]
+    pub time_format: TimeFormat,
+    /// Only rates needed to value this account's holdings into base currency
+    pub account_id: Option<String>,
+    /// Only rates needed to value current holdings (default account, or all accounts)
+    pub relevant_only: Option<bool>,
+}
+
+/// Keeps the stored rates used to convert each currency into the base currency,
+/// including intermediate legs of cross-currency paths. Currencies with no path
+/// to base are returned separately.
+pub fn relevant_exchange_rates(
+    rates: Vec<ExchangeRate>,
+    currencies: &BTreeSet<String>,
+    base_currency: &str,
+) -> (Vec<ExchangeRate>, Vec<String>) {
+    let base_currency = base_currency.to_uppercase();
+    let mut used: HashSet<String> = HashSet::new();
+    let mut unresolved = Vec::new();
+    for currency in currencies.iter().filter(|c| **c != base_currency) {
+        match find_fx_path(&rates, currency, &base_currency) {
+            Some(hops) => used.extend(hops.iter().map(|hop| hop.source.id.clone())),
+            None => unresolved.push(currency.clone()),
+        }
+    }
+    let rates = rates.into_iter().filter(|rate| used.contains(&rate.id)).collect();
+    (rates, unresolved)
+}
+
+/// Convert exchange rates to JSON format for external API
+pub fn exchange_rates_to_json(rates: Vec<ExchangeRate>, time_format: TimeFormat) -> Vec<Value> {
+    rates.into_iter()
+        .map(|r| json!({
+            "from": r.from_currency,
+            "to": r.to_currency,
+            "rate": r.rate,
+            "timestamp": time_format.render(r.timestamp)
+        }))
+        .collect()
+}
+
+/// Rates in `after` that are new or carry a later timestamp than the same pair in `before`
+pub fn refreshed_exchange_rates(before: &[ExchangeRate], after: Vec<ExchangeRate>) -> Vec<ExchangeRate> {
+    let previous: HashMap<&str, DateTime<Utc>> = before
+        .iter()
+        .map(|rate| (rate.id.as_str(), rate.timestamp))
+        .collect();
+    after
+        .into_iter()
+        .filter(|rate| previous.get(rate.id.as_str()).is_none_or(|&t| rate.timestamp > t))
+        .collect()
+}
+
+/// One conversion step in an FX path
+#[derive(Clone, Copy)]
+pub struct FxHop<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    pub rate: Decimal,
+    /// Whether the stored rate was quoted the other way round and inverted
+    pub inverted: bool,
+    pub source: &'a ExchangeRate,
+}
+
+/// Finds the path with the fewest hops between two currencies, using stored rates in
+/// either direction. Returns an empty path when both currencies are the same.
+pub fn find_fx_path<'a>(rates: &'a [ExchangeRate], from: &str, to: &str) -> Option<Vec<FxHop<'a>>> {
+    if from == to {
+        return Some(Vec::new());
+    }
+
+    // Direct quotes are listed before inverted ones so they win ties
+    let mut edges: HashMap<&str, Vec<FxHop<'a>>> = HashMap::new();
+    for rate in rates.iter().filter(|r| !r.rate.is_zero()) {
+        edges.entry(rate.from_currency.as_str()).or_default().push(FxHop {
+            from: &rate.from_currency,
+            to: &rate.to_currency,
+            rate: rate.rate,
+            inverted: false,
+            source: rate,
+        });
+    }
+    for rate in rates.iter().filter(|r| !r.rate.is_zero()) {
+        edges.entry(rate.to_currency.as_str()).or_default().push(FxHop {
+            from: &rate.to_currency,
+            to: &rate.from_currency,
+            rate: Decimal::ONE / rate.rate,
+            inverted: true,
+            source: rate,
+        });
+    }
+
+    // Breadth-first search, remembering the hop that first reached each currency
+    let mut reached_by: HashMap<&str, Option<(&str, usize)>> = HashMap::new();
+    let mut queue: VecDeque<&str> = VecDeque::new();
+    reached_by.insert(from, None);
+    queue.push_back(from);
+    while let Some(currency) = queue.pop_front() {
+        if currency == to {
+            break;
+        }
+        for (index, hop) in edges.get(currency).into_iter().flatten().enumerate() {
+            if !reached_by.contains_key(hop.to) {
+                reached_by.insert(hop.to, Some((currency, index)));
+                queue.push_back(hop.to);
+            }
+        }
+    }
+
+    reached_by.get(to)?;
+    let mut path = Vec::new();
+    let mut currency = to;
+    while let Some(Some((previous, index))) = reached_by.get(currency) {
+        path.push(edges[previous][*index]);
+        currency = *previous;
+    }
+    path.reverse();
+    Some(path)
+}
+
+/// Machine-readable code returned when no stored rate links two currencies
+pub const RATE_NOT_FOUND_CODE: &str = "RATE_NOT_FOUND";
+
+/// Error body for a currency pair with no stored rate in either direction
+pub fn rate_not_found_response(from: &str, to: &str) -> Value {
+    json!({
+        "error": format!("No exchange rate from {} to {}", from, to),
+        "code": RATE_NOT_FOUND_CODE
+    })
+}
+
+/// Rate for one currency pair, synthesized as `1 / rate` when only the opposite
+/// direction is stored. `source` is the stored rate it came from.
+#[derive(Clone, Copy, Debug)]
+pub struct PairRate<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    pub rate: Decimal,
+    pub synthesized: bool,
+    pub source: &'a ExchangeRate,
+}
+
+/// Finds the stored rate for `from`→`to`, falling back to the inverse of a stored
+/// `to`→`from` rate.
+pub fn find_pair_rate<'a>(rates: &'a [ExchangeRate], from: &'a str, to: &'a str) -> Option<PairRate<'a>> {
+    if let Some(rate) = rates
+        .iter()
+        .find(|r| r.from_currency == from && r.to_currency == to)
+    {
+        return Some(PairRate {
+            from,
+            to,
+            rate: rate.rate,
+            synthesized: false,
+            source: rate,
+        });
+    }
+    rates
+        .iter()
+        .find(|r| r.from_currency == to && r.to_currency == from && !r.rate.is_zero())
+        .map(|rate| PairRate {
+            from,
+            to,
+            rate: Decimal::ONE / rate.rate,
+            synthesized: true,
+            source: rate,
+        })
+}
+
+/// Convert a pair rate to JSON format for external API
+pub fn pair_rate_to_json(pair: &PairRate) -> Value {
+    json!({
+        "from": pair.from,
+        "to": pair.to,
+        "rate": pair.rate,
+        "synthesized": pair.synthesized,
+        "dataSource": pair.source.source,
+        "timestamp": pair.source.timestamp.to_rfc3339()
+    })
+}
+
+/// How a path converts: `identity` for the same currency, `direct` or `inverse` for a
+/// single stored rate, `chained` through intermediate currencies
+pub fn fx_path_method(hops: &[FxHop]) -> &'static str {
+    match hops {
+        [] => "identity",
+        [hop] if hop.inverted => "inverse",
+        [_] => "direct",
+        _ => "chained",
+    }
+}
+
+/// Convert an FX hop to JSON format for external API
+pub fn fx_hop_to_json(hop: &FxHop) -> Value {
+    json!({
+        "from": hop.from,
+        "to": hop.to,
+        "rate": hop.rate,
+        "inverted": hop.inverted,
+        "synthesized": hop.inverted,
+        "dataSource": hop.source.source,
+        "timestamp": hop.source.timestamp.to_rfc3339()
+    })
+}
+
+/// Create health response JSON. `version` is the version of the crate serving the
+/// API; `coreVersion` is this crate's.
+pub fn create_health_response(port: u16, version: &str) -> Value {
+    json!({
+        "status": "ok",
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "port": port,
+        "version": version,
+        "coreVersion": API_VERSION
+    })
+}
+
+/// Version reported in the opt-in response `meta` block
+pub const API_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Adds `meta: {serverTime, apiVersion}` next to the payload keys of a JSON object
+/// response. Other JSON values are left untouched.
+pub fn add_response_meta(body: &mut Value) {
+    if let Some(object) = body.as_object_mut() {
+        object.insert(
+            "meta".to_string(),
+            json!({
+                "serverTime": Utc::now().to_rfc3339(),
+                "apiVersion": API_VERSION
+            }),
+        );
+    }
+}
+
+/// Create root response JSON
+pub fn create_root_response(port: u16) -> Value {
+    json!({
+        "message": "Wealthfolio External API",
+        "status": "running",
+        "port": port
+    })
+}
+
+#[derive(Deserialize)]
+pub struct HoldingsQuery {
+    account_id: Option<String>,
+    /// Minimum absolute base-currency market value for a holding to be listed
+    min_value: Option<Decimal>,
+    /// Minimum absolute quantity for a security to be listed; cash is not filtered by quantity
+    min_quantity: Option<Decimal>,
+    /// Past date (YYYY-MM-DD) to reconstruct holdings for, instead of the current snapshot
+    as_of: Option<NaiveDate>,
+    /// Recompute weights relative to the returned holdings instead of the whole portfolio
+    rebase_weights: Option<bool>,
+    /// Comma-separated holding keys to return, e.g. `instrument,quantity,marketValue`
+    fields: Option<String>,
+    /// Only list holdings whose quote is older than `stale_days`
+    stale_only: Option<bool>,
+    /// Quote age in days beyond which a holding is stale (default 3)
+    stale_days: Option<i64>,
+    /// `short`, `long` or `all` (default)
+    #[serde(default)]
+    positions: PositionsFilter,
+    /// Append fully sold positions with their realized gain, flagged `closed`
+    include_closed: Option<bool>,
+    /// Comma-separated tags; only holdings carrying at least one of them are listed
+    tags: Option<String>,
+    /// Comma-separated account ids left out of the aggregation
+    exclude_accounts: Option<String>,
+    /// Render monetary amounts as decimal strings instead of JSON numbers
+    exact: Option<bool>,
+    /// Page size; all holdings are returned when omitted
+    limit: Option<usize>,
+    /// Number of holdings to skip
+    offset: Option<usize>,
+    /// Accounts listed in a holdings query body; only settable through `HoldingsQueryRequest`
+    #[serde(skip)]
+    account_ids: Option<Vec<String>>,
+    /// Order requested in a holdings query body, applied on top of the default order
+    #[serde(skip)]
+    sort: Option<HoldingsSort>,
+}
+
+/// Body of `POST /api/portfolio/holdings/query`: the GET holdings filters as JSON,
+/// with an account list that would not fit in a query string and an explicit sort
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HoldingsQueryRequest {
+    pub account_ids: Option<Vec<String>>,
+    #[serde(default)]
+    pub exclude_accounts: Vec<String>,
+    pub min_value: Option<Decimal>,
+    pub min_quantity: Option<Decimal>,
+    pub as_of: Option<NaiveDate>,
+    pub rebase_weights: Option<bool>,
+    #[serde(default)]
+    pub fields: Vec<String>,
+    pub stale_only: Option<bool>,
+    pub stale_days: Option<i64>,
+    #[serde(default)]
+    pub positions: PositionsFilter,
+    pub include_closed: Option<bool>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub exact: Option<bool>,
+    pub sort: Option<HoldingsSort>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+impl HoldingsQuery {
+    /// Whether `holding` falls below the `min_value` or `min_quantity` thresholds.
+    /// Both compare magnitudes, so a large short position is not dust.
+    fn is_dust(&self, holding: &Holding) -> bool {
+        let below_value = self
+            .min_value
+            .is_some_and(|min| holding.market_value.base.abs() < min);
+        let below_quantity = holding.holding_type == HoldingType::Security
+            && self.min_quantity.is_some_and(|min| holding.quantity.abs() < min);
+        below_value || below_quantity
+    }
+}
+
+impl From<HoldingsQueryRequest> for HoldingsQuery {
+    fn from(request: HoldingsQueryRequest) -> Self {
+        // Lists go through the same comma-separated parsing as the query string
+        let join = |items: Vec<String>| (!items.is_empty()).then(|| items.join(","));
+        HoldingsQuery {
+            account_id: None,
+            min_value: request.min_value,
+            min_quantity: request.min_quantity,
+            as_of: request.as_of,
+            rebase_weights: request.rebase_weights,
+            fields: join(request.fields),
+            stale_only: request.stale_only,
+            stale_days: request.stale_days,
+            positions: request.positions,
+            include_closed: request.include_closed,
+            tags: join(request.tags),
+            exclude_accounts: join(request.exclude_accounts),
+            exact: request.exact,
+            limit: request.limit,
+            offset: request.offset,
+            account_ids: request.account_ids,
+            sort: request.sort,
+        }
+    }
+}
+
+/// Holdings sort requested in a query body
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HoldingsSort {
+    pub by: HoldingsSortKey,
+    #[serde(default)]
+    pub direction: SortDirection,
+}
+
+/// Holding value a sort compares; amounts compare in base currency
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HoldingsSortKey {
+    Symbol,
+    MarketValue,
+    Quantity,
+    Weight,
+    UnrealizedGain,
+    DayChangePct,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl HoldingsSort {
+    /// Stable, so holdings that compare equal keep their default order. Missing
+    /// values sort before present ones in ascending order.
+    pub fn apply(&self, holdings: &mut [Holding]) {
+        let unrealized = |h: &Holding| h.unrealized_gain.as_ref().map(|gain| gain.base);
+        let compare = |a: &Holding, b: &Holding| match self.by {
+            HoldingsSortKey::Symbol => allocation_bucket(a, AllocationGroup::Symbol)
+                .cmp(&allocation_bucket(b, AllocationGroup::Symbol)),
+            HoldingsSortKey::MarketValue => a.market_value.base.cmp(&b.market_value.base),
+            HoldingsSortKey::Quantity => a.quantity.cmp(&b.quantity),
+            HoldingsSortKey::Weight => a.weight.cmp(&b.weight),
+            HoldingsSortKey::UnrealizedGain => unrealized(a).cmp(&unrealized(b)),
+            HoldingsSortKey::DayChangePct => a.day_change_pct.cmp(&b.day_change_pct),
+        };
+        match self.direction {
+            SortDirection::Asc => holdings.sort_by(compare),
+            SortDirection::Desc => holdings.sort_by(|a, b| compare(b, a)),
+        }
+    }
+}
+
+/// Page of a list selected by the `limit` and `offset` query parameters
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Pagination {
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+/// Describes the page returned by `Pagination::apply`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageInfo {
+    pub total: usize,
+    pub limit: Option<usize>,
+    pub offset: usize,
+    pub has_more: bool,
+}
+
+impl Pagination {
+    pub fn new(limit: Option<usize>, offset: Option<usize>) -> Self {
+        Self {
+            limit,
+            offset: offset.unwrap_or(0),
+        }
+    }
+
+    /// Applies the server's page size cap to a requested `limit`, clamping larger
+    /// limits down to it. A missing or zero `limit` stays unpaged and returns every item.
+    pub fn clamp(self, max_page_size: usize) -> Self {
+        Self {
+            limit: self
+                .limit
+                .filter(|limit| *limit > 0)
+                .map(|limit| limit.min(max_page_size)),
+            offset: self.offset,
+        }
+    }
+
+    /// Keeps only this page of `items`
+    pub fn apply<T>(&self, items: &mut Vec<T>) -> PageInfo {
+        let total = items.len();
+        items.drain(..self.offset.min(total));
+        if let Some(limit) = self.limit {
+            items.truncate(limit);
+        }
+        PageInfo {
+            total,
+            limit: self.limit,
+            offset: self.offset,
+            has_more: self.offset + items.len() < total,
+        }
+    }
+}
+
+/// Builds an RFC 8288 `Link` header with `next` and `prev` pages for a paginated
+/// response, keeping every other query parameter of the request as it was sent.
+/// Returns `None` when the response is not paginated or has no neighbouring page.
+pub fn pagination_link_header(path: &str, query: Option<&str>, response: &Value) -> Option<String> {
+    let limit = response.get("limit")?.as_u64()?;
+    let offset = response.get("offset")?.as_u64()?;
+    let total = response.get("total")?.as_u64()?;
+    if limit == 0 {
+        return None;
+    }
+
+    let kept: Vec<&str> = query
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| {
+            let key = pair.split('=').next().unwrap_or_default();
+            !pair.is_empty() && key != "limit" && key != "offset"
+        })
+        .collect();
+    let link = |offset: u64, rel: &str| {
+        let mut params = kept.clone();
+        let page = format!("limit={}&offset={}", limit, offset);
+        params.push(&page);
+        format!("<{}?{}>; rel=\"{}\"", path, params.join("&"), rel)
+    };
+
+    let mut links = Vec::new();
+    if offset + limit < total {
+        links.push(link(offset + limit, "next"));
+    }
+    if offset > 0 {
+        links.push(link(offset.saturating_sub(limit), "prev"));
+    }
+    (!links.is_empty()).then(|| links.join(", "))
+}
+
+/// Which side of the book to list in the holdings response
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PositionsFilter {
+    Short,
+    Long,
+    #[default]
+    All,
+}
+
+impl PositionsFilter {
+    /// Short positions have a negative quantity
+    pub fn matches(&self, holding: &Holding) -> bool {
+        match self {
+            PositionsFilter::Short => holding.quantity.is_sign_negative(),
+            PositionsFilter::Long => !holding.quantity.is_sign_negative(),
+            PositionsFilter::All => true,
+        }
+    }
+}
+
+/// Health check handler
+pub async fn health_handler(port: u16, version: &str) -> Value {
+    create_health_response(port, version)
+}
+
+/// Root handler
+pub async fn root_handler(port: u16) -> Value {
+    create_root_response(port)
+}
+
+/// Portfolio holdings handler
+pub async fn portfolio_holdings_handler(
+    service: &dyn ExternalApiServiceTrait,
+    query: HoldingsQuery,
+) -> Value {
+    match service.get_holdings(query).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Internal server error: {}", e)
+        }),
+    }
+}
+
+/// Holdings query body handler
+pub async fn holdings_query_handler(
+    service: &dyn ExternalApiServiceTrait,
+    request: HoldingsQueryRequest,
+) -> Value {
+    portfolio_holdings_handler(service, request.into()).await
+}
+
+/// Portfolio accounts handler
+pub async fn portfolio_accounts_handler(service: &dyn ExternalApiServiceTrait) -> Value {
+    match service.get_accounts() {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get accounts: {}", e)
+        }),
+    }
+}
+
+/// Accounts cache validators; `None` when there are no accounts or they can't be read,
+/// in which case the list is served unconditionally
+pub async fn accounts_version_handler(service: &dyn ExternalApiServiceTrait) -> Option<ResourceVersion> {
+    match service.get_accounts_version() {
+        Ok(version) => version,
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to get accounts version");
+            None
+        }
+    }
+}
+
+/// Account recompute handler
+pub async fn recompute_account_handler(
+    service: &dyn ExternalApiServiceTrait,
+    account_id: &str,
+) -> Value {
+    match service.recompute_account(account_id).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to recompute account {}: {}", account_id, e)
+        }),
+    }
+}
+
+/// Rebalance handler
+pub async fn rebalance_handler(
+    service: &dyn ExternalApiServiceTrait,
+    request: RebalanceRequest,
+) -> Value {
+    match service.get_rebalance(request).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to calculate rebalance: {}", e)
+        }),
+    }
+}
+
+/// Allocation history handler
+pub async fn allocation_history_handler(
+    service: &dyn ExternalApiServiceTrait,
+    query: AllocationHistoryQuery,
+) -> Value {
+    let group_by = query.group_by.as_deref().unwrap_or("assetClass");
+    let interval = query.interval.as_deref().unwrap_or("monthly");
+    match service
+        .get_allocation_history(group_by, interval, query.from, query.to)
+        .await
+    {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get allocation history: {}", e)
+        }),
+    }
+}
+
+/// Tax lots query
+#[derive(Deserialize)]
+pub struct LotsQuery {
+    /// Account to list lots for; the default account or all accounts when omitted
+    account_id: Option<String>,
+}
+
+/// Tax lots handler
+pub async fn lots_handler(service: &dyn ExternalApiServiceTrait, symbol: &str, query: LotsQuery) -> Value {
+    match service.get_lots(symbol, query.account_id).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get lots for {}: {}", symbol, e)
+        }),
+    }
+}
+
+/// Unrealized gain by term query
+#[derive(Deserialize)]
+pub struct UnrealizedByTermQuery {
+    /// Account to classify lots for; the default account or all accounts when omitted
+    account_id: Option<String>,
+}
+
+/// Unrealized gain by holding period handler
+pub async fn unrealized_by_term_handler(
+    service: &dyn ExternalApiServiceTrait,
+    query: UnrealizedByTermQuery,
+) -> Value {
+    match service.get_unrealized_by_term(query.account_id).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get unrealized gain by term: {}", e)
+        }),
+    }
+}
+
+/// Account currency exposure handler
+pub async fn account_currency_exposure_handler(
+    service: &dyn ExternalApiServiceTrait,
+    account_id: &str,
+) -> Value {
+    match service.get_account_currency_exposure(account_id).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get currency exposure for account {}: {}", account_id, e)
+        }),
+    }
+}
+
+/// Trade simulation handler
+pub async fn simulate_trade_handler(
+    service: &dyn ExternalApiServiceTrait,
+    request: SimulateTradeRequest,
+) -> Value {
+    match service.simulate_trade(request).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to simulate trade: {}", e)
+        }),
+    }
+}
+
+/// Cash summary handler
+pub async fn cash_summary_handler(service: &dyn ExternalApiServiceTrait) -> Value {
+    match service.get_cash_summary().await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get cash summary: {}", e)
+        }),
+    }
+}
+
+/// Asset classes handler
+pub async fn asset_classes_handler(service: &dyn ExternalApiServiceTrait) -> Value {
+    match service.get_asset_classes().await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get asset classes: {}", e)
+        }),
+    }
+}
+
+/// Top holdings query parameters
+#[derive(Deserialize)]
+pub struct TopHoldingsQuery {
+    account_id: Option<String>,
+    /// Number of holdings to list (default 10)
+    limit: Option<usize>,
+}
+
+/// Sector exposure query parameters
+#[derive(Deserialize)]
+pub struct SectorExposureQuery {
+    account_id: Option<String>,
+    /// Split funds across their constituent sectors (default false)
+    look_through: Option<bool>,
+}
+
+/// Sector exposure handler
+pub async fn sector_exposure_handler(service: &dyn ExternalApiServiceTrait, query: SectorExposureQuery) -> Value {
+    match service
+        .get_sector_exposure(query.account_id, query.look_through.unwrap_or(false))
+        .await
+    {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get sector exposure: {}", e)
+        }),
+    }
+}
+
+/// Top holdings handler
+pub async fn top_holdings_handler(service: &dyn ExternalApiServiceTrait, query: TopHoldingsQuery) -> Value {
+    match service
+        .get_top_holdings(query.account_id, query.limit.unwrap_or(DEFAULT_TOP_HOLDINGS))
+        .await
+    {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get top holdings: {}", e)
+        }),
+    }
+}
+
+/// Per-account day change handler
+pub async fn accounts_day_change_handler(service: &dyn ExternalApiServiceTrait) -> Value {
+    match service.get_accounts_day_change().await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get account day change: {}", e)
+        }),
+    }
+}
+
+/// Exchange rates handler
+pub async fn exchange_rates_handler(
+    service: &dyn ExternalApiServiceTrait,
+    query: ExchangeRatesQuery,
+) -> Value {
+    match service.get_exchange_rates(query).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get exchange rates: {}", e)
+        }),
+    }
+}
+
+/// FX path handler
+pub async fn fx_path_handler(service: &dyn ExternalApiServiceTrait, query: FxPathQuery) -> Value {
+    match service.get_fx_path(&query.from, &query.to) {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get FX path from {} to {}: {}", query.from, query.to, e)
+        }),
+    }
+}
+
+/// Rates-to-base handler
+pub async fn rates_to_base_handler(service: &dyn ExternalApiServiceTrait, query: RatesToBaseQuery) -> Value {
+    let currencies: Vec<String> = query
+        .currencies
+        .split(',')
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .collect();
+    match service.get_rates_to_base(&currencies) {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get rates to base currency: {}", e)
+        }),
+    }
+}
+
+/// Exchange rate handler
+pub async fn exchange_rate_handler(service: &dyn ExternalApiServiceTrait, from: &str, to: &str) -> Value {
+    match service.get_exchange_rate(from, to) {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get exchange rate from {} to {}: {}", from, to, e)
+        }),
+    }
+}
+
+/// Currency conversion handler
+pub async fn convert_amount_handler(service: &dyn ExternalApiServiceTrait, query: FxConvertQuery) -> Value {
+    match service.convert_amount(&query.from, &query.to, query.amount) {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to convert {} to {}: {}", query.from, query.to, e)
+        }),
+    }
+}
+
+/// Base currency handler
+pub async fn base_currency_handler(service: &dyn ExternalApiServiceTrait) -> Value {
+    match service.get_base_currency() {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get base currency: {}", e)
+        }),
+    }
+}
+
+/// Base currency history handler
+pub async fn base_currency_history_handler(service: &dyn ExternalApiServiceTrait) -> Value {
+    match service.get_base_currency_history() {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get base currency history: {}", e)
+        }),
+    }
+}
+
+/// Default account settings handler
+pub async fn default_account_handler(service: &dyn ExternalApiServiceTrait) -> Value {
+    match service.get_default_account() {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get default account: {}", e)
+        }),
+    }
+}
+
+/// Default account update handler
+pub async fn set_default_account_handler(
+    service: &dyn ExternalApiServiceTrait,
+    request: DefaultAccountRequest,
+) -> Value {
+    match service.set_default_account(request).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to set default account: {}", e)
+        }),
+    }
+}
+
+/// Integration credential status handler
+pub async fn config_status_handler(service: &dyn ExternalApiServiceTrait) -> Value {
+    match service.get_config_status().await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get config status: {}", e)
+        }),
+    }
+}
+
+/// Schema version of the `GET /api/export` document, bumped on breaking layout changes.
+pub const EXPORT_SCHEMA_VERSION: u32 = 1;
+
+/// Output formats for a portfolio export
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    /// One JSON document
+    Json,
+}
+
+impl ExportFormat {
+    pub fn parse(value: Option<&str>) -> Result<Self> {
+        match value.map(str::trim) {
+            None | Some("") | Some("json") => Ok(ExportFormat::Json),
+            Some("zip") => Err(Error::Validation(ValidationError::InvalidInput(
+                "format 'zip' is not supported yet, use 'json'".to_string(),
+            ))),
+            Some(other) => Err(Error::Validation(ValidationError::InvalidInput(format!(
+                "Unsupported format '{}', expected 'json'",
+                other
+            )))),
+        }
+    }
+}
+
+/// Export query parameters
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    /// `json` (default)
+    format: Option<String>,
+}
+
+/// Portfolio export handler
+pub async fn export_handler(service: &dyn ExternalApiServiceTrait, query: ExportQuery) -> Value {
+    let result = match ExportFormat::parse(query.format.as_deref()) {
+        Ok(ExportFormat::Json) => service.export_snapshot().await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to export portfolio: {}", e)
+        }),
+    }
+}
+
+/// Convert quote summaries to JSON format for external API
+pub fn quote_summaries_to_json(summaries: Vec<QuoteSummary>) -> Vec<Value> {
+    summaries.into_iter()
+        .map(|s| json!({
+            "symbol": s.symbol,
+            "exchange": s.exchange,
+            "name": s.short_name,
+            "type": s.quote_type
+        }))
+        .collect()
+}
+
+/// Asset classes accepted by the search `asset_class` filter, matched against the
+/// provider quote type.
+const SEARCH_ASSET_CLASSES: [&str; 9] = [
+    "EQUITY",
+    "ETF",
+    "MUTUALFUND",
+    "CRYPTOCURRENCY",
+    "FUTURE",
+    "INDEX",
+    "CURRENCY",
+    "OPTION",
+    "COMMODITY",
+];
+
+/// Parses a comma-separated `asset_class` filter, rejecting unknown classes.
+fn parse_search_asset_classes(value: &str) -> Result<Vec<&'static str>> {
+    let mut classes = Vec::new();
+    for name in value.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        let class = SEARCH_ASSET_CLASSES
+            .iter()
+            .find(|class| class.eq_ignore_ascii_case(name))
+            .ok_or_else(|| {
+                Error::Validation(ValidationError::InvalidInput(format!(
+                    "Unknown asset_class '{}', expected one of: {}",
+                    name,
+                    SEARCH_ASSET_CLASSES.join(", ").to_lowercase()
+                )))
+            })?;
+        classes.push(*class);
+    }
+    Ok(classes)
+}
+
+/// Number of candidates listed when a symbol query is ambiguous.
+const MAX_RESOLVE_CANDIDATES: usize = 5;
+
+/// Picks the search result to use for `query`, with a confidence between 0 and 1.
+/// An exact symbol or name match wins outright; otherwise the top result must score
+/// at least twice the runner-up. Returns `None` when there is no clear pick.
+pub fn best_symbol_match(query: &str, results: &[QuoteSummary]) -> Option<(usize, f64)> {
+    let query = query.trim();
+    if let Some(index) = results.iter().position(|r| {
+        r.symbol.eq_ignore_ascii_case(query)
+            || r.short_name.eq_ignore_ascii_case(query)
+            || r.long_name.eq_ignore_ascii_case(query)
+    }) {
+        return Some((index, 1.0));
+    }
+
+    let (top_index, top) = results
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.score.total_cmp(&b.score))?;
+    let runner_up = results
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| *index != top_index)
+        .map(|(_, r)| r.score)
+        .fold(0.0_f64, f64::max);
+
+    if results.len() == 1 {
+        return Some((top_index, 0.9));
+    }
+    if top.score <= 0.0 || top.score < runner_up * 2.0 {
+        return None;
+    }
+    let confidence = top.score / (top.score + runner_up);
+    Some((top_index, (confidence * 100.0).round() / 100.0))
+}
+
+/// Convert quote to JSON format for external API
+pub fn quote_to_json(quote: Quote, time_format: TimeFormat) -> Value {
+    json!({
+        "id": quote.id,
+        "symbol": quote.symbol,
+        "timestamp": time_format.render(quote.timestamp),
+        "open": quote.open,
+        "high": quote.high,
+        "low": quote.low,
+        "close": quote.close,
+        "volume": quote.volume,
+        "currency": quote.currency,
+        "dataSource": quote.data_source
+    })
+}
+
+/// Convert provider settings to the data sources available for a symbol, manual entry included
+pub fn data_sources_to_json(providers: &[MarketDataProviderSetting], active_source: &str) -> Vec<Value> {
+    let mut sources: Vec<Value> = providers
+        .iter()
+        .map(|p| json!({
+            "id": p.id,
+            "name": p.name,
+            "priority": p.priority,
+            "enabled": p.enabled,
+            "active": p.id == active_source
+        }))
+        .collect();
+    sources.push(json!({
+        "id": DataSource::Manual.as_str(),
+        "name": "Manual",
+        "priority": null,
+        "enabled": true,
+        "active": active_source == DataSource::Manual.as_str()
+    }));
+    sources
+}
+
+/// Whether a market data provider needs an API key before it can be used
+pub fn provider_requires_credential(provider_id: &str) -> bool {
+    provider_id != DATA_SOURCE_YAHOO && provider_id != DATA_SOURCE_MANUAL
+}
+
+/// Credential status for each provider. `has_credential` answers `None` when no secret
+/// store is available, which reports `configured: null`. Only booleans are emitted;
+/// secret values never reach this function.
+pub fn config_status_to_json(
+    providers: &[MarketDataProviderSetting],
+    has_credential: impl Fn(&str) -> Option<bool>,
+    secret_store_available: bool,
+) -> Value {
+    let mut missing = Vec::new();
+    let integrations: Vec<Value> = providers
+        .iter()
+        .map(|p| {
+            let requires_credential = provider_requires_credential(&p.id);
+            let configured = if requires_credential { has_credential(&p.id) } else { Some(true) };
+            if p.enabled && configured == Some(false) {
+                missing.push(p.id.clone());
+            }
+            json!({
+                "id": p.id,
+                "name": p.name,
+                "enabled": p.enabled,
+                "requiresCredential": requires_credential,
+                "configured": configured
+            })
+        })
+        .collect();
+    json!({
+        "secretStoreAvailable": secret_store_available,
+        "integrations": integrations,
+        "missing": missing
+    })
+}
+
+/// Profile age in days beyond which it is reported stale by default.
+pub const DEFAULT_PROFILE_STALE_DAYS: i64 = 90;
+
+/// How old an asset's stored profile is, judged by when the asset row was last
+/// written, and whether it carries the sector and country breakdowns.
+pub fn profile_freshness_to_json(asset: &Asset, now: DateTime<Utc>, stale_days: i64) -> Value {
+    let updated_at = asset.updated_at.and_utc();
+    let age_days = (now - updated_at).num_days().max(0);
+    let has_breakdown = |value: &Option<String>| {
+        value
+            .as_deref()
+            .is_some_and(|v| !matches!(v.trim(), "" | "[]" | "null"))
+    };
+    json!({
+        "symbol": asset.symbol,
+        "dataSource": asset.data_source,
+        "profileUpdatedAt": updated_at.to_rfc3339(),
+        "ageDays": age_days,
+        "staleAfterDays": stale_days,
+        "stale": age_days > stale_days,
+        "hasSectors": has_breakdown(&asset.sectors),
+        "hasCountries": has_breakdown(&asset.countries)
+    })
+}
+
+/// Sync status for each data source: the provider's recorded sync metadata, the
+/// number of non-cash assets configured to use it, and the newest stored quote it
+/// supplied. Sources used by assets but absent from the provider table (such as
+/// `MANUAL`) are listed after the providers with no sync metadata.
+pub fn sync_status_to_json(
+    providers: &[MarketDataProviderSetting],
+    assets: &[Asset],
+    latest_quotes: &HashMap<String, Quote>,
+) -> Vec<Value> {
+    let mut symbols: BTreeMap<String, usize> = BTreeMap::new();
+    for asset in assets {
+        *symbols.entry(asset.data_source.to_uppercase()).or_default() += 1;
+    }
+    let mut last_quote: HashMap<&str, NaiveDate> = HashMap::new();
+    for quote in latest_quotes.values() {
+        let date = quote.timestamp.date_naive();
+        let entry = last_quote.entry(quote.data_source.as_str()).or_insert(date);
+        *entry = (*entry).max(date);
+    }
+
+    let source = |id: &str, name: &str, enabled: Option<bool>, provider: Option<&MarketDataProviderSetting>| {
+        json!({
+            "source": id,
+            "name": name,
+            "enabled": enabled,
+            "lastSyncedAt": provider.and_then(|p| p.last_synced_at.clone()),
+            "lastSyncStatus": provider.and_then(|p| p.last_sync_status.clone()),
+            "lastError": provider.and_then(|p| p.last_sync_error.clone()),
+            "symbols": symbols.get(&id.to_uppercase()).copied().unwrap_or(0),
+            "lastQuoteDate": last_quote.get(id).map(|d| d.to_string())
+        })
+    };
+    let mut sources: Vec<Value> = providers
+        .iter()
+        .map(|p| source(&p.id, &p.name, Some(p.enabled), Some(p)))
+        .collect();
+    for id in symbols.keys() {
+        if !providers.iter().any(|p| p.id.eq_ignore_ascii_case(id)) {
+            sources.push(source(id, id, None, None));
+        }
+    }
+    sources
+}
+
+/// Recompute each holding's weight as its share of the given holdings' base market value
+pub fn rebase_holding_weights(holdings: &mut [Holding]) {
+    let total: Decimal = holdings.iter().map(|h| h.market_value.base).sum();
+    for holding in holdings.iter_mut() {
+        holding.weight = if total > Decimal::ZERO {
+            (holding.market_value.base / total).round_dp(4)
+        } else {
+            Decimal::ZERO
+        };
+    }
+}
+
+/// Rescales each quote's prices by its provider adjustment factor (`adjclose / close`),
+/// so splits and dividends no longer show as jumps. Quotes are only touched when every
+/// one carries an adjusted close; returns whether the series was adjusted.
+pub fn adjust_quotes(quotes: &mut [Quote]) -> bool {
+    if quotes.is_empty()
+        || quotes
+            .iter()
+            .any(|q| q.adjclose <= Decimal::ZERO || q.close <= Decimal::ZERO)
+    {
+        return false;
+    }
+    for quote in quotes.iter_mut() {
+        let factor = quote.adjclose / quote.close;
+        quote.open *= factor;
+        quote.high *= factor;
+        quote.low *= factor;
+        quote.close = quote.adjclose;
+    }
+    true
+}
+
+/// Lookback window for quote statistics
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StatsWindow {
+    OneMonth,
+    ThreeMonths,
+    OneYear,
+}
+
+impl StatsWindow {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "1m" => Ok(Self::OneMonth),
+            "3m" => Ok(Self::ThreeMonths),
+            "1y" => Ok(Self::OneYear),
+            other => Err(Error::Validation(ValidationError::InvalidInput(format!(
+                "Unsupported window '{}', expected 1m, 3m or 1y",
+                other
+            )))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::OneMonth => "1m",
+            Self::ThreeMonths => "3m",
+            Self::OneYear => "1y",
+        }
+    }
+
+    fn months(&self) -> u32 {
+        match self {
+            Self::OneMonth => 1,
+            Self::ThreeMonths => 3,
+            Self::OneYear => 12,
+        }
+    }
+}
+
+/// Close price summary over a window; `min` and `max` carry the date they were first hit
+#[derive(Debug, PartialEq)]
+pub struct QuoteStats {
+    pub min: (Decimal, NaiveDate),
+    pub max: (Decimal, NaiveDate),
+    pub average: Decimal,
+    pub count: usize,
+}
+
+/// Min, max and mean close of the quotes, or `None` when there are none
+pub fn quote_stats(quotes: &[Quote]) -> Option<QuoteStats> {
+    let mut sorted: Vec<&Quote> = quotes.iter().collect();
+    sorted.sort_by_key(|q| q.timestamp);
+    let first = sorted.first()?;
+    let mut min = (first.close, first.timestamp.date_naive());
+    let mut max = min;
+    let mut total = Decimal::ZERO;
+    for quote in &sorted {
+        if quote.close < min.0 {
+            min = (quote.close, quote.timestamp.date_naive());
+        }
+        if quote.close > max.0 {
+            max = (quote.close, quote.timestamp.date_naive());
+        }
+        total += quote.close;
+    }
+    Some(QuoteStats {
+        min,
+        max,
+        average: (total / Decimal::from(sorted.len())).round_dp(6),
+        count: sorted.len(),
+    })
+}
+
+/// Monday to Friday. No exchange holiday calendar is available, so holidays count as
+/// trading days.
+fn is_weekday(date: NaiveDate) -> bool {
+    date.weekday().number_from_monday() <= 5
+}
+
+/// Run of weekdays without a quote; `days` counts only the weekdays in it
+#[derive(Debug, PartialEq)]
+pub struct QuoteGap {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub days: usize,
+}
+
+/// Weekday runs between `from` and `to` (inclusive) with no quote date. Weekends
+/// neither count as missing nor split a gap.
+pub fn quote_gaps(dates: &BTreeSet<NaiveDate>, from: NaiveDate, to: NaiveDate) -> Vec<QuoteGap> {
+    let mut gaps = Vec::new();
+    let mut current: Option<QuoteGap> = None;
+    for date in from.iter_days().take_while(|d| *d <= to).filter(|d| is_weekday(*d)) {
+        if dates.contains(&date) {
+            gaps.extend(current.take());
+            continue;
+        }
+        match current.as_mut() {
+            Some(gap) => {
+                gap.to = date;
+                gap.days += 1;
+            }
+            None => {
+                current = Some(QuoteGap {
+                    from: date,
+                    to: date,
+                    days: 1,
+                })
+            }
+        }
+    }
+    gaps.extend(current);
+    gaps
+}
+
+/// Convert quotes to JSON format for external API
+pub fn quotes_to_json(quotes: Vec<Quote>, time_format: TimeFormat) -> Vec<Value> {
+    quotes.into_iter()
+        .map(|q| quote_to_json(q, time_format))
+        .collect()
+}
+
+/// Groups quotes by symbol within `from..=to`, oldest first. Every requested
+/// symbol gets an entry, empty when it has no quotes in the window.
+pub fn quote_history_by_symbol(
+    symbols: &[String],
+    quotes: Vec<Quote>,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> BTreeMap<String, Vec<Quote>> {
+    let mut history: BTreeMap<String, Vec<Quote>> =
+        symbols.iter().map(|symbol| (symbol.clone(), Vec::new())).collect();
+    for quote in quotes {
+        let date = quote.timestamp.date_naive();
+        if date < from || date > to {
+            continue;
+        }
+        if let Some(series) = history.get_mut(&quote.symbol) {
+            series.push(quote);
+        }
+    }
+    for series in history.values_mut() {
+        series.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+    }
+    history
+}
+
+/// Rebases a date-ordered quote series to its first close: `pctChange` is the
+/// fractional change since that quote, `null` throughout when it closed at zero.
+pub fn pct_change_series(quotes: &[Quote]) -> Vec<Value> {
+    let baseline = quotes.first().map(|q| q.close).unwrap_or_default();
+    quotes
+        .iter()
+        .map(|q| {
+            let pct_change = (!baseline.is_zero()).then(|| (q.close / baseline - Decimal::ONE).round_dp(6));
+            json!({
+                "date": q.timestamp.date_naive().to_string(),
+                "pctChange": pct_change
+            })
+        })
+        .collect()
+}
+
+/// Convert performance metrics to JSON format for external API
+pub fn performance_to_json(performance: PerformanceMetrics) -> Value {
+    json!({
+        "id": performance.id,
+        "currency": performance.currency,
+        "periodStartDate": performance.period_start_date.map(|d| d.to_string()),
+        "periodEndDate": performance.period_end_date.map(|d| d.to_string()),
+        "cumulativeTWR": performance.cumulative_twr,
+        "gainLossAmount": performance.gain_loss_amount,
+        "annualizedTWR": performance.annualized_twr,
+        "simpleReturn": performance.simple_return,
+        "annualizedSimpleReturn": performance.annualized_simple_return,
+        "volatility": performance.volatility,
+        "maxDrawdown": performance.max_drawdown
+    })
+}
+
+/// Convert simple performance metrics to JSON format for external API
+pub fn simple_performances_to_json(performances: Vec<SimplePerformanceMetrics>) -> Vec<Value> {
+    performances.into_iter()
+        .map(|p| json!({
+            "accountId": p.account_id,
+            "totalValue": p.total_value,
+            "accountCurrency": p.account_currency,
+            "baseCurrency": p.base_currency,
+            "fxRateToBase": p.fx_rate_to_base,
+            "totalGainLossAmount": p.total_gain_loss_amount,
+            "cumulativeReturnPercent": p.cumulative_return_percent,
+            "dayGainLossAmount": p.day_gain_loss_amount,
+            "dayReturnPercentModDietz": p.day_return_percent_mod_dietz,
+            "portfolioWeight": p.portfolio_weight
+        }))
+        .collect()
+}
+
+/// Convert activities to JSON format for external API
+pub fn activities_to_json(
+    activities: Vec<Activity>,
+    time_format: TimeFormat,
+    numbers: NumberFormat,
+    precision: &CurrencyPrecision,
+) -> Vec<Value> {
+    activities.into_iter()
+        .map(|a| activity_to_json(a, time_format, numbers, precision))
+        .collect()
+}
+
+/// Convert one activity to JSON format for external API
+pub fn activity_to_json(
+    a: Activity,
+    time_format: TimeFormat,
+    numbers: NumberFormat,
+    precision: &CurrencyPrecision,
+) -> Value {
+    let fee = numbers.render_amount(a.fee, &a.currency, precision);
+    let total_amount = a.amount.map(|amount| numbers.render_amount(amount, &a.currency, precision));
+    json!({
+        "id": a.id,
+        "accountId": a.account_id,
+        "activityType": a.activity_type,
+        "date": time_format.render(a.activity_date),
+        "assetId": a.asset_id,
+        "quantity": a.quantity,
+        "price": numbers.render(a.unit_price),
+        "currency": a.currency,
+        "fee": fee,
+        "totalAmount": total_amount
+    })
+}
+
+/// Quantity held after applying one activity to `net_quantity`. Activities that do
+/// not move units, such as dividends, leave it unchanged.
+pub fn net_quantity_after(net_quantity: Decimal, activity: &Activity) -> Decimal {
+    match activity.activity_type.as_str() {
+        activities::ACTIVITY_TYPE_BUY
+        | activities::ACTIVITY_TYPE_ADD_HOLDING
+        | activities::ACTIVITY_TYPE_TRANSFER_IN => net_quantity + activity.quantity,
+        activities::ACTIVITY_TYPE_SELL
+        | activities::ACTIVITY_TYPE_REMOVE_HOLDING
+        | activities::ACTIVITY_TYPE_TRANSFER_OUT => net_quantity - activity.quantity,
+        // The split ratio is carried in `amount`
+        activities::ACTIVITY_TYPE_SPLIT => match activity.amount.filter(|r| !r.is_zero()) {
+            Some(ratio) => net_quantity * ratio,
+            None => net_quantity,
+        },
+        _ => net_quantity,
+    }
+}
+
+/// Bucket an activity is grouped under: its asset, or `$CASH-<currency>` for cash movements
+fn activity_symbol_bucket(activity: &Activity) -> String {
+    if activity.asset_id.is_empty() || activity.asset_id.starts_with(CASH_ASSET_PREFIX) {
+        format!("{}-{}", CASH_ASSET_PREFIX, activity.currency)
+    } else {
+        activity.asset_id.clone()
+    }
+}
+
+/// Groups activities by symbol with the net quantity held and the total invested
+/// (purchase cost including fees, in the activity currency)
+pub fn activities_by_symbol_to_json(
+    mut history: Vec<Activity>,
+    time_format: TimeFormat,
+    numbers: NumberFormat,
+    precision: &CurrencyPrecision,
+) -> Vec<Value> {
+    history.sort_by_key(|a| a.activity_date);
+
+    let mut groups: BTreeMap<String, Vec<Activity>> = BTreeMap::new();
+    for activity in history {
+        groups.entry(activity_symbol_bucket(&activity)).or_default().push(activity);
+    }
+
+    groups
+        .into_iter()
+        .map(|(symbol, items)| {
+            let mut net_quantity = Decimal::ZERO;
+            let mut total_invested = Decimal::ZERO;
+            for activity in &items {
+                net_quantity = net_quantity_after(net_quantity, activity);
+                if activity.activity_type == activities::ACTIVITY_TYPE_BUY {
+                    total_invested += activity.quantity * activity.unit_price + activity.fee;
+                }
+            }
+            let currency = items.first().map(|a| a.currency.clone());
+            let total_invested =
+                numbers.render_amount(total_invested, currency.as_deref().unwrap_or_default(), precision);
+            json!({
+                "symbol": symbol,
+                "currency": currency,
+                "netQuantity": net_quantity,
+                "totalInvested": total_invested,
+                "activityCount": items.len(),
+                "activities": activities_to_json(items, time_format, numbers, precision)
+            })
+        })
+        .collect()
+}
+
+/// Default account update body; a null or empty `accountId` clears the default
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DefaultAccountRequest {
+    pub account_id: Option<String>,
+}
+
+/// Market data search query
+#[derive(Deserialize)]
+pub struct MarketDataSearchQuery {
+    q: String,
+    /// Comma-separated quote types to keep, e.g. `etf,equity`
+    asset_class: Option<String>,
+}
+
+/// Quote symbol parameter
+#[derive(Deserialize)]
+pub struct QuoteSymbolParam {
+    symbol: String,
+}
+
+/// Manual quote body
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManualQuoteRequest {
+    price: Decimal,
+    currency: String,
+    /// Quote date, defaults to today
+    date: Option<NaiveDate>,
+}
+
+impl ManualQuoteRequest {
+    fn validate(&self) -> Result<()> {
+        if self.price <= Decimal::ZERO {
+            return Err(Error::Validation(ValidationError::InvalidInput(
+                "price must be positive".to_string(),
+            )));
+        }
+        if self.currency.trim().is_empty() {
+            return Err(Error::Validation(ValidationError::InvalidInput(
+                "currency is required".to_string(),
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Preferred data source body
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataSourceUpdateRequest {
+    data_source: String,
+}
+
+/// Dimension used to bucket holdings into an allocation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AllocationGroup {
+    #[default]
+    AssetClass,
+    Symbol,
+}
+
+impl AllocationGroup {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "assetclass" | "asset_class" => Ok(Self::AssetClass),
+            "symbol" => Ok(Self::Symbol),
+            other => Err(Error::Validation(ValidationError::InvalidInput(format!(
+                "Unsupported group_by '{}', expected assetClass or symbol",
+                other
+            )))),
+        }
+    }
+}
+
+/// Base-currency market value per allocation bucket
+pub struct Allocation {
+    pub values: BTreeMap<String, Decimal>,
+    pub total: Decimal,
+}
+
+impl Allocation {
+    /// Bucket value as a fraction of the total
+    pub fn weight(&self, bucket: &str) -> Decimal {
+        match self.values.get(bucket) {
+            Some(value) if self.total > Decimal::ZERO => *value / self.total,
+            _ => Decimal::ZERO,
+        }
+    }
+}
+
+/// Bucket a holding falls into for the given grouping; cash is its own class
+pub fn allocation_bucket(holding: &Holding, group_by: AllocationGroup) -> String {
+    match (group_by, &holding.instrument) {
+        (AllocationGroup::AssetClass, Some(instrument)) => instrument
+            .asset_class
+            .clone()
+            .filter(|class| !class.trim().is_empty())
+            .unwrap_or_else(|| "UNCLASSIFIED".to_string()),
+        (AllocationGroup::AssetClass, None) => CASH_ASSET_CLASS.to_string(),
+        (AllocationGroup::Symbol, Some(instrument)) => instrument.symbol.clone(),
+        (AllocationGroup::Symbol, None) => format!("{}-{}", CASH_ASSET_PREFIX, holding.local_currency),
+    }
+}
+
+/// Day change summed over holdings, with the previous close value it is measured against
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DayChange {
+    pub amount: Decimal,
+    pub previous_value: Decimal,
+}
+
+impl DayChange {
+    pub fn add(&mut self, amount: Decimal, previous_value: Decimal) {
+        self.amount += amount;
+        self.previous_value += previous_value;
+    }
+
+    /// Change as a fraction of the previous close, like a holding's `dayChangePct`.
+    /// `None` when there was no previous value to measure against.
+    pub fn return_pct(&self) -> Option<Decimal> {
+        if self.previous_value.is_zero() {
+            return if self.amount.is_zero() { Some(Decimal::ZERO) } else { None };
+        }
+        Some((self.amount / self.previous_value.abs()).round_dp(4))
+    }
+}
+
+/// Distinct asset classes across holdings, each with its observed subclasses and
+/// holding counts. Classes follow the allocation buckets, so cash is its own class.
+pub fn asset_classes_to_json(holdings: &[Holding]) -> Vec<Value> {
+    let mut classes: BTreeMap<String, (usize, BTreeMap<String, usize>)> = BTreeMap::new();
+    for holding in holdings {
+        let entry = classes
+            .entry(allocation_bucket(holding, AllocationGroup::AssetClass))
+            .or_default();
+        entry.0 += 1;
+        let subclass = holding
+            .instrument
+            .as_ref()
+            .and_then(|i| i.asset_subclass.as_deref())
+            .map(str::trim)
+            .filter(|subclass| !subclass.is_empty());
+        if let Some(subclass) = subclass {
+            *entry.1.entry(subclass.to_string()).or_insert(0) += 1;
+        }
+    }
+
+    classes
+        .into_iter()
+        .map(|(class, (count, subclasses))| json!({
+            "assetClass": class,
+            "holdingCount": count,
+            "subclasses": subclasses
+                .into_iter()
+                .map(|(subclass, count)| json!({"assetSubclass": subclass, "holdingCount": count}))
+                .collect::<Vec<_>>()
+        }))
+        .collect()
+}
+
+/// Splits buckets into the `limit` largest by value, biggest first, and the rest
+pub fn top_buckets(allocation: &Allocation, limit: usize) -> (Vec<(&str, Decimal)>, Vec<(&str, Decimal)>) {
+    let mut buckets: Vec<(&str, Decimal)> = allocation
+        .values
+        .iter()
+        .map(|(bucket, value)| (bucket.as_str(), *value))
+        .collect();
+    buckets.sort_by(|a, b| b.1.cmp(&a.1));
+    let rest = buckets.split_off(limit.min(buckets.len()));
+    (buckets, rest)
+}
+
+/// Sums holdings' base-currency market value per bucket
+pub fn allocation_by(holdings: &[Holding], group_by: AllocationGroup) -> Allocation {
+    let mut values: BTreeMap<String, Decimal> = BTreeMap::new();
+    for holding in holdings {
+        *values
+            .entry(allocation_bucket(holding, group_by))
+            .or_insert(Decimal::ZERO) += holding.market_value.base;
+    }
+    let total = values.values().copied().sum();
+    Allocation { values, total }
+}
+
+/// Whether an instrument is a pooled fund (ETF or mutual fund), judged by its subclass
+pub fn is_fund(instrument: &Instrument) -> bool {
+    let subclass = instrument
+        .asset_subclass
+        .as_deref()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+    matches!(subclass.as_str(), "etf" | "mutualfund" | "fund")
+}
+
+/// Sums holdings' base-currency market value per sector. Securities with stored
+/// sector weights are split across those sectors, the weights normalized to sum to
+/// one; funds are only split when `look_through` is set. Anything else lands in its
+/// subclass, or its asset class when it has none. Returns the symbols of the funds
+/// that were looked through.
+pub fn sector_exposure(holdings: &[Holding], look_through: bool) -> (Allocation, Vec<String>) {
+    let mut values: BTreeMap<String, Decimal> = BTreeMap::new();
+    let mut looked_through = Vec::new();
+    for holding in holdings {
+        let value = holding.market_value.base;
+        let weights: Vec<(&str, Decimal)> = holding
+            .instrument
+            .as_ref()
+            .filter(|instrument| look_through || !is_fund(instrument))
+            .and_then(|instrument| instrument.sectors.as_ref())
+            .map(|sectors| {
+                sectors
+                    .iter()
+                    .filter(|sector| !sector.name.trim().is_empty())
+                    .filter_map(|sector| {
+                        Decimal::try_from(sector.weight)
+                            .ok()
+                            .filter(|weight| *weight > Decimal::ZERO)
+                            .map(|weight| (sector.name.trim(), weight))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let weight_sum: Decimal = weights.iter().map(|(_, weight)| *weight).sum();
+
+        if weight_sum.is_zero() {
+            let bucket = holding
+                .instrument
+                .as_ref()
+                .and_then(|instrument| instrument.asset_subclass.clone())
+                .filter(|subclass| !subclass.trim().is_empty())
+                .unwrap_or_else(|| allocation_bucket(holding, AllocationGroup::AssetClass));
+            *values.entry(bucket).or_insert(Decimal::ZERO) += value;
+            continue;
+        }
+
+        for (sector, weight) in weights {
+            *values.entry(sector.to_string()).or_insert(Decimal::ZERO) += value * weight / weight_sum;
+        }
+        if let Some(instrument) = holding.instrument.as_ref().filter(|i| is_fund(i)) {
+            if !looked_through.contains(&instrument.symbol) {
+                looked_through.push(instrument.symbol.clone());
+            }
+        }
+    }
+    let total = values.values().copied().sum();
+    (Allocation { values, total }, looked_through)
+}
+
+/// Moves a trade's base-currency value between an allocation's buckets, each given as
+/// `(bucket, change)`. Buckets left empty are dropped.
+pub fn apply_trade(
+    allocation: &Allocation,
+    security: (&str, Decimal),
+    cash: (&str, Decimal),
+) -> Allocation {
+    let mut values = allocation.values.clone();
+    for (bucket, change) in [security, cash] {
+        *values.entry(bucket.to_string()).or_insert(Decimal::ZERO) += change;
+    }
+    values.retain(|_, value| !value.is_zero());
+    let total = values.values().copied().sum();
+    Allocation { values, total }
+}
+
+/// Side of a simulated trade
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TradeSide {
+    Buy,
+    Sell,
+}
+
+/// Hypothetical trade to simulate against current holdings
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulateTradeRequest {
+    symbol: String,
+    side: TradeSide,
+    quantity: Decimal,
+    price: Decimal,
+    fee: Option<Decimal>,
+    /// Trade currency; defaults to the position's or the asset's currency
+    currency: Option<String>,
+    account_id: Option<String>,
+    #[serde(default)]
+    group_by: AllocationGroup,
+}
+
+impl SimulateTradeRequest {
+    fn validate(&self) -> std::result::Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        if self.symbol.trim().is_empty() {
+            errors.push(FieldError::new("symbol", "is required"));
+        }
+        if self.quantity <= Decimal::ZERO {
+            errors.push(FieldError::new("quantity", "must be positive"));
+        }
+        if self.price <= Decimal::ZERO {
+            errors.push(FieldError::new("price", "must be positive"));
+        }
+        if self.fee.is_some_and(|fee| fee.is_sign_negative()) {
+            errors.push(FieldError::new("fee", "must not be negative"));
+        }
+        into_validation_result(errors)
+    }
+}
+
+/// Rebalance body: target weights per bucket as fractions summing to 1
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RebalanceRequest {
+    targets: HashMap<String, Decimal>,
+    #[serde(default)]
+    group_by: AllocationGroup,
+    account_id: Option<String>,
+}
+
+impl RebalanceRequest {
+    fn validate(&self) -> std::result::Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        if self.targets.is_empty() {
+            errors.push(FieldError::new("targets", "must contain at least one bucket"));
+        }
+        for (bucket, weight) in &self.targets {
+            if weight.is_sign_negative() || *weight > Decimal::ONE {
+                errors.push(FieldError::new(
+                    &format!("targets.{}", bucket),
+                    "must be between 0 and 1",
+                ));
+            }
+        }
+        let sum: Decimal = self.targets.values().copied().sum();
+        if !self.targets.is_empty() && (sum - Decimal::ONE).abs() > dec!(0.0001) {
+            errors.push(FieldError::new(
+                "targets",
+                format!("weights must sum to 1 (100%), got {}", sum),
+            ));
+        }
+        into_validation_result(errors)
+    }
+}
+
+/// Allocation history query
+#[derive(Deserialize)]
+pub struct AllocationHistoryQuery {
+    /// `assetClass` (default) or `symbol`
+    group_by: Option<String>,
+    /// `monthly` (default), `quarterly` or `yearly`
+    interval: Option<String>,
+    /// Inclusive start date (YYYY-MM-DD), defaults to a year before `to`
+    from: Option<String>,
+    /// Inclusive end date (YYYY-MM-DD), defaults to today
+    to: Option<String>,
+}
+
+/// Gains by period query
+#[derive(Deserialize)]
+pub struct GainsQuery {
+    account_id: Option<String>,
+    /// `monthly`, `quarterly` (default) or `yearly`
+    interval: Option<String>,
+}
+
+/// Bucket size for period reports
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GainsInterval {
+    Monthly,
+    Quarterly,
+    Yearly,
+}
+
+impl GainsInterval {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "monthly" => Ok(Self::Monthly),
+            "quarterly" => Ok(Self::Quarterly),
+            "yearly" => Ok(Self::Yearly),
+            other => Err(Error::Validation(ValidationError::InvalidInput(format!(
+                "Unsupported interval '{}', expected monthly, quarterly or yearly",
+                other
+            )))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Monthly => "monthly",
+            Self::Quarterly => "quarterly",
+            Self::Yearly => "yearly",
+        }
+    }
+
+    fn months(&self) -> u32 {
+        match self {
+            Self::Monthly => 1,
+            Self::Quarterly => 3,
+            Self::Yearly => 12,
+        }
+    }
+
+    /// First day of the period containing `date`
+    pub fn period_start(&self, date: NaiveDate) -> NaiveDate {
+        let month = match self {
+            Self::Monthly => date.month(),
+            Self::Quarterly => (date.month() - 1) / 3 * 3 + 1,
+            Self::Yearly => 1,
+        };
+        NaiveDate::from_ymd_opt(date.year(), month, 1).unwrap_or(date)
+    }
+
+    pub fn next_period_start(&self, period_start: NaiveDate) -> NaiveDate {
+        period_start
+            .checked_add_months(Months::new(self.months()))
+            .unwrap_or(NaiveDate::MAX)
+    }
+
+    /// Label such as `2024-03`, `2024-Q1` or `2024`
+    pub fn label(&self, period_start: NaiveDate) -> String {
+        match self {
+            Self::Monthly => period_start.format("%Y-%m").to_string(),
+            Self::Quarterly => format!("{}-Q{}", period_start.year(), (period_start.month() - 1) / 3 + 1),
+            Self::Yearly => period_start.year().to_string(),
+        }
+    }
+}
+
+/// Most points an allocation history request may compute; each point values
+/// every account's holdings on that date.
+pub const MAX_ALLOCATION_POINTS: usize = 120;
+
+/// Last day of each period between `from` and `to`, ending with `to` itself when the
+/// range stops partway through a period.
+pub fn interval_boundaries(from: NaiveDate, to: NaiveDate, interval: GainsInterval) -> Vec<NaiveDate> {
+    let mut dates = Vec::new();
+    let mut period_start = interval.period_start(from);
+    while period_start <= to {
+        let next = interval.next_period_start(period_start);
+        let period_end = next.pred_opt().unwrap_or(next).min(to);
+        if period_end >= from {
+            dates.push(period_end);
+        }
+        if next == NaiveDate::MAX {
+            break;
+        }
+        period_start = next;
+    }
+    dates
+}
+
+/// Days a deposit may land after (or before) the matching withdrawal
+pub const TRANSFER_MATCH_DAYS: i64 = 3;
+
+/// One side of a cash movement between accounts
+#[derive(Clone, Debug)]
+pub struct TransferLeg {
+    pub activity_id: String,
+    pub account_id: String,
+    pub activity_type: String,
+    pub date: NaiveDate,
+    pub currency: String,
+    pub amount: Decimal,
+}
+
+impl TransferLeg {
+    fn to_json(&self) -> Value {
+        json!({
+            "activityId": self.activity_id,
+            "accountId": self.account_id,
+            "activityType": self.activity_type,
+            "date": self.date,
+            "currency": self.currency,
+            "amount": self.amount
+        })
+    }
+}
+
+/// Pairs each outflow with an inflow into a different account of the same currency and
+/// amount (to the cent) within `TRANSFER_MATCH_DAYS`, preferring the closest date. Each
+/// leg is used at most once; returns `(outflow, inflow)` index pairs.
+pub fn match_transfers(outflows: &[TransferLeg], inflows: &[TransferLeg]) -> Vec<(usize, usize)> {
+    let mut used = vec![false; inflows.len()];
+    let mut pairs = Vec::new();
+    for (out_index, outflow) in outflows.iter().enumerate() {
+        let candidate = inflows
+            .iter()
+            .enumerate()
+            .filter(|(i, inflow)| {
+                !used[*i]
+                    && inflow.account_id != outflow.account_id
+                    && inflow.currency == outflow.currency
+                    && (inflow.amount - outflow.amount).abs() < dec!(0.01)
+                    && (inflow.date - outflow.date).num_days().abs() <= TRANSFER_MATCH_DAYS
+            })
+            .min_by_key(|(_, inflow)| (inflow.date - outflow.date).num_days().abs());
+        if let Some((in_index, _)) = candidate {
+            used[in_index] = true;
+            pairs.push((out_index, in_index));
+        }
+    }
+    pairs
+}
+
+/// Fees summed in each activity currency and in base currency
+#[derive(Default)]
+struct FeeTotals {
+    base: Decimal,
+    local: BTreeMap<String, Decimal>,
+    periods: BTreeMap<NaiveDate, FeeTotals>,
+}
+
+impl FeeTotals {
+    fn add(&mut self, currency: &str, fee: Decimal, fee_base: Decimal) {
+        self.base += fee_base;
+        *self.local.entry(currency.to_string()).or_insert(Decimal::ZERO) += fee;
+    }
+}
+
+/// Splits a daily cumulative TWR curve into `(periodEnd, periodReturn, cumulativeReturn)`
+/// rows, one per interval. Period returns are geometric, so chaining them reproduces
+/// the final cumulative return.
+pub fn twr_series(returns: &[ReturnData], interval: GainsInterval) -> Vec<(NaiveDate, Decimal, Decimal)> {
+    // Last point of each period, keyed by the period start
+    let mut period_ends: BTreeMap<NaiveDate, &ReturnData> = BTreeMap::new();
+    for point in returns {
+        period_ends.insert(interval.period_start(point.date), point);
+    }
+
+    let mut previous = Decimal::ZERO;
+    period_ends
+        .into_values()
+        .map(|point| {
+            let growth = Decimal::ONE + previous;
+            let period_return = if growth.is_zero() {
+                Decimal::ZERO
+            } else {
+                (Decimal::ONE + point.value) / growth - Decimal::ONE
+            };
+            previous = point.value;
+            (point.date, period_return.round_dp(6), point.value)
+        })
+        .collect()
+}
+
+/// Fewest overlapping daily returns accepted for a beta regression.
+pub const MIN_BETA_SAMPLES: usize = 20;
+
+/// Fewest daily returns a historical VaR is computed from
+pub const MIN_VAR_SAMPLES: usize = 20;
+
+/// Default VaR confidence level
+pub const DEFAULT_VAR_CONFIDENCE: f64 = 0.95;
+
+/// Historical Value-at-Risk as a fraction of portfolio value: the loss at the
+/// `1 - confidence` quantile of the daily returns (lower empirical quantile),
+/// scaled to `horizon_days` by the square root of time. Never below zero, since a
+/// quantile that is still a gain puts nothing at risk.
+pub fn historical_var(daily_returns: &[Decimal], confidence: Decimal, horizon_days: u32) -> Decimal {
+    if daily_returns.is_empty() {
+        return Decimal::ZERO;
+    }
+    let mut sorted = daily_returns.to_vec();
+    sorted.sort();
+    let tail = ((Decimal::ONE - confidence) * Decimal::from(sorted.len()))
+        .floor()
+        .to_usize()
+        .unwrap_or(0)
+        .min(sorted.len() - 1);
+    let one_day = (-sorted[tail]).max(Decimal::ZERO);
+    let scale = Decimal::from(horizon_days).sqrt().unwrap_or(Decimal::ONE);
+    (one_day * scale).round_dp(6)
+}
+
+/// Fewest shared daily returns a correlation matrix is computed from
+pub const MIN_CORRELATION_SAMPLES: usize = 20;
+
+/// Pairwise return correlations over the dates every kept symbol has a return for
+#[derive(Debug, PartialEq)]
+pub struct CorrelationMatrix {
+    pub symbols: Vec<String>,
+    /// Row and column order follows `symbols`; `None` where a symbol's returns never vary
+    pub matrix: Vec<Vec<Option<Decimal>>>,
+    /// Number of shared daily returns each correlation is based on
+    pub samples: usize,
+    /// Symbols left out for lack of overlapping history, with their own return count
+    pub dropped: Vec<(String, usize)>,
+}
+
+/// Daily close-to-close returns keyed by the later date of each pair of quotes
+fn daily_returns(closes: &BTreeMap<NaiveDate, Decimal>) -> BTreeMap<NaiveDate, Decimal> {
+    closes
+        .iter()
+        .zip(closes.iter().skip(1))
+        .filter(|((_, previous), _)| !previous.is_zero())
+        .map(|((_, previous), (date, close))| (*date, close / previous - Decimal::ONE))
+        .collect()
+}
+
+/// Pearson correlation of two equally long return series
+fn pearson(x: &[Decimal], y: &[Decimal]) -> Option<Decimal> {
+    let n = Decimal::from(x.len());
+    let mean_x = x.iter().sum::<Decimal>() / n;
+    let mean_y = y.iter().sum::<Decimal>() / n;
+    let mut covariance = Decimal::ZERO;
+    let mut variance_x = Decimal::ZERO;
+    let mut variance_y = Decimal::ZERO;
+    for (x, y) in x.iter().zip(y) {
+        let (dx, dy) = (*x - mean_x, *y - mean_y);
+        covariance += dx * dy;
+        variance_x += dx * dx;
+        variance_y += dy * dy;
+    }
+    let denominator = (variance_x * variance_y).sqrt()?;
+    if denominator.is_zero() {
+        return None;
+    }
+    Some((covariance / denominator).round_dp(4))
+}
+
+/// Correlates daily returns derived from each symbol's closes. While the symbols share
+/// fewer than `MIN_CORRELATION_SAMPLES` return dates, the one with the shortest history
+/// is dropped, so the matrix is computed over a common window.
+pub fn correlation_matrix(closes: &BTreeMap<String, BTreeMap<NaiveDate, Decimal>>) -> CorrelationMatrix {
+    let mut returns: Vec<(String, BTreeMap<NaiveDate, Decimal>)> = closes
+        .iter()
+        .map(|(symbol, series)| (symbol.clone(), daily_returns(series)))
+        .collect();
+    let mut dropped = Vec::new();
+    let shared_dates = loop {
+        let shared: BTreeSet<NaiveDate> = match returns.split_first() {
+            Some(((_, first), rest)) => first
+                .keys()
+                .filter(|date| rest.iter().all(|(_, r)| r.contains_key(*date)))
+                .copied()
+                .collect(),
+            None => BTreeSet::new(),
+        };
+        if shared.len() >= MIN_CORRELATION_SAMPLES || returns.is_empty() {
+            break shared;
+        }
+        let shortest = returns
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, (symbol, r))| (r.len(), symbol.clone()))
+            .map(|(i, _)| i)
+            .unwrap_or_default();
+        let (symbol, r) = returns.remove(shortest);
+        dropped.push((symbol, r.len()));
+    };
+
+    let aligned: Vec<Vec<Decimal>> = returns
+        .iter()
+        .map(|(_, r)| shared_dates.iter().map(|date| r[date]).collect())
+        .collect();
+    let matrix = (0..aligned.len())
+        .map(|i| {
+            (0..aligned.len())
+                .map(|j| if i == j { Some(Decimal::ONE) } else { pearson(&aligned[i], &aligned[j]) })
+                .collect()
+        })
+        .collect();
+
+    CorrelationMatrix {
+        symbols: returns.into_iter().map(|(symbol, _)| symbol).collect(),
+        matrix,
+        samples: shared_dates.len(),
+        dropped,
+    }
+}
+
+/// Trailing windows reported by the annualized returns endpoint, in years
+pub const ANNUALIZED_WINDOWS: &[(&str, u32)] = &[("1Y", 1), ("3Y", 3), ("5Y", 5)];
+
+/// Start of the trailing `years` window ending at `as_of`, or `None` when it would
+/// reach back before `inception` and only the since-inception figure is available.
+pub fn annualized_window_start(as_of: NaiveDate, years: u32, inception: NaiveDate) -> Option<NaiveDate> {
+    let start = as_of.checked_sub_months(Months::new(12 * years))?;
+    (start >= inception).then_some(start)
+}
+
+/// Window two accounts can be compared over: the requested range clipped to the
+/// `(start, end)` spans both accounts have history for. `None` when nothing overlaps.
+pub fn comparison_window(
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    a: (NaiveDate, NaiveDate),
+    b: (NaiveDate, NaiveDate),
+) -> Option<(NaiveDate, NaiveDate)> {
+    let start = a.0.max(b.0).max(from.unwrap_or(NaiveDate::MIN));
+    let end = a.1.min(b.1).min(to.unwrap_or(NaiveDate::MAX));
+    (start <= end).then_some((start, end))
+}
+
+/// Excess annualized return per unit of annualized volatility, or `None` when the
+/// series has no volatility
+pub fn sharpe_ratio(annualized_return: Decimal, volatility: Decimal, risk_free_rate: Decimal) -> Option<Decimal> {
+    if volatility <= Decimal::ZERO {
+        return None;
+    }
+    Some(((annualized_return - risk_free_rate) / volatility).round_dp(4))
+}
+
+/// Regresses portfolio daily returns on benchmark daily returns, both derived from
+/// cumulative return curves on the dates the two curves share. Returns beta, daily
+/// alpha, R² and the number of daily returns used.
+pub fn beta_regression(
+    portfolio: &[ReturnData],
+    benchmark: &[ReturnData],
+) -> Result<(Decimal, Decimal, Decimal, usize)> {
+    let benchmark_curve: HashMap<NaiveDate, Decimal> =
+        benchmark.iter().map(|point| (point.date, point.value)).collect();
+    let aligned: Vec<(Decimal, Decimal)> = portfolio
+        .iter()
+        .filter_map(|point| benchmark_curve.get(&point.date).map(|b| (*b, point.value)))
+        .collect();
+
+    // (benchmark return, portfolio return) between consecutive shared dates
+    let pairs: Vec<(Decimal, Decimal)> = aligned
+        .windows(2)
+        .filter_map(|window| {
+            let (b0, p0) = window[0];
+            let (b1, p1) = window[1];
+            let (b_growth, p_growth) = (Decimal::ONE + b0, Decimal::ONE + p0);
+            if b_growth.is_zero() || p_growth.is_zero() {
+                return None;
+            }
+            Some((
+                (Decimal::ONE + b1) / b_growth - Decimal::ONE,
+                (Decimal::ONE + p1) / p_growth - Decimal::ONE,
+            ))
+        })
+        .collect();
+
+    if pairs.len() < MIN_BETA_SAMPLES {
+        return Err(Error::Validation(ValidationError::InvalidInput(format!(
+            "At least {} overlapping daily returns are needed, found {}; widen the date range or sync the benchmark's quotes",
+            MIN_BETA_SAMPLES,
+            pairs.len()
+        ))));
+    }
+
+    let n = Decimal::from(pairs.len());
+    let mean_x = pairs.iter().map(|(x, _)| *x).sum::<Decimal>() / n;
+    let mean_y = pairs.iter().map(|(_, y)| *y).sum::<Decimal>() / n;
+    let mut covariance = Decimal::ZERO;
+    let mut variance_x = Decimal::ZERO;
+    let mut variance_y = Decimal::ZERO;
+    for (x, y) in &pairs {
+        let (dx, dy) = (*x - mean_x, *y - mean_y);
+        covariance += dx * dy;
+        variance_x += dx * dx;
+        variance_y += dy * dy;
+    }
+    if variance_x.is_zero() {
+        return Err(Error::Validation(ValidationError::InvalidInput(
+            "Benchmark returns do not vary over the window".to_string(),
+        )));
+    }
+
+    let beta = covariance / variance_x;
+    let alpha = mean_y - beta * mean_x;
+    let r_squared = if variance_y.is_zero() {
+        Decimal::ZERO
+    } else {
+        covariance * covariance / (variance_x * variance_y)
+    };
+    Ok((beta.round_dp(4), alpha.round_dp(6), r_squared.round_dp(4), pairs.len()))
+}
+
+/// One position's values and cash flows (base currency) over a contribution period
+pub struct ContributionPosition {
+    pub symbol: String,
+    pub start_value: Decimal,
+    pub end_value: Decimal,
+    /// Money put into the position during the period
+    pub purchases: Decimal,
+    /// Purchases less sale proceeds and dividends received
+    pub net_flow: Decimal,
+}
+
+impl ContributionPosition {
+    pub fn new(symbol: &str) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            start_value: Decimal::ZERO,
+            end_value: Decimal::ZERO,
+            purchases: Decimal::ZERO,
+            net_flow: Decimal::ZERO,
+        }
+    }
+}
+
+/// Each position's contribution to the period return, largest first, and the total
+/// return. A position's weight is its starting value plus purchases over the capital
+/// at work (all positions plus starting cash), and its return is its gain over that
+/// same capital, so `weight × return` is its gain over the capital at work.
+pub fn return_contributions(
+    positions: Vec<ContributionPosition>,
+    cash_start: Decimal,
+) -> (Vec<Value>, Decimal) {
+    let invested = |p: &ContributionPosition| p.start_value + p.purchases;
+    let capital: Decimal = positions.iter().map(invested).sum::<Decimal>() + cash_start;
+    let ratio = |value: Decimal, total: Decimal| {
+        if total > Decimal::ZERO {
+            value / total
+        } else {
+            Decimal::ZERO
+        }
+    };
+
+    let mut rows: Vec<(Decimal, Value)> = positions
+        .into_iter()
+        .filter(|p| !(p.start_value.is_zero() && p.end_value.is_zero() && p.net_flow.is_zero()))
+        .map(|p| {
+            let gain = p.end_value - p.start_value - p.net_flow;
+            let contribution = ratio(gain, capital);
+            let row = json!({
+                "symbol": p.symbol,
+                "startValue": p.start_value.round_dp(2),
+                "endValue": p.end_value.round_dp(2),
+                "netFlow": p.net_flow.round_dp(2),
+                "gain": gain.round_dp(2),
+                "weight": ratio(invested(&p), capital).round_dp(4),
+                "return": ratio(gain, invested(&p)).round_dp(4),
+                "contribution": contribution.round_dp(6)
+            });
+            (contribution, row)
+        })
+        .collect();
+    rows.sort_by(|a, b| b.0.cmp(&a.0));
+
+    let total_return: Decimal = rows.iter().map(|(contribution, _)| *contribution).sum();
+    (
+        rows.into_iter().map(|(_, row)| row).collect(),
+        total_return.round_dp(6),
+    )
+}
+
+/// Horizons reported by the value changes endpoint, shortest first
+pub const VALUE_HORIZONS: &[&str] = &["1D", "1W", "1M", "YTD", "1Y", "ALL"];
+
+/// Date a value-change horizon looks back to from `as_of`. `YTD` compares against the
+/// last day of the previous year; `ALL` has no fixed date and starts at inception.
+pub fn horizon_start(horizon: &str, as_of: NaiveDate) -> Option<NaiveDate> {
+    match horizon {
+        "1D" => as_of.pred_opt(),
+        "1W" => as_of.checked_sub_signed(chrono::Duration::days(7)),
+        "1M" => as_of.checked_sub_months(Months::new(1)),
+        "YTD" => NaiveDate::from_ymd_opt(as_of.year() - 1, 12, 31),
+        "1Y" => as_of.checked_sub_months(Months::new(12)),
+        _ => None,
+    }
+}
+
+/// Value on the last date on or before `target` in a date-sorted series. Targets before
+/// the first point fall back to it and are flagged as before inception.
+pub fn value_at_horizon(values: &[(NaiveDate, Decimal)], target: NaiveDate) -> (NaiveDate, Decimal, bool) {
+    let (inception_date, inception_value) = values[0];
+    match values.iter().rev().find(|(date, _)| *date <= target) {
+        Some(&(date, value)) => (date, value, false),
+        None => (inception_date, inception_value, target < inception_date),
+    }
+}
+
+/// Days of income counted by the trailing yield.
+pub const YIELD_WINDOW_DAYS: i64 = 365;
+
+/// Yield of `income` over `value`. When the position has only been held for
+/// `held_days` (less than the yield window), the income is scaled up to a full
+/// year and the result is flagged as annualized. Returns no yield for a zero value.
+pub fn trailing_yield(income: Decimal, value: Decimal, held_days: Option<i64>) -> (Option<Decimal>, bool) {
+    if value <= Decimal::ZERO {
+        return (None, false);
+    }
+    match held_days {
+        Some(days) => {
+            let annual_income =
+                income * Decimal::from(YIELD_WINDOW_DAYS) / Decimal::from(days.max(1));
+            (Some((annual_income / value).round_dp(6)), true)
+        }
+        None => (Some((income / value).round_dp(6)), false),
+    }
+}
+
+/// Re-reads the security holdings through `get_holding`, the only holdings call that
+/// fills in open lots; `get_holdings` leaves `lots` empty. Cash is dropped, and
+/// positions that fail to load are skipped and reported in the partial errors.
+pub async fn holdings_with_lots(
+    holdings_service: &dyn HoldingsServiceTrait,
+    holdings: &[Holding],
+    base_currency: &str,
+) -> (Vec<Holding>, Vec<Value>) {
+    let mut with_lots = Vec::new();
+    let mut partial_errors = Vec::new();
+    for holding in holdings.iter().filter(|h| h.holding_type == HoldingType::Security) {
+        let Some(instrument) = holding.instrument.as_ref() else {
+            continue;
+        };
+        match holdings_service
+            .get_holding(&holding.account_id, &instrument.id, base_currency)
+            .await
+        {
+            Ok(Some(position)) => with_lots.push(position),
+            Ok(None) => {}
+            Err(e) => {
+                tracing::warn!(
+                    account_id = %holding.account_id,
+                    symbol = %instrument.symbol,
+                    error = %e,
+                    "Failed to load lots for holding"
+                );
+                partial_errors.push(json!({
+                    "accountId": holding.account_id,
+                    "symbol": instrument.symbol,
+                    "error": e.to_string()
+                }));
+            }
+        }
+    }
+    (with_lots, partial_errors)
+}
+
+/// Replays `history` (oldest first) through the core lot engine, one `Position` per
+/// account and asset: buys, added holdings and transfers in open lots, sells,
+/// removals and transfers out relieve them FIFO, and splits rescale the open lots.
+/// Only sells realize a gain, against the cost basis of the lots they relieve.
+/// `convert(amount, from, to, date)` moves amounts into the position currency and
+/// the gains into `base_currency`. Returns every sale, the quantity left in each
+/// position, and a partial error for each activity that could not be replayed. A
+/// sale whose gain cannot be converted still relieves its lots but is left out.
+fn realize_lots(
+    history: &[Activity],
+    base_currency: &str,
+    convert: impl Fn(Decimal, &str, &str, NaiveDate) -> Result<Decimal>,
+) -> (Vec<RealizedSale>, HashMap<(String, String), Decimal>, Vec<Value>) {
+    let mut realized = Vec::new();
+    let mut partial_errors = Vec::new();
+    let mut positions: HashMap<(String, String), Position> = HashMap::new();
+    for activity in history {
+        let date = activity.activity_date.date_naive();
+        let position = positions
+            .entry((activity.account_id.clone(), activity.asset_id.clone()))
+            .or_insert_with(|| {
+                Position::new(
+                    activity.account_id.clone(),
+                    activity.asset_id.clone(),
+                    activity.currency.clone(),
+                    activity.activity_date,
+                )
+            });
+        let to_position_currency = |amount: Decimal| {
+            if position.currency == activity.currency {
+                Ok(amount)
+            } else {
+                convert(amount, &activity.currency, &position.currency, date)
+            }
+        };
+        let position_prices = || -> Result<(Decimal, Decimal)> {
+            Ok((
+                to_position_currency(activity.unit_price)?,
+                to_position_currency(activity.fee)?,
+            ))
+        };
+
+        let outcome = match activity.activity_type.as_str() {
+            activities::ACTIVITY_TYPE_BUY
+            | activities::ACTIVITY_TYPE_ADD_HOLDING
+            | activities::ACTIVITY_TYPE_TRANSFER_IN => position_prices().and_then(|(unit_price, fee)| {
+                let mut lot_activity = activity.clone();
+                lot_activity.unit_price = unit_price;
+                lot_activity.fee = fee;
+                lot_activity.currency = position.currency.clone();
+                position.add_lot(&lot_activity).map(|_| ())
+            }),
+            activities::ACTIVITY_TYPE_SELL => {
+                let prices = position_prices();
+                position
+                    .reduce_lots_fifo(activity.quantity)
+                    .and_then(|(quantity, cost_basis)| {
+                        let (unit_price, fee) = prices?;
+                        let gain = quantity * unit_price - fee - cost_basis;
+                        realized.push(RealizedSale {
+                            account_id: activity.account_id.clone(),
+                            asset_id: activity.asset_id.clone(),
+                            currency: position.currency.clone(),
+                            date,
+                            gain,
+                            gain_base: convert(gain, &position.currency, base_currency, date)?,
+                        });
+                        Ok(())
+                    })
+            }
+            activities::ACTIVITY_TYPE_REMOVE_HOLDING | activities::ACTIVITY_TYPE_TRANSFER_OUT => {
+                position.reduce_lots_fifo(activity.quantity).map(|_| ())
+            }
+            activities::ACTIVITY_TYPE_SPLIT => match activity.amount {
+                Some(ratio) if ratio > Decimal::ZERO => position.apply_split(ratio, &activity.id),
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        };
+        if let Err(e) = outcome {
+            tracing::warn!(
+                activity_id = %activity.id,
+                error = %e,
+                "Skipping activity in realized gain replay"
+            );
+            partial_errors.push(json!({
+                "accountId": activity.account_id,
+                "activityId": activity.id,
+                "error": e.to_string()
+            }));
+        }
+    }
+    let remaining = positions
+        .into_iter()
+        .map(|(key, position)| (key, position.quantity))
+        .collect();
+    (realized, remaining, partial_errors)
+}
+
+/// Whether a lot acquired on `acquired` has been held for more than one year
+/// on `as_of`. A lot bought on Feb 29 turns long-term after Feb 28 of the next year.
+pub fn is_long_term(acquired: NaiveDate, as_of: NaiveDate) -> bool {
+    acquired
+        .checked_add_months(Months::new(12))
+        .is_some_and(|anniversary| as_of > anniversary)
+}
+
+/// Return contribution query
+#[derive(Deserialize)]
+pub struct ContributionQuery {
+    account_id: Option<String>,
+    /// Inclusive start date (YYYY-MM-DD), defaults to January 1 of `to`'s year
+    from: Option<String>,
+    /// Inclusive end date (YYYY-MM-DD), defaults to today
+    to: Option<String>,
+}
+
+/// Correlation matrix query
+#[derive(Deserialize)]
+pub struct CorrelationQuery {
+    account_id: Option<String>,
+    /// Inclusive start date (YYYY-MM-DD), defaults to one year before `to`
+    from: Option<String>,
+    /// Inclusive end date (YYYY-MM-DD), defaults to today
+    to: Option<String>,
+}
+
+/// Sharpe ratio query
+#[derive(Deserialize)]
+pub struct SharpeQuery {
+    /// Account to measure; the default account or the whole portfolio when omitted
+    account_id: Option<String>,
+    /// Inclusive start date (YYYY-MM-DD)
+    from: Option<String>,
+    /// Inclusive end date (YYYY-MM-DD)
+    to: Option<String>,
+    /// Annual risk-free rate as a fraction, e.g. `0.02`; defaults to zero
+    risk_free_rate: Option<f64>,
+}
+
+/// Value-at-Risk query
+#[derive(Deserialize)]
+pub struct VarQuery {
+    /// Account to measure; the default account or the whole portfolio when omitted
+    account_id: Option<String>,
+    /// Confidence level as a fraction, e.g. `0.99`; defaults to 0.95
+    confidence: Option<f64>,
+    /// Holding period in days; defaults to 1
+    horizon: Option<u32>,
+    /// Start of the return history (YYYY-MM-DD); all history when omitted
+    from: Option<String>,
+}
+
+/// Beta query
+#[derive(Deserialize)]
+pub struct BetaQuery {
+    /// Account to measure; the default account or the whole portfolio when omitted
+    account_id: Option<String>,
+    /// Benchmark symbol, e.g. `SPY`
+    benchmark: String,
+    /// Inclusive start date (YYYY-MM-DD)
+    from: Option<String>,
+    /// Inclusive end date (YYYY-MM-DD)
+    to: Option<String>,
+}
+
+/// TWR series query
+#[derive(Deserialize)]
+pub struct TwrQuery {
+    /// Inclusive start date (YYYY-MM-DD)
+    from: Option<String>,
+    /// Inclusive end date (YYYY-MM-DD)
+    to: Option<String>,
+    /// `monthly` (default), `quarterly` or `yearly`
+    interval: Option<String>,
+}
+
+/// Portfolio performance summary query
+#[derive(Deserialize)]
+pub struct PerformanceSummaryQuery {
+    /// Comma-separated account ids left out of the aggregation
+    exclude_accounts: Option<String>,
+}
+
+/// Valuation series query
+#[derive(Deserialize)]
+pub struct ValuationSeriesQuery {
+    /// Inclusive start date (YYYY-MM-DD)
+    from: Option<String>,
+    /// Inclusive end date (YYYY-MM-DD)
+    to: Option<String>,
+}
+
+/// Fees summary query
+#[derive(Deserialize)]
+pub struct FeesQuery {
+    account_id: Option<String>,
+    /// Inclusive start date (YYYY-MM-DD)
+    from: Option<String>,
+    /// Inclusive end date (YYYY-MM-DD)
+    to: Option<String>,
+}
+
+/// Symbol activity history query
+#[derive(Deserialize)]
+pub struct SymbolActivitiesQuery {
+    /// Inclusive start date (YYYY-MM-DD)
+    from: Option<String>,
+    /// Inclusive end date (YYYY-MM-DD)
+    to: Option<String>,
+}
+
+/// Transfer reconciliation query
+#[derive(Deserialize)]
+pub struct TransfersQuery {
+    /// Inclusive start date (YYYY-MM-DD)
+    from: Option<String>,
+    /// Inclusive end date (YYYY-MM-DD)
+    to: Option<String>,
+}
+
+/// Parses an optional `YYYY-MM-DD` query parameter
+fn parse_date_param(name: &str, value: Option<&str>) -> Result<Option<NaiveDate>> {
+    value
+        .map(|raw| {
+            NaiveDate::parse_from_str(raw.trim(), "%Y-%m-%d").map_err(|_| {
+                Error::Validation(ValidationError::InvalidInput(format!(
+                    "{} must be a date in YYYY-MM-DD format",
+                    name
+                )))
+            })
+        })
+        .transpose()
+}
+
+/// Unrealized gain in base currency from the last valuation on or before `date`
+fn unrealized_gain_at(valuations: &[DailyAccountValuation], date: NaiveDate) -> Option<Decimal> {
+    valuations
+        .iter()
+        .filter(|v| v.valuation_date <= date)
+        .max_by_key(|v| v.valuation_date)
+        .map(|v| (v.investment_market_value - v.cost_basis) * v.fx_rate_to_base)
+}
+
+/// Valuation rows as base-currency points in date order. `fxRateToBase` is kept
+/// so callers can recover the account-currency figures the TWR is computed on.
+fn valuation_series_to_json(valuations: &[DailyAccountValuation]) -> Vec<Value> {
+    let mut rows: Vec<&DailyAccountValuation> = valuations.iter().collect();
+    rows.sort_by_key(|v| v.valuation_date);
+    rows.into_iter()
+        .map(|v| json!({
+            "date": v.valuation_date.to_string(),
+            "value": v.total_value * v.fx_rate_to_base,
+            "netContribution": v.net_contribution * v.fx_rate_to_base,
+            "fxRateToBase": v.fx_rate_to_base
+        }))
+        .collect()
+}
+
+/// FX path query
+#[derive(Deserialize)]
+pub struct FxPathQuery {
+    from: String,
+    to: String,
+}
+
+/// Rates-to-base query
+#[derive(Deserialize)]
+pub struct RatesToBaseQuery {
+    /// Comma-separated currency codes, e.g. `EUR,JPY`
+    currencies: String,
+}
+
+/// Currency conversion query
+#[derive(Deserialize)]
+pub struct FxConvertQuery {
+    from: String,
+    to: String,
+    amount: Decimal,
+}
+
+/// Performance account parameter
+#[derive(Deserialize)]
+pub struct PerformanceAccountParam {
+    account_id: String,
+}
+
+/// Activity date range query
+#[derive(Deserialize)]
+pub struct ActivityRangeQuery {
+    account_id: Option<String>,
+}
+
+/// Activities query
+#[derive(Deserialize)]
+pub struct ActivitiesQuery {
+    account_id: Option<String>,
+    /// `symbol` nests activities under each asset with per-symbol totals
+    group_by: Option<String>,
+    #[serde(default)]
+    time_format: TimeFormat,
+    /// Render monetary amounts as decimal strings instead of JSON numbers
+    exact: Option<bool>,
+    /// Page size (activities, or symbol groups with `group_by=symbol`)
+    limit: Option<usize>,
+    /// Number of entries to skip
+    offset: Option<usize>,
+}
+
+/// Market data search handler
+pub async fn market_data_search_handler(
+    service: &dyn ExternalApiServiceTrait,
+    query: MarketDataSearchQuery,
+) -> Value {
+    match service.search_market_data(&query.q, query.asset_class.as_deref()).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to search market data: {}", e)
+        }),
+    }
+}
+
+/// Symbol resolution handler
+pub async fn resolve_symbol_handler(
+    service: &dyn ExternalApiServiceTrait,
+    query: MarketDataSearchQuery,
+) -> Value {
+    match service.resolve_symbol(&query.q).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to resolve symbol: {}", e)
+        }),
+    }
+}
+
+/// Quote handler
+pub async fn quote_handler(
+    service: &dyn ExternalApiServiceTrait,
+    symbol: &str,
+    query: QuoteQuery,
+) -> Value {
+    match service.get_quote(symbol, query.time_format, query.source.as_deref()).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get quote for {}: {}", symbol, e)
+        }),
+    }
+}
+
+/// Historical quotes handler
+pub async fn historical_quotes_handler(
+    service: &dyn ExternalApiServiceTrait,
+    symbol: &str,
+    query: QuoteQuery,
+) -> Value {
+    match service
+        .get_historical_quotes(
+            symbol,
+            query.time_format,
+            query.source.as_deref(),
+            query.adjusted.unwrap_or(false),
+            query.normalize,
+        )
+        .await
+    {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get historical quotes for {}: {}", symbol, e)
+        }),
+    }
+}
+
+/// Quote gaps query parameters
+#[derive(Deserialize)]
+pub struct QuoteGapsQuery {
+    /// Inclusive start date (YYYY-MM-DD), defaults to the first stored quote
+    from: Option<String>,
+    /// Inclusive end date (YYYY-MM-DD), defaults to today
+    to: Option<String>,
+}
+
+/// Quote gaps handler
+pub async fn quote_gaps_handler(
+    service: &dyn ExternalApiServiceTrait,
+    symbol: &str,
+    query: QuoteGapsQuery,
+) -> Value {
+    match service.get_quote_gaps(symbol, query.from, query.to) {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to find quote gaps for {}: {}", symbol, e)
+        }),
+    }
+}
+
+/// Bulk quote history query parameters
+#[derive(Deserialize)]
+pub struct BulkHistoryQuery {
+    /// Comma-separated symbols
+    symbols: String,
+    /// Inclusive start date (YYYY-MM-DD), defaults to one year before `to`
+    from: Option<String>,
+    /// Inclusive end date (YYYY-MM-DD), defaults to today
+    to: Option<String>,
+    /// `raw` (default) or `pct` to rebase each series to its first quote
+    #[serde(default)]
+    normalize: QuoteNormalization,
+}
+
+/// Bulk quote history handler
+pub async fn bulk_history_handler(service: &dyn ExternalApiServiceTrait, query: BulkHistoryQuery) -> Value {
+    let mut symbols: Vec<String> = query
+        .symbols
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+    symbols.sort();
+    symbols.dedup();
+    match service.get_historical_quotes_bulk(&symbols, query.from, query.to, query.normalize) {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get historical quotes: {}", e)
+        }),
+    }
+}
+
+/// Data source sync status handler
+pub async fn sync_status_handler(service: &dyn ExternalApiServiceTrait) -> Value {
+    match service.get_sync_status().await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get sync status: {}", e)
+        }),
+    }
+}
+
+/// Profile freshness handler
+pub async fn profile_freshness_handler(
+    service: &dyn ExternalApiServiceTrait,
+    symbol: &str,
+    query: ProfileFreshnessQuery,
+) -> Value {
+    let stale_days = query.stale_days.unwrap_or(DEFAULT_PROFILE_STALE_DAYS);
+    match service.get_profile_freshness(symbol, stale_days) {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get profile freshness for {}: {}", symbol, e)
+        }),
+    }
+}
+
+/// Quote statistics handler
+pub async fn quote_stats_handler(
+    service: &dyn ExternalApiServiceTrait,
+    symbol: &str,
+    query: QuoteStatsQuery,
+) -> Value {
+    match service.get_quote_stats(symbol, query.window.as_deref().unwrap_or("1y")) {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get quote stats for {}: {}", symbol, e)
+        }),
+    }
+}
+
+/// Ensure symbol handler
+pub async fn ensure_symbol_handler(service: &dyn ExternalApiServiceTrait, symbol: &str) -> Value {
+    match service.ensure_symbol(symbol).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to ensure symbol {}: {}", symbol, e)
+        }),
+    }
+}
+
+/// Manual quote handler
+pub async fn manual_quote_handler(
+    service: &dyn ExternalApiServiceTrait,
+    symbol: &str,
+    request: ManualQuoteRequest,
+) -> Value {
+    match service.add_manual_quote(symbol, request).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to save manual quote for {}: {}", symbol, e)
+        }),
+    }
+}
+
+/// Data sources handler
+pub async fn data_sources_handler(
+    service: &dyn ExternalApiServiceTrait,
+    symbol: &str,
+) -> Value {
+    match service.get_data_sources(symbol).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get data sources for {}: {}", symbol, e)
+        }),
+    }
+}
+
+/// Set data source handler
+pub async fn set_data_source_handler(
+    service: &dyn ExternalApiServiceTrait,
+    symbol: &str,
+    request: DataSourceUpdateRequest,
+) -> Value {
+    match service.set_data_source(symbol, request).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to set data source for {}: {}", symbol, e)
+        }),
+    }
+}
+
+/// Missing quotes handler
+pub async fn missing_quotes_handler(service: &dyn ExternalApiServiceTrait) -> Value {
+    match service.get_missing_quotes().await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get missing quotes: {}", e)
+        }),
+    }
+}
+
+/// Held symbols' latest quotes handler
+pub async fn held_quotes_handler(service: &dyn ExternalApiServiceTrait, query: TimeFormatQuery) -> Value {
+    match service.get_held_quotes(query.time_format).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get quotes for held symbols: {}", e)
+        }),
+    }
+}
+
+/// Account performance handler
+pub async fn account_performance_handler(
+    service: &dyn ExternalApiServiceTrait,
+    account_id: &str,
+) -> Value {
+    match service.get_account_performance(account_id).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get performance for account {}: {}", account_id, e)
+        }),
+    }
+}
+
+/// TWR series handler
+pub async fn twr_series_handler(
+    service: &dyn ExternalApiServiceTrait,
+    account_id: &str,
+    query: TwrQuery,
+) -> Value {
+    let interval = query.interval.as_deref().unwrap_or("monthly");
+    match service.get_twr_series(account_id, query.from, query.to, interval).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get TWR series: {}", e)
+        }),
+    }
+}
+
+/// Valuation series handler
+pub async fn valuation_series_handler(
+    service: &dyn ExternalApiServiceTrait,
+    account_id: &str,
+    query: ValuationSeriesQuery,
+) -> Value {
+    match service.get_valuation_series(account_id, query.from, query.to) {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get valuations for account {}: {}", account_id, e)
+        }),
+    }
+}
+
+/// Annualized returns handler
+pub async fn annualized_returns_handler(service: &dyn ExternalApiServiceTrait, account_id: &str) -> Value {
+    match service.get_annualized_returns(account_id).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get annualized returns for account {}: {}", account_id, e)
+        }),
+    }
+}
+
+/// Account comparison query parameters
+#[derive(Deserialize)]
+pub struct CompareAccountsQuery {
+    /// First account id
+    a: String,
+    /// Second account id
+    b: String,
+    /// Inclusive start date (YYYY-MM-DD), defaults to the later inception
+    from: Option<String>,
+    /// Inclusive end date (YYYY-MM-DD), defaults to the latest shared valuation
+    to: Option<String>,
+}
+
+/// Account comparison handler
+pub async fn compare_accounts_handler(service: &dyn ExternalApiServiceTrait, query: CompareAccountsQuery) -> Value {
+    match service.compare_accounts(&query.a, &query.b, query.from, query.to).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to compare accounts {} and {}: {}", query.a, query.b, e)
+        }),
+    }
+}
+
+/// Beta handler
+pub async fn beta_handler(service: &dyn ExternalApiServiceTrait, query: BetaQuery) -> Value {
+    match service
+        .get_beta(query.account_id, &query.benchmark, query.from, query.to)
+        .await
+    {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to compute beta against {}: {}", query.benchmark, e)
+        }),
+    }
+}
+
+/// Portfolio yield handler
+pub async fn yield_handler(service: &dyn ExternalApiServiceTrait) -> Value {
+    match service.get_yield().await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get portfolio yield: {}", e)
+        }),
+    }
+}
+
+/// Value changes handler
+pub async fn value_changes_handler(service: &dyn ExternalApiServiceTrait) -> Value {
+    match service.get_value_changes() {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get value changes: {}", e)
+        }),
+    }
+}
+
+/// Correlation matrix handler
+pub async fn correlation_handler(service: &dyn ExternalApiServiceTrait, query: CorrelationQuery) -> Value {
+    match service
+        .get_correlation_matrix(query.account_id, query.from, query.to)
+        .await
+    {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to compute correlations: {}", e)
+        }),
+    }
+}
+
+/// Sharpe ratio handler
+pub async fn sharpe_handler(service: &dyn ExternalApiServiceTrait, query: SharpeQuery) -> Value {
+    match service
+        .get_sharpe(query.account_id, query.from, query.to, query.risk_free_rate)
+        .await
+    {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to compute Sharpe ratio: {}", e)
+        }),
+    }
+}
+
+/// Value-at-Risk handler
+pub async fn var_handler(service: &dyn ExternalApiServiceTrait, query: VarQuery) -> Value {
+    let confidence = query.confidence.unwrap_or(DEFAULT_VAR_CONFIDENCE);
+    let horizon = query.horizon.unwrap_or(1);
+    match service.get_var(query.account_id, confidence, horizon, query.from).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to compute VaR: {}", e)
+        }),
+    }
+}
+
+/// Return contribution handler
+pub async fn return_contribution_handler(
+    service: &dyn ExternalApiServiceTrait,
+    query: ContributionQuery,
+) -> Value {
+    match service
+        .get_return_contribution(query.account_id, query.from, query.to)
+        .await
+    {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get return contribution: {}", e)
+        }),
+    }
+}
+
+/// Portfolio performance summary handler
+pub async fn portfolio_performance_summary_handler(
+    service: &dyn ExternalApiServiceTrait,
+    query: PerformanceSummaryQuery,
+) -> Value {
+    match service.get_portfolio_performance_summary(query.exclude_accounts).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get portfolio performance summary: {}", e)
+        }),
+    }
+}
+
+/// Gains by period handler
+pub async fn gains_by_period_handler(
+    service: &dyn ExternalApiServiceTrait,
+    query: GainsQuery,
+) -> Value {
+    let interval = query.interval.as_deref().unwrap_or("quarterly");
+    match service.get_gains_by_period(query.account_id, interval) {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get gains by period: {}", e)
+        }),
+    }
+}
+
+/// Fees summary handler
+pub async fn fees_summary_handler(
+    service: &dyn ExternalApiServiceTrait,
+    query: FeesQuery,
+) -> Value {
+    match service.get_fees_summary(query.account_id, query.from, query.to) {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get fees summary: {}", e)
+        }),
+    }
+}
+
+/// Symbol activity history handler
+pub async fn symbol_activities_handler(
+    service: &dyn ExternalApiServiceTrait,
+    symbol: &str,
+    query: SymbolActivitiesQuery,
+) -> Value {
+    match service.get_symbol_activities(symbol, query.from, query.to) {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get activities for {}: {}", symbol, e)
+        }),
+    }
+}
+
+/// Transfer reconciliation handler
+pub async fn transfers_handler(service: &dyn ExternalApiServiceTrait, query: TransfersQuery) -> Value {
+    match service.get_transfers(query.from, query.to) {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get transfers: {}", e)
+        }),
+    }
+}
+
+/// Activity date range handler
+pub async fn activity_date_range_handler(
+    service: &dyn ExternalApiServiceTrait,
+    query: ActivityRangeQuery,
+) -> Value {
+    match service.get_activity_date_range(query.account_id) {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get activity date range: {}", e)
+        }),
+    }
+}
+
+/// Activities handler
+pub async fn activities_handler(
+    service: &dyn ExternalApiServiceTrait,
+    query: ActivitiesQuery,
+) -> Value {
+    let page = Pagination::new(query.limit, query.offset);
+    match service.get_activities(
+        query.account_id,
+        query.group_by.as_deref(),
+        query.time_format,
+        NumberFormat::from_exact(query.exact),
+        page,
+    ) {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get activities: {}", e)
+        }),
+    }
+}
+
+
+/// Request header carrying a client-chosen key that makes a create request safe to retry.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Default time an idempotency key is remembered (24 hours).
+pub const DEFAULT_IDEMPOTENCY_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// Machine-readable code returned when an idempotency key cannot be honoured
+pub const IDEMPOTENCY_CONFLICT_CODE: &str = "IDEMPOTENCY_CONFLICT";
+
+struct IdempotencyRecord {
+    request: Value,
+    /// `None` while the first request with this key is still running
+    response: Option<Value>,
+    stored_at: std::time::Instant,
+}
+
+/// Recently used idempotency keys with the request each was first used for and the
+/// response it produced. Keys expire `ttl` after they were claimed.
+pub struct IdempotencyStore {
+    ttl: std::time::Duration,
+    records: RwLock<HashMap<String, IdempotencyRecord>>,
+}
+
+impl IdempotencyStore {
+    pub fn new(ttl: std::time::Duration) -> Self {
+        Self {
+            ttl,
+            records: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn records(&self) -> Result<std::sync::RwLockWriteGuard<'_, HashMap<String, IdempotencyRecord>>> {
+        self.records
+            .write()
+            .map_err(|e| Error::Unexpected(format!("Idempotency store lock poisoned: {}", e)))
+    }
+
+    /// Reserves `key` for `request`. Returns `None` when the caller should go ahead,
+    /// or the response to send instead: the original response for a repeated request,
+    /// or a conflict error while it is still running or when the body differs.
+    pub fn claim(&self, key: &str, request: &Value) -> Result<Option<Value>> {
+        let mut records = self.records()?;
+        let ttl = self.ttl;
+        records.retain(|_, record| record.stored_at.elapsed() < ttl);
+
+        let Some(record) = records.get(key) else {
+            records.insert(
+                key.to_string(),
+                IdempotencyRecord {
+                    request: request.clone(),
+                    response: None,
+                    stored_at: std::time::Instant::now(),
+                },
+            );
+            return Ok(None);
+        };
+        let conflict = |message: &str| {
+            json!({
+                "error": message,
+                "code": IDEMPOTENCY_CONFLICT_CODE
+            })
+        };
+        if record.request != *request {
+            return Ok(Some(conflict(
+                "Idempotency-Key was already used with a different request body",
+            )));
+        }
+        Ok(Some(match &record.response {
+            Some(response) => response.clone(),
+            None => conflict("A request with this Idempotency-Key is still in progress"),
+        }))
+    }
+
+    /// Stores the response of a claimed key so retries replay it.
+    pub fn complete(&self, key: &str, response: Value) -> Result<()> {
+        if let Some(record) = self.records()?.get_mut(key) {
+            record.response = Some(response);
+        }
+        Ok(())
+    }
+
+    /// Forgets a claimed key whose request failed.
+    pub fn release(&self, key: &str) -> Result<()> {
+        self.records()?.remove(key);
+        Ok(())
+    }
+}
+
+/// Activity creation handler; `idempotency_key` comes from the `Idempotency-Key` header
+pub async fn create_activity_handler(
+    service: &dyn ExternalApiServiceTrait,
+    payload: Value,
+    idempotency_key: Option<&str>,
+) -> Value {
+    match service.create_activity(payload, idempotency_key).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to create activity: {}", e)
+        }),
+    }
+}
+
+/// Activity validation handler
+pub async fn validate_activity_handler(service: &dyn ExternalApiServiceTrait, payload: Value) -> Value {
+    match service.validate_activity(payload).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to validate activity: {}", e)
+        }),
+    }
+}
+
+/// Direction in which the portfolio value must cross the threshold
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum AlertCondition {
+    Above,
+    Below,
+}
+
+impl AlertCondition {
+    pub fn is_met(&self, value: Decimal, threshold: Decimal) -> bool {
+        match self {
+            AlertCondition::Above => value >= threshold,
+            AlertCondition::Below => value <= threshold,
+        }
+    }
+}
+
+/// Alert registration body
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertRequest {
+    callback_url: String,
+    condition: AlertCondition,
+    threshold: Decimal,
+    account_id: Option<String>,
+}
+
+impl AlertRequest {
+    fn validate(&self) -> Result<()> {
+        if !(self.callback_url.starts_with("http://") || self.callback_url.starts_with("https://")) {
+            return Err(Error::Validation(ValidationError::InvalidInput(
+                "callbackUrl must be an http(s) URL".to_string(),
+            )));
+        }
+        if self.threshold.is_sign_negative() {
+            return Err(Error::Validation(ValidationError::InvalidInput(
+                "threshold must not be negative".to_string(),
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Registered portfolio value alert, kept in memory only
+#[derive(Clone, Debug)]
+pub struct Alert {
+    id: String,
+    callback_url: String,
+    condition: AlertCondition,
+    threshold: Decimal,
+    account_id: Option<String>,
+    created_at: DateTime<Utc>,
+    last_value: Option<Decimal>,
+    last_evaluated_at: Option<DateTime<Utc>>,
+    last_triggered_at: Option<DateTime<Utc>>,
+    is_met: bool,
+}
+
+/// Convert an alert to JSON format for external API
+pub fn alert_to_json(alert: &Alert) -> Value {
+    json!({
+        "id": alert.id,
+        "callbackUrl": alert.callback_url,
+        "condition": alert.condition,
+        "threshold": alert.threshold,
+        "accountId": alert.account_id,
+        "createdAt": alert.created_at.to_rfc3339(),
+        "lastValue": alert.last_value,
+        "lastEvaluatedAt": alert.last_evaluated_at.map(|dt| dt.to_rfc3339()),
+        "lastTriggeredAt": alert.last_triggered_at.map(|dt| dt.to_rfc3339()),
+        "isMet": alert.is_met
+    })
+}
+
+/// Portfolio value behind each alert, valuing each account scope once per round with
+/// `value_of`. Alerts whose scope fails to value are logged, skipped and reported in
+/// the partial errors, so one failing alert doesn't hold up the rest of the round.
+pub async fn alert_scope_values<'a, F, Fut>(
+    alerts: &'a [Alert],
+    mut value_of: F,
+) -> (Vec<(&'a Alert, Decimal)>, Vec<Value>)
+where
+    F: FnMut(Option<String>) -> Fut,
+    Fut: Future<Output = Result<Decimal>>,
+{
+    let mut values: HashMap<Option<String>, std::result::Result<Decimal, String>> = HashMap::new();
+    let mut valued = Vec::new();
+    let mut partial_errors = Vec::new();
+    for alert in alerts {
+        if !values.contains_key(&alert.account_id) {
+            let value = value_of(alert.account_id.clone()).await.map_err(|e| e.to_string());
+            values.insert(alert.account_id.clone(), value);
+        }
+        match &values[&alert.account_id] {
+            Ok(value) => valued.push((alert, *value)),
+            Err(e) => {
+                tracing::warn!(alert_id = %alert.id, error = %e, "Skipping alert that could not be evaluated");
+                partial_errors.push(json!({
+                    "alertId": alert.id,
+                    "accountId": alert.account_id,
+                    "error": e
+                }));
+            }
+        }
+    }
+    (valued, partial_errors)
+}
+
+/// Register alert handler
+pub async fn register_alert_handler(
+    service: &dyn ExternalApiServiceTrait,
+    request: AlertRequest,
+) -> Value {
+    match service.register_alert(request) {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to register alert: {}", e)
+        }),
+    }
+}
+
+/// Delete alert handler
+pub async fn delete_alert_handler(
+    service: &dyn ExternalApiServiceTrait,
+    alert_id: &str,
+) -> Value {
+    match service.delete_alert(alert_id) {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to delete alert {}: {}", alert_id, e)
+        }),
+    }
+}
+
+/// List alerts handler
+pub async fn list_alerts_handler(service: &dyn ExternalApiServiceTrait) -> Value {
+    match service.list_alerts() {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to list alerts: {}", e)
+        }),
+    }
+}
+
+/// Appends symbols not already on the watchlist, trimming blanks and
+/// ignoring case when checking for duplicates.
+pub fn merge_watchlist_symbols(watchlist: &mut Vec<String>, symbols: &[String]) {
+    for symbol in symbols.iter().map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        if !watchlist.iter().any(|s| s.eq_ignore_ascii_case(symbol)) {
+            watchlist.push(symbol.to_string());
+        }
+    }
+}
+
+/// Watchlist additions
+#[derive(Deserialize)]
+pub struct WatchlistRequest {
+    symbols: Vec<String>,
+}
+
+/// Watchlist query parameters
+#[derive(Deserialize)]
+pub struct WatchlistQuery {
+    /// `quotes` attaches the latest stored quote for each symbol
+    include: Option<String>,
+    /// `rfc3339` (default) or `epoch_ms`
+    #[serde(default)]
+    time_format: TimeFormat,
+}
+
+/// Watchlist removal query
+#[derive(Deserialize)]
+pub struct WatchlistRemoveQuery {
+    /// Comma-separated symbols to remove
+    symbols: String,
+}
+
+/// Watchlist handler
+pub async fn watchlist_handler(service: &dyn ExternalApiServiceTrait, query: WatchlistQuery) -> Value {
+    let include_quotes = query
+        .include
+        .as_deref()
+        .is_some_and(|include| include.split(',').any(|part| part.trim() == "quotes"));
+    match service.get_watchlist(include_quotes, query.time_format) {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get watchlist: {}", e)
+        }),
+    }
+}
+
+/// Watchlist addition handler
+pub async fn add_to_watchlist_handler(
+    service: &dyn ExternalApiServiceTrait,
+    request: WatchlistRequest,
+) -> Value {
+    match service.add_to_watchlist(request).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to update watchlist: {}", e)
+        }),
+    }
+}
+
+/// Watchlist removal handler
+pub async fn remove_from_watchlist_handler(
+    service: &dyn ExternalApiServiceTrait,
+    query: WatchlistRemoveQuery,
+) -> Value {
+    match service.remove_from_watchlist(&query.symbols).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to update watchlist: {}", e)
+        }),
+    }
+}
+
+/// Portfolio group body; an `id` matching an existing group replaces it
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PortfolioGroupRequest {
+    id: Option<String>,
+    name: String,
+    account_ids: Vec<String>,
+}
+
+impl PortfolioGroupRequest {
+    fn validate(&self) -> std::result::Result<(), Vec<FieldError>> {
+        let mut errors = Vec::new();
+        if self.name.trim().is_empty() {
+            errors.push(FieldError::new("name", "is required"));
+        }
+        if self.account_ids.is_empty() {
+            errors.push(FieldError::new("accountIds", "must list at least one account"));
+        }
+        into_validation_result(errors)
+    }
+}
+
+/// Portfolio groups handler
+pub async fn portfolio_groups_handler(service: &dyn ExternalApiServiceTrait) -> Value {
+    match service.list_portfolio_groups() {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to list portfolios: {}", e)
+        }),
+    }
+}
+
+/// Portfolio group save handler
+pub async fn save_portfolio_group_handler(
+    service: &dyn ExternalApiServiceTrait,
+    request: PortfolioGroupRequest,
+) -> Value {
+    match service.save_portfolio_group(request).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to save portfolio: {}", e)
+        }),
+    }
+}
+
+/// Portfolio group summary handler
+pub async fn portfolio_group_summary_handler(
+    service: &dyn ExternalApiServiceTrait,
+    group_id: &str,
+) -> Value {
+    match service.get_portfolio_group_summary(group_id).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get summary for portfolio {}: {}", group_id, e)
+        }),
+    }
+}
+
+/// One manually maintained exchange rate in a bulk upload
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExchangeRateRow {
+    from: String,
+    to: String,
+    rate: Decimal,
+    /// When the rate was observed, defaults to now
+    timestamp: Option<DateTime<Utc>>,
+}
+
+/// Checks every row of a bulk exchange-rate upload, naming failing fields by
+/// row index (e.g. `[2].rate`) so the whole batch can be fixed in one pass.
+pub fn validate_exchange_rate_rows(rows: &[ExchangeRateRow]) -> std::result::Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    if rows.is_empty() {
+        errors.push(FieldError::new("body", "must contain at least one rate"));
+    }
+    for (index, row) in rows.iter().enumerate() {
+        let field = |name: &str| format!("[{}].{}", index, name);
+        let from = row.from.trim();
+        let to = row.to.trim();
+        if !is_currency_code(from) {
+            errors.push(FieldError::new(&field("from"), "must be a 3-letter ISO 4217 code"));
+        }
+        if !is_currency_code(to) {
+            errors.push(FieldError::new(&field("to"), "must be a 3-letter ISO 4217 code"));
+        }
+        if is_currency_code(from) && from.eq_ignore_ascii_case(to) {
+            errors.push(FieldError::new(&field("to"), "must differ from `from`"));
+        }
+        if row.rate <= Decimal::ZERO {
+            errors.push(FieldError::new(&field("rate"), "must be positive"));
+        }
+    }
+    into_validation_result(errors)
+}
+
+/// Bulk exchange-rate upload handler
+pub async fn upsert_exchange_rates_handler(
+    service: &dyn ExternalApiServiceTrait,
+    rows: Vec<ExchangeRateRow>,
+) -> Value {
+    match service.upsert_exchange_rates(rows).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to save exchange rates: {}", e)
+        }),
+    }
+}
+
+/// Exchange rate refresh handler
+pub async fn refresh_exchange_rates_handler(service: &dyn ExternalApiServiceTrait) -> Value {
+    match service.refresh_exchange_rates().await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to refresh exchange rates: {}", e)
+        }),
+    }
+}
+
+/// Symbol under which an instrument's tags are stored
+fn tag_key(symbol: &str) -> String {
+    symbol.trim().to_uppercase()
+}
+
+/// Splits a comma-separated `tags` query value, dropping blanks
+pub fn split_tags(tags: &str) -> Vec<String> {
+    tags.split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Splits a comma-separated list of account ids, dropping blanks
+pub fn split_account_ids(ids: &str) -> Vec<String> {
+    ids.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Trims tags and drops blanks and case-insensitive duplicates, keeping the first spelling
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+        if !normalized.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+            normalized.push(tag.to_string());
+        }
+    }
+    normalized
+}
+
+/// Replacement tag list for an instrument
+#[derive(Deserialize)]
+pub struct InstrumentTagsRequest {
+    tags: Vec<String>,
+}
+
+/// Instrument tags handler
+pub async fn instrument_tags_handler(service: &dyn ExternalApiServiceTrait, symbol: &str) -> Value {
+    match service.get_instrument_tags(symbol) {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to get tags for {}: {}", symbol, e)
+        }),
+    }
+}
+
+/// Instrument tags update handler
+pub async fn set_instrument_tags_handler(
+    service: &dyn ExternalApiServiceTrait,
+    symbol: &str,
+    request: InstrumentTagsRequest,
+) -> Value {
+    match service.set_instrument_tags(symbol, request).await {
+        Ok(result) => result,
+        Err(e) => json!({
+            "error": format!("Failed to set tags for {}: {}", symbol, e)
+        }),
+    }
+}
+
+/// Machine-readable code returned when a request body fails validation
+pub const VALIDATION_ERROR_CODE: &str = "VALIDATION_ERROR";
+
+/// Machine-readable code returned when a request body is not in an accepted media type
+pub const UNSUPPORTED_MEDIA_TYPE_CODE: &str = "UNSUPPORTED_MEDIA_TYPE";
+
+/// Media types accepted by JSON write routes
+pub const JSON_MEDIA_TYPES: &[&str] = &["application/json"];
+
+/// Whether a `Content-Type` value names one of the `accepted` media types. Parameters such
+/// as `charset` are ignored, and `application/*+json` counts as JSON.
+pub fn content_type_is_accepted(content_type: &str, accepted: &[&str]) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    accepted.iter().any(|media_type| {
+        essence == *media_type
+            || (*media_type == "application/json"
+                && essence.starts_with("application/")
+                && essence.ends_with("+json"))
+    })
+}
+
+/// Structured 415 body naming the received and accepted media types
+pub fn unsupported_media_type_response(content_type: Option<&str>, accepted: &[&str]) -> Value {
+    json!({
+        "error": format!(
+            "Unsupported Content-Type '{}', expected {}",
+            content_type.unwrap_or("none"),
+            accepted.join(" or ")
+        ),
+        "code": UNSUPPORTED_MEDIA_TYPE_CODE,
+        "received": content_type,
+        "accepted": accepted
+    })
+}
+
+/// A single rejected field in a request body
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub reason: String,
+}
+
+impl FieldError {
+    fn new(field: &str, reason: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum FieldKind {
+    String,
+    Decimal,
+    Bool,
+    Date,
+}
+
+struct FieldSpec {
+    name: &'static str,
+    kind: FieldKind,
+    required: bool,
+}
+
+const fn field(name: &'static str, kind: FieldKind, required: bool) -> FieldSpec {
+    FieldSpec { name, kind, required }
+}
+
+/// Fields of an activity creation body (mirrors `NewActivity`)
+const ACTIVITY_FIELDS: &[FieldSpec] = &[
+    field("accountId", FieldKind::String, true),
+    field("assetId", FieldKind::String, true),
+    field("activityType", FieldKind::String, true),
+    field("activityDate", FieldKind::Date, true),
+    field("currency", FieldKind::String, true),
+    field("quantity", FieldKind::Decimal, false),
+    field("unitPrice", FieldKind::Decimal, false),
+    field("fee", FieldKind::Decimal, false),
+    field("amount", FieldKind::Decimal, false),
+    field("isDraft", FieldKind::Bool, false),
+    field("comment", FieldKind::String, false),
+];
+
+/// Fields of an account creation body (mirrors `NewAccount`)
+const ACCOUNT_FIELDS: &[FieldSpec] = &[
+    field("name", FieldKind::String, true),
+    field("accountType", FieldKind::String, true),
+    field("currency", FieldKind::String, true),
+    field("isDefault", FieldKind::Bool, true),
+    field("isActive", FieldKind::Bool, true),
+    field("group", FieldKind::String, false),
+    field("platformId", FieldKind::String, false),
+];
+
+const ACTIVITY_TYPES: [&str; 13] = [
+    activities::ACTIVITY_TYPE_BUY,
+    activities::ACTIVITY_TYPE_SELL,
+    activities::ACTIVITY_TYPE_DIVIDEND,
+    activities::ACTIVITY_TYPE_INTEREST,
+    activities::ACTIVITY_TYPE_DEPOSIT,
+    activities::ACTIVITY_TYPE_WITHDRAWAL,
+    activities::ACTIVITY_TYPE_TRANSFER_IN,
+    activities::ACTIVITY_TYPE_TRANSFER_OUT,
+    activities::ACTIVITY_TYPE_FEE,
+    activities::ACTIVITY_TYPE_TAX,
+    activities::ACTIVITY_TYPE_SPLIT,
+    activities::ACTIVITY_TYPE_ADD_HOLDING,
+    activities::ACTIVITY_TYPE_REMOVE_HOLDING,
+];
+
+fn check_field_kind(value: &Value, kind: FieldKind) -> std::result::Result<(), String> {
+    match kind {
+        FieldKind::String => match value {
+            Value::String(text) if text.trim().is_empty() => Err("must not be empty".to_string()),
+            Value::String(_) => Ok(()),
+            _ => Err("must be a string".to_string()),
+        },
+        FieldKind::Decimal => match value {
+            Value::Number(_) => Ok(()),
+            Value::String(text) if text.trim().parse::<Decimal>().is_ok() => Ok(()),
+            _ => Err("must be a number".to_string()),
+        },
+        FieldKind::Bool => match value {
+            Value::Bool(_) => Ok(()),
+            _ => Err("must be a boolean".to_string()),
+        },
+        FieldKind::Date => match value {
+            Value::String(text)
+                if DateTime::parse_from_rfc3339(text).is_ok()
+                    || NaiveDate::parse_from_str(text, "%Y-%m-%d").is_ok() =>
+            {
+                Ok(())
+            }
+            _ => Err("must be an ISO 8601 date (YYYY-MM-DD) or RFC 3339 timestamp".to_string()),
+        },
+    }
+}
+
+fn validate_fields(payload: &Value, specs: &[FieldSpec]) -> Vec<FieldError> {
+    let Some(object) = payload.as_object() else {
+        return vec![FieldError::new("$", "body must be a JSON object")];
+    };
+
+    let mut errors = Vec::new();
+    for spec in specs {
+        match object.get(spec.name) {
+            None | Some(Value::Null) => {
+                if spec.required {
+                    errors.push(FieldError::new(spec.name, "is required"));
+                }
+            }
+            Some(value) => {
+                if let Err(reason) = check_field_kind(value, spec.kind) {
+                    errors.push(FieldError::new(spec.name, reason));
+                }
+            }
+        }
+    }
+    errors
+}
+
+fn is_currency_code(code: &str) -> bool {
+    code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+fn check_currency_code(payload: &Value, errors: &mut Vec<FieldError>) {
+    if let Some(currency) = payload.get("currency").and_then(Value::as_str) {
+        let currency = currency.trim();
+        if !currency.is_empty() && !is_currency_code(currency) {
+            errors.push(FieldError::new("currency", "must be a 3-letter ISO 4217 code"));
+        }
+    }
+}
+
+/// Rejects negative values in the given numeric fields. Fields that are missing or not
+/// numbers are left to the type checks.
+fn check_non_negative(payload: &Value, names: &[&str], errors: &mut Vec<FieldError>) {
+    for name in names {
+        let value = match payload.get(*name) {
+            Some(Value::Number(number)) => number.to_string().parse::<Decimal>().ok(),
+            Some(Value::String(text)) => text.trim().parse::<Decimal>().ok(),
+            _ => None,
+        };
+        if value.is_some_and(|value| value.is_sign_negative() && !value.is_zero()) {
+            errors.push(FieldError::new(name, "must not be negative"));
+        }
+    }
+}
+
+fn into_validation_result(errors: Vec<FieldError>) -> std::result::Result<(), Vec<FieldError>> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Checks the required fields and types of an activity creation body
+pub fn validate_activity_payload(payload: &Value) -> std::result::Result<(), Vec<FieldError>> {
+    let mut errors = validate_fields(payload, ACTIVITY_FIELDS);
+
+    if let Some(activity_type) = payload.get("activityType").and_then(Value::as_str) {
+        let activity_type = activity_type.trim();
+        if !activity_type.is_empty() && !ACTIVITY_TYPES.contains(&activity_type) {
+            errors.push(FieldError::new(
+                "activityType",
+                format!("must be one of {}", ACTIVITY_TYPES.join(", ")),
+            ));
+        }
+    }
+    check_currency_code(payload, &mut errors);
+    check_non_negative(payload, &["quantity", "unitPrice", "fee"], &mut errors);
+
+    into_validation_result(errors)
+}
+
+/// Checks the required fields and types of an account creation body
+pub fn validate_account_payload(payload: &Value) -> std::result::Result<(), Vec<FieldError>> {
+    let mut errors = validate_fields(payload, ACCOUNT_FIELDS);
+    check_currency_code(payload, &mut errors);
+    into_validation_result(errors)
+}
+
+/// Builds the structured error body for rejected request payloads
+pub fn validation_error_response(details: &[FieldError]) -> Value {
+    json!({
+        "error": "Request body failed validation",
+        "code": VALIDATION_ERROR_CODE,
+        "details": details
+    })
+}
+
+/// Expected shape of a query parameter
+#[derive(Clone, Copy)]
+enum QueryParamFormat {
+    /// Non-negative integer
+    Count,
+    /// `YYYY-MM-DD`
+    Date,
+    Bool,
+    /// One of the listed values, case-insensitive
+    OneOf(&'static [&'static str]),
+}
+
+impl QueryParamFormat {
+    fn check(&self, value: &str) -> std::result::Result<(), String> {
+        let valid = match self {
+            Self::Count => value.parse::<usize>().is_ok(),
+            Self::Date => NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok(),
+            Self::Bool => matches!(value, "true" | "false"),
+            Self::OneOf(values) => values.iter().any(|v| v.eq_ignore_ascii_case(value.trim())),
+        };
+        if valid {
+            return Ok(());
+        }
+        Err(match self {
+            Self::Count => "must be a non-negative integer".to_string(),
+            Self::Date => "must be a date in YYYY-MM-DD format".to_string(),
+            Self::Bool => "must be true or false".to_string(),
+            Self::OneOf(values) => format!("must be one of: {}", values.join(", ")),
+        })
+    }
+}
+
+/// Query parameters shared across endpoints, checked before a request reaches its handler
+const QUERY_PARAMS: &[(&str, QueryParamFormat)] = &[
+    ("limit", QueryParamFormat::Count),
+    ("offset", QueryParamFormat::Count),
+    ("stale_days", QueryParamFormat::Count),
+    ("as_of", QueryParamFormat::Date),
+    ("from", QueryParamFormat::Date),
+    ("to", QueryParamFormat::Date),
+    ("rebase_weights", QueryParamFormat::Bool),
+    ("stale_only", QueryParamFormat::Bool),
+    ("include_closed", QueryParamFormat::Bool),
+    ("adjusted", QueryParamFormat::Bool),
+    ("group_by", QueryParamFormat::OneOf(&["assetClass", "asset_class", "symbol"])),
+    ("interval", QueryParamFormat::OneOf(&["monthly", "quarterly", "yearly"])),
+    ("positions", QueryParamFormat::OneOf(&["short", "long", "all"])),
+    ("time_format", QueryParamFormat::OneOf(&["rfc3339", "epoch_ms"])),
+];
+
+/// Routes whose `from` and `to` query parameters are currency codes, not dates
+const CURRENCY_PAIR_PATHS: &[&str] = &["/api/fx/path", "/api/fx/convert"];
+
+/// Checks the shared query parameters of a request to `path`, naming each malformed
+/// one and the format it expects. Parameters not in the shared table are left to the
+/// route's own parsing.
+pub fn validate_query_params(path: &str, query: Option<&str>) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    for pair in query.unwrap_or_default().split('&').filter(|pair| !pair.is_empty()) {
+        let (name, raw) = pair.split_once('=').unwrap_or((pair, ""));
+        if CURRENCY_PAIR_PATHS.contains(&path) && matches!(name, "from" | "to") {
+            continue;
+        }
+        let Some((_, format)) = QUERY_PARAMS.iter().find(|(param, _)| *param == name) else {
+            continue;
+        };
+        let value = urlencoding::decode(&raw.replace('+', " "))
+            .map(|value| value.into_owned())
+            .unwrap_or_else(|_| raw.to_string());
+        if let Err(reason) = format.check(&value) {
+            errors.push(FieldError::new(name, format!("{}, got '{}'", reason, value)));
+        }
+    }
+    errors
+}
+
+/// Structured 400 body listing malformed query parameters
+pub fn query_validation_response(details: &[FieldError]) -> Value {
+    json!({
+        "error": "Query parameters failed validation",
+        "code": VALIDATION_ERROR_CODE,
+        "details": details
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid_activity() -> Value {
+        json!({
+            "accountId": "acc-1",
+            "assetId": "AAPL",
+            "activityType": "BUY",
+            "activityDate": "2024-03-01",
+            "quantity": 10,
+            "unitPrice": "187.5",
+            "currency": "USD",
+            "isDraft": false
+        })
+    }
+
+    fn fields(errors: &[FieldError]) -> Vec<&str> {
+        errors.iter().map(|e| e.field.as_str()).collect()
+    }
+
+    /// A posted USD activity on `day` (YYYY-MM-DD) for AAPL in `acc-1`, at a unit price of 100
+    fn activity(id: &str, activity_type: &str, day: &str, quantity: Decimal) -> Activity {
+        let activity_date = NaiveDate::parse_from_str(day, "%Y-%m-%d")
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc();
+        Activity {
+            id: id.to_string(),
+            account_id: "acc-1".to_string(),
+            asset_id: "AAPL".to_string(),
+            activity_type: activity_type.to_string(),
+            activity_date,
+            quantity,
+            unit_price: dec!(100),
+            currency: "USD".to_string(),
+            fee: Decimal::ZERO,
+            amount: None,
+            is_draft: false,
+            comment: None,
+            created_at: activity_date,
+            updated_at: activity_date,
+        }
+    }
+
+    /// A Yahoo exchange rate converting `from` into `to`, stamped now
+    fn rate(from: &str, to: &str, value: Decimal) -> ExchangeRate {
+        ExchangeRate {
+            id: ExchangeRate::make_fx_symbol(from, to),
+            from_currency: from.to_string(),
+            to_currency: to.to_string(),
+            rate: value,
+            source: DataSource::Yahoo,
+            timestamp: Utc::now(),
+        }
+    }
+
+    /// A Yahoo quote closing at `close` on `day` (YYYY-MM-DD), with every price set to it
+    fn quote(symbol: &str, day: &str, close: Decimal) -> Quote {
+        Quote {
+            id: format!("{}_{}", day, symbol),
+            symbol: symbol.to_string(),
+            timestamp: NaiveDate::parse_from_str(day, "%Y-%m-%d")
+                .unwrap()
+                .and_hms_opt(16, 0, 0)
+                .unwrap()
+                .and_utc(),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            adjclose: close,
+            volume: Decimal::ZERO,
+            currency: "USD".to_string(),
+            data_source: DataSource::Yahoo,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn accepts_valid_activity() {
+        assert_eq!(validate_activity_payload(&valid_activity()), Ok(()));
+    }
+
+    #[test]
+    fn rejects_non_object_body() {
+        let errors = validate_activity_payload(&json!([1, 2])).unwrap_err();
+        assert_eq!(errors, vec![FieldError::new("$", "body must be a JSON object")]);
+    }
+
+    #[test]
+    fn reports_every_missing_required_activity_field() {
+        let errors = validate_activity_payload(&json!({})).unwrap_err();
+        assert_eq!(
+            fields(&errors),
+            vec!["accountId", "assetId", "activityType", "activityDate", "currency"]
+        );
+        assert!(errors.iter().all(|e| e.reason == "is required"));
+    }
+
+    #[test]
+    fn rejects_wrong_activity_field_types() {
+        let mut payload = valid_activity();
+        payload["quantity"] = json!("ten");
+        payload["isDraft"] = json!("no");
+        payload["accountId"] = json!(42);
+
+        let errors = validate_activity_payload(&payload).unwrap_err();
+        assert_eq!(fields(&errors), vec!["accountId", "quantity", "isDraft"]);
+        assert_eq!(errors[1].reason, "must be a number");
+    }
+
+    #[test]
+    fn rejects_unknown_activity_type_and_bad_date() {
+        let mut payload = valid_activity();
+        payload["activityType"] = json!("GIFT");
+        payload["activityDate"] = json!("03/01/2024");
+
+        let errors = validate_activity_payload(&payload).unwrap_err();
+        assert_eq!(fields(&errors), vec!["activityDate", "activityType"]);
+    }
+
+    #[test]
+    fn rejects_negative_activity_amounts() {
+        let mut payload = valid_activity();
+        payload["quantity"] = json!(-5);
+        payload["fee"] = json!("-1.5");
+        payload["unitPrice"] = json!("0");
+
+        let errors = validate_activity_payload(&payload).unwrap_err();
+        assert_eq!(fields(&errors), vec!["quantity", "fee"]);
+        assert_eq!(errors[0].reason, "must not be negative");
+    }
+
+    #[test]
+    fn null_optional_fields_are_allowed() {
+        let mut payload = valid_activity();
+        payload["fee"] = Value::Null;
+        payload["comment"] = Value::Null;
+        assert_eq!(validate_activity_payload(&payload), Ok(()));
+    }
+
+    #[test]
+    fn rejects_malformed_account() {
+        let payload = json!({
+            "name": "  ",
+            "accountType": "SECURITIES",
+            "currency": "usdollar",
+            "isDefault": "yes"
+        });
+
+        let errors = validate_account_payload(&payload).unwrap_err();
+        assert_eq!(fields(&errors), vec!["name", "isDefault", "isActive", "currency"]);
+    }
+
+    #[test]
+    fn holdings_query_body_maps_onto_get_filters() {
+        let request: HoldingsQueryRequest = serde_json::from_value(json!({
+            "accountIds": ["acc-1", "acc-2"],
+            "excludeAccounts": ["acc-2"],
+            "tags": ["core", "dividend"],
+            "fields": [],
+            "positions": "long",
+            "sort": {"by": "marketValue", "direction": "desc"},
+            "limit": 20
+        }))
+        .unwrap();
+        let query = HoldingsQuery::from(request);
+
+        assert_eq!(query.account_ids, Some(vec!["acc-1".to_string(), "acc-2".to_string()]));
+        assert_eq!(query.exclude_accounts.as_deref(), Some("acc-2"));
+        assert_eq!(query.tags.as_deref(), Some("core,dividend"));
+        assert_eq!(query.fields, None);
+        assert_eq!(query.positions, PositionsFilter::Long);
+        assert_eq!(
+            query.sort,
+            Some(HoldingsSort {
+                by: HoldingsSortKey::MarketValue,
+                direction: SortDirection::Desc
+            })
+        );
+        assert_eq!(query.limit, Some(20));
+
+        let empty = HoldingsQuery::from(HoldingsQueryRequest::default());
+        assert_eq!(empty.account_ids, None);
+        assert_eq!(empty.positions, PositionsFilter::All);
+    }
+
+    #[test]
+    fn select_fields_keeps_requested_and_envelope_keys() {
+        let mut items = vec![json!({
+            "id": "h-1",
+            "quantity": 10,
+            "marketValue": {"local": 100, "base": 700},
+            "baseCurrency": "CNY"
+        })];
+        select_fields(&mut items, "quantity, marketValue,unknown");
+        assert_eq!(
+            items[0],
+            json!({"quantity": 10, "marketValue": {"local": 100, "base": 700}, "baseCurrency": "CNY"})
+        );
+    }
+
+    #[test]
+    fn twr_series_chains_to_final_cumulative_return() {
+        let point = |date: &str, value: Decimal| ReturnData {
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(),
+            value,
+        };
+        let returns = vec![
+            point("2024-01-02", dec!(0)),
+            point("2024-01-31", dec!(0.10)),
+            point("2024-02-15", dec!(0.05)),
+            point("2024-02-29", dec!(-0.01)),
+            point("2024-03-29", dec!(0.21)),
+        ];
+
+        let series = twr_series(&returns, GainsInterval::Monthly);
+        let dates: Vec<String> = series.iter().map(|(date, _, _)| date.to_string()).collect();
+        assert_eq!(dates, vec!["2024-01-31", "2024-02-29", "2024-03-29"]);
+        assert_eq!(series[0].1, dec!(0.10));
+        assert_eq!(series[1].1, dec!(-0.1));
+
+        let chained = series
+            .iter()
+            .fold(Decimal::ONE, |acc, (_, period_return, _)| acc * (Decimal::ONE + period_return));
+        assert_eq!((chained - Decimal::ONE).round_dp(4), dec!(0.21));
+        assert_eq!(series[2].2, dec!(0.21));
+    }
+
+    #[test]
+    fn historical_var_reads_the_loss_quantile() {
+        let mut returns = vec![dec!(0.01); 18];
+        returns.push(dec!(-0.03));
+        returns.push(dec!(-0.05));
+
+        // 5% of 20 returns: the second worst day
+        assert_eq!(historical_var(&returns, dec!(0.95), 1), dec!(0.03));
+        assert_eq!(historical_var(&returns, dec!(0.99), 1), dec!(0.05));
+        // Square root of time: four days doubles the one-day figure
+        assert_eq!(historical_var(&returns, dec!(0.95), 4), dec!(0.06));
+        assert_eq!(historical_var(&[dec!(0.01); 20], dec!(0.95), 1), Decimal::ZERO);
+    }
+
+    #[test]
+    fn sharpe_ratio_uses_excess_return_over_volatility() {
+        assert_eq!(sharpe_ratio(dec!(0.12), dec!(0.2), dec!(0.02)), Some(dec!(0.5)));
+        assert_eq!(sharpe_ratio(dec!(0.12), dec!(0.2), Decimal::ZERO), Some(dec!(0.6)));
+        assert_eq!(sharpe_ratio(dec!(0.12), Decimal::ZERO, Decimal::ZERO), None);
+    }
+
+    #[test]
+    fn beta_regression_recovers_leveraged_portfolio() {
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let (mut benchmark, mut portfolio) = (Vec::new(), Vec::new());
+        let (mut benchmark_growth, mut portfolio_growth) = (Decimal::ONE, Decimal::ONE);
+        for day in 0..30 {
+            let date = start + chrono::Duration::days(day);
+            if day > 0 {
+                let daily = if day % 2 == 0 { dec!(0.01) } else { dec!(-0.005) };
+                benchmark_growth *= Decimal::ONE + daily;
+                portfolio_growth *= Decimal::ONE + daily * dec!(2);
+            }
+            benchmark.push(ReturnData { date, value: benchmark_growth - Decimal::ONE });
+            portfolio.push(ReturnData { date, value: portfolio_growth - Decimal::ONE });
+        }
+
+        let (beta, alpha, r_squared, samples) = beta_regression(&portfolio, &benchmark).unwrap();
+        assert_eq!(beta, dec!(2));
+        assert_eq!(alpha, Decimal::ZERO);
+        assert_eq!(r_squared, dec!(1));
+        assert_eq!(samples, 29);
+
+        assert!(beta_regression(&portfolio[..10], &benchmark).is_err());
+    }
+
+    #[test]
+    fn content_type_is_accepted_ignores_parameters() {
+        assert!(content_type_is_accepted("application/json", JSON_MEDIA_TYPES));
+        assert!(content_type_is_accepted("Application/JSON; charset=utf-8", JSON_MEDIA_TYPES));
+        assert!(content_type_is_accepted("application/merge-patch+json", JSON_MEDIA_TYPES));
+        assert!(!content_type_is_accepted("application/x-www-form-urlencoded", JSON_MEDIA_TYPES));
+        assert!(!content_type_is_accepted("text/csv", JSON_MEDIA_TYPES));
+        assert!(content_type_is_accepted("text/csv; header=present", &["text/csv"]));
+
+        let response = unsupported_media_type_response(Some("text/plain"), JSON_MEDIA_TYPES);
+        assert_eq!(response["code"], UNSUPPORTED_MEDIA_TYPE_CODE);
+        assert_eq!(response["accepted"], json!(["application/json"]));
+    }
+
+    #[test]
+    fn interval_boundaries_end_each_period_and_the_range() {
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let dates = interval_boundaries(date("2024-01-15"), date("2024-04-10"), GainsInterval::Monthly);
+        let dates: Vec<String> = dates.iter().map(|d| d.to_string()).collect();
+        assert_eq!(dates, vec!["2024-01-31", "2024-02-29", "2024-03-31", "2024-04-10"]);
+
+        let dates = interval_boundaries(date("2024-02-01"), date("2024-12-31"), GainsInterval::Quarterly);
+        assert_eq!(dates.len(), 4);
+        assert_eq!(dates[0], date("2024-03-31"));
+        assert_eq!(dates[3], date("2024-12-31"));
+
+        assert_eq!(AllocationGroup::parse("assetClass").unwrap(), AllocationGroup::AssetClass);
+        assert!(AllocationGroup::parse("sector").is_err());
+    }
+
+    #[test]
+    fn sector_exposure_looks_through_funds_with_sector_weights() {
+        let sector = |name: &str, weight: f64| crate::portfolio::holdings::Sector {
+            name: name.to_string(),
+            weight,
+        };
+        let holding = |symbol: &str, subclass: Option<&str>, sectors: Option<Vec<crate::portfolio::holdings::Sector>>, value: Decimal| Holding {
+            id: format!("SEC-acc-1-{}", symbol),
+            account_id: "acc-1".to_string(),
+            holding_type: HoldingType::Security,
+            instrument: Some(Instrument {
+                id: symbol.to_string(),
+                symbol: symbol.to_string(),
+                name: None,
+                currency: "USD".to_string(),
+                notes: None,
+                data_source: None,
+                asset_class: Some("EQUITY".to_string()),
+                asset_subclass: subclass.map(str::to_string),
+                countries: None,
+                sectors,
+                profile_updated_at: None,
+            }),
+            quantity: Decimal::ONE,
+            open_date: None,
+            lots: None,
+            local_currency: "USD".to_string(),
+            base_currency: "USD".to_string(),
+            fx_rate: None,
+            market_value: MonetaryValue { local: value, base: value },
+            cost_basis: None,
+            price: None,
+            quote_date: None,
+            unrealized_gain: None,
+            unrealized_gain_pct: None,
+            realized_gain: None,
+            realized_gain_pct: None,
+            total_gain: None,
+            total_gain_pct: None,
+            day_change: None,
+            day_change_pct: None,
+            prev_close_value: None,
+            weight: Decimal::ZERO,
+            as_of_date: NaiveDate::from_ymd_opt(2026, 3, 2).unwrap(),
+        };
+        let holdings = vec![
+            holding("AAPL", Some("Stock"), Some(vec![sector("Technology", 1.0)]), dec!(400)),
+            holding(
+                "VTI",
+                Some("ETF"),
+                Some(vec![sector("Technology", 0.3), sector("Financial Services", 0.1)]),
+                dec!(1000),
+            ),
+            holding("BND", Some("ETF"), None, dec!(600)),
+        ];
+
+        let (exposure, looked_through) = sector_exposure(&holdings, true);
+        assert_eq!(exposure.values["Technology"], dec!(1150));
+        assert_eq!(exposure.values["Financial Services"], dec!(250));
+        assert_eq!(exposure.values["ETF"], dec!(600));
+        assert_eq!(exposure.total, dec!(2000));
+        assert_eq!(looked_through, vec!["VTI".to_string()]);
+
+        let (exposure, looked_through) = sector_exposure(&holdings, false);
+        assert_eq!(exposure.values["Technology"], dec!(400));
+        assert_eq!(exposure.values["ETF"], dec!(1600));
+        assert!(looked_through.is_empty());
+    }
+
+    #[test]
+    fn idempotency_store_replays_completed_requests() {
+        let store = IdempotencyStore::new(std::time::Duration::from_secs(60));
+        let request = valid_activity();
+        assert_eq!(store.claim("key-1", &request).unwrap(), None);
+
+        let in_progress = store.claim("key-1", &request).unwrap().unwrap();
+        assert_eq!(in_progress["code"], IDEMPOTENCY_CONFLICT_CODE);
+
+        let response = json!({"activity": {"id": "act-1"}});
+        store.complete("key-1", response.clone()).unwrap();
+        assert_eq!(store.claim("key-1", &request).unwrap(), Some(response));
+
+        let mut other = valid_activity();
+        other["quantity"] = json!(20);
+        let mismatch = store.claim("key-1", &other).unwrap().unwrap();
+        assert_eq!(mismatch["code"], IDEMPOTENCY_CONFLICT_CODE);
+
+        store.release("key-1").unwrap();
+        assert_eq!(store.claim("key-1", &other).unwrap(), None);
+    }
+
+    #[test]
+    fn idempotency_keys_expire() {
+        let store = IdempotencyStore::new(std::time::Duration::ZERO);
+        assert_eq!(store.claim("key-1", &valid_activity()).unwrap(), None);
+        assert_eq!(store.claim("key-1", &valid_activity()).unwrap(), None);
+    }
+
+    #[test]
+    fn return_contributions_sum_to_total_return() {
+        let position = |symbol: &str, start: Decimal, end: Decimal, purchases: Decimal, net_flow: Decimal| {
+            ContributionPosition {
+                symbol: symbol.to_string(),
+                start_value: start,
+                end_value: end,
+                purchases,
+                net_flow,
+            }
+        };
+        let positions = vec![
+            // Held throughout, up 10%
+            position("AAPL", dec!(600), dec!(660), dec!(0), dec!(0)),
+            // Bought 200 during the period, now worth 180
+            position("MSFT", dec!(0), dec!(180), dec!(200), dec!(200)),
+            // Sold out for 150 after starting at 100
+            position("TSLA", dec!(100), dec!(0), dec!(0), dec!(-150)),
+        ];
+
+        let (rows, total_return) = return_contributions(positions, dec!(100));
+        let symbols: Vec<&str> = rows.iter().map(|r| r["symbol"].as_str().unwrap()).collect();
+        assert_eq!(symbols, vec!["AAPL", "TSLA", "MSFT"]);
+        // Capital at work: 600 + 200 + 100 + 100 cash = 1000
+        let decimal = |value: &Value| serde_json::from_value::<Decimal>(value.clone()).unwrap();
+        assert_eq!(decimal(&rows[0]["contribution"]), dec!(0.06));
+        assert_eq!(decimal(&rows[1]["return"]), dec!(0.5));
+        assert_eq!(decimal(&rows[2]["contribution"]), dec!(-0.02));
+        assert_eq!(total_return, dec!(0.09));
+    }
+
+    #[test]
+    fn pagination_slices_and_reports_more() {
+        let mut items: Vec<u32> = (0..5).collect();
+        let page = Pagination::new(Some(2), Some(2)).apply(&mut items);
+        assert_eq!(items, vec![2, 3]);
+        assert!(page.has_more);
+        assert_eq!(page.total, 5);
+
+        let mut items: Vec<u32> = (0..5).collect();
+        let page = Pagination::new(None, Some(7)).apply(&mut items);
+        assert!(items.is_empty());
+        assert!(!page.has_more);
+    }
+
+    #[test]
+    fn pagination_clamps_to_max_page_size() {
+        assert_eq!(Pagination::new(Some(1_000_000), None).clamp(500).limit, Some(500));
+        assert_eq!(Pagination::new(Some(0), Some(3)).clamp(500), Pagination::new(None, Some(3)));
+        assert_eq!(Pagination::new(None, None).clamp(500).limit, None);
+        assert_eq!(Pagination::new(Some(20), None).clamp(500).limit, Some(20));
+    }
+
+    #[test]
+    fn pair_rate_synthesizes_missing_inverse() {
+        let rates = vec![rate("USD", "EUR", dec!(0.8))];
+        let timestamp = rates[0].timestamp;
+
+        let direct = find_pair_rate(&rates, "USD", "EUR").unwrap();
+        assert!(!direct.synthesized);
+        assert_eq!(direct.rate, dec!(0.8));
+
+        let inverse = find_pair_rate(&rates, "EUR", "USD").unwrap();
+        assert!(inverse.synthesized);
+        assert_eq!(inverse.rate, dec!(1.25));
+        assert_eq!(inverse.source.timestamp, timestamp);
+
+        assert!(find_pair_rate(&rates, "EUR", "JPY").is_none());
+    }
+
+    #[test]
+    fn refreshed_rates_are_new_or_newer_pairs() {
+        let now = Utc::now();
+        let stamped = |from: &str, to: &str, timestamp: DateTime<Utc>| ExchangeRate {
+            timestamp,
+            ..rate(from, to, dec!(1.1))
+        };
+        let yesterday = now - chrono::Duration::days(1);
+        let before = vec![stamped("EUR", "USD", yesterday), stamped("GBP", "USD", yesterday)];
+        let after = vec![
+            stamped("EUR", "USD", now),
+            stamped("GBP", "USD", yesterday),
+            stamped("JPY", "USD", now),
+        ];
+
+        let ids: Vec<String> = refreshed_exchange_rates(&before, after)
+            .into_iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(ids, vec!["EURUSD=X", "JPYUSD=X"]);
+    }
+
+    #[test]
+    fn fx_path_method_names_direct_inverse_and_chained_paths() {
+        let rates = vec![rate("EUR", "USD", dec!(2)), rate("USD", "JPY", dec!(2))];
+        let method = |from: &str, to: &str| find_fx_path(&rates, from, to).map(|hops| fx_path_method(&hops));
+
+        assert_eq!(method("USD", "USD"), Some("identity"));
+        assert_eq!(method("EUR", "USD"), Some("direct"));
+        assert_eq!(method("JPY", "USD"), Some("inverse"));
+        assert_eq!(method("EUR", "JPY"), Some("chained"));
+        assert_eq!(method("EUR", "CHF"), None);
+    }
+
+    #[test]
+    fn relevant_rates_cover_holding_currencies_via_cross_paths() {
+        let rates = vec![
+            rate("USD", "CNY", dec!(1.1)),
+            rate("HKD", "USD", dec!(1.1)),
+            rate("EUR", "USD", dec!(1.1)),
+            rate("GBP", "JPY", dec!(1.1)),
+        ];
+        let currencies: BTreeSet<String> = ["CNY", "HKD", "CHF"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+
+        let (relevant, unresolved) = relevant_exchange_rates(rates, &currencies, "CNY");
+        let ids: Vec<String> = relevant.into_iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec!["USDCNY=X", "HKDUSD=X"]);
+        assert_eq!(unresolved, vec!["CHF"]);
+    }
+
+    #[test]
+    fn response_meta_sits_beside_the_payload() {
+        let mut body = json!({"holdings": [], "baseCurrency": "CNY"});
+        add_response_meta(&mut body);
+        assert_eq!(body["baseCurrency"], "CNY");
+        assert_eq!(body["meta"]["apiVersion"], API_VERSION);
+        assert!(body["meta"]["serverTime"].is_string());
+
+        let mut list = json!([1, 2]);
+        add_response_meta(&mut list);
+        assert_eq!(list, json!([1, 2]));
+    }
+
+    #[test]
+    fn query_params_name_the_malformed_ones() {
+        let errors = validate_query_params(
+            "/api/portfolio/activities",
+            Some("limit=abc&offset=10&group_by=sector&account_id=acc-1"),
+        );
+        assert_eq!(fields(&errors), vec!["limit", "group_by"]);
+        assert!(errors[1].reason.contains("assetClass, asset_class, symbol"));
+
+        let errors = validate_query_params("/api/portfolio/fees", Some("from=2024-13-01&to=2024-12-31"));
+        assert_eq!(fields(&errors), vec!["from"]);
+        assert!(errors[0].reason.contains("YYYY-MM-DD"));
+
+        // Currency codes on the FX routes are not dates
+        assert!(validate_query_params("/api/fx/convert", Some("from=USD&to=EUR&amount=5")).is_empty());
+        assert!(validate_query_params("/api/portfolio/holdings", None).is_empty());
+    }
+
+    #[test]
+    fn lots_turn_long_term_after_one_year() {
+        let acquired = NaiveDate::from_ymd_opt(2025, 3, 15).unwrap();
+        assert!(!is_long_term(acquired, NaiveDate::from_ymd_opt(2026, 3, 14).unwrap()));
+        assert!(!is_long_term(acquired, NaiveDate::from_ymd_opt(2026, 3, 15).unwrap()));
+        assert!(is_long_term(acquired, NaiveDate::from_ymd_opt(2026, 3, 16).unwrap()));
+
+        let leap_day = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+        assert!(!is_long_term(leap_day, NaiveDate::from_ymd_opt(2025, 2, 28).unwrap()));
+        assert!(is_long_term(leap_day, NaiveDate::from_ymd_opt(2025, 3, 1).unwrap()));
+    }
+
+    struct LatestSnapshotService(crate::portfolio::snapshot::AccountStateSnapshot);
+
+    #[async_trait]
+    impl SnapshotServiceTrait for LatestSnapshotService {
+        async fn calculate_holdings_snapshots(&self, _account_ids: Option<&[String]>) -> Result<usize> {
+            Err(Error::Unexpected("LatestSnapshotService::calculate_holdings_snapshots not implemented".to_string()))
+        }
+        async fn force_recalculate_holdings_snapshots(&self, _account_ids: Option<&[String]>) -> Result<usize> {
+            Err(Error::Unexpected("LatestSnapshotService::force_recalculate_holdings_snapshots not implemented".to_string()))
+        }
+        fn get_holdings_keyframes(
+            &self,
+            _account_id: &str,
+            _start_date: Option<NaiveDate>,
+            _end_date: Option<NaiveDate>,
+        ) -> Result<Vec<crate::portfolio::snapshot::AccountStateSnapshot>> {
+            Err(Error::Unexpected("LatestSnapshotService::get_holdings_keyframes not implemented".to_string()))
+        }
+        fn get_daily_holdings_snapshots(
+            &self,
+            _account_id: &str,
+            _start_date: Option<NaiveDate>,
+            _end_date: Option<NaiveDate>,
+        ) -> Result<Vec<crate::portfolio::snapshot::AccountStateSnapshot>> {
+            Err(Error::Unexpected("LatestSnapshotService::get_daily_holdings_snapshots not implemented".to_string()))
+        }
+        fn get_latest_holdings_snapshot(
+            &self,
+            _account_id: &str,
+        ) -> Result<Option<crate::portfolio::snapshot::AccountStateSnapshot>> {
+            Ok(Some(self.0.clone()))
+        }
+        fn get_holdings_snapshot_as_of(
+            &self,
+            _account_id: &str,
+            _date: NaiveDate,
+        ) -> Result<Option<crate::portfolio::snapshot::AccountStateSnapshot>> {
+            Err(Error::Unexpected("LatestSnapshotService::get_holdings_snapshot_as_of not implemented".to_string()))
+        }
+        async fn calculate_total_portfolio_snapshots(&self) -> Result<usize> {
+            Err(Error::Unexpected("LatestSnapshotService::calculate_total_portfolio_snapshots not implemented".to_string()))
+        }
+    }
+
+    struct StubAssetService;
+
+    impl StubAssetService {
+        fn asset(id: &str) -> Asset {
+            Asset {
+                id: id.to_string(),
+                symbol: id.to_string(),
+                currency: "USD".to_string(),
+                ..Default::default()
+            }
+        }
+    }
+
+    #[async_trait]
+    impl AssetServiceTrait for StubAssetService {
+        fn get_assets(&self) -> Result<Vec<Asset>> {
+            Err(Error::Unexpected("StubAssetService::get_assets not implemented".to_string()))
+        }
+        fn get_asset_by_id(&self, asset_id: &str) -> Result<Asset> {
+            Ok(Self::asset(asset_id))
+        }
+        async fn delete_asset(&self, _asset_id: &str) -> Result<()> {
+            Err(Error::Unexpected("StubAssetService::delete_asset not implemented".to_string()))
+        }
+        async fn update_asset_profile(
+            &self,
+            _asset_id: &str,
+            _payload: crate::assets::UpdateAssetProfile,
+        ) -> Result<Asset> {
+            Err(Error::Unexpected("StubAssetService::update_asset_profile not implemented".to_string()))
+        }
+        fn load_cash_assets(&self, _base_currency: &str) -> Result<Vec<Asset>> {
+            Err(Error::Unexpected("StubAssetService::load_cash_assets not implemented".to_string()))
+        }
+        async fn create_cash_asset(&self, _currency: &str) -> Result<Asset> {
+            Err(Error::Unexpected("StubAssetService::create_cash_asset not implemented".to_string()))
+        }
+        async fn get_or_create_asset(&self, _asset_id: &str, _context_currency: Option<String>) -> Result<Asset> {
+            Err(Error::Unexpected("StubAssetService::get_or_create_asset not implemented".to_string()))
+        }
+        async fn update_asset_data_source(&self, _asset_id: &str, _data_source: String) -> Result<Asset> {
+            Err(Error::Unexpected("StubAssetService::update_asset_data_source not implemented".to_string()))
+        }
+        async fn get_assets_by_symbols(&self, symbols: &[String]) -> Result<Vec<Asset>> {
+            Ok(symbols.iter().map(|symbol| Self::asset(symbol)).collect())
+        }
+    }
+
+    /// Values every security at one price, with no FX conversion
+    struct FixedPriceValuation(Decimal);
+
+    #[async_trait]
+    impl crate::portfolio::holdings::HoldingsValuationServiceTrait for FixedPriceValuation {
+        async fn calculate_holdings_live_valuation(&self, holdings: &mut [Holding]) -> Result<()> {
+            for holding in holdings.iter_mut().filter(|h| h.holding_type == HoldingType::Security) {
+                let value = holding.quantity * self.0;
+                holding.price = Some(self.0);
+                holding.fx_rate = Some(Decimal::ONE);
+                holding.market_value = MonetaryValue { local: value, base: value };
+            }
+            Ok(())
+        }
+        async fn calculate_holdings_valuation_as_of(&self, _holdings: &mut [Holding], _as_of: NaiveDate) -> Result<()> {
+            Err(Error::Unexpected("FixedPriceValuation::calculate_holdings_valuation_as_of not implemented".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn lots_are_loaded_through_get_holding() {
+        use crate::portfolio::snapshot::{AccountStateSnapshot, Lot, Position};
+
+        let lot = |id: &str, acquired: DateTime<Utc>, quantity: Decimal, cost_basis: Decimal| Lot {
+            id: id.to_string(),
+            position_id: "acc-1_AAPL".to_string(),
+            acquisition_date: acquired,
+            quantity,
+            cost_basis,
+            acquisition_price: cost_basis / quantity,
+            acquisition_fees: Decimal::ZERO,
+        };
+        let old = Utc::now() - chrono::Duration::days(800);
+        let recent = Utc::now() - chrono::Duration::days(30);
+        let position = Position {
+            id: "acc-1_AAPL".to_string(),
+            account_id: "acc-1".to_string(),
+            asset_id: "AAPL".to_string(),
+            quantity: dec!(15),
+            total_cost_basis: dec!(1900),
+            currency: "USD".to_string(),
+            inception_date: old,
+            lots: VecDeque::from(vec![
+                lot("lot-1", old, dec!(10), dec!(1000)),
+                lot("lot-2", recent, dec!(5), dec!(900)),
+            ]),
+            ..Default::default()
+        };
+        let snapshot = AccountStateSnapshot {
+            account_id: "acc-1".to_string(),
+            positions: HashMap::from([("AAPL".to_string(), position)]),
+            cash_balances: HashMap::from([("USD".to_string(), dec!(250))]),
+            ..Default::default()
+        };
+        let holdings_service = crate::portfolio::holdings::HoldingsService::new(
+            Arc::new(StubAssetService),
+            Arc::new(LatestSnapshotService(snapshot)),
+            Arc::new(FixedPriceValuation(dec!(150))),
+        );
+
+        let holdings = holdings_service.get_holdings("acc-1", "USD").await.unwrap();
+        assert_eq!(holdings.len(), 2);
+        assert!(holdings.iter().all(|h| h.lots.is_none()));
+
+        let (positions, partial_errors) = holdings_with_lots(&holdings_service, &holdings, "USD").await;
+        assert!(partial_errors.is_empty());
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].price, Some(dec!(150)));
+        let lots = positions[0].lots.as_ref().unwrap();
+        assert_eq!(lots.iter().map(|l| l.id.as_str()).collect::<Vec<_>>(), vec!["lot-1", "lot-2"]);
+        let today = Utc::now().date_naive();
+        assert!(is_long_term(lots[0].acquisition_date.date_naive(), today));
+        assert!(!is_long_term(lots[1].acquisition_date.date_naive(), today));
+    }
+
+    #[test]
+    fn refresh_hint_waits_for_the_next_session_when_closed() {
+        let at = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc);
+
+        // Wednesday mid-session
+        assert_eq!(refresh_after_secs(at("2026-03-11T16:00:00Z"), Some("YAHOO")), INTRADAY_REFRESH_SECS);
+        assert_eq!(refresh_after_secs(at("2026-03-11T16:00:00Z"), Some("ALPHA_VANTAGE")), DAILY_REFRESH_SECS);
+        assert_eq!(refresh_after_secs(at("2026-03-11T16:00:00Z"), Some("MANUAL")), MANUAL_REFRESH_SECS);
+
+        // Wednesday after the close: Thursday 13:30
+        assert_eq!(refresh_after_secs(at("2026-03-11T22:00:00Z"), None), 15 * 3600 + 1800);
+        // Saturday noon: Monday 13:30
+        assert_eq!(refresh_after_secs(at("2026-03-14T12:00:00Z"), None), 49 * 3600 + 1800);
+    }
+
+    #[test]
+    fn config_status_flags_enabled_providers_without_credentials() {
+        let provider = |id: &str, enabled: bool| MarketDataProviderSetting {
+            id: id.to_string(),
+            name: id.to_string(),
+            description: String::new(),
+            url: None,
+            priority: 1,
+            enabled,
+            logo_filename: None,
+            last_synced_at: None,
+            last_sync_status: None,
+            last_sync_error: None,
+        };
+        let providers = vec![
+            provider(DATA_SOURCE_YAHOO, true),
+            provider(DATA_SOURCE_ALPHA_VANTAGE, true),
+            provider(DATA_SOURCE_METAL_PRICE_API, false),
+        ];
+
+        let status = config_status_to_json(&providers, |_| Some(false), true);
+        assert_eq!(status["missing"], json!([DATA_SOURCE_ALPHA_VANTAGE]));
+        assert_eq!(status["integrations"][0]["configured"], json!(true));
+        assert_eq!(status["integrations"][1]["requiresCredential"], json!(true));
+
+        // Without a secret store the status is unknown rather than missing
+        let status = config_status_to_json(&providers, |_| None, false);
+        assert_eq!(status["missing"], json!([]));
+        assert_eq!(status["integrations"][1]["configured"], Value::Null);
+    }
+
+    #[test]
+    fn net_quantity_follows_trades_transfers_and_splits() {
+        let step = |activity_type: &str, quantity: Decimal, amount: Option<Decimal>| Activity {
+            amount,
+            ..activity(activity_type, activity_type, "2025-01-02", quantity)
+        };
+        let history = [
+            step("BUY", dec!(10), None),
+            step("TRANSFER_IN", dec!(5), None),
+            step("DIVIDEND", Decimal::ZERO, Some(dec!(12))),
+            step("SPLIT", Decimal::ZERO, Some(dec!(2))),
+            step("SELL", dec!(4), None),
+            step("TRANSFER_OUT", dec!(6), None),
+        ];
+
+        let running: Vec<Decimal> = history
+            .iter()
+            .scan(Decimal::ZERO, |net, a| {
+                *net = net_quantity_after(*net, a);
+                Some(*net)
+            })
+            .collect();
+        assert_eq!(running, vec![dec!(10), dec!(15), dec!(15), dec!(30), dec!(26), dec!(20)]);
+    }
+
+    #[tokio::test]
+    async fn failing_alert_scope_does_not_block_the_others() {
+        let alert = |id: &str, account_id: Option<&str>| Alert {
+            id: id.to_string(),
+            callback_url: "http://localhost/hook".to_string(),
+            condition: AlertCondition::Above,
+            threshold: dec!(100),
+            account_id: account_id.map(str::to_string),
+            created_at: Utc::now(),
+            last_value: None,
+            last_evaluated_at: None,
+            last_triggered_at: None,
+            is_met: false,
+        };
+        let alerts = vec![
+            alert("a1", Some("acc-ok")),
+            alert("a2", Some("acc-broken")),
+            alert("a3", None),
+            alert("a4", Some("acc-ok")),
+        ];
+        let calls = std::cell::Cell::new(0);
+
+        let (valued, partial_errors) = alert_scope_values(&alerts, |account_id| {
+            calls.set(calls.get() + 1);
+            async move {
+                match account_id.as_deref() {
+                    Some("acc-broken") => Err(Error::Unexpected("valuation failed".to_string())),
+                    Some(_) => Ok(dec!(150)),
+                    None => Ok(dec!(900)),
+                }
+            }
+        })
+        .await;
+
+        let valued: Vec<(&str, Decimal)> = valued.iter().map(|(a, v)| (a.id.as_str(), *v)).collect();
+        assert_eq!(valued, vec![("a1", dec!(150)), ("a3", dec!(900)), ("a4", dec!(150))]);
+        assert_eq!(partial_errors.len(), 1);
+        assert_eq!(partial_errors[0]["alertId"], json!("a2"));
+        // Each account scope is valued once per round, failed or not
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn realized_gains_match_the_core_fifo_lots_across_splits_and_transfers() {
+        let trade = |id: &str, asset_id: &str, activity_type: &str, day: &str, quantity: Decimal, unit_price: Decimal| Activity {
+            asset_id: asset_id.to_string(),
+            unit_price,
+            fee: if activity_type == "SELL" { dec!(10) } else { Decimal::ZERO },
+            amount: (activity_type == "SPLIT").then_some(dec!(2)),
+            ..activity(id, activity_type, day, quantity)
+        };
+        let history = [
+            trade("b1", "AAPL", "BUY", "2025-01-02", dec!(10), dec!(100)),
+            trade("b2", "AAPL", "BUY", "2025-02-03", dec!(10), dec!(120)),
+            trade("m1", "MSFT", "BUY", "2025-02-10", dec!(10), dec!(50)),
+            trade("sp", "AAPL", "SPLIT", "2025-03-03", Decimal::ZERO, Decimal::ZERO),
+            trade("s1", "AAPL", "SELL", "2025-04-01", dec!(30), dec!(70)),
+            trade("m2", "MSFT", "SELL", "2025-04-15", dec!(4), dec!(60)),
+            trade("t1", "AAPL", "TRANSFER_OUT", "2025-05-02", dec!(5), dec!(70)),
+            trade("s2", "AAPL", "SELL", "2025-06-02", dec!(5), dec!(65)),
+        ];
+
+        let (sales, remaining, partial_errors) = realize_lots(&history, "USD", |amount, _, _, _| Ok(amount));
+        assert!(partial_errors.is_empty());
+
+        // The same activities through a core position directly
+        let mut position = Position::new("acc-1".into(), "AAPL".into(), "USD".into(), history[0].activity_date);
+        position.add_lot(&history[0]).unwrap();
+        position.add_lot(&history[1]).unwrap();
+        position.apply_split(dec!(2), "sp").unwrap();
+        let (_, first_cost) = position.reduce_lots_fifo(dec!(30)).unwrap();
+        position.reduce_lots_fifo(dec!(5)).unwrap();
+        let (_, second_cost) = position.reduce_lots_fifo(dec!(5)).unwrap();
+
+        // FIFO relieves the whole first lot before the second, unlike average cost
+        assert_eq!(first_cost, dec!(1600));
+        let gains: Vec<Decimal> = sales.iter().map(|sale| sale.gain).collect();
+        assert_eq!(
+            gains,
+            vec![dec!(2100) - dec!(10) - first_cost, dec!(240) - dec!(10) - dec!(200), dec!(325) - dec!(10) - second_cost]
+        );
+        assert_eq!(gains, vec![dec!(490), dec!(30), dec!(15)]);
+        assert_eq!(remaining[&("acc-1".to_string(), "AAPL".to_string())], Decimal::ZERO);
+        assert_eq!(remaining[&("acc-1".to_string(), "MSFT".to_string())], dec!(6));
+
+        // The summary total is the closed AAPL gain plus the open MSFT sale
+        let total: Decimal = sales.iter().map(|sale| sale.gain_base).sum();
+        let closed: Decimal = sales.iter().filter(|sale| sale.asset_id == "AAPL").map(|sale| sale.gain_base).sum();
+        assert_eq!(closed, dec!(505));
+        assert_eq!(total, closed + dec!(30));
+
+        // Without an exchange rate into base currency the sales still relieve their
+        // lots, but their gains are reported instead of counted unconverted
+        let (sales, remaining, partial_errors) = realize_lots(&history, "EUR", |amount, from, to, _| {
+            if to == "EUR" {
+                Err(Error::Unexpected(format!("No rate for {}/{}", from, to)))
+            } else {
+                Ok(amount)
+            }
+        });
+        assert!(sales.is_empty());
+        assert_eq!(remaining[&("acc-1".to_string(), "AAPL".to_string())], Decimal::ZERO);
+        let skipped: Vec<&Value> = partial_errors.iter().map(|error| &error["activityId"]).collect();
+        assert_eq!(skipped, vec![&json!("s1"), &json!("m2"), &json!("s2")]);
+    }
+
+    #[test]
+    fn activities_sort_by_date_then_account_asset_and_id() {
+        let buy = |id: &str, account_id: &str, asset_id: &str, day: &str| Activity {
+            account_id: account_id.to_string(),
+            asset_id: asset_id.to_string(),
+            ..activity(id, "BUY", day, Decimal::ONE)
+        };
+        let mut activities = vec![
+            buy("4", "acc-2", "AAPL", "2026-03-02"),
+            buy("3", "acc-1", "MSFT", "2026-03-02"),
+            buy("2", "acc-1", "AAPL", "2026-03-02"),
+            buy("1", "acc-9", "ZZZ", "2026-03-01"),
+            buy("0", "acc-1", "AAPL", "2026-03-02"),
+        ];
+
+        sort_activities(&mut activities);
+        let ids: Vec<&str> = activities.iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(ids, vec!["1", "0", "2", "3", "4"]);
+    }
+
+    #[test]
+    fn correlation_drops_symbols_without_enough_shared_history() {
+        let start = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        let series = |days: i64, price: &dyn Fn(i64) -> Decimal| -> BTreeMap<NaiveDate, Decimal> {
+            (0..days).map(|d| (start + chrono::Duration::days(d), price(d))).collect()
+        };
+        // Alternating up/down moves, in step and inverted
+        let wave = |d: i64| if d % 2 == 0 { dec!(100) } else { dec!(110) };
+        let inverse = |d: i64| if d % 2 == 0 { dec!(110) } else { dec!(100) };
+        let closes = BTreeMap::from([
+            ("AAA".to_string(), series(30, &wave)),
+            ("BBB".to_string(), series(30, &wave)),
+            ("CCC".to_string(), series(30, &inverse)),
+            ("NEW".to_string(), series(5, &wave)),
+        ]);
+
+        let result = correlation_matrix(&closes);
+        assert_eq!(result.symbols, vec!["AAA", "BBB", "CCC"]);
+        assert_eq!(result.dropped, vec![("NEW".to_string(), 4)]);
+        assert_eq!(result.samples, 29);
+        assert_eq!(result.matrix[0][0], Some(Decimal::ONE));
+        assert_eq!(result.matrix[0][1], Some(Decimal::ONE));
+        assert_eq!(result.matrix[0][2], Some(dec!(-1)));
+    }
+
+    #[test]
+    fn transfers_pair_across_accounts_by_amount_and_date() {
+        let leg = |account: &str, date: &str, amount: Decimal| TransferLeg {
+            activity_id: format!("{}-{}", account, date),
+            account_id: account.to_string(),
+            activity_type: "DEPOSIT".to_string(),
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(),
+            currency: "USD".to_string(),
+            amount,
+        };
+        let outflows = vec![
+            leg("checking", "2026-02-02", dec!(500)),
+            leg("checking", "2026-02-10", dec!(250)),
+        ];
+        let inflows = vec![
+            // Same account: a refund, not a transfer
+            leg("checking", "2026-02-02", dec!(500)),
+            leg("broker", "2026-02-06", dec!(500)),
+            leg("broker", "2026-02-03", dec!(500)),
+            leg("broker", "2026-02-20", dec!(250)),
+        ];
+
+        // The closest-dated deposit wins; the 250 arrives too late to match
+        assert_eq!(match_transfers(&outflows, &inflows), vec![(0, 2)]);
+    }
+
+    #[test]
+    fn quotes_are_adjusted_only_with_full_adjustment_data() {
+        let adjusted = |day: &str, close: Decimal, adjclose: Decimal| Quote {
+            high: close * dec!(1.1),
+            adjclose,
+            ..quote("NVDA", day, close)
+        };
+
+        // Before a 10:1 split, then after it
+        let mut quotes = vec![
+            adjusted("2026-03-02", dec!(1200), dec!(120)),
+            adjusted("2026-03-03", dec!(125), dec!(125)),
+        ];
+        assert!(adjust_quotes(&mut quotes));
+        assert_eq!(quotes[0].close, dec!(120));
+        assert_eq!(quotes[0].high, dec!(132));
+        assert_eq!(quotes[1].close, dec!(125));
+
+        let mut raw = vec![
+            adjusted("2026-03-02", dec!(1200), dec!(120)),
+            adjusted("2026-03-03", dec!(125), Decimal::ZERO),
+        ];
+        assert!(!adjust_quotes(&mut raw));
+        assert_eq!(raw[0].close, dec!(1200));
+    }
+
+    #[test]
+    fn exact_numbers_keep_decimal_scale() {
+        let value = MonetaryValue {
+            local: dec!(1234.5600),
+            base: dec!(0.1),
+        };
+        let precision = CurrencyPrecision::default();
+        assert_eq!(
+            NumberFormat::Exact.render_money(&value, "JPY", "USD", &precision),
+            json!({"local": "1234.5600", "base": "0.1"})
+        );
+        assert_eq!(NumberFormat::Float.render(dec!(0.1)), json!(0.1));
+        assert_eq!(NumberFormat::from_exact(None), NumberFormat::Float);
+        assert_eq!(NumberFormat::from_exact(Some(true)), NumberFormat::Exact);
+    }
+
+    #[test]
+    fn amounts_are_rounded_to_currency_precision() {
+        let precision = CurrencyPrecision::default();
+        assert_eq!(precision.round(dec!(1234.56), "JPY"), dec!(1235));
+        assert_eq!(precision.round(dec!(1.23456), "KWD"), dec!(1.235));
+        assert_eq!(precision.round(dec!(0.123456789), "btc"), dec!(0.12345679));
+        assert_eq!(precision.round(dec!(10.006), "XYZ"), dec!(10.01));
+
+        let configured = precision.with_rules("usd=4, bogus, EUR=x").with_default_places(1);
+        assert_eq!(configured.places("USD"), 4);
+        assert_eq!(configured.places("EUR"), 1);
+        assert_eq!(configured.places("JPY"), 0);
+
+        let value = MonetaryValue {
+            local: dec!(150234.7),
+            base: dec!(1001.5678),
+        };
+        assert_eq!(
+            NumberFormat::Float.render_money(&value, "JPY", "USD", &CurrencyPrecision::default()),
+            json!({"local": dec!(150235), "base": dec!(1001.57)})
+        );
+    }
+
+    #[test]
+    fn quote_gaps_skip_weekends() {
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        // Mon 2026-03-02 .. Fri 2026-03-13
+        let dates: BTreeSet<NaiveDate> = ["2026-03-02", "2026-03-03", "2026-03-10", "2026-03-12"]
+            .iter()
+            .map(|d| date(d))
+            .collect();
+
+        let gaps = quote_gaps(&dates, date("2026-03-02"), date("2026-03-13"));
+        assert_eq!(
+            gaps,
+            vec![
+                QuoteGap { from: date("2026-03-04"), to: date("2026-03-09"), days: 4 },
+                QuoteGap { from: date("2026-03-11"), to: date("2026-03-11"), days: 1 },
+                QuoteGap { from: date("2026-03-13"), to: date("2026-03-13"), days: 1 },
+            ]
+        );
+        assert!(quote_gaps(&dates, date("2026-03-07"), date("2026-03-08")).is_empty());
+    }
+
+    #[test]
+    fn profile_freshness_measures_age_from_last_update() {
+        let asset = Asset {
+            id: "AAPL".to_string(),
+            symbol: "AAPL".to_string(),
+            data_source: DATA_SOURCE_YAHOO.to_string(),
+            sectors: Some(r#"[{"name":"Technology","weight":1.0}]"#.to_string()),
+            countries: Some("[]".to_string()),
+            updated_at: NaiveDate::from_ymd_opt(2026, 1, 1).unwrap().and_hms_opt(12, 0, 0).unwrap(),
+            ..Default::default()
+        };
+        let now = DateTime::parse_from_rfc3339("2026-04-15T12:00:00Z").unwrap().with_timezone(&Utc);
 
-/// Create health response JSON
-pub fn create_health_response(port: u16) -> Value {
-    json!({
-        "status": "ok",
-        "timestamp": chrono::Utc::now().to_rfc3339(),
-        "port": port
-    })
-}
+        let freshness = profile_freshness_to_json(&asset, now, DEFAULT_PROFILE_STALE_DAYS);
+        assert_eq!(freshness["profileUpdatedAt"], "2026-01-01T12:00:00+00:00");
+        assert_eq!(freshness["ageDays"], 104);
+        assert_eq!(freshness["stale"], true);
+        assert_eq!(freshness["hasSectors"], true);
+        assert_eq!(freshness["hasCountries"], false);
 
-/// Create root response JSON
-pub fn create_root_response(port: u16) -> Value {
-    json!({
-        "message": "Wealthfolio External API",
-        "status": "running",
-        "port": port
-    })
-}
+        assert_eq!(profile_freshness_to_json(&asset, now, 120)["stale"], false);
+    }
 
-#[derive(Deserialize)]
-pub struct HoldingsQuery {
-    account_id: Option<String>,
-}
+    #[test]
+    fn sync_status_reports_provider_metadata_and_coverage() {
+        let provider = MarketDataProviderSetting {
+            id: DATA_SOURCE_YAHOO.to_string(),
+            name: "Yahoo Finance".to_string(),
+            description: String::new(),
+            url: None,
+            priority: 1,
+            enabled: true,
+            logo_filename: None,
+            last_synced_at: Some("2026-03-02T21:00:00Z".to_string()),
+            last_sync_status: Some("FAILED".to_string()),
+            last_sync_error: Some("rate limited".to_string()),
+        };
+        let asset = |id: &str, data_source: &str| Asset {
+            id: id.to_string(),
+            symbol: id.to_string(),
+            data_source: data_source.to_string(),
+            ..Default::default()
+        };
+        let assets = vec![asset("AAPL", "YAHOO"), asset("MSFT", "yahoo"), asset("HOUSE", DATA_SOURCE_MANUAL)];
+        let quotes = HashMap::from([("AAPL".to_string(), quote("AAPL", "2026-03-02", dec!(1)))]);
 
-/// Health check handler
-pub async fn health_handler(port: u16) -> Value {
-    create_health_response(port)
-}
+        let sources = sync_status_to_json(&[provider], &assets, &quotes);
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[0]["source"], DATA_SOURCE_YAHOO);
+        assert_eq!(sources[0]["lastError"], "rate limited");
+        assert_eq!(sources[0]["symbols"], 2);
+        assert_eq!(sources[0]["lastQuoteDate"], "2026-03-02");
+        assert_eq!(sources[1]["source"], DATA_SOURCE_MANUAL);
+        assert_eq!(sources[1]["enabled"], Value::Null);
+        assert_eq!(sources[1]["symbols"], 1);
+        assert_eq!(sources[1]["lastQuoteDate"], Value::Null);
+    }
 
-/// Root handler
-pub async fn root_handler(port: u16) -> Value {
-    create_root_response(port)
-}
+    #[test]
+    fn quote_stats_report_first_dates_of_extremes() {
+        let quotes = vec![
+            quote("NVDA", "2026-03-04", dec!(90)),
+            quote("NVDA", "2026-03-02", dec!(100)),
+            quote("NVDA", "2026-03-03", dec!(120)),
+            quote("NVDA", "2026-03-05", dec!(90)),
+        ];
 
-/// Portfolio holdings handler
-pub async fn portfolio_holdings_handler(
-    service: &dyn ExternalApiServiceTrait,
-    query: HoldingsQuery,
-) -> Value {
-    match service.get_holdings(query.account_id).await {
-        Ok(result) => result,
-        Err(e) => json!({
-            "error": format!("Internal server error: {}", e)
-        }),
+        let stats = quote_stats(&quotes).unwrap();
+        assert_eq!(stats.min, (dec!(90), NaiveDate::from_ymd_opt(2026, 3, 4).unwrap()));
+        assert_eq!(stats.max, (dec!(120), NaiveDate::from_ymd_opt(2026, 3, 3).unwrap()));
+        assert_eq!(stats.average, dec!(100));
+        assert_eq!(stats.count, 4);
+        assert!(quote_stats(&[]).is_none());
     }
-}
 
-/// Portfolio accounts handler
-pub async fn portfolio_accounts_handler(service: &dyn ExternalApiServiceTrait) -> Value {
-    match service.get_accounts() {
-        Ok(result) => result,
-        Err(e) => json!({
-            "error": format!("Failed to get accounts: {}", e)
-        }),
+    #[test]
+    fn quote_history_keeps_symbols_without_quotes_in_window() {
+        let quotes = vec![
+            quote("AAPL", "2026-03-04", dec!(1)),
+            quote("AAPL", "2026-03-02", dec!(1)),
+            quote("AAPL", "2026-02-27", dec!(1)),
+            quote("MSFT", "2026-01-15", dec!(1)),
+        ];
+        let symbols = vec!["AAPL".to_string(), "MSFT".to_string(), "TSLA".to_string()];
+        let from = NaiveDate::from_ymd_opt(2026, 3, 1).unwrap();
+        let to = NaiveDate::from_ymd_opt(2026, 3, 31).unwrap();
+
+        let history = quote_history_by_symbol(&symbols, quotes, from, to);
+        assert_eq!(history.len(), 3);
+        let aapl: Vec<NaiveDate> = history["AAPL"].iter().map(|q| q.timestamp.date_naive()).collect();
+        assert_eq!(
+            aapl,
+            vec![NaiveDate::from_ymd_opt(2026, 3, 2).unwrap(), NaiveDate::from_ymd_opt(2026, 3, 4).unwrap()]
+        );
+        assert!(history["MSFT"].is_empty());
+        assert!(history["TSLA"].is_empty());
     }
-}
 
-/// Exchange rates handler
-pub async fn exchange_rates_handler(service: &dyn ExternalApiServiceTrait) -> Value {
-    match service.get_exchange_rates() {
-        Ok(result) => result,
-        Err(e) => json!({
-            "error": format!("Failed to get exchange rates: {}", e)
-        }),
+    #[test]
+    fn pct_change_series_rebases_to_first_close() {
+        let quotes = vec![
+            quote("AAPL", "2026-03-02", dec!(200)),
+            quote("AAPL", "2026-03-03", dec!(210)),
+            quote("AAPL", "2026-03-04", dec!(190)),
+        ];
+
+        let series = pct_change_series(&quotes);
+        assert_eq!(series[0], json!({"date": "2026-03-02", "pctChange": 0.0}));
+        assert_eq!(series[1]["pctChange"], 0.05);
+        assert_eq!(series[2]["pctChange"], -0.05);
+
+        let zero = pct_change_series(&[quote("AAPL", "2026-03-02", Decimal::ZERO), quote("AAPL", "2026-03-03", dec!(1))]);
+        assert_eq!(zero[1]["pctChange"], Value::Null);
+        assert!(pct_change_series(&[]).is_empty());
     }
-}
 
-/// Base currency handler
-pub async fn base_currency_handler(service: &dyn ExternalApiServiceTrait) -> Value {
-    match service.get_base_currency() {
-        Ok(result) => result,
-        Err(e) => json!({
-            "error": format!("Failed to get base currency: {}", e)
-        }),
+    #[test]
+    fn value_horizons_fall_back_to_inception() {
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let values = vec![
+            (date("2026-03-02"), dec!(1000)),
+            (date("2026-03-06"), dec!(1100)),
+            (date("2026-03-09"), dec!(1150)),
+        ];
+        let as_of = date("2026-03-09");
+
+        assert_eq!(horizon_start("1D", as_of), Some(date("2026-03-08")));
+        assert_eq!(horizon_start("YTD", as_of), Some(date("2025-12-31")));
+        assert_eq!(horizon_start("ALL", as_of), None);
+
+        // A weekend target uses the Friday valuation
+        assert_eq!(
+            value_at_horizon(&values, date("2026-03-08")),
+            (date("2026-03-06"), dec!(1100), false)
+        );
+        assert_eq!(
+            value_at_horizon(&values, date("2025-12-31")),
+            (date("2026-03-02"), dec!(1000), true)
+        );
     }
-}
 
-/// Convert quote summaries to JSON format for external API
-pub fn quote_summaries_to_json(summaries: Vec<QuoteSummary>) -> Vec<Value> {
-    summaries.into_iter()
-        .map(|s| json!({
-            "symbol": s.symbol,
-            "exchange": s.exchange,
-            "name": s.short_name,
-            "type": s.quote_type
-        }))
-        .collect()
-}
+    #[test]
+    fn trailing_yield_annualizes_short_holdings() {
+        assert_eq!(trailing_yield(dec!(40), dec!(1000), None), (Some(dec!(0.04)), false));
 
-/// Convert quote to JSON format for external API
-pub fn quote_to_json(quote: Quote) -> Value {
-    json!({
-        "id": quote.id,
-        "symbol": quote.symbol,
-        "timestamp": quote.timestamp.to_rfc3339(),
-        "open": quote.open,
-        "high": quote.high,
-        "low": quote.low,
-        "close": quote.close,
-        "volume": quote.volume,
-        "currency": quote.currency,
-        "dataSource": quote.data_source
-    })
-}
+        // A fifth of a year of dividends counts five times
+        let (yield_pct, annualized) = trailing_yield(dec!(20), dec!(1000), Some(73));
+        assert_eq!(yield_pct, Some(dec!(0.1)));
+        assert!(annualized);
 
-/// Convert quotes to JSON format for external API
-pub fn quotes_to_json(quotes: Vec<Quote>) -> Vec<Value> {
-    quotes.into_iter()
-        .map(|q| quote_to_json(q))
-        .collect()
-}
+        assert_eq!(trailing_yield(dec!(5), Decimal::ZERO, None), (None, false));
+    }
 
-/// Convert performance metrics to JSON format for external API
-pub fn performance_to_json(performance: PerformanceMetrics) -> Value {
-    json!({
-        "id": performance.id,
-        "currency": performance.currency,
-        "periodStartDate": performance.period_start_date.map(|d| d.to_string()),
-        "periodEndDate": performance.period_end_date.map(|d| d.to_string()),
-        "cumulativeTWR": performance.cumulative_twr,
-        "gainLossAmount": performance.gain_loss_amount,
-        "annualizedTWR": performance.annualized_twr,
-        "simpleReturn": performance.simple_return,
-        "annualizedSimpleReturn": performance.annualized_simple_return,
-        "volatility": performance.volatility,
-        "maxDrawdown": performance.max_drawdown
-    })
-}
+    #[test]
+    fn exchange_rate_rows_report_errors_per_row() {
+        let rows: Vec<ExchangeRateRow> = serde_json::from_value(json!([
+            {"from": "USD", "to": "KZT", "rate": 470.5, "timestamp": "2026-01-09T00:00:00Z"},
+            {"from": "usd", "to": "USD", "rate": 1},
+            {"from": "TENGE", "to": "EUR", "rate": -2}
+        ]))
+        .unwrap();
 
-/// Convert simple performance metrics to JSON format for external API
-pub fn simple_performances_to_json(performances: Vec<SimplePerformanceMetrics>) -> Vec<Value> {
-    performances.into_iter()
-        .map(|p| json!({
-            "accountId": p.account_id,
-            "totalValue": p.total_value,
-            "accountCurrency": p.account_currency,
-            "baseCurrency": p.base_currency,
-            "fxRateToBase": p.fx_rate_to_base,
-            "totalGainLossAmount": p.total_gain_loss_amount,
-            "cumulativeReturnPercent": p.cumulative_return_percent,
-            "dayGainLossAmount": p.day_gain_loss_amount,
-            "dayReturnPercentModDietz": p.day_return_percent_mod_dietz,
-            "portfolioWeight": p.portfolio_weight
-        }))
-        .collect()
-}
+        let errors = validate_exchange_rate_rows(&rows).unwrap_err();
+        assert_eq!(fields(&errors), vec!["[1].to", "[2].from", "[2].rate"]);
+        assert!(validate_exchange_rate_rows(&rows[..1]).is_ok());
+    }
 
-/// Convert activities to JSON format for external API
-pub fn activities_to_json(activities: Vec<Activity>) -> Vec<Value> {
-    activities.into_iter()
-        .map(|a| json!({
-            "id": a.id,
-            "accountId": a.account_id,
-            "activityType": a.activity_type,
-            "date": a.activity_date.to_rfc3339(),
-            "assetId": a.asset_id,
-            "quantity": a.quantity,
-            "price": a.unit_price,
-            "currency": a.currency,
-            "fee": a.fee,
-            "totalAmount": a.amount
-        }))
-        .collect()
-}
+    #[test]
+    fn link_header_keeps_other_query_params() {
+        let response = json!({"total": 120, "limit": 50, "offset": 50});
+        let header = pagination_link_header(
+            "/api/portfolio/activities",
+            Some("account_id=acc%201&limit=50&offset=50&group_by=symbol"),
+            &response,
+        )
+        .unwrap();
+        assert_eq!(
+            header,
+            "</api/portfolio/activities?account_id=acc%201&group_by=symbol&limit=50&offset=100>; rel=\"next\", \
+             </api/portfolio/activities?account_id=acc%201&group_by=symbol&limit=50&offset=0>; rel=\"prev\""
+        );
 
-/// Market data search query
-#[derive(Deserialize)]
-pub struct MarketDataSearchQuery {
-    q: String,
-}
+        let unpaginated = json!({"total": 120, "limit": null, "offset": 0});
+        assert_eq!(pagination_link_header("/api/portfolio/holdings", None, &unpaginated), None);
+    }
 
-/// Quote symbol parameter
-#[derive(Deserialize)]
-pub struct QuoteSymbolParam {
-    symbol: String,
-}
+    #[test]
+    fn apply_trade_moves_value_between_buckets() {
+        let before = Allocation {
+            values: BTreeMap::from([
+                ("CASH".to_string(), dec!(200)),
+                ("Equity".to_string(), dec!(800)),
+            ]),
+            total: dec!(1000),
+        };
 
-/// Performance account parameter
-#[derive(Deserialize)]
-pub struct PerformanceAccountParam {
-    account_id: String,
-}
+        let after = apply_trade(&before, ("Fixed Income", dec!(150)), ("CASH", dec!(-151)));
+        assert_eq!(after.total, dec!(999));
+        assert_eq!(after.values["Fixed Income"], dec!(150));
+        assert_eq!(after.values["CASH"], dec!(49));
 
-/// Activities query
-#[derive(Deserialize)]
-pub struct ActivitiesQuery {
-    account_id: Option<String>,
-}
+        let sold_out = apply_trade(&before, ("Equity", dec!(-800)), ("CASH", dec!(800)));
+        assert!(!sold_out.values.contains_key("Equity"));
+        assert_eq!(sold_out.weight("CASH"), Decimal::ONE);
+    }
 
-/// Market data search handler
-pub async fn market_data_search_handler(
-    service: &dyn ExternalApiServiceTrait,
-    query: MarketDataSearchQuery,
-) -> Value {
-    match service.search_market_data(&query.q).await {
-        Ok(result) => result,
-        Err(e) => json!({
-            "error": format!("Failed to search market data: {}", e)
-        }),
+    #[test]
+    fn day_change_return_is_measured_against_previous_close() {
+        let mut change = DayChange::default();
+        assert_eq!(change.return_pct(), Some(Decimal::ZERO));
+
+        change.add(dec!(30), dec!(1000));
+        change.add(dec!(-10), dec!(1000));
+        assert_eq!(change.amount, dec!(20));
+        assert_eq!(change.return_pct(), Some(dec!(0.01)));
+
+        let unmeasured = DayChange {
+            amount: dec!(5),
+            previous_value: Decimal::ZERO,
+        };
+        assert_eq!(unmeasured.return_pct(), None);
     }
-}
 
-/// Quote handler
-pub async fn quote_handler(
-    service: &dyn ExternalApiServiceTrait,
-    symbol: &str,
-) -> Value {
-    match service.get_quote(symbol) {
-        Ok(result) => result,
-        Err(e) => json!({
-            "error": format!("Failed to get quote for {}: {}", symbol, e)
-        }),
+    #[test]
+    fn annualized_windows_need_full_history() {
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let as_of = date("2026-03-31");
+        let inception = date("2023-01-15");
+
+        assert_eq!(annualized_window_start(as_of, 1, inception), Some(date("2025-03-31")));
+        assert_eq!(annualized_window_start(as_of, 3, inception), Some(date("2023-03-31")));
+        assert_eq!(annualized_window_start(as_of, 5, inception), None);
+        // Month-end anchors clamp to the shorter month
+        assert_eq!(annualized_window_start(date("2024-02-29"), 1, inception), Some(date("2023-02-28")));
     }
-}
 
-/// Historical quotes handler
-pub async fn historical_quotes_handler(
-    service: &dyn ExternalApiServiceTrait,
-    symbol: &str,
-) -> Value {
-    match service.get_historical_quotes(symbol) {
-        Ok(result) => result,
-        Err(e) => json!({
-            "error": format!("Failed to get historical quotes for {}: {}", symbol, e)
-        }),
+    #[test]
+    fn comparison_window_starts_at_later_inception() {
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let a = (date("2022-01-03"), date("2026-03-31"));
+        let b = (date("2024-05-01"), date("2026-03-30"));
+
+        assert_eq!(comparison_window(None, None, a, b), Some((date("2024-05-01"), date("2026-03-30"))));
+        assert_eq!(
+            comparison_window(Some(date("2023-01-01")), Some(date("2025-12-31")), a, b),
+            Some((date("2024-05-01"), date("2025-12-31")))
+        );
+        assert_eq!(
+            comparison_window(Some(date("2025-01-01")), None, a, b),
+            Some((date("2025-01-01"), date("2026-03-30")))
+        );
+        assert_eq!(comparison_window(None, Some(date("2023-12-31")), a, b), None);
     }
-}
 
-/// Account performance handler
-pub async fn account_performance_handler(
-    service: &dyn ExternalApiServiceTrait,
-    account_id: &str,
-) -> Value {
-    match service.get_account_performance(account_id).await {
-        Ok(result) => result,
-        Err(e) => json!({
-            "error": format!("Failed to get performance for account {}: {}", account_id, e)
-        }),
+    #[test]
+    fn valuation_series_converts_to_base_in_date_order() {
+        let valuation = |day: u32, total: Decimal, contributed: Decimal| DailyAccountValuation {
+            id: format!("acc-1_{}", day),
+            account_id: "acc-1".to_string(),
+            valuation_date: NaiveDate::from_ymd_opt(2024, 3, day).unwrap(),
+            account_currency: "EUR".to_string(),
+            base_currency: "USD".to_string(),
+            fx_rate_to_base: dec!(1.1),
+            cash_balance: Decimal::ZERO,
+            investment_market_value: total,
+            total_value: total,
+            cost_basis: contributed,
+            net_contribution: contributed,
+            calculated_at: Utc::now(),
+        };
+        let series = valuation_series_to_json(&[
+            valuation(2, dec!(1100), dec!(1000)),
+            valuation(1, dec!(1000), dec!(1000)),
+        ]);
+
+        assert_eq!(series[0]["date"], "2024-03-01");
+        assert_eq!(series[1]["date"], "2024-03-02");
+        assert_eq!(series[1]["value"], json!(dec!(1210.0)));
+        assert_eq!(series[1]["netContribution"], json!(dec!(1100.0)));
+        assert_eq!(series[1]["fxRateToBase"], json!(dec!(1.1)));
     }
-}
 
-/// Portfolio performance summary handler
-pub async fn portfolio_performance_summary_handler(service: &dyn ExternalApiServiceTrait) -> Value {
-    match service.get_portfolio_performance_summary() {
-        Ok(result) => result,
-        Err(e) => json!({
-            "error": format!("Failed to get portfolio performance summary: {}", e)
-        }),
+    #[test]
+    fn top_buckets_rank_by_value_and_keep_the_rest() {
+        let allocation = Allocation {
+            values: BTreeMap::from([
+                ("AAPL".to_string(), dec!(300)),
+                ("$CASH-USD".to_string(), dec!(100)),
+                ("MSFT".to_string(), dec!(500)),
+                ("NVDA".to_string(), dec!(100)),
+            ]),
+            total: dec!(1000),
+        };
+
+        let (top, rest) = top_buckets(&allocation, 2);
+        assert_eq!(top, vec![("MSFT", dec!(500)), ("AAPL", dec!(300))]);
+        assert_eq!(rest, vec![("$CASH-USD", dec!(100)), ("NVDA", dec!(100))]);
+
+        let (all, none) = top_buckets(&allocation, 10);
+        assert_eq!(all.len(), 4);
+        assert!(none.is_empty());
     }
-}
 
-/// Activities handler
-pub async fn activities_handler(
-    service: &dyn ExternalApiServiceTrait,
-    query: ActivitiesQuery,
-) -> Value {
-    match service.get_activities(query.account_id) {
-        Ok(result) => result,
-        Err(e) => json!({
-            "error": format!("Failed to get activities: {}", e)
-        }),
+    #[test]
+    fn watchlist_merge_skips_blanks_and_duplicates() {
+        let mut watchlist = vec!["AAPL".to_string()];
+        merge_watchlist_symbols(
+            &mut watchlist,
+            &["aapl".to_string(), " MSFT ".to_string(), "".to_string(), "MSFT".to_string()],
+        );
+        assert_eq!(watchlist, vec!["AAPL", "MSFT"]);
+    }
+
+    #[test]
+    fn tags_are_trimmed_and_deduplicated_per_symbol() {
+        let tags = normalize_tags(&[
+            " core ".to_string(),
+            "Core".to_string(),
+            "".to_string(),
+            "speculative".to_string(),
+        ]);
+        assert_eq!(tags, vec!["core", "speculative"]);
+        assert_eq!(split_tags("core, ,speculative"), vec!["core", "speculative"]);
+    }
+
+    fn summary(symbol: &str, name: &str, score: f64) -> QuoteSummary {
+        QuoteSummary {
+            symbol: symbol.to_string(),
+            short_name: name.to_string(),
+            long_name: name.to_string(),
+            score,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn resolves_exact_symbol_with_full_confidence() {
+        let results = vec![summary("APLE", "Apple Hospitality", 900.0), summary("AAPL", "Apple Inc.", 100.0)];
+        assert_eq!(best_symbol_match("aapl", &results), Some((1, 1.0)));
+    }
+
+    #[test]
+    fn resolves_clear_top_score_and_reports_ambiguity() {
+        let clear = vec![summary("AAPL", "Apple Inc.", 300.0), summary("APLE", "Apple Hospitality", 100.0)];
+        assert_eq!(best_symbol_match("apple", &clear), Some((0, 0.75)));
+
+        let close = vec![summary("AAPL", "Apple Inc.", 300.0), summary("APLE", "Apple Hospitality", 200.0)];
+        assert_eq!(best_symbol_match("apple", &close), None);
+        assert_eq!(best_symbol_match("apple", &[]), None);
+    }
+
+    #[test]
+    fn resource_version_honours_conditional_headers() {
+        let version = ResourceVersion {
+            last_modified: DateTime::parse_from_rfc3339("2024-03-01T10:00:00.250Z")
+                .unwrap()
+                .with_timezone(&Utc),
+            etag: "W/\"abc\"".to_string(),
+        };
+        assert_eq!(version.last_modified_header(), "Fri, 01 Mar 2024 10:00:00 GMT");
+
+        assert!(version.is_fresh(None, Some("Fri, 01 Mar 2024 10:00:00 GMT")));
+        assert!(!version.is_fresh(None, Some("Fri, 01 Mar 2024 09:59:59 GMT")));
+        assert!(!version.is_fresh(None, Some("not a date")));
+        assert!(version.is_fresh(Some("W/\"xyz\", W/\"abc\""), None));
+        // A changed ETag wins even if the date alone would say unchanged
+        assert!(!version.is_fresh(Some("W/\"xyz\""), Some("Fri, 01 Mar 2024 10:00:00 GMT")));
+        assert!(!version.is_fresh(None, None));
+    }
+
+    #[test]
+    fn time_format_renders_rfc3339_or_epoch_millis() {
+        let timestamp = DateTime::parse_from_rfc3339("2024-03-01T10:00:00.250Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(TimeFormat::default().render(timestamp), json!("2024-03-01T10:00:00.250+00:00"));
+        assert_eq!(TimeFormat::EpochMs.render(timestamp), json!(1709287200250_i64));
+    }
+
+    #[test]
+    fn parses_search_asset_classes() {
+        assert_eq!(parse_search_asset_classes("etf, Equity").unwrap(), vec!["ETF", "EQUITY"]);
+        let error = parse_search_asset_classes("etf,stonks").unwrap_err();
+        assert!(error.to_string().contains("Unknown asset_class 'stonks'"));
+    }
+
+    #[test]
+    fn validation_response_lists_details() {
+        let response = validation_error_response(&[FieldError::new("name", "is required")]);
+        assert_eq!(response["code"], VALIDATION_ERROR_CODE);
+        assert_eq!(response["details"][0]["field"], "name");
+        assert_eq!(response["details"][0]["reason"], "is required");
     }
 }
@@ -6,7 +6,7 @@ use crate::errors::Result;
 use crate::fx::currency::{denormalization_multiplier, normalize_currency_code};
 use crate::market_data::market_data_model::DataSource;
 use async_trait::async_trait;
-use chrono::{NaiveDate, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use rust_decimal::Decimal;
 use std::sync::{Arc, RwLock};
 
@@ -181,6 +181,14 @@ impl FxServiceTrait for FxService {
     }
 
     async fn add_exchange_rate(&self, new_rate: NewExchangeRate) -> Result<ExchangeRate> {
+        self.add_exchange_rate_at(new_rate, Utc::now()).await
+    }
+
+    async fn add_exchange_rate_at(
+        &self,
+        new_rate: NewExchangeRate,
+        timestamp: DateTime<Utc>,
+    ) -> Result<ExchangeRate> {
         // Create the FX asset with the original currency codes (not normalized)
         self.repository
             .create_fx_asset(
@@ -196,7 +204,7 @@ impl FxServiceTrait for FxService {
             to_currency: new_rate.to_currency,
             rate: new_rate.rate,
             source: new_rate.source,
-            timestamp: Utc::now(),
+            timestamp,
         };
 
         self.repository.save_exchange_rate(rate).await
@@ -2,7 +2,7 @@ use super::fx_model::{ExchangeRate, NewExchangeRate};
 use crate::errors::Result;
 use crate::market_data::market_data_model::Quote;
 use async_trait::async_trait;
-use chrono::{NaiveDate, NaiveDateTime};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
 use rust_decimal::Decimal;
 
 /// Trait defining the contract for FX repository operations.
@@ -69,6 +69,12 @@ pub trait FxServiceTrait: Send + Sync {
     ) -> Result<Decimal>;
     fn get_latest_exchange_rates(&self) -> Result<Vec<ExchangeRate>>;
     async fn add_exchange_rate(&self, new_rate: NewExchangeRate) -> Result<ExchangeRate>;
+    /// Upserts a rate observed at `timestamp`, replacing any rate stored for that pair and day.
+    async fn add_exchange_rate_at(
+        &self,
+        new_rate: NewExchangeRate,
+        timestamp: DateTime<Utc>,
+    ) -> Result<ExchangeRate>;
     async fn update_exchange_rate(
         &self,
         from_currency: &str,
@@ -135,6 +135,34 @@ impl MarketDataServiceTrait for MarketDataService {
             .map_err(|e| e.into())
     }
 
+    async fn get_historical_quotes_with_failover(
+        &self,
+        symbol: &str,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        sources: Option<&[String]>,
+    ) -> Result<(String, Vec<Quote>)> {
+        let start_time: SystemTime = Utc
+            .from_utc_datetime(&start_date.and_hms_opt(0, 0, 0).unwrap())
+            .into();
+        let end_time: SystemTime = Utc
+            .from_utc_datetime(&end_date.and_hms_opt(23, 59, 59).unwrap())
+            .into();
+
+        self.provider_registry
+            .read()
+            .await
+            .historical_quotes_with_failover(
+                symbol,
+                start_time,
+                end_time,
+                "USD".to_string(),
+                sources,
+            )
+            .await
+            .map_err(|e| e.into())
+    }
+
     async fn sync_market_data(&self) -> Result<((), Vec<(String, String)>)> {
         debug!("Syncing market data.");
         let assets = self.asset_repository.list()?;
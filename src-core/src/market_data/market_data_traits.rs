@@ -28,6 +28,13 @@ pub trait MarketDataServiceTrait: Send + Sync {
         start_date: NaiveDate,
         end_date: NaiveDate,
     ) -> Result<Vec<Quote>>;
+    async fn get_historical_quotes_with_failover(
+        &self,
+        symbol: &str,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        sources: Option<&[String]>,
+    ) -> Result<(String, Vec<Quote>)>;
     async fn sync_market_data(&self) -> Result<((), Vec<(String, String)>)>;
     async fn resync_market_data(
         &self,
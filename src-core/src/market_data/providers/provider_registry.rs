@@ -231,6 +231,50 @@ impl ProviderRegistry {
         Err(MarketDataError::NotFound(symbol.to_string()))
     }
 
+    /// Fetches historical quotes trying providers in the given order (or the configured
+    /// priority order when `order` is `None`), falling back to the next provider on an
+    /// error or an empty result. Returns the id of the provider that answered.
+    pub async fn historical_quotes_with_failover(
+        &self,
+        symbol: &str,
+        start: SystemTime,
+        end: SystemTime,
+        fallback_currency: String,
+        order: Option<&[String]>,
+    ) -> Result<(String, Vec<ModelQuote>), MarketDataError> {
+        let providers: Vec<(&String, &Arc<dyn MarketDataProvider + Send + Sync>)> = match order {
+            Some(ids) => ids
+                .iter()
+                .filter_map(|id| match self.data_providers.get_key_value(id) {
+                    Some(entry) => Some(entry),
+                    None => {
+                        warn!("Requested provider '{}' is not enabled. Skipping.", id);
+                        None
+                    }
+                })
+                .collect(),
+            None => self.get_enabled_providers(),
+        };
+
+        for (provider_id, p) in providers {
+            match p
+                .get_historical_quotes(symbol, start, end, fallback_currency.clone())
+                .await
+            {
+                Ok(q_vec) if !q_vec.is_empty() => return Ok((provider_id.clone(), q_vec)),
+                Ok(_) | Err(MarketDataError::NoData) => info!(
+                    "Provider '{}' returned no historical quotes for symbol '{}'. Trying next.",
+                    provider_id, symbol
+                ),
+                Err(e) => warn!(
+                    "Provider '{}' failed to get historical quotes for symbol '{}': {:?}. Trying next.",
+                    provider_id, symbol, e
+                ),
+            }
+        }
+        Err(MarketDataError::NotFound(symbol.to_string()))
+    }
+
     pub async fn historical_quotes_bulk(
         &self,
         symbols_with_currencies: &[(String, String)],
@@ -1,4 +1,4 @@
-use chrono::{DateTime, NaiveDate, Utc};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
@@ -45,6 +45,9 @@ pub struct Instrument {
     // Display categorization
     pub countries: Option<Vec<Country>>,
     pub sectors: Option<Vec<Sector>>,
+
+    // When the stored asset profile was last written
+    pub profile_updated_at: Option<NaiveDateTime>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -89,6 +92,9 @@ pub struct Holding {
     pub market_value: MonetaryValue,
     pub cost_basis: Option<MonetaryValue>,
     pub price: Option<Decimal>,
+    // Date of the quote behind `price`; None for cash or when no quote was found
+    #[serde(default)]
+    pub quote_date: Option<NaiveDate>,
 
     // Total performance (since inception or purchase)
     pub unrealized_gain: Option<MonetaryValue>,
@@ -5,9 +5,9 @@ use crate::fx::currency::{get_normalization_rule, normalize_currency_code};
 use crate::portfolio::holdings::holdings_model::{
     Country, Holding, HoldingType, Instrument, MonetaryValue, Sector,
 };
-use crate::portfolio::snapshot::{self, Position, SnapshotServiceTrait};
+use crate::portfolio::snapshot::{self, AccountStateSnapshot, Position, SnapshotServiceTrait};
 use async_trait::async_trait;
-use chrono::Utc;
+use chrono::{NaiveDate, Utc};
 use log::{debug, error, warn};
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
@@ -21,6 +21,15 @@ use super::HoldingsValuationServiceTrait;
 pub trait HoldingsServiceTrait: Send + Sync {
     async fn get_holdings(&self, account_id: &str, base_currency: &str) -> Result<Vec<Holding>>;
 
+    /// Reconstructs holdings as they stood on `as_of`, valued with that date's quotes and FX rates.
+    /// Returns an empty list when the account had no activity up to that date.
+    async fn get_holdings_as_of(
+        &self,
+        account_id: &str,
+        base_currency: &str,
+        as_of: NaiveDate,
+    ) -> Result<Vec<Holding>>;
+
     /// Retrieves a specific holding for an account, calculates its valuation, and includes lot details.
     async fn get_holding(
         &self,
@@ -49,95 +58,25 @@ impl HoldingsService {
             valuation_service,
         }
     }
-}
-
-fn apply_factor_to_monetary_value(value: &mut MonetaryValue, factor: Decimal) {
-    value.local *= factor;
-}
-
-fn apply_factor_to_optional_monetary_value(value: &mut Option<MonetaryValue>, factor: Decimal) {
-    if let Some(v) = value {
-        apply_factor_to_monetary_value(v, factor);
-    }
-}
-
-fn normalize_holding_currency(holding: &mut Holding) {
-    if let Some(instrument) = holding.instrument.as_mut() {
-        let normalized_currency = normalize_currency_code(&instrument.currency);
-        instrument.currency = normalized_currency.to_string();
-    }
-
-    if let Some(rule) = get_normalization_rule(&holding.local_currency) {
-        let factor = rule.factor;
-        holding.local_currency = rule.major_code.to_string();
-
-        if let Some(rate) = holding.fx_rate {
-            holding.fx_rate = Some(rate / factor);
-        }
-
-        if holding.holding_type == HoldingType::Security {
-            if let Some(price) = holding.price {
-                holding.price = Some(price * factor);
-            }
-        } else if holding.holding_type == HoldingType::Cash {
-            holding.price = Some(Decimal::ONE);
-        }
-
-        apply_factor_to_monetary_value(&mut holding.market_value, factor);
-        apply_factor_to_optional_monetary_value(&mut holding.cost_basis, factor);
-        apply_factor_to_optional_monetary_value(&mut holding.unrealized_gain, factor);
-        apply_factor_to_optional_monetary_value(&mut holding.realized_gain, factor);
-        apply_factor_to_optional_monetary_value(&mut holding.total_gain, factor);
-        apply_factor_to_optional_monetary_value(&mut holding.day_change, factor);
-        apply_factor_to_optional_monetary_value(&mut holding.prev_close_value, factor);
 
-        if let Some(lots) = holding.lots.as_mut() {
-            for lot in lots {
-                lot.cost_basis *= factor;
-                lot.acquisition_price *= factor;
-                lot.acquisition_fees *= factor;
-            }
-        }
-    }
-}
-
-#[async_trait]
-impl HoldingsServiceTrait for HoldingsService {
-    async fn get_holdings(&self, account_id: &str, base_currency: &str) -> Result<Vec<Holding>> {
-        debug!(
-            "Getting holdings for account {} in base currency {}",
-            account_id, base_currency
-        );
-        let today = Utc::now().date_naive();
-
-        let latest_snapshot = match self
-            .snapshot_service
-            .get_latest_holdings_snapshot(account_id)
-        {
-            Ok(Some(snap)) => snap,
-            Ok(None) => {
-                warn!(
-                    "No calculated holdings found for account {}. Returning empty holdings list.",
-                    account_id
-                );
-                return Ok(Vec::new());
-            }
-            Err(core_error) => {
-                error!(
-                    "Failed to get latest snapshot for account {}: {}",
-                    account_id, core_error
-                );
-                return Err(core_error);
-            }
-        };
+    /// Turns a holdings snapshot into valued holdings. Uses live quotes when `as_of` is `None`,
+    /// otherwise the quotes and FX rates in effect on that date.
+    async fn build_holdings(
+        &self,
+        account_id: &str,
+        base_currency: &str,
+        holdings_snapshot: AccountStateSnapshot,
+        as_of: Option<NaiveDate>,
+    ) -> Result<Vec<Holding>> {
+        let as_of_date = as_of.unwrap_or_else(|| Utc::now().date_naive());
 
-        let snapshot_positions: Vec<snapshot::Position> = latest_snapshot
+        let snapshot_positions: Vec<snapshot::Position> = holdings_snapshot
             .positions
             .values()
             .filter(|p| p.quantity != Decimal::ZERO)
             .cloned()
             .collect();
-        let cash_balances_map: &HashMap<String, Decimal> = &latest_snapshot.cash_balances;
+        let cash_balances_map: &HashMap<String, Decimal> = &holdings_snapshot.cash_balances;
 
         let security_symbols: Vec<String> = snapshot_positions
             .iter()
@@ -197,6 +136,7 @@ impl HoldingsServiceTrait for HoldingsService {
                                     })
                                     .collect()
                             }),
+                            profile_updated_at: Some(asset.updated_at),
                         };
                         (asset.id, instrument)
                     })
@@ -245,6 +185,7 @@ impl HoldingsServiceTrait for HoldingsService {
                     base: Decimal::ZERO,
                 }),
                 price: None,
+                quote_date: None,
                 unrealized_gain: None,
                 unrealized_gain_pct: None,
                 realized_gain: None,
@@ -255,7 +196,7 @@ impl HoldingsServiceTrait for HoldingsService {
                 day_change_pct: None,
                 prev_close_value: None,
                 weight: Decimal::ZERO,
-                as_of_date: today,
+                as_of_date,
             };
             holdings.push(holding_view);
         }
@@ -285,6 +226,7 @@ impl HoldingsServiceTrait for HoldingsService {
                     base: Decimal::ZERO,
                 }),
                 price: Some(dec!(1.0)),
+                quote_date: None,
                 unrealized_gain: Some(MonetaryValue::zero()),
                 unrealized_gain_pct: Some(Decimal::ZERO),
                 realized_gain: Some(MonetaryValue::zero()),
@@ -298,21 +240,29 @@ impl HoldingsServiceTrait for HoldingsService {
                     base: Decimal::ZERO,
                 }),
                 weight: Decimal::ZERO,
-                as_of_date: today,
+                as_of_date,
             };
             holdings.push(holding_view);
         }
 
         if !holdings.is_empty() {
-            match self
-                .valuation_service
-                .calculate_holdings_live_valuation(&mut holdings)
-                .await
-            {
+            let valuation = match as_of {
+                Some(date) => {
+                    self.valuation_service
+                        .calculate_holdings_valuation_as_of(&mut holdings, date)
+                        .await
+                }
+                None => {
+                    self.valuation_service
+                        .calculate_holdings_live_valuation(&mut holdings)
+                        .await
+                }
+            };
+            match valuation {
                 Ok(_) => (),
                 Err(e) => {
                     error!(
-                         "Live valuation calculation failed for account {}: {}. Returning partially valued holdings.",
+                         "Valuation calculation failed for account {}: {}. Returning partially valued holdings.",
                          account_id, e
                      );
                 }
@@ -347,6 +297,118 @@ impl HoldingsServiceTrait for HoldingsService {
 
         Ok(holdings)
     }
+}
+
+fn apply_factor_to_monetary_value(value: &mut MonetaryValue, factor: Decimal) {
+    value.local *= factor;
+}
+
+fn apply_factor_to_optional_monetary_value(value: &mut Option<MonetaryValue>, factor: Decimal) {
+    if let Some(v) = value {
+        apply_factor_to_monetary_value(v, factor);
+    }
+}
+
+fn normalize_holding_currency(holding: &mut Holding) {
+    if let Some(instrument) = holding.instrument.as_mut() {
+        let normalized_currency = normalize_currency_code(&instrument.currency);
+        instrument.currency = normalized_currency.to_string();
+    }
+
+    if let Some(rule) = get_normalization_rule(&holding.local_currency) {
+        let factor = rule.factor;
+        holding.local_currency = rule.major_code.to_string();
+
+        if let Some(rate) = holding.fx_rate {
+            holding.fx_rate = Some(rate / factor);
+        }
+
+        if holding.holding_type == HoldingType::Security {
+            if let Some(price) = holding.price {
+                holding.price = Some(price * factor);
+            }
+        } else if holding.holding_type == HoldingType::Cash {
+            holding.price = Some(Decimal::ONE);
+        }
+
+        apply_factor_to_monetary_value(&mut holding.market_value, factor);
+        apply_factor_to_optional_monetary_value(&mut holding.cost_basis, factor);
+        apply_factor_to_optional_monetary_value(&mut holding.unrealized_gain, factor);
+        apply_factor_to_optional_monetary_value(&mut holding.realized_gain, factor);
+        apply_factor_to_optional_monetary_value(&mut holding.total_gain, factor);
+        apply_factor_to_optional_monetary_value(&mut holding.day_change, factor);
+        apply_factor_to_optional_monetary_value(&mut holding.prev_close_value, factor);
+
+        if let Some(lots) = holding.lots.as_mut() {
+            for lot in lots {
+                lot.cost_basis *= factor;
+                lot.acquisition_price *= factor;
+                lot.acquisition_fees *= factor;
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl HoldingsServiceTrait for HoldingsService {
+    async fn get_holdings(&self, account_id: &str, base_currency: &str) -> Result<Vec<Holding>> {
+        debug!(
+            "Getting holdings for account {} in base currency {}",
+            account_id, base_currency
+        );
+        let latest_snapshot = match self
+            .snapshot_service
+            .get_latest_holdings_snapshot(account_id)
+        {
+            Ok(Some(snap)) => snap,
+            Ok(None) => {
+                warn!(
+                    "No calculated holdings found for account {}. Returning empty holdings list.",
+                    account_id
+                );
+                return Ok(Vec::new());
+            }
+            Err(core_error) => {
+                error!(
+                    "Failed to get latest snapshot for account {}: {}",
+                    account_id, core_error
+                );
+                return Err(core_error);
+            }
+        };
+
+        self.build_holdings(account_id, base_currency, latest_snapshot, None)
+            .await
+    }
+
+    async fn get_holdings_as_of(
+        &self,
+        account_id: &str,
+        base_currency: &str,
+        as_of: NaiveDate,
+    ) -> Result<Vec<Holding>> {
+        debug!(
+            "Getting holdings for account {} as of {} in base currency {}",
+            account_id, as_of, base_currency
+        );
+
+        match self
+            .snapshot_service
+            .get_holdings_snapshot_as_of(account_id, as_of)?
+        {
+            Some(snap) => {
+                self.build_holdings(account_id, base_currency, snap, Some(as_of))
+                    .await
+            }
+            None => {
+                debug!(
+                    "No holdings for account {} on or before {}. Returning empty holdings list.",
+                    account_id, as_of
+                );
+                Ok(Vec::new())
+            }
+        }
+    }
 
     async fn get_holding(
         &self,
@@ -461,6 +523,7 @@ impl HoldingsServiceTrait for HoldingsService {
                     })
                     .collect()
             }),
+            profile_updated_at: Some(asset_details.updated_at),
         };
 
         let holding_view = Holding {
@@ -480,6 +543,7 @@ impl HoldingsServiceTrait for HoldingsService {
                 base: Decimal::ZERO,
             }),
             price: None,
+            quote_date: None,
             unrealized_gain: None,
             unrealized_gain_pct: None,
             realized_gain: None,
@@ -550,6 +614,7 @@ mod tests {
                 asset_subclass: None,
                 countries: None,
                 sectors: None,
+                profile_updated_at: None,
             }),
             quantity: dec!(1),
             open_date: None,
@@ -574,6 +639,7 @@ mod tests {
                 base: dec!(30),
             }),
             price: Some(dec!(3090)),
+            quote_date: None,
             unrealized_gain: Some(MonetaryValue {
                 local: dec!(90),
                 base: dec!(0.9),
@@ -645,6 +711,7 @@ mod tests {
                 base: dec!(10),
             }),
             price: Some(dec!(1)),
+            quote_date: None,
             unrealized_gain: Some(MonetaryValue::zero()),
             unrealized_gain_pct: Some(Decimal::ZERO),
             realized_gain: Some(MonetaryValue::zero()),
@@ -1,22 +1,32 @@
 use crate::errors::Result;
 use crate::fx::currency::{normalize_amount, normalize_currency_code};
 use crate::fx::fx_traits::FxServiceTrait;
-use crate::market_data::market_data_model::LatestQuotePair;
+use crate::market_data::market_data_model::{LatestQuotePair, Quote};
 use crate::market_data::market_data_traits::MarketDataServiceTrait;
 use crate::portfolio::holdings::{Holding, HoldingType, MonetaryValue};
 use async_trait::async_trait;
-use chrono::Utc;
+use chrono::{Duration, NaiveDate, Utc};
 use log::{debug, warn};
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 #[async_trait]
 pub trait HoldingsValuationServiceTrait: Send + Sync {
     async fn calculate_holdings_live_valuation(&self, holdings: &mut [Holding]) -> Result<()>;
+
+    /// Values holdings with the last quotes and FX rates available on or before `as_of`.
+    async fn calculate_holdings_valuation_as_of(
+        &self,
+        holdings: &mut [Holding],
+        as_of: NaiveDate,
+    ) -> Result<()>;
 }
 
+/// How far back to look for a quote when valuing holdings on a past date.
+const HISTORICAL_QUOTE_LOOKBACK_DAYS: i64 = 30;
+
 #[derive(Clone)]
 pub struct HoldingsValuationService {
     fx_service: Arc<dyn FxServiceTrait>,
@@ -39,9 +49,16 @@ impl HoldingsValuationService {
         &self,
         from_curr: &str,
         to_curr: &str,
+        as_of: Option<NaiveDate>,
         context_msg: &str,
     ) -> Decimal {
-        match self.fx_service.get_latest_exchange_rate(from_curr, to_curr) {
+        let rate = match as_of {
+            Some(date) => self
+                .fx_service
+                .get_exchange_rate_for_date(from_curr, to_curr, date),
+            None => self.fx_service.get_latest_exchange_rate(from_curr, to_curr),
+        };
+        match rate {
             Ok(rate) => rate,
             Err(e) => {
                 warn!(
@@ -78,6 +95,46 @@ impl HoldingsValuationService {
 
         Ok(latest_quote_pairs)
     }
+
+    // Builds quote pairs from the last two quotes on or before `as_of`
+    fn fetch_quote_data_as_of(
+        &self,
+        holdings: &[Holding],
+        as_of: NaiveDate,
+    ) -> Result<HashMap<String, LatestQuotePair>> {
+        let required_symbols: HashSet<String> = holdings
+            .iter()
+            .filter(|holding| holding.holding_type == HoldingType::Security)
+            .filter_map(|holding| holding.instrument.as_ref().map(|inst| inst.symbol.clone()))
+            .collect();
+        if required_symbols.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let start_date = as_of - Duration::days(HISTORICAL_QUOTE_LOOKBACK_DAYS);
+        let mut quotes_by_symbol: HashMap<String, Vec<Quote>> = HashMap::new();
+        for quote in self
+            .market_data_service
+            .get_historical_quotes_for_symbols_in_range(&required_symbols, start_date, as_of)?
+        {
+            if quote.timestamp.date_naive() <= as_of {
+                quotes_by_symbol
+                    .entry(quote.symbol.clone())
+                    .or_default()
+                    .push(quote);
+            }
+        }
+
+        Ok(quotes_by_symbol
+            .into_iter()
+            .filter_map(|(symbol, mut quotes)| {
+                quotes.sort_by_key(|quote| quote.timestamp);
+                let latest = quotes.pop()?;
+                let previous = quotes.pop();
+                Some((symbol, LatestQuotePair { latest, previous }))
+            })
+            .collect())
+    }
 }
 
 #[async_trait]
@@ -100,22 +157,26 @@ impl HoldingsValuationServiceTrait for HoldingsValuationService {
         for holding in holdings.iter_mut() {
             match holding.holding_type {
                 HoldingType::Security => {
-                    if let Some(sym) = holding.instrument.as_ref().map(|i| i.symbol.clone()) {
-                        holding.as_of_date = latest_quote_pairs
-                            .get(&sym)
-                            .map(|qp| qp.latest.timestamp.date_naive())
-                            .unwrap_or(today);
-                    } else {
-                        holding.as_of_date = today;
-                    }
+                    holding.quote_date = holding
+                        .instrument
+                        .as_ref()
+                        .and_then(|i| latest_quote_pairs.get(&i.symbol))
+                        .map(|qp| qp.latest.timestamp.date_naive());
+                    holding.as_of_date = holding.quote_date.unwrap_or(today);
                     let base_currency = holding.base_currency.clone();
-                    self.calculate_security_valuation(holding, &base_currency, &latest_quote_pairs)
-                        .await?;
+                    self.calculate_security_valuation(
+                        holding,
+                        &base_currency,
+                        &latest_quote_pairs,
+                        None,
+                    )
+                    .await?;
                 }
                 HoldingType::Cash => {
+                    holding.quote_date = None;
                     holding.as_of_date = today;
                     let base_currency = holding.base_currency.clone();
-                    self.calculate_cash_valuation(holding, &base_currency)?;
+                    self.calculate_cash_valuation(holding, &base_currency, None)?;
                 }
             }
         }
@@ -123,6 +184,52 @@ impl HoldingsValuationServiceTrait for HoldingsValuationService {
         debug!("Finished calculate_holdings_live_valuation.");
         Ok(())
     }
+
+    async fn calculate_holdings_valuation_as_of(
+        &self,
+        holdings: &mut [Holding],
+        as_of: NaiveDate,
+    ) -> Result<()> {
+        if holdings.is_empty() {
+            return Ok(());
+        }
+        debug!(
+            "Starting calculate_holdings_valuation_as_of {} for {} holdings.",
+            as_of,
+            holdings.len()
+        );
+
+        let quote_pairs = self.fetch_quote_data_as_of(holdings, as_of)?;
+
+        for holding in holdings.iter_mut() {
+            let base_currency = holding.base_currency.clone();
+            match holding.holding_type {
+                HoldingType::Security => {
+                    holding.quote_date = holding
+                        .instrument
+                        .as_ref()
+                        .and_then(|i| quote_pairs.get(&i.symbol))
+                        .map(|qp| qp.latest.timestamp.date_naive());
+                    self.calculate_security_valuation(
+                        holding,
+                        &base_currency,
+                        &quote_pairs,
+                        Some(as_of),
+                    )
+                    .await?;
+                }
+                HoldingType::Cash => {
+                    holding.quote_date = None;
+                    self.calculate_cash_valuation(holding, &base_currency, Some(as_of))?;
+                }
+            }
+            // The holding reflects the requested date even if its last quote is older
+            holding.as_of_date = as_of;
+        }
+
+        debug!("Finished calculate_holdings_valuation_as_of.");
+        Ok(())
+    }
 }
 
 // --- New Helper Methods for Valuation ---
@@ -133,6 +240,7 @@ impl HoldingsValuationService {
         holding: &mut Holding,
         base_currency: &str,
         latest_quote_pairs: &HashMap<String, LatestQuotePair>,
+        as_of: Option<NaiveDate>,
     ) -> Result<()> {
         let instrument = match &holding.instrument {
             Some(inst) => inst,
@@ -154,6 +262,7 @@ impl HoldingsValuationService {
         let fx_rate_local_to_base = self.get_fx_rate_or_fallback(
             pos_currency,
             base_currency,
+            as_of,
             &format!("{}: FX Local->Base", context_msg),
         );
         holding.fx_rate = Some(fx_rate_local_to_base);
@@ -199,6 +308,7 @@ impl HoldingsValuationService {
             let fx_rate_quote_to_base = self.get_fx_rate_or_fallback(
                 normalized_quote_currency,
                 base_currency,
+                as_of,
                 &format!("{}: FX Quote->Base", context_msg),
             );
 
@@ -209,6 +319,7 @@ impl HoldingsValuationService {
             let fx_rate_quote_to_local = self.get_fx_rate_or_fallback(
                 normalized_quote_currency,
                 pos_currency,
+                as_of,
                 &format!("{}: FX Quote->Local", context_msg),
             );
 
@@ -221,9 +332,15 @@ impl HoldingsValuationService {
             };
 
             if let Some(cost_basis) = &holding.cost_basis {
-                let cost_basis_base = cost_basis.base;
+                // A short position's cost basis is the proceeds owed back, so it carries the
+                // position's negative sign and the gain grows as the price falls
+                let (cost_basis_local, cost_basis_base) = if quantity.is_sign_negative() {
+                    (-cost_basis.local.abs(), -cost_basis.base.abs())
+                } else {
+                    (cost_basis.local, cost_basis.base)
+                };
 
-                let unrealized_gain_local = market_value_local - cost_basis.local;
+                let unrealized_gain_local = market_value_local - cost_basis_local;
                 let unrealized_gain_base = market_value_base - cost_basis_base;
 
                 holding.unrealized_gain = Some(MonetaryValue {
@@ -233,7 +350,7 @@ impl HoldingsValuationService {
 
                 if cost_basis_base != dec!(0) {
                     holding.unrealized_gain_pct =
-                        Some((unrealized_gain_base / cost_basis_base).round_dp(4));
+                        Some((unrealized_gain_base / cost_basis_base.abs()).round_dp(4));
                 } else if unrealized_gain_base != dec!(0) {
                     holding.unrealized_gain_pct = Some(dec!(1.0));
                 } else {
@@ -277,7 +394,7 @@ impl HoldingsValuationService {
 
                     if prev_value_base != dec!(0) {
                         holding.day_change_pct =
-                            Some((day_change_base / prev_value_base).round_dp(4));
+                            Some((day_change_base / prev_value_base.abs()).round_dp(4));
                     } else if day_change_base != dec!(0) {
                         holding.day_change_pct = None;
                     } else {
@@ -323,7 +440,12 @@ impl HoldingsValuationService {
         Ok(())
     }
 
-    fn calculate_cash_valuation(&self, holding: &mut Holding, base_currency: &str) -> Result<()> {
+    fn calculate_cash_valuation(
+        &self,
+        holding: &mut Holding,
+        base_currency: &str,
+        as_of: Option<NaiveDate>,
+    ) -> Result<()> {
         let cash_currency = &holding.local_currency;
         let cash_amount = holding.quantity;
         let context_msg = format!("HoldingValuation [CASH {}]", cash_currency);
@@ -332,7 +454,7 @@ impl HoldingsValuationService {
         holding.price = Some(dec!(1.0));
 
         let fx_rate_cash_to_base =
-            self.get_fx_rate_or_fallback(cash_currency, base_currency, &context_msg);
+            self.get_fx_rate_or_fallback(cash_currency, base_currency, as_of, &context_msg);
         holding.fx_rate = Some(fx_rate_cash_to_base);
 
         let value_base = cash_amount * fx_rate_cash_to_base;
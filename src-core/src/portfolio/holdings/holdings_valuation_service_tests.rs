@@ -54,6 +54,13 @@ mod tests {
         async fn add_exchange_rate(&self, _new_rate: NewExchangeRate) -> Result<ExchangeRate> {
             unimplemented!()
         }
+        async fn add_exchange_rate_at(
+            &self,
+            _new_rate: NewExchangeRate,
+            _timestamp: chrono::DateTime<Utc>,
+        ) -> Result<ExchangeRate> {
+            unimplemented!()
+        }
         fn get_historical_rates(
             &self,
             _from_currency: &str,
@@ -72,11 +79,12 @@ mod tests {
         }
         fn get_exchange_rate_for_date(
             &self,
-            _from_currency: &str,
-            _to_currency: &str,
+            from_currency: &str,
+            to_currency: &str,
             _date: NaiveDate,
         ) -> Result<Decimal> {
-            unimplemented!()
+            // Mock rates are not dated
+            self.get_latest_exchange_rate(from_currency, to_currency)
         }
         fn convert_currency(
             &self,
@@ -147,6 +155,7 @@ mod tests {
     #[derive(Clone, Default)]
     struct MockMarketDataService {
         quotes: Arc<Mutex<HashMap<String, LatestQuotePair>>>,
+        history: Arc<Mutex<Vec<Quote>>>,
         should_fail: Arc<Mutex<bool>>,
     }
 
@@ -155,6 +164,11 @@ mod tests {
             let mut quotes = self.quotes.lock().unwrap();
             quotes.insert(symbol.to_string(), LatestQuotePair { latest, previous });
         }
+
+        fn add_historical_quote(&self, symbol: &str, mut quote: Quote) {
+            quote.symbol = symbol.to_string();
+            self.history.lock().unwrap().push(quote);
+        }
     }
 
     #[async_trait]
@@ -197,6 +211,15 @@ mod tests {
         ) -> Result<Vec<Quote>> {
             unimplemented!()
         }
+        async fn get_historical_quotes_with_failover(
+            &self,
+            _symbol: &str,
+            _start_date: NaiveDate,
+            _end_date: NaiveDate,
+            _sources: Option<&[String]>,
+        ) -> Result<(String, Vec<Quote>)> {
+            unimplemented!()
+        }
         async fn sync_market_data(&self) -> Result<((), Vec<(String, String)>)> {
             unimplemented!()
         }
@@ -208,11 +231,20 @@ mod tests {
         }
         fn get_historical_quotes_for_symbols_in_range(
             &self,
-            _symbols: &HashSet<String>,
-            _start_date: NaiveDate,
-            _end_date: NaiveDate,
+            symbols: &HashSet<String>,
+            start_date: NaiveDate,
+            end_date: NaiveDate,
         ) -> Result<Vec<Quote>> {
-            unimplemented!()
+            let history = self.history.lock().unwrap();
+            Ok(history
+                .iter()
+                .filter(|quote| symbols.contains(&quote.symbol))
+                .filter(|quote| {
+                    let date = quote.timestamp.date_naive();
+                    date >= start_date && date <= end_date
+                })
+                .cloned()
+                .collect())
         }
         async fn get_daily_quotes(
             &self,
@@ -317,6 +349,7 @@ mod tests {
                 countries: None,
                 sectors: None,
                 data_source: None,
+                profile_updated_at: None,
             })
         } else {
             None
@@ -340,6 +373,7 @@ mod tests {
             as_of_date: NaiveDate::from_ymd_opt(1970, 1, 1).unwrap(), // Placeholder
             market_value: MonetaryValue::zero(),                      // To be calculated
             price: None,                                              // To be calculated
+            quote_date: None,                                         // To be calculated
             fx_rate: None,                                            // To be calculated
             unrealized_gain: None,                                    // To be calculated
             unrealized_gain_pct: None,                                // To be calculated
@@ -531,6 +565,69 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_short_security_valuation() {
+        let (_fx_service, market_data_service, valuation_service) = setup_test_env();
+
+        let latest_quote = create_quote("2024-01-10", dec!(90.0), "CAD");
+        let prev_quote = create_quote("2024-01-09", dec!(95.0), "CAD");
+        market_data_service.add_quote_pair("XYZ.TO", latest_quote, Some(prev_quote));
+
+        // Shorted 10 shares at 100
+        let mut holdings = vec![create_holding(
+            "h1",
+            HoldingType::Security,
+            "XYZ.TO",
+            dec!(-10),
+            "CAD",
+            "CAD",
+            Some(dec!(1000.0)),
+            Some("XYZ Corp"),
+        )];
+
+        let result = valuation_service
+            .calculate_holdings_live_valuation(&mut holdings)
+            .await;
+        assert!(result.is_ok());
+        let holding = &holdings[0];
+
+        assert_monetary_value_approx(
+            Some(&holding.market_value),
+            dec!(-900.0),
+            dec!(-900.0),
+            TOLERANCE,
+            "Market Value",
+        );
+        // The price fell, so the short gained: -900 - (-1000)
+        assert_monetary_value_approx(
+            holding.unrealized_gain.as_ref(),
+            dec!(100.0),
+            dec!(100.0),
+            TOLERANCE,
+            "Unrealized Gain",
+        );
+        assert_decimal_approx(
+            holding.unrealized_gain_pct,
+            dec!(0.1),
+            TOLERANCE,
+            "Unrealized Gain Pct",
+        );
+        // -900 - (-950)
+        assert_monetary_value_approx(
+            holding.day_change.as_ref(),
+            dec!(50.0),
+            dec!(50.0),
+            TOLERANCE,
+            "Day Change",
+        );
+        assert_decimal_approx(
+            holding.day_change_pct,
+            dec!(0.0526),
+            TOLERANCE,
+            "Day Change Pct",
+        );
+    }
+
     #[tokio::test]
     async fn test_security_valuation_with_fx() {
         let (fx_service, market_data_service, valuation_service) = setup_test_env();
@@ -1182,8 +1279,14 @@ mod tests {
             "h1 Day Change (Missing Test)",
         );
 
+        assert_eq!(h1.quote_date, Some(NaiveDate::from_str("2024-01-10").unwrap()));
+
         // Check the holding with missing data has zero/None values
         let h_missing = holdings.iter().find(|h| h.id == "h_missing").unwrap();
+        assert!(
+            h_missing.quote_date.is_none(),
+            "Missing holding has no quote date even though it is valued today"
+        );
         assert_eq!(
             h_missing.market_value,
             MonetaryValue::zero(),
@@ -1606,6 +1709,76 @@ mod tests {
         assert!(holding.day_change.is_none(), "Day Change No Instrument");
     }
 
+    #[tokio::test]
+    async fn test_valuation_as_of_uses_last_quote_within_lookback() {
+        let (_fx_service, market_data_service, valuation_service) = setup_test_env();
+
+        // No quote on the requested date itself: the last one before it is used
+        market_data_service
+            .add_historical_quote("AAPL", create_quote("2024-03-01", dec!(90.0), "USD"));
+        market_data_service
+            .add_historical_quote("AAPL", create_quote("2024-03-04", dec!(100.0), "USD"));
+        market_data_service
+            .add_historical_quote("AAPL", create_quote("2024-03-12", dec!(120.0), "USD"));
+        // Only quoted more than HISTORICAL_QUOTE_LOOKBACK_DAYS (30) days earlier
+        market_data_service
+            .add_historical_quote("STALE", create_quote("2024-01-10", dec!(50.0), "USD"));
+
+        let mut holdings = vec![
+            create_holding(
+                "h1",
+                HoldingType::Security,
+                "AAPL",
+                dec!(10),
+                "USD",
+                "CAD",
+                Some(dec!(800.0)),
+                None,
+            ),
+            create_holding(
+                "h2",
+                HoldingType::Security,
+                "STALE",
+                dec!(5),
+                "USD",
+                "CAD",
+                Some(dec!(200.0)),
+                None,
+            ),
+        ];
+        let as_of = NaiveDate::from_str("2024-03-10").unwrap();
+
+        let result = valuation_service
+            .calculate_holdings_valuation_as_of(&mut holdings, as_of)
+            .await;
+        assert!(result.is_ok());
+
+        let valued = &holdings[0];
+        assert_eq!(valued.as_of_date, as_of);
+        assert_eq!(valued.quote_date, Some(NaiveDate::from_str("2024-03-04").unwrap()));
+        assert_decimal_approx(valued.price, dec!(100.0), TOLERANCE, "As-of Price");
+        assert_monetary_value_approx(
+            Some(&valued.market_value),
+            dec!(1000.0),
+            dec!(1300.0), // USD -> CAD at 1.3
+            TOLERANCE,
+            "As-of Market Value",
+        );
+        assert_monetary_value_approx(
+            valued.day_change.as_ref(),
+            dec!(100.0), // 10 * (100 - 90)
+            dec!(130.0),
+            TOLERANCE,
+            "As-of Day Change",
+        );
+
+        let stale = &holdings[1];
+        assert_eq!(stale.as_of_date, as_of);
+        assert!(stale.quote_date.is_none());
+        assert!(stale.price.is_none(), "Quote outside lookback is ignored");
+        assert_eq!(stale.market_value, MonetaryValue::zero());
+    }
+
     #[tokio::test]
     async fn test_empty_holdings_list() {
         let (_fx_service, _market_data_service, valuation_service) = setup_test_env();
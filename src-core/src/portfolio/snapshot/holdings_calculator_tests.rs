@@ -155,6 +155,15 @@ mod tests {
                 "MockFxService::add_exchange_rate not implemented".to_string(),
             ))
         }
+        async fn add_exchange_rate_at(
+            &self,
+            _new_rate: crate::fx::fx_model::NewExchangeRate,
+            _timestamp: DateTime<Utc>,
+        ) -> Result<crate::fx::fx_model::ExchangeRate> {
+            Err(crate::errors::Error::Unexpected(
+                "MockFxService::add_exchange_rate_at not implemented".to_string(),
+            ))
+        }
         fn get_historical_rates(
             &self,
             _from_currency: &str,
@@ -62,6 +62,15 @@ pub trait SnapshotServiceTrait: Send + Sync {
         account_id: &str,
     ) -> Result<Option<AccountStateSnapshot>>;
 
+    /// Retrieves the last saved **holdings** snapshot on or before `date` for a specific account,
+    /// i.e. the holdings as they stood on that date. Returns `Ok(None)` when the account has no
+    /// snapshot that early. Valuation fields will be zero or default.
+    fn get_holdings_snapshot_as_of(
+        &self,
+        account_id: &str,
+        date: NaiveDate,
+    ) -> Result<Option<AccountStateSnapshot>>;
+
     /// Calculates and stores aggregated "TOTAL" portfolio snapshots based on individual account holdings.
     /// This should typically be run after `calculate_holdings_snapshots` has processed individual accounts.
     /// It iterates through each day from the earliest activity to the present, generating a TOTAL snapshot
@@ -1132,6 +1141,16 @@ impl SnapshotServiceTrait for SnapshotService {
         }
     }
 
+    fn get_holdings_snapshot_as_of(
+        &self,
+        account_id: &str,
+        date: NaiveDate,
+    ) -> Result<Option<AccountStateSnapshot>> {
+        // Keyframes carry holdings forward, so the latest one on or before `date` is the state on `date`
+        self.snapshot_repository
+            .get_latest_snapshot_before_date(account_id, date)
+    }
+
     async fn calculate_total_portfolio_snapshots(&self) -> Result<usize> {
         self.calculate_total_portfolio_snapshots_impl().await
     }
@@ -52,6 +52,13 @@ mod tests {
         async fn add_exchange_rate(&self, _new_rate: NewExchangeRate) -> AppResult<ExchangeRate> {
             unimplemented!()
         }
+        async fn add_exchange_rate_at(
+            &self,
+            _new_rate: NewExchangeRate,
+            _timestamp: DateTime<Utc>,
+        ) -> AppResult<ExchangeRate> {
+            unimplemented!()
+        }
         fn get_historical_rates(
             &self,
             _from_currency: &str,
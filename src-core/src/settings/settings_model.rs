@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use diesel::prelude::*;
 use diesel::Queryable;
 use serde::{Deserialize, Serialize};
@@ -30,6 +31,23 @@ impl Default for Settings {
     }
 }
 
+/// A base currency and when it took effect
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BaseCurrencyChange {
+    pub currency: String,
+    pub effective_at: DateTime<Utc>,
+}
+
+/// Named set of accounts aggregated together, e.g. "Family" or "Personal"
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PortfolioGroup {
+    pub id: String,
+    pub name: String,
+    pub account_ids: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct SettingsUpdate {
@@ -1,9 +1,11 @@
 use super::settings_repository::SettingsRepositoryTrait;
 use crate::errors::{DatabaseError, Error, Result};
 use crate::fx::fx_traits::FxServiceTrait;
-use crate::settings::{Settings, SettingsUpdate};
+use crate::settings::{BaseCurrencyChange, PortfolioGroup, Settings, SettingsUpdate};
 use async_trait::async_trait;
+use chrono::Utc;
 use log::{debug, error};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 // Define the trait for SettingsService
@@ -17,9 +19,29 @@ pub trait SettingsServiceTrait: Send + Sync {
 
     async fn update_base_currency(&self, new_base_currency: &str) -> Result<()>;
 
+    /// Base currency changes, oldest first. Only changes made since history
+    /// tracking was added are recorded.
+    fn get_base_currency_history(&self) -> Result<Vec<BaseCurrencyChange>>;
+
     fn is_auto_update_check_enabled(&self) -> Result<bool>;
 
     fn is_sync_enabled(&self) -> Result<bool>;
+
+    fn get_default_account_id(&self) -> Result<Option<String>>;
+
+    async fn update_default_account_id(&self, account_id: Option<&str>) -> Result<()>;
+
+    fn get_watchlist(&self) -> Result<Vec<String>>;
+
+    async fn update_watchlist(&self, symbols: &[String]) -> Result<()>;
+
+    fn get_portfolio_groups(&self) -> Result<Vec<PortfolioGroup>>;
+
+    async fn update_portfolio_groups(&self, groups: &[PortfolioGroup]) -> Result<()>;
+
+    fn get_instrument_tags(&self) -> Result<BTreeMap<String, Vec<String>>>;
+
+    async fn update_instrument_tags(&self, tags: &BTreeMap<String, Vec<String>>) -> Result<()>;
 }
 
 pub struct SettingsService {
@@ -84,10 +106,35 @@ impl SettingsServiceTrait for SettingsService {
             }
         }
 
+        // Read the history first so an unreadable one aborts before anything is written
+        let mut history = self.get_base_currency_history()?;
+        history.push(BaseCurrencyChange {
+            currency: new_base_currency.to_string(),
+            effective_at: Utc::now(),
+        });
+        let history = serde_json::to_string(&history).map_err(|e| {
+            Error::Unexpected(format!("Failed to serialize base currency history: {}", e))
+        })?;
+
         self.settings_repository
             .update_setting("base_currency", new_base_currency)
             .await?;
-        Ok(())
+        self.settings_repository
+            .update_setting("base_currency_history", &history)
+            .await
+    }
+
+    fn get_base_currency_history(&self) -> Result<Vec<BaseCurrencyChange>> {
+        match self.settings_repository.get_setting("base_currency_history") {
+            Ok(value) if value.is_empty() => Ok(Vec::new()),
+            Ok(value) => serde_json::from_str(&value).map_err(|e| {
+                Error::Unexpected(format!("Stored base currency history is malformed: {}", e))
+            }),
+            Err(Error::Database(DatabaseError::QueryFailed(diesel::result::Error::NotFound))) => {
+                Ok(Vec::new())
+            }
+            Err(e) => Err(e),
+        }
     }
 
     fn is_auto_update_check_enabled(&self) -> Result<bool> {
@@ -112,6 +159,91 @@ impl SettingsServiceTrait for SettingsService {
             Err(e) => Err(e),
         }
     }
+
+    fn get_default_account_id(&self) -> Result<Option<String>> {
+        match self.settings_repository.get_setting("default_account_id") {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::Database(DatabaseError::QueryFailed(diesel::result::Error::NotFound))) => {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn update_default_account_id(&self, account_id: Option<&str>) -> Result<()> {
+        // An empty value clears the default
+        self.settings_repository
+            .update_setting("default_account_id", account_id.unwrap_or_default())
+            .await
+    }
+
+    fn get_watchlist(&self) -> Result<Vec<String>> {
+        match self.settings_repository.get_setting("watchlist") {
+            Ok(value) if value.is_empty() => Ok(Vec::new()),
+            Ok(value) => serde_json::from_str(&value).map_err(|e| {
+                Error::Unexpected(format!("Stored watchlist is not a symbol list: {}", e))
+            }),
+            Err(Error::Database(DatabaseError::QueryFailed(diesel::result::Error::NotFound))) => {
+                Ok(Vec::new())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn update_watchlist(&self, symbols: &[String]) -> Result<()> {
+        // Stored as a JSON array of symbols
+        let value = serde_json::to_string(symbols)
+            .map_err(|e| Error::Unexpected(format!("Failed to serialize watchlist: {}", e)))?;
+        self.settings_repository
+            .update_setting("watchlist", &value)
+            .await
+    }
+
+    fn get_portfolio_groups(&self) -> Result<Vec<PortfolioGroup>> {
+        match self.settings_repository.get_setting("portfolio_groups") {
+            Ok(value) if value.is_empty() => Ok(Vec::new()),
+            Ok(value) => serde_json::from_str(&value).map_err(|e| {
+                Error::Unexpected(format!("Stored portfolio groups are malformed: {}", e))
+            }),
+            Err(Error::Database(DatabaseError::QueryFailed(diesel::result::Error::NotFound))) => {
+                Ok(Vec::new())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn update_portfolio_groups(&self, groups: &[PortfolioGroup]) -> Result<()> {
+        let value = serde_json::to_string(groups).map_err(|e| {
+            Error::Unexpected(format!("Failed to serialize portfolio groups: {}", e))
+        })?;
+        self.settings_repository
+            .update_setting("portfolio_groups", &value)
+            .await
+    }
+
+    fn get_instrument_tags(&self) -> Result<BTreeMap<String, Vec<String>>> {
+        match self.settings_repository.get_setting("instrument_tags") {
+            Ok(value) if value.is_empty() => Ok(BTreeMap::new()),
+            Ok(value) => serde_json::from_str(&value).map_err(|e| {
+                Error::Unexpected(format!("Stored instrument tags are malformed: {}", e))
+            }),
+            Err(Error::Database(DatabaseError::QueryFailed(diesel::result::Error::NotFound))) => {
+                Ok(BTreeMap::new())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn update_instrument_tags(&self, tags: &BTreeMap<String, Vec<String>>) -> Result<()> {
+        // Stored as a JSON object of symbol to tag list
+        let value = serde_json::to_string(tags).map_err(|e| {
+            Error::Unexpected(format!("Failed to serialize instrument tags: {}", e))
+        })?;
+        self.settings_repository
+            .update_setting("instrument_tags", &value)
+            .await
+    }
 }
 
 impl SettingsService {
@@ -1,8 +1,11 @@
 use std::sync::Arc;
 
 use crate::{
-    api::shared::{trigger_activity_portfolio_job, ActivityImpact},
+    api::shared::{
+        import_route, trigger_activity_portfolio_job, ActivityImpact, MAX_IMPORT_BODY_BYTES,
+    },
     error::ApiResult,
+    events::{ServerEvent, ACTIVITIES_IMPORT_COMPLETE},
     main_lib::AppState,
 };
 use axum::{
@@ -150,14 +153,36 @@ struct ImportBody {
     activities: Vec<ActivityImport>,
 }
 
+#[derive(serde::Deserialize)]
+struct ImportQuery {
+    /// Validate and return the parsed rows without writing anything
+    #[serde(default, alias = "dryRun")]
+    dry_run: bool,
+}
+
 async fn import_activities(
     State(state): State<Arc<AppState>>,
+    Query(q): Query<ImportQuery>,
     Json(body): Json<ImportBody>,
 ) -> ApiResult<Json<Vec<ActivityImport>>> {
+    if q.dry_run {
+        // Same validation as a real import, so the summary has the same shape,
+        // but no activities are inserted and no recalculation is triggered
+        let res = state
+            .activity_service
+            .check_activities_import(body.account_id, body.activities)
+            .await?;
+        return Ok(Json(res));
+    }
+
     let res = state
         .activity_service
-        .import_activities(body.account_id, body.activities)
+        .import_activities(body.account_id.clone(), body.activities)
         .await?;
+    state.event_bus.publish(ServerEvent::with_payload(
+        ACTIVITIES_IMPORT_COMPLETE,
+        serde_json::json!({ "accountId": body.account_id, "count": res.len() }),
+    ));
     trigger_activity_portfolio_job(
         state,
         res.iter()
@@ -209,8 +234,14 @@ pub fn router() -> Router<Arc<AppState>> {
         .route("/activities", post(create_activity).put(update_activity))
         .route("/activities/bulk", post(save_activities))
         .route("/activities/{id}", delete(delete_activity))
-        .route("/activities/import/check", post(check_activities_import))
-        .route("/activities/import", post(import_activities))
+        .route(
+            "/activities/import/check",
+            import_route(post(check_activities_import), MAX_IMPORT_BODY_BYTES),
+        )
+        .route(
+            "/activities/import",
+            import_route(post(import_activities), MAX_IMPORT_BODY_BYTES),
+        )
         .route(
             "/activities/import/mapping",
             get(get_account_import_mapping).post(save_account_import_mapping),
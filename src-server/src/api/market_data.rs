@@ -1,8 +1,9 @@
 use std::sync::Arc;
 
 use crate::{
-    api::shared::{enqueue_portfolio_job, PortfolioJobConfig},
+    api::shared::{enqueue_portfolio_job, import_route, PortfolioJobConfig, MAX_IMPORT_BODY_BYTES},
     error::ApiResult,
+    events::{ServerEvent, QUOTES_UPDATED},
     main_lib::AppState,
 };
 use axum::{
@@ -91,6 +92,10 @@ async fn update_quote(
     quote.symbol = symbol;
     let target_symbol = quote.symbol.clone();
     state.market_data_service.update_quote(quote).await?;
+    state.event_bus.publish(ServerEvent::with_payload(
+        QUOTES_UPDATED,
+        serde_json::json!({ "symbols": [target_symbol.clone()] }),
+    ));
     enqueue_portfolio_job(
         state.clone(),
         PortfolioJobConfig {
@@ -143,6 +148,13 @@ async fn import_quotes_csv(
         .market_data_service
         .import_quotes_from_csv(body.quotes, body.overwrite_existing)
         .await?;
+    let mut symbols: Vec<String> = result.iter().map(|q| q.symbol.clone()).collect();
+    symbols.sort();
+    symbols.dedup();
+    state.event_bus.publish(ServerEvent::with_payload(
+        QUOTES_UPDATED,
+        serde_json::json!({ "symbols": symbols }),
+    ));
 
     enqueue_portfolio_job(
         state,
@@ -207,7 +219,10 @@ pub fn router() -> Router<Arc<AppState>> {
         .route("/market-data/quotes/latest", post(get_latest_quotes))
         .route("/market-data/quotes/{symbol}", put(update_quote))
         .route("/market-data/quotes/id/{id}", delete(delete_quote))
-        .route("/market-data/quotes/import", post(import_quotes_csv))
+        .route(
+            "/market-data/quotes/import",
+            import_route(post(import_quotes_csv), MAX_IMPORT_BODY_BYTES),
+        )
         .route("/market-data/sync/history", post(sync_history_quotes))
         .route("/market-data/sync", post(sync_market_data))
 }
@@ -9,11 +9,30 @@ use crate::{
     main_lib::AppState,
 };
 use anyhow::anyhow;
+use axum::{extract::DefaultBodyLimit, routing::MethodRouter};
 use serde_json::json;
+use tower_http::{decompression::RequestDecompressionLayer, limit::RequestBodyLimitLayer};
 use wealthfolio_core::{
     accounts::AccountServiceTrait, activities::Activity, constants::PORTFOLIO_TOTAL_ACCOUNT_ID,
 };
 
+/// Largest import body accepted, measured after decompression. Matches axum's default
+/// extractor limit, which `import_route` replaces.
+pub const MAX_IMPORT_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Wraps an import route so `Content-Encoding: gzip` bodies are decompressed before the
+/// handler parses them. The size limit applies to the decompressed bytes, so a small
+/// compressed upload cannot expand past it. Uncompressed bodies pass through unchanged.
+pub fn import_route<S>(route: MethodRouter<S>, max_body_bytes: usize) -> MethodRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    route
+        .layer(RequestBodyLimitLayer::new(max_body_bytes))
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestDecompressionLayer::new())
+}
+
 /// Normalize file paths by stripping file:// prefix
 pub fn normalize_file_path(path: &str) -> String {
     path.strip_prefix("file://").unwrap_or(path).to_string()
@@ -328,3 +347,54 @@ pub fn trigger_activity_portfolio_job(state: Arc<AppState>, impacts: Vec<Activit
 
     enqueue_portfolio_job(state, config);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::{to_bytes, Body},
+        http::{header, Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+    use tower::ServiceExt;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[tokio::test]
+    async fn import_routes_decompress_gzip_within_the_body_limit() {
+        let app = Router::new().route(
+            "/import",
+            import_route(post(|body: String| async move { body }), 64),
+        );
+        let send = |body: Vec<u8>, gzipped: bool| {
+            let mut request = Request::builder().method("POST").uri("/import");
+            if gzipped {
+                request = request.header(header::CONTENT_ENCODING, "gzip");
+            }
+            app.clone().oneshot(request.body(Body::from(body)).unwrap())
+        };
+
+        let response = send(b"symbol,quantity".to_vec(), false).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"symbol,quantity");
+
+        let response = send(gzip(b"symbol,quantity"), true).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"symbol,quantity");
+
+        // Compresses to well under the limit but expands past it
+        let bomb = gzip(&[b'0'; 4096]);
+        assert!(bomb.len() < 64);
+        let response = send(bomb, true).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
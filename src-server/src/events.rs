@@ -1,3 +1,6 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
 use serde_json::Value;
 use tokio::sync::broadcast;
 
@@ -8,6 +11,8 @@ pub const MARKET_SYNC_ERROR: &str = "market:sync-error";
 pub const PORTFOLIO_UPDATE_START: &str = "portfolio:update-start";
 pub const PORTFOLIO_UPDATE_COMPLETE: &str = "portfolio:update-complete";
 pub const PORTFOLIO_UPDATE_ERROR: &str = "portfolio:update-error";
+pub const QUOTES_UPDATED: &str = "market:quotes-updated";
+pub const ACTIVITIES_IMPORT_COMPLETE: &str = "activities:import-complete";
 
 /// Serializable envelope that carries event names and optional payloads.
 #[derive(Clone, Debug)]
@@ -53,3 +58,153 @@ impl EventBus {
         let _ = self.sender.send(event);
     }
 }
+
+/// Event paired with the sequential id assigned by an [`EventJournal`].
+#[derive(Clone, Debug)]
+pub struct JournaledEvent {
+    pub id: u64,
+    pub event: ServerEvent,
+}
+
+struct JournalState {
+    next_id: u64,
+    recent: VecDeque<JournaledEvent>,
+}
+
+/// Buffered events and live receiver handed out by [`EventJournal::subscribe_after`].
+pub struct EventSubscription {
+    /// Buffered events newer than the requested id.
+    pub replay: Vec<JournaledEvent>,
+    /// Set when the replay is incomplete: events after the requested id were already
+    /// evicted, or the id was never issued by this journal (e.g. before a restart).
+    pub gap: bool,
+    pub receiver: broadcast::Receiver<JournaledEvent>,
+}
+
+/// Keeps a bounded history of selected bus events so that streaming clients
+/// can resume from the last id they received.
+pub struct EventJournal {
+    state: Mutex<JournalState>,
+    capacity: usize,
+    sender: broadcast::Sender<JournaledEvent>,
+}
+
+impl EventJournal {
+    /// Creates a journal fed by `bus`, keeping only events whose name is in `names`.
+    pub fn spawn(bus: &EventBus, names: &'static [&'static str], capacity: usize) -> Arc<Self> {
+        let journal = Arc::new(Self::new(capacity));
+
+        let mut receiver = bus.subscribe();
+        let forwarder = Arc::clone(&journal);
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) if names.contains(&event.name) => forwarder.record(event),
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!("Event journal lagged, skipped {} events", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+
+        journal
+    }
+
+    fn new(capacity: usize) -> Self {
+        let (sender, _receiver) = broadcast::channel(capacity);
+        Self {
+            state: Mutex::new(JournalState {
+                next_id: 1,
+                recent: VecDeque::with_capacity(capacity),
+            }),
+            capacity,
+            sender,
+        }
+    }
+
+    fn record(&self, event: ServerEvent) {
+        let Ok(mut state) = self.state.lock() else {
+            tracing::error!("Event journal lock poisoned, dropping {} event", event.name);
+            return;
+        };
+        let entry = JournaledEvent {
+            id: state.next_id,
+            event,
+        };
+        state.next_id += 1;
+        if state.recent.len() == self.capacity {
+            state.recent.pop_front();
+        }
+        state.recent.push_back(entry.clone());
+        // Sent under the lock so that `subscribe_after` never misses or repeats an event.
+        let _ = self.sender.send(entry);
+    }
+
+    /// Returns the buffered events newer than `last_id` together with a receiver for
+    /// everything recorded afterwards, flagging a gap when the buffer no longer covers `last_id`.
+    pub fn subscribe_after(&self, last_id: Option<u64>) -> anyhow::Result<EventSubscription> {
+        let state = self
+            .state
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Event journal lock poisoned"))?;
+        let receiver = self.sender.subscribe();
+        let Some(last_id) = last_id else {
+            return Ok(EventSubscription {
+                replay: Vec::new(),
+                gap: false,
+                receiver,
+            });
+        };
+
+        let oldest_id = state.recent.front().map_or(state.next_id, |entry| entry.id);
+        let replay = state
+            .recent
+            .iter()
+            .filter(|entry| entry.id > last_id)
+            .cloned()
+            .collect();
+        Ok(EventSubscription {
+            replay,
+            gap: last_id < oldest_id - 1 || last_id >= state.next_id,
+            receiver,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replay_ids(subscription: &EventSubscription) -> Vec<u64> {
+        subscription.replay.iter().map(|entry| entry.id).collect()
+    }
+
+    #[test]
+    fn subscribe_after_flags_evicted_and_unknown_ids() {
+        let journal = EventJournal::new(2);
+        for _ in 0..4 {
+            journal.record(ServerEvent::new(QUOTES_UPDATED));
+        }
+
+        // Ids 3 and 4 are buffered, so resuming from 2 is complete
+        let resumed = journal.subscribe_after(Some(2)).unwrap();
+        assert_eq!(replay_ids(&resumed), vec![3, 4]);
+        assert!(!resumed.gap);
+
+        // Id 2 was evicted, so resuming from 1 misses it
+        let evicted = journal.subscribe_after(Some(1)).unwrap();
+        assert_eq!(replay_ids(&evicted), vec![3, 4]);
+        assert!(evicted.gap);
+
+        // An id this journal never issued, e.g. from before a restart
+        let unknown = journal.subscribe_after(Some(9)).unwrap();
+        assert!(unknown.replay.is_empty());
+        assert!(unknown.gap);
+
+        let fresh = journal.subscribe_after(None).unwrap();
+        assert!(fresh.replay.is_empty());
+        assert!(!fresh.gap);
+    }
+}
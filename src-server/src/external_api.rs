@@ -1,16 +1,38 @@
 use axum::{
-    extract::{Path, Query},
-    routing::get,
+    body::{to_bytes, Body},
+    extract::{DefaultBodyLimit, Path, Query, Request},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri},
+    middleware::{self, Next},
+    response::{
+        sse::{Event as SseEvent, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{delete, get, patch, post, put, MethodRouter},
     Router,
     Json,
 };
+use axum_server::tls_rustls::RustlsConfig;
+use serde_json::json;
+use std::convert::Infallible;
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::limit::RequestBodyLimitLayer;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::StreamExt;
+use tracing::Instrument;
 
 // Import from local
+use crate::events::{
+    EventJournal, JournaledEvent, ACTIVITIES_IMPORT_COMPLETE, MARKET_SYNC_COMPLETE,
+    PORTFOLIO_UPDATE_COMPLETE, QUOTES_UPDATED,
+};
 use crate::main_lib::AppState;
 
 // Import core modules
+use wealthfolio_core::external_api::{CurrencyPrecision, ExternalApiFeatures};
 use wealthfolio_core::{ExternalApiService, ExternalApiServiceTrait};
 
 #[derive(Clone)]
@@ -18,31 +40,241 @@ pub struct ExternalApiConfig {
     pub port: u16,
     pub host: String,
     pub service: Arc<dyn ExternalApiServiceTrait>,
+    /// Largest request body accepted by write routes
+    pub max_body_bytes: usize,
+    /// Route groups to mount
+    pub features: ExternalApiFeatures,
+    pub events: Arc<EventJournal>,
+    /// PEM certificate chain; with `tls_key_path` the API is served over HTTPS
+    pub tls_cert_path: Option<PathBuf>,
+    /// PEM private key matching `tls_cert_path`
+    pub tls_key_path: Option<PathBuf>,
+    /// Browser origins allowed to call the API (`*` for any); CORS is off when empty
+    pub cors_allow_origins: Vec<String>,
+    /// Request headers browsers may send cross-origin
+    pub cors_allow_headers: Vec<String>,
+    /// Worker threads of a dedicated runtime; `None` shares the main server's runtime
+    pub worker_threads: Option<usize>,
+}
+
+/// Request headers allowed cross-origin unless `WF_EXTERNAL_API_CORS_ALLOW_HEADERS` overrides them.
+const DEFAULT_CORS_ALLOW_HEADERS: &[&str] = &["content-type", "x-request-id", "idempotency-key", "x-include-meta"];
+
+/// Bus events forwarded to `/api/events/stream` subscribers.
+const STREAMED_EVENTS: &[&str] = &[
+    MARKET_SYNC_COMPLETE,
+    PORTFOLIO_UPDATE_COMPLETE,
+    QUOTES_UPDATED,
+    ACTIVITIES_IMPORT_COMPLETE,
+];
+
+/// Number of recent events kept for clients resuming with `Last-Event-ID`.
+const EVENT_REPLAY_CAPACITY: usize = 256;
+
+/// Sent first to a resuming client whose `Last-Event-ID` is no longer buffered, telling
+/// it that events were missed and its state should be refetched.
+const STREAM_RESET: &str = "stream:reset";
+
+fn to_sse_event(entry: JournaledEvent) -> Option<SseEvent> {
+    let data = json!({
+        "type": entry.event.name,
+        "payload": entry.event.payload,
+    });
+    match SseEvent::default()
+        .id(entry.id.to_string())
+        .event(entry.event.name)
+        .json_data(data)
+    {
+        Ok(event) => Some(event),
+        Err(e) => {
+            tracing::error!("Failed to serialize SSE payload for {}: {}", entry.event.name, e);
+            None
+        }
+    }
+}
+
+/// Reset event for a client resuming from `last_event_id`. It carries no id, so the
+/// client's `Last-Event-ID` only moves with the events that follow.
+fn stream_reset_event(last_event_id: Option<u64>) -> SseEvent {
+    SseEvent::default().event(STREAM_RESET).data(
+        json!({
+            "type": STREAM_RESET,
+            "payload": { "lastEventId": last_event_id },
+        })
+        .to_string(),
+    )
+}
+
+async fn stream_events(events: Arc<EventJournal>, headers: HeaderMap) -> Response {
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+    let subscription = match events.subscribe_after(last_event_id) {
+        Ok(subscription) => subscription,
+        Err(e) => {
+            tracing::error!("Failed to subscribe to events: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": e.to_string() })),
+            )
+                .into_response();
+        }
+    };
+
+    let reset = subscription.gap.then(|| stream_reset_event(last_event_id));
+    let replayed = tokio_stream::iter(reset).chain(
+        tokio_stream::iter(subscription.replay).filter_map(to_sse_event),
+    );
+    let live = BroadcastStream::new(subscription.receiver)
+        .filter_map(|entry| entry.ok().and_then(to_sse_event));
+    let stream = replayed.chain(live).map(Ok::<_, Infallible>);
+
+    Sse::new(stream)
+        .keep_alive(
+            KeepAlive::new()
+                .interval(Duration::from_secs(15))
+                .text("keep-alive"),
+        )
+        .into_response()
+}
+
+/// Health and root routes. `get` routes also answer `HEAD` with the same status and
+/// headers and an empty body, which is what uptime monitors probe with.
+fn status_routes(port: u16) -> Router {
+    Router::new()
+        .route("/api/health", get(move || async move { Json(wealthfolio_core::external_api::health_handler(port, env!("CARGO_PKG_VERSION")).await) }))
+        .route("/", get(move || async move { Json(wealthfolio_core::external_api::root_handler(port).await) }))
+}
+
+/// Mounts a route only when its feature group is enabled, leaving disabled routes
+/// unregistered so they answer 404.
+trait RouteIf {
+    fn route_if(self, enabled: bool, path: &str, route: MethodRouter) -> Self;
+}
+
+impl RouteIf for Router {
+    fn route_if(self, enabled: bool, path: &str, route: MethodRouter) -> Self {
+        if enabled {
+            self.route(path, route)
+        } else {
+            self
+        }
+    }
 }
 
 pub fn create_external_api_router(config: ExternalApiConfig) -> Router {
     let port = config.port;
     let service_clone = config.service.clone();
+    let max_body_bytes = config.max_body_bytes;
+    let features = config.features;
+    let events = config.events.clone();
 
-    Router::new()
-        .route("/api/health", get(move || async move { Json(wealthfolio_core::external_api::health_handler(port).await) }))
-        .route("/", get(move || async move { Json(wealthfolio_core::external_api::root_handler(port).await) }))
+    let router = status_routes(port)
         .route("/api/portfolio/holdings", get({
             let service = service_clone.clone();
-            move |Query(query): Query<wealthfolio_core::external_api::HoldingsQuery>| async move {
-                Json(wealthfolio_core::external_api::portfolio_holdings_handler(service.as_ref(), query).await)
+            move |uri: Uri, Query(query): Query<wealthfolio_core::external_api::HoldingsQuery>| async move {
+                with_refresh_hint(
+                    paginated_response(&uri, wealthfolio_core::external_api::portfolio_holdings_handler(service.as_ref(), query).await),
+                    None,
+                )
             }
         }))
+        .route_if(features.writes, "/api/portfolio/holdings/query", write_route(post({
+            let service = service_clone.clone();
+            move |Json(request): Json<wealthfolio_core::external_api::HoldingsQueryRequest>| async move {
+                with_refresh_hint(
+                    Json(wealthfolio_core::external_api::holdings_query_handler(service.as_ref(), request).await).into_response(),
+                    None,
+                )
+            }
+        }), max_body_bytes))
         .route("/api/portfolio/accounts", get({
+            let service = service_clone.clone();
+            move |headers: HeaderMap| accounts_response(service.clone(), headers)
+        }))
+        .route("/api/portfolio/accounts/day-change", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::accounts_day_change_handler(service.as_ref()).await)
+            }
+        }))
+        .route_if(features.writes, "/api/portfolio/accounts/{id}/recompute", write_route(post({
+            let service = service_clone.clone();
+            move |Path(account_id): Path<String>| async move {
+                Json(wealthfolio_core::external_api::recompute_account_handler(service.as_ref(), &account_id).await)
+            }
+        }), max_body_bytes))
+        .route("/api/portfolio/allocation/history", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::AllocationHistoryQuery>| async move {
+                Json(wealthfolio_core::external_api::allocation_history_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/asset-classes", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::asset_classes_handler(service.as_ref()).await)
+            }
+        }))
+        .route("/api/portfolio/allocation/sectors", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::SectorExposureQuery>| async move {
+                Json(wealthfolio_core::external_api::sector_exposure_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/top-holdings", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::TopHoldingsQuery>| async move {
+                Json(wealthfolio_core::external_api::top_holdings_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/cash", get({
             let service = service_clone.clone();
             move || async move {
-                Json(wealthfolio_core::external_api::portfolio_accounts_handler(service.as_ref()).await)
+                Json(wealthfolio_core::external_api::cash_summary_handler(service.as_ref()).await)
             }
         }))
         .route("/api/exchange-rates", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::ExchangeRatesQuery>| async move {
+                Json(wealthfolio_core::external_api::exchange_rates_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route_if(features.writes, "/api/exchange-rates", write_route(post({
+            let service = service_clone.clone();
+            move |Json(rows): Json<Vec<wealthfolio_core::external_api::ExchangeRateRow>>| async move {
+                Json(wealthfolio_core::external_api::upsert_exchange_rates_handler(service.as_ref(), rows).await)
+            }
+        }), max_body_bytes))
+        .route_if(features.writes, "/api/exchange-rates/refresh", write_route(post({
             let service = service_clone.clone();
             move || async move {
-                Json(wealthfolio_core::external_api::exchange_rates_handler(service.as_ref()).await)
+                Json(wealthfolio_core::external_api::refresh_exchange_rates_handler(service.as_ref()).await)
+            }
+        }), max_body_bytes))
+        .route("/api/fx/path", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::FxPathQuery>| async move {
+                Json(wealthfolio_core::external_api::fx_path_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/fx/to-base", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::RatesToBaseQuery>| async move {
+                Json(wealthfolio_core::external_api::rates_to_base_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/exchange-rates/{from}/{to}", get({
+            let service = service_clone.clone();
+            move |Path((from, to)): Path<(String, String)>| async move {
+                Json(wealthfolio_core::external_api::exchange_rate_handler(service.as_ref(), &from, &to).await)
+            }
+        }))
+        .route("/api/fx/convert", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::FxConvertQuery>| async move {
+                Json(wealthfolio_core::external_api::convert_amount_handler(service.as_ref(), query).await)
             }
         }))
         .route("/api/settings/base-currency", get({
@@ -51,25 +283,123 @@ pub fn create_external_api_router(config: ExternalApiConfig) -> Router {
                 Json(wealthfolio_core::external_api::base_currency_handler(service.as_ref()).await)
             }
         }))
+        .route("/api/settings/base-currency/history", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::base_currency_history_handler(service.as_ref()).await)
+            }
+        }))
+        .route("/api/config/status", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::config_status_handler(service.as_ref()).await)
+            }
+        }))
+        .route("/api/settings/default-account", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::default_account_handler(service.as_ref()).await)
+            }
+        }))
+        .route_if(features.writes, "/api/settings/default-account", write_route(put({
+            let service = service_clone.clone();
+            move |Json(request): Json<wealthfolio_core::external_api::DefaultAccountRequest>| async move {
+                Json(wealthfolio_core::external_api::set_default_account_handler(service.as_ref(), request).await)
+            }
+        }), max_body_bytes))
+        .route_if(features.exports, "/api/export", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::ExportQuery>| async move {
+                Json(wealthfolio_core::external_api::export_handler(service.as_ref(), query).await)
+            }
+        }))
         // Market data routes
-        .route("/api/market-data/search", get({
+        .route_if(features.market_data, "/api/market-data/search", get({
             let service = service_clone.clone();
             move |Query(query): Query<wealthfolio_core::external_api::MarketDataSearchQuery>| async move {
                 Json(wealthfolio_core::external_api::market_data_search_handler(service.as_ref(), query).await)
             }
         }))
-        .route("/api/market-data/quotes/{symbol}", get({
+        .route_if(features.market_data, "/api/market-data/resolve", get({
             let service = service_clone.clone();
-            move |Path(symbol): Path<String>| async move {
-                Json(wealthfolio_core::external_api::quote_handler(service.as_ref(), &symbol).await)
+            move |Query(query): Query<wealthfolio_core::external_api::MarketDataSearchQuery>| async move {
+                Json(wealthfolio_core::external_api::resolve_symbol_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route_if(features.market_data, "/api/market-data/sync-status", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::sync_status_handler(service.as_ref()).await)
+            }
+        }))
+        .route_if(features.market_data, "/api/market-data/history", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::BulkHistoryQuery>| async move {
+                Json(wealthfolio_core::external_api::bulk_history_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route_if(features.market_data, "/api/market-data/missing", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::missing_quotes_handler(service.as_ref()).await)
+            }
+        }))
+        .route_if(features.market_data, "/api/market-data/quotes/{symbol}", get({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Query(query): Query<wealthfolio_core::external_api::QuoteQuery>| async move {
+                let body = wealthfolio_core::external_api::quote_handler(service.as_ref(), &symbol, query).await;
+                let data_source = body["dataSource"].as_str().map(str::to_string);
+                with_refresh_hint(Json(body).into_response(), data_source.as_deref())
+            }
+        }))
+        .route_if(features.market_data, "/api/market-data/historical/{symbol}", get({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Query(query): Query<wealthfolio_core::external_api::QuoteQuery>| async move {
+                Json(wealthfolio_core::external_api::historical_quotes_handler(service.as_ref(), &symbol, query).await)
+            }
+        }))
+        .route_if(features.market_data, "/api/market-data/{symbol}/gaps", get({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Query(query): Query<wealthfolio_core::external_api::QuoteGapsQuery>| async move {
+                Json(wealthfolio_core::external_api::quote_gaps_handler(service.as_ref(), &symbol, query).await)
+            }
+        }))
+        .route_if(features.market_data, "/api/market-data/{symbol}/profile/freshness", get({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Query(query): Query<wealthfolio_core::external_api::ProfileFreshnessQuery>| async move {
+                Json(wealthfolio_core::external_api::profile_freshness_handler(service.as_ref(), &symbol, query).await)
+            }
+        }))
+        .route_if(features.market_data, "/api/market-data/{symbol}/stats", get({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Query(query): Query<wealthfolio_core::external_api::QuoteStatsQuery>| async move {
+                Json(wealthfolio_core::external_api::quote_stats_handler(service.as_ref(), &symbol, query).await)
             }
         }))
-        .route("/api/market-data/historical/{symbol}", get({
+        .route_if(features.writes && features.market_data, "/api/market-data/{symbol}/ensure", write_route(post({
             let service = service_clone.clone();
             move |Path(symbol): Path<String>| async move {
-                Json(wealthfolio_core::external_api::historical_quotes_handler(service.as_ref(), &symbol).await)
+                Json(wealthfolio_core::external_api::ensure_symbol_handler(service.as_ref(), &symbol).await)
+            }
+        }), max_body_bytes))
+        .route_if(features.writes && features.market_data, "/api/market-data/{symbol}/quote", write_route(post({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Json(request): Json<wealthfolio_core::external_api::ManualQuoteRequest>| async move {
+                Json(wealthfolio_core::external_api::manual_quote_handler(service.as_ref(), &symbol, request).await)
+            }
+        }), max_body_bytes))
+        .route_if(features.market_data, "/api/market-data/{symbol}/sources", get({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>| async move {
+                Json(wealthfolio_core::external_api::data_sources_handler(service.as_ref(), &symbol).await)
             }
         }))
+        .route_if(features.writes && features.market_data, "/api/market-data/{symbol}/sources", write_route(patch({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Json(request): Json<wealthfolio_core::external_api::DataSourceUpdateRequest>| async move {
+                Json(wealthfolio_core::external_api::set_data_source_handler(service.as_ref(), &symbol, request).await)
+            }
+        }), max_body_bytes))
         // Performance routes
         .route("/api/portfolio/performance/{account_id}", get({
             let service = service_clone.clone();
@@ -77,35 +407,668 @@ pub fn create_external_api_router(config: ExternalApiConfig) -> Router {
                 Json(wealthfolio_core::external_api::account_performance_handler(service.as_ref(), &account_id).await)
             }
         }))
-        .route("/api/portfolio/performance/summary", get({
+        .route("/api/portfolio/positions/{symbol}/lots", get({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Query(query): Query<wealthfolio_core::external_api::LotsQuery>| async move {
+                Json(wealthfolio_core::external_api::lots_handler(service.as_ref(), &symbol, query).await)
+            }
+        }))
+        .route("/api/portfolio/unrealized-by-term", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::UnrealizedByTermQuery>| async move {
+                Json(wealthfolio_core::external_api::unrealized_by_term_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/accounts/{id}/currency-exposure", get({
+            let service = service_clone.clone();
+            move |Path(account_id): Path<String>| async move {
+                Json(wealthfolio_core::external_api::account_currency_exposure_handler(service.as_ref(), &account_id).await)
+            }
+        }))
+        .route("/api/portfolio/accounts/{id}/twr", get({
+            let service = service_clone.clone();
+            move |Path(account_id): Path<String>, Query(query): Query<wealthfolio_core::external_api::TwrQuery>| async move {
+                Json(wealthfolio_core::external_api::twr_series_handler(service.as_ref(), &account_id, query).await)
+            }
+        }))
+        .route("/api/portfolio/compare", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::CompareAccountsQuery>| async move {
+                Json(wealthfolio_core::external_api::compare_accounts_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/accounts/{id}/annualized", get({
+            let service = service_clone.clone();
+            move |Path(account_id): Path<String>| async move {
+                Json(wealthfolio_core::external_api::annualized_returns_handler(service.as_ref(), &account_id).await)
+            }
+        }))
+        .route("/api/portfolio/accounts/{id}/valuations", get({
+            let service = service_clone.clone();
+            move |Path(account_id): Path<String>, Query(query): Query<wealthfolio_core::external_api::ValuationSeriesQuery>| async move {
+                Json(wealthfolio_core::external_api::valuation_series_handler(service.as_ref(), &account_id, query).await)
+            }
+        }))
+        .route("/api/portfolio/beta", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::BetaQuery>| async move {
+                Json(wealthfolio_core::external_api::beta_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/sharpe", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::SharpeQuery>| async move {
+                Json(wealthfolio_core::external_api::sharpe_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/var", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::VarQuery>| async move {
+                Json(wealthfolio_core::external_api::var_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/correlation", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::CorrelationQuery>| async move {
+                Json(wealthfolio_core::external_api::correlation_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/contribution", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::ContributionQuery>| async move {
+                Json(wealthfolio_core::external_api::return_contribution_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/yield", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::yield_handler(service.as_ref()).await)
+            }
+        }))
+        .route("/api/portfolio/value-changes", get({
             let service = service_clone.clone();
             move || async move {
-                Json(wealthfolio_core::external_api::portfolio_performance_summary_handler(service.as_ref()).await)
+                Json(wealthfolio_core::external_api::value_changes_handler(service.as_ref()).await)
+            }
+        }))
+        .route("/api/portfolio/quotes", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::TimeFormatQuery>| async move {
+                with_refresh_hint(
+                    Json(wealthfolio_core::external_api::held_quotes_handler(service.as_ref(), query).await).into_response(),
+                    None,
+                )
+            }
+        }))
+        .route("/api/portfolio/performance/summary", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::PerformanceSummaryQuery>| async move {
+                Json(wealthfolio_core::external_api::portfolio_performance_summary_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/gains", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::GainsQuery>| async move {
+                Json(wealthfolio_core::external_api::gains_by_period_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/fees", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::FeesQuery>| async move {
+                Json(wealthfolio_core::external_api::fees_summary_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/symbols/{symbol}/activities", get({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Query(query): Query<wealthfolio_core::external_api::SymbolActivitiesQuery>| async move {
+                Json(wealthfolio_core::external_api::symbol_activities_handler(service.as_ref(), &symbol, query).await)
+            }
+        }))
+        .route("/api/portfolio/transfers", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::TransfersQuery>| async move {
+                Json(wealthfolio_core::external_api::transfers_handler(service.as_ref(), query).await)
             }
         }))
+        .route_if(features.writes, "/api/portfolio/simulate", write_route(post({
+            let service = service_clone.clone();
+            move |Json(request): Json<wealthfolio_core::external_api::SimulateTradeRequest>| async move {
+                Json(wealthfolio_core::external_api::simulate_trade_handler(service.as_ref(), request).await)
+            }
+        }), max_body_bytes))
+        .route_if(features.writes, "/api/portfolio/rebalance", write_route(post({
+            let service = service_clone.clone();
+            move |Json(request): Json<wealthfolio_core::external_api::RebalanceRequest>| async move {
+                Json(wealthfolio_core::external_api::rebalance_handler(service.as_ref(), request).await)
+            }
+        }), max_body_bytes))
         // Activities routes
+        .route("/api/portfolio/activity-range", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::ActivityRangeQuery>| async move {
+                Json(wealthfolio_core::external_api::activity_date_range_handler(service.as_ref(), query).await)
+            }
+        }))
         .route("/api/portfolio/activities", get({
             let service = service_clone.clone();
-            move |Query(query): Query<wealthfolio_core::external_api::ActivitiesQuery>| async move {
-                Json(wealthfolio_core::external_api::activities_handler(service.as_ref(), query).await)
+            move |uri: Uri, Query(query): Query<wealthfolio_core::external_api::ActivitiesQuery>| async move {
+                paginated_response(&uri, wealthfolio_core::external_api::activities_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route_if(features.writes, "/api/portfolio/activities", write_route(post({
+            let service = service_clone.clone();
+            move |headers: HeaderMap, Json(payload): Json<serde_json::Value>| async move {
+                let idempotency_key = headers
+                    .get(wealthfolio_core::external_api::IDEMPOTENCY_KEY_HEADER)
+                    .and_then(|value| value.to_str().ok());
+                Json(wealthfolio_core::external_api::create_activity_handler(service.as_ref(), payload, idempotency_key).await)
+            }
+        }), max_body_bytes))
+        .route_if(features.writes, "/api/portfolio/activities/validate", write_route(post({
+            let service = service_clone.clone();
+            move |Json(payload): Json<serde_json::Value>| async move {
+                Json(wealthfolio_core::external_api::validate_activity_handler(service.as_ref(), payload).await)
+            }
+        }), max_body_bytes))
+        // Alert routes
+        .route("/api/alerts", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::list_alerts_handler(service.as_ref()).await)
+            }
+        }))
+        .route_if(features.writes, "/api/alerts", write_route(post({
+            let service = service_clone.clone();
+            move |Json(request): Json<wealthfolio_core::external_api::AlertRequest>| async move {
+                Json(wealthfolio_core::external_api::register_alert_handler(service.as_ref(), request).await)
+            }
+        }), max_body_bytes))
+        .route_if(features.writes, "/api/alerts/{id}", delete({
+            let service = service_clone.clone();
+            move |Path(alert_id): Path<String>| async move {
+                Json(wealthfolio_core::external_api::delete_alert_handler(service.as_ref(), &alert_id).await)
             }
         }))
+        // Portfolio group routes
+        .route("/api/portfolios", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::portfolio_groups_handler(service.as_ref()).await)
+            }
+        }))
+        .route_if(features.writes, "/api/portfolios", write_route(post({
+            let service = service_clone.clone();
+            move |Json(request): Json<wealthfolio_core::external_api::PortfolioGroupRequest>| async move {
+                Json(wealthfolio_core::external_api::save_portfolio_group_handler(service.as_ref(), request).await)
+            }
+        }), max_body_bytes))
+        .route("/api/portfolios/{id}/summary", get({
+            let service = service_clone.clone();
+            move |Path(group_id): Path<String>| async move {
+                Json(wealthfolio_core::external_api::portfolio_group_summary_handler(service.as_ref(), &group_id).await)
+            }
+        }))
+        // Instrument tag routes
+        .route("/api/instruments/{symbol}/tags", get({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>| async move {
+                Json(wealthfolio_core::external_api::instrument_tags_handler(service.as_ref(), &symbol).await)
+            }
+        }))
+        .route_if(features.writes, "/api/instruments/{symbol}/tags", write_route(put({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Json(request): Json<wealthfolio_core::external_api::InstrumentTagsRequest>| async move {
+                Json(wealthfolio_core::external_api::set_instrument_tags_handler(service.as_ref(), &symbol, request).await)
+            }
+        }), max_body_bytes))
+        // Watchlist routes
+        .route("/api/watchlist", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::WatchlistQuery>| async move {
+                Json(wealthfolio_core::external_api::watchlist_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route_if(features.writes, "/api/watchlist", delete({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::WatchlistRemoveQuery>| async move {
+                Json(wealthfolio_core::external_api::remove_from_watchlist_handler(service.as_ref(), query).await)
+            }
+        }).merge(write_route(post({
+            let service = service_clone.clone();
+            move |Json(request): Json<wealthfolio_core::external_api::WatchlistRequest>| async move {
+                Json(wealthfolio_core::external_api::add_to_watchlist_handler(service.as_ref(), request).await)
+            }
+        }), max_body_bytes)))
+        // Event stream
+        .route("/api/events/stream", get(move |headers: HeaderMap| stream_events(events.clone(), headers)))
+        .layer(middleware::from_fn(reject_malformed_query))
+        .layer(middleware::from_fn(include_meta))
+        .layer(middleware::from_fn(request_id_middleware));
+
+    with_cors(router, &config.cors_allow_origins, &config.cors_allow_headers)
+}
+
+/// Adds CORS to every route, current and future, when any origin is allowed. The layer
+/// answers preflight `OPTIONS` requests itself, so routes never see them.
+fn with_cors(router: Router, origins: &[String], allow_headers: &[String]) -> Router {
+    if origins.is_empty() {
+        return router;
+    }
+
+    let allow_origin = if origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(origins.iter().filter_map(|origin| {
+            HeaderValue::from_str(origin)
+                .map_err(|_| tracing::warn!(origin = %origin, "Ignoring invalid CORS origin"))
+                .ok()
+        }))
+    };
+    let allow_headers: Vec<HeaderName> = allow_headers
+        .iter()
+        .filter_map(|name| {
+            HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| tracing::warn!(header = %name, "Ignoring invalid CORS header"))
+                .ok()
+        })
+        .collect();
+    let cors = CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([
+            Method::GET,
+            Method::HEAD,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allow_headers(allow_headers)
+        .expose_headers([
+            HeaderName::from_static("x-request-id"),
+            HeaderName::from_static(wealthfolio_core::external_api::REFRESH_HINT_HEADER),
+            header::LINK,
+            header::ETAG,
+            header::LAST_MODIFIED,
+        ])
+        .max_age(Duration::from_secs(600));
+
+    router
+        .layer(cors)
+        .layer(middleware::from_fn(preflight_no_content))
+}
+
+/// Answers CORS preflights with 204 No Content instead of the CORS layer's empty 200.
+async fn preflight_no_content(request: Request, next: Next) -> Response {
+    let is_preflight = request.method() == Method::OPTIONS
+        && request.headers().contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    let mut response = next.run(request).await;
+    if is_preflight && response.status() == StatusCode::OK {
+        *response.status_mut() = StatusCode::NO_CONTENT;
+    }
+    response
+}
+
+/// Serves the accounts list with `Last-Modified`/`ETag`, answering 304 Not Modified
+/// when the client's `If-None-Match` or `If-Modified-Since` shows it is current.
+async fn accounts_response(service: Arc<dyn ExternalApiServiceTrait>, headers: HeaderMap) -> Response {
+    let version = wealthfolio_core::external_api::accounts_version_handler(service.as_ref()).await;
+    let Some(version) = version else {
+        return Json(wealthfolio_core::external_api::portfolio_accounts_handler(service.as_ref()).await)
+            .into_response();
+    };
+
+    let header_str = |name: header::HeaderName| headers.get(name).and_then(|value| value.to_str().ok());
+    let validators = [
+        (header::LAST_MODIFIED, version.last_modified_header()),
+        (header::ETAG, version.etag.clone()),
+    ];
+    if version.is_fresh(header_str(header::IF_NONE_MATCH), header_str(header::IF_MODIFIED_SINCE)) {
+        return (StatusCode::NOT_MODIFIED, validators).into_response();
+    }
+    (
+        validators,
+        Json(wealthfolio_core::external_api::portfolio_accounts_handler(service.as_ref()).await),
+    )
+        .into_response()
+}
+
+/// Serves a paginated list, adding a `Link` header pointing at its neighbouring pages.
+fn paginated_response(uri: &Uri, body: serde_json::Value) -> Response {
+    let link = wealthfolio_core::external_api::pagination_link_header(uri.path(), uri.query(), &body)
+        .and_then(|value| HeaderValue::from_str(&value).ok());
+    let mut response = Json(body).into_response();
+    if let Some(link) = link {
+        response.headers_mut().insert(header::LINK, link);
+    }
+    response
+}
+
+/// Adds a `Refresh-Hint` header with the seconds until fresh prices are expected, so
+/// pollers back off outside market hours.
+fn with_refresh_hint(mut response: Response, data_source: Option<&str>) -> Response {
+    let secs = wealthfolio_core::external_api::refresh_after_secs(chrono::Utc::now(), data_source);
+    response.headers_mut().insert(
+        wealthfolio_core::external_api::REFRESH_HINT_HEADER,
+        HeaderValue::from(secs),
+    );
+    response
+}
+
+/// Wraps a write route: caps the body size, answering 413 Payload Too Large when exceeded,
+/// and rejects non-JSON bodies with 415. Replaces axum's built-in body limit so the
+/// configured limit is the only one that applies.
+fn write_route(route: MethodRouter, max_body_bytes: usize) -> MethodRouter {
+    route
+        .layer(RequestBodyLimitLayer::new(max_body_bytes))
+        .layer(DefaultBodyLimit::disable())
+        .layer(middleware::from_fn(require_json_body))
+}
+
+/// Answers 415 Unsupported Media Type with a structured error when a request carries a
+/// body that is not JSON, instead of surfacing the `Json` extractor's parse failure.
+async fn require_json_body(request: Request, next: Next) -> Response {
+    let headers = request.headers();
+    let has_body = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .map_or_else(|| headers.contains_key(header::TRANSFER_ENCODING), |len| len > 0);
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok());
+    let accepted = wealthfolio_core::external_api::JSON_MEDIA_TYPES;
+    if has_body
+        && !content_type.is_some_and(|value| {
+            wealthfolio_core::external_api::content_type_is_accepted(value, accepted)
+        })
+    {
+        return (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Json(wealthfolio_core::external_api::unsupported_media_type_response(
+                content_type,
+                accepted,
+            )),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+/// Answers 400 with a structured `VALIDATION_ERROR` naming each malformed shared query
+/// parameter and its expected format, instead of the `Query` extractor's generic rejection.
+async fn reject_malformed_query(request: Request, next: Next) -> Response {
+    let uri = request.uri();
+    let details = wealthfolio_core::external_api::validate_query_params(uri.path(), uri.query());
+    if !details.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(wealthfolio_core::external_api::query_validation_response(&details)),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+const INCLUDE_META_HEADER: &str = "x-include-meta";
+
+/// Adds a `meta` block with the server time and API version to JSON object responses
+/// when the client sends `X-Include-Meta: true`. Other clients get the payload unchanged.
+async fn include_meta(request: Request, next: Next) -> Response {
+    let wanted = request
+        .headers()
+        .get(INCLUDE_META_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.trim().eq_ignore_ascii_case("true"));
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            wealthfolio_core::external_api::content_type_is_accepted(
+                value,
+                wealthfolio_core::external_api::JSON_MEDIA_TYPES,
+            )
+        });
+    if !wanted || !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("Failed to read response body for meta: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let Ok(mut value) = serde_json::from_slice::<serde_json::Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    wealthfolio_core::external_api::add_response_meta(&mut value);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(value.to_string()))
+}
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Tags each request with the caller's `X-Request-ID` (or a fresh UUID), runs it inside a
+/// span carrying that id so core service logs can be correlated, and echoes it back.
+async fn request_id_middleware(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    let span = tracing::info_span!(
+        "external_api_request",
+        request_id = %request_id,
+        method = %request.method(),
+        path = %request.uri().path(),
+    );
+    let mut response = next.run(request).instrument(span).await;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
 }
 
 /// Starts the external API server
 pub async fn start_external_api(config: ExternalApiConfig) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let app = create_external_api_router(config.clone());
 
+    // Periodically evaluate registered portfolio value alerts
+    let alert_service = config.service.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(
+            wealthfolio_core::external_api::ALERT_EVALUATION_INTERVAL_SECS,
+        ));
+        loop {
+            interval.tick().await;
+            if let Err(e) = alert_service.evaluate_alerts().await {
+                tracing::warn!("Failed to evaluate alerts: {}", e);
+            }
+        }
+    });
+
     let addr: SocketAddr = format!("{}:{}", config.host, config.port).parse()?;
-    println!("🚀 External API Server ready at http://{}:{}", config.host, config.port);
-    println!("📊 Health endpoint: http://{}:{}/api/health", config.host, config.port);
+    match (&config.tls_cert_path, &config.tls_key_path) {
+        (Some(cert_path), Some(key_path)) => {
+            // Negotiates HTTP/2 or HTTP/1.1 through ALPN
+            let tls = RustlsConfig::from_pem_file(cert_path, key_path).await?;
+            tracing::info!(
+                address = %addr,
+                scheme = "https",
+                cert_path = %cert_path.display(),
+                health_url = %format!("https://{}/api/health", addr),
+                "External API server listening with TLS"
+            );
+            axum_server::bind_rustls(addr, tls)
+                .serve(app.into_make_service())
+                .await?;
+        }
+        (cert_path, key_path) => {
+            if cert_path.is_some() || key_path.is_some() {
+                tracing::warn!(
+                    "Both a TLS certificate and key are required for HTTPS; serving plain HTTP"
+                );
+            }
+            let listener = tokio::net::TcpListener::bind(&addr).await?;
+            let bound = listener.local_addr()?;
+            tracing::info!(
+                address = %bound,
+                scheme = "http",
+                health_url = %format!("http://{}/api/health", bound),
+                "External API server listening"
+            );
+            // With axum's `http2` feature the connection builder detects the HTTP/2 preface, so
+            // HTTP/1.1 clients and HTTP/2 prior-knowledge (h2c) clients share the same port
+            axum::serve(listener, app).await?;
+        }
+    }
+
+    Ok(())
+}
 
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, app).await?;
+/// Starts the external API in the background. With `worker_threads` set it runs on its own
+/// multi-threaded runtime, so bursts of API traffic don't starve the main web server;
+/// otherwise it is spawned on the current runtime.
+pub fn spawn_external_api(config: ExternalApiConfig) -> std::io::Result<()> {
+    let Some(worker_threads) = config.worker_threads else {
+        tokio::spawn(async move {
+            if let Err(e) = start_external_api(config).await {
+                tracing::error!("Failed to start External API: {}", e);
+            }
+        });
+        return Ok(());
+    };
 
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(worker_threads)
+        .thread_name("external-api-worker")
+        .enable_all()
+        .build()?;
+    tracing::info!(worker_threads, "Starting External API on a dedicated runtime");
+    std::thread::Builder::new()
+        .name("external-api".to_string())
+        .spawn(move || {
+            if let Err(e) = runtime.block_on(start_external_api(config)) {
+                tracing::error!("Failed to start External API: {}", e);
+            }
+        })?;
     Ok(())
 }
 
+/// Reads a comma-separated list from an environment variable, dropping blank entries.
+fn list_from_env(name: &str) -> Option<Vec<String>> {
+    std::env::var(name).ok().map(|value| {
+        value
+            .split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect()
+    })
+}
+
+/// Reads a route group switch from an environment variable: `true`, `1`, `yes` or `on`
+/// enable the group, `false`, `0`, `no` or `off` disable it, and anything else,
+/// including an unset variable, keeps `default`.
+fn flag_from_env(name: &str, default: bool) -> bool {
+    std::env::var(name).map_or(default, |value| {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => true,
+            "false" | "0" | "no" | "off" => false,
+            _ => default,
+        }
+    })
+}
+
+/// Reads a TLS file path from an environment variable, ignoring blank values.
+fn tls_path_from_env(name: &str) -> Option<PathBuf> {
+    std::env::var(name)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Reads the holdings fetch concurrency limit from `WF_EXTERNAL_API_HOLDINGS_CONCURRENCY`.
+fn holdings_concurrency_from_env() -> usize {
+    std::env::var("WF_EXTERNAL_API_HOLDINGS_CONCURRENCY")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(wealthfolio_core::external_api::DEFAULT_HOLDINGS_CONCURRENCY)
+}
+
+/// Reads how long idempotency keys are kept from `WF_EXTERNAL_API_IDEMPOTENCY_TTL_SECS`.
+fn idempotency_ttl_from_env() -> Duration {
+    let secs = std::env::var("WF_EXTERNAL_API_IDEMPOTENCY_TTL_SECS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(wealthfolio_core::external_api::DEFAULT_IDEMPOTENCY_TTL_SECS);
+    Duration::from_secs(secs)
+}
+
+/// Reads the concurrent provider call limit from `WF_EXTERNAL_API_PROVIDER_CONCURRENCY`.
+fn provider_concurrency_from_env() -> usize {
+    std::env::var("WF_EXTERNAL_API_PROVIDER_CONCURRENCY")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(wealthfolio_core::external_api::DEFAULT_PROVIDER_CONCURRENCY)
+}
+
+/// Reads the largest page size for paginated lists from `WF_EXTERNAL_API_MAX_PAGE_SIZE`.
+fn max_page_size_from_env() -> usize {
+    std::env::var("WF_EXTERNAL_API_MAX_PAGE_SIZE")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(wealthfolio_core::external_api::DEFAULT_MAX_PAGE_SIZE)
+}
+
+/// Reads the write-route body size limit from `WF_EXTERNAL_API_MAX_BODY_BYTES`.
+fn max_body_bytes_from_env() -> usize {
+    std::env::var("WF_EXTERNAL_API_MAX_BODY_BYTES")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(wealthfolio_core::external_api::DEFAULT_MAX_BODY_BYTES)
+}
+
+/// Reads currency rounding rules from `WF_EXTERNAL_API_CURRENCY_PRECISION` (e.g. `JPY=0,BTC=8`)
+/// on top of the built-in ISO defaults, and the fallback precision from
+/// `WF_EXTERNAL_API_DEFAULT_CURRENCY_PRECISION`.
+fn currency_precision_from_env() -> CurrencyPrecision {
+    let mut precision = CurrencyPrecision::default();
+    if let Ok(rules) = std::env::var("WF_EXTERNAL_API_CURRENCY_PRECISION") {
+        precision = precision.with_rules(&rules);
+    }
+    if let Some(places) = std::env::var("WF_EXTERNAL_API_DEFAULT_CURRENCY_PRECISION")
+        .ok()
+        .and_then(|value| value.trim().parse::<u32>().ok())
+    {
+        precision = precision.with_default_places(places);
+    }
+    precision
+}
+
+/// Reads the dedicated runtime's worker thread count from `WF_EXTERNAL_API_WORKER_THREADS`.
+/// Unset, blank or zero keeps the external API on the shared runtime.
+fn worker_threads_from_env() -> Option<usize> {
+    std::env::var("WF_EXTERNAL_API_WORKER_THREADS")
+        .ok()
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .filter(|value| *value > 0)
+}
+
 /// Creates external API config from AppState
 pub fn create_external_api_config(
     port: u16,
@@ -120,11 +1083,215 @@ pub fn create_external_api_config(
         state.market_data_service.clone(),
         state.performance_service.clone(),
         state.activity_service.clone(),
-    ));
+        state.asset_service.clone(),
+        state.valuation_service.clone(),
+        state.snapshot_service.clone(),
+    )
+    .with_holdings_concurrency(holdings_concurrency_from_env())
+    .with_idempotency_ttl(idempotency_ttl_from_env())
+    .with_max_page_size(max_page_size_from_env())
+    .with_provider_concurrency(provider_concurrency_from_env())
+    .with_currency_precision(currency_precision_from_env())
+    .with_secret_store(state.secret_store.clone()));
+    let events = EventJournal::spawn(&state.event_bus, STREAMED_EVENTS, EVENT_REPLAY_CAPACITY);
+    let default_features = ExternalApiFeatures::default();
 
     ExternalApiConfig {
         port,
         host,
         service,
+        events,
+        max_body_bytes: max_body_bytes_from_env(),
+        features: ExternalApiFeatures {
+            writes: flag_from_env("WF_EXTERNAL_API_ENABLE_WRITES", default_features.writes),
+            market_data: flag_from_env("WF_EXTERNAL_API_ENABLE_MARKET_DATA", default_features.market_data),
+            exports: flag_from_env("WF_EXTERNAL_API_ENABLE_EXPORTS", default_features.exports),
+        },
+        tls_cert_path: tls_path_from_env("WF_EXTERNAL_API_TLS_CERT_PATH"),
+        tls_key_path: tls_path_from_env("WF_EXTERNAL_API_TLS_KEY_PATH"),
+        cors_allow_origins: list_from_env("WF_EXTERNAL_API_CORS_ALLOW_ORIGINS").unwrap_or_default(),
+        cors_allow_headers: list_from_env("WF_EXTERNAL_API_CORS_ALLOW_HEADERS").unwrap_or_else(|| {
+            DEFAULT_CORS_ALLOW_HEADERS.iter().map(|name| name.to_string()).collect()
+        }),
+        worker_threads: worker_threads_from_env(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::{to_bytes, Body},
+        http::{self, header, Method, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn head_requests_return_headers_without_body() {
+        for uri in ["/api/health", "/"] {
+            let response = status_routes(3333)
+                .oneshot(
+                    http::Request::builder()
+                        .method(Method::HEAD)
+                        .uri(uri)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            assert!(body.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn health_reports_server_and_core_versions() {
+        let response = status_routes(3333)
+            .oneshot(http::Request::builder().uri("/api/health").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(body["coreVersion"], wealthfolio_core::external_api::API_VERSION);
+    }
+
+    #[tokio::test]
+    async fn write_routes_reject_non_json_bodies() {
+        let app = Router::new().route("/write", write_route(post(|| async { "ok" }), 1024));
+        let send = |content_type: &'static str| {
+            app.clone().oneshot(
+                http::Request::builder()
+                    .method(Method::POST)
+                    .uri("/write")
+                    .header(header::CONTENT_TYPE, content_type)
+                    .header(header::CONTENT_LENGTH, "3")
+                    .body(Body::from("a=b"))
+                    .unwrap(),
+            )
+        };
+
+        let response = send("application/x-www-form-urlencoded").await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "UNSUPPORTED_MEDIA_TYPE");
+
+        let response = send("application/json; charset=utf-8").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn disabled_route_groups_are_not_mounted() {
+        let app = Router::new()
+            .route("/read", get(|| async { "ok" }))
+            .route_if(false, "/read", write_route(post(|| async { "ok" }), 1024))
+            .route_if(false, "/write", write_route(post(|| async { "ok" }), 1024));
+        let send = |method: Method, uri: &'static str| {
+            app.clone().oneshot(
+                http::Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        assert_eq!(send(Method::GET, "/read").await.unwrap().status(), StatusCode::OK);
+        assert_eq!(
+            send(Method::POST, "/read").await.unwrap().status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        assert_eq!(send(Method::POST, "/write").await.unwrap().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn write_routes_are_off_by_default() {
+        let features = ExternalApiFeatures::default();
+        assert!(!features.writes);
+
+        let app = Router::new()
+            .route("/api/activities", get(|| async { "ok" }))
+            .route_if(features.writes, "/api/activities", write_route(post(|| async { "ok" }), 1024))
+            .route_if(features.writes, "/api/exchange-rates/refresh", write_route(post(|| async { "ok" }), 1024));
+        let send = |method: Method, uri: &'static str| {
+            app.clone().oneshot(
+                http::Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from("{}"))
+                    .unwrap(),
+            )
+        };
+
+        assert_eq!(send(Method::GET, "/api/activities").await.unwrap().status(), StatusCode::OK);
+        assert_eq!(
+            send(Method::POST, "/api/exchange-rates/refresh").await.unwrap().status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            send(Method::POST, "/api/activities").await.unwrap().status(),
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+    }
+
+    #[tokio::test]
+    async fn meta_is_added_only_when_requested() {
+        let app = status_routes(3333).layer(middleware::from_fn(include_meta));
+        let send = |include_meta: Option<&'static str>| {
+            let mut request = http::Request::builder().uri("/api/health");
+            if let Some(value) = include_meta {
+                request = request.header(INCLUDE_META_HEADER, value);
+            }
+            app.clone().oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        let response = send(Some("true")).await.unwrap();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["meta"]["apiVersion"], wealthfolio_core::external_api::API_VERSION);
+
+        let response = send(None).await.unwrap();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert!(body.get("meta").is_none());
+    }
+
+    #[tokio::test]
+    async fn cors_preflight_allows_listed_headers() {
+        let app = with_cors(
+            Router::new().route("/write", post(|| async { "ok" })),
+            &["https://dashboard.example".to_string()],
+            &["content-type".to_string(), "x-api-key".to_string()],
+        );
+
+        let response = app
+            .oneshot(
+                http::Request::builder()
+                    .method(Method::OPTIONS)
+                    .uri("/write")
+                    .header(header::ORIGIN, "https://dashboard.example")
+                    .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+                    .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "content-type,x-api-key")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "https://dashboard.example");
+        assert!(headers[header::ACCESS_CONTROL_ALLOW_METHODS].to_str().unwrap().contains("POST"));
+        assert!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS]
+            .to_str()
+            .unwrap()
+            .contains("x-api-key"));
     }
 }
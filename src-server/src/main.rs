@@ -21,17 +21,12 @@ async fn main() -> anyhow::Result<()> {
     let state = build_state(&config).await?;
 
     // Start External API server
-    let external_api_state = Arc::clone(&state);
-    tokio::spawn(async move {
-        let external_api_config = external_api::create_external_api_config(
-            3333,
-            "0.0.0.0".to_string(),
-            external_api_state,
-        );
-        if let Err(e) = external_api::start_external_api(external_api_config).await {
-            tracing::error!("Failed to start External API: {}", e);
-        }
-    });
+    let external_api_config = external_api::create_external_api_config(
+        3333,
+        "0.0.0.0".to_string(),
+        Arc::clone(&state),
+    );
+    external_api::spawn_external_api(external_api_config)?;
 
     let static_dir = std::path::PathBuf::from(&config.static_dir);
     let index_file = static_dir.join("index.html");
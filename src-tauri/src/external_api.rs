@@ -1,16 +1,24 @@
 use axum::{
-    extract::{Path, Query},
-    routing::get,
+    body::{to_bytes, Body},
+    extract::{DefaultBodyLimit, Path, Query, Request},
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch, post, put, MethodRouter},
     Router,
     Json,
 };
 use std::net::SocketAddr;
+use tower_http::limit::RequestBodyLimitLayer;
 use std::sync::Arc;
+use std::time::Duration;
 
 // Import from local crate
 use crate::context::ServiceContext;
+use crate::secret_store::shared_secret_store;
 
 // Import core modules
+use wealthfolio_core::external_api::ExternalApiFeatures;
 use wealthfolio_core::{ExternalApiService, ExternalApiServiceTrait};
 
 #[derive(Clone)]
@@ -18,31 +26,147 @@ pub struct ExternalApiConfig {
     pub port: u16,
     pub host: String,
     pub service: Arc<dyn ExternalApiServiceTrait>,
+    /// Largest request body accepted by write routes
+    pub max_body_bytes: usize,
+    /// Route groups to mount
+    pub features: ExternalApiFeatures,
+}
+
+/// Health and root routes. `get` routes also answer `HEAD` with the same status and
+/// headers and an empty body, which is what uptime monitors probe with.
+fn status_routes(port: u16) -> Router {
+    Router::new()
+        .route("/api/health", get(move || async move { Json(wealthfolio_core::external_api::health_handler(port, env!("CARGO_PKG_VERSION")).await) }))
+        .route("/", get(move || async move { Json(wealthfolio_core::external_api::root_handler(port).await) }))
+}
+
+/// Mounts a route only when its feature group is enabled, leaving disabled routes
+/// unregistered so they answer 404.
+trait RouteIf {
+    fn route_if(self, enabled: bool, path: &str, route: MethodRouter) -> Self;
+}
+
+impl RouteIf for Router {
+    fn route_if(self, enabled: bool, path: &str, route: MethodRouter) -> Self {
+        if enabled {
+            self.route(path, route)
+        } else {
+            self
+        }
+    }
 }
 
 pub fn create_external_api_router(config: ExternalApiConfig) -> Router {
     let port = config.port;
     let service_clone = config.service.clone();
+    let max_body_bytes = config.max_body_bytes;
+    let features = config.features;
 
-    Router::new()
-        .route("/api/health", get(move || async move { Json(wealthfolio_core::external_api::health_handler(port).await) }))
-        .route("/", get(move || async move { Json(wealthfolio_core::external_api::root_handler(port).await) }))
+    status_routes(port)
         .route("/api/portfolio/holdings", get({
             let service = service_clone.clone();
-            move |Query(query): Query<wealthfolio_core::external_api::HoldingsQuery>| async move {
-                Json(wealthfolio_core::external_api::portfolio_holdings_handler(service.as_ref(), query).await)
+            move |uri: Uri, Query(query): Query<wealthfolio_core::external_api::HoldingsQuery>| async move {
+                with_refresh_hint(
+                    paginated_response(&uri, wealthfolio_core::external_api::portfolio_holdings_handler(service.as_ref(), query).await),
+                    None,
+                )
             }
         }))
+        .route_if(features.writes, "/api/portfolio/holdings/query", write_route(post({
+            let service = service_clone.clone();
+            move |Json(request): Json<wealthfolio_core::external_api::HoldingsQueryRequest>| async move {
+                with_refresh_hint(
+                    Json(wealthfolio_core::external_api::holdings_query_handler(service.as_ref(), request).await).into_response(),
+                    None,
+                )
+            }
+        }), max_body_bytes))
         .route("/api/portfolio/accounts", get({
+            let service = service_clone.clone();
+            move |headers: HeaderMap| accounts_response(service.clone(), headers)
+        }))
+        .route("/api/portfolio/accounts/day-change", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::accounts_day_change_handler(service.as_ref()).await)
+            }
+        }))
+        .route_if(features.writes, "/api/portfolio/accounts/{id}/recompute", write_route(post({
+            let service = service_clone.clone();
+            move |Path(account_id): Path<String>| async move {
+                Json(wealthfolio_core::external_api::recompute_account_handler(service.as_ref(), &account_id).await)
+            }
+        }), max_body_bytes))
+        .route("/api/portfolio/allocation/history", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::AllocationHistoryQuery>| async move {
+                Json(wealthfolio_core::external_api::allocation_history_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/asset-classes", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::asset_classes_handler(service.as_ref()).await)
+            }
+        }))
+        .route("/api/portfolio/allocation/sectors", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::SectorExposureQuery>| async move {
+                Json(wealthfolio_core::external_api::sector_exposure_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/top-holdings", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::TopHoldingsQuery>| async move {
+                Json(wealthfolio_core::external_api::top_holdings_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/cash", get({
             let service = service_clone.clone();
             move || async move {
-                Json(wealthfolio_core::external_api::portfolio_accounts_handler(service.as_ref()).await)
+                Json(wealthfolio_core::external_api::cash_summary_handler(service.as_ref()).await)
             }
         }))
         .route("/api/exchange-rates", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::ExchangeRatesQuery>| async move {
+                Json(wealthfolio_core::external_api::exchange_rates_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route_if(features.writes, "/api/exchange-rates", write_route(post({
+            let service = service_clone.clone();
+            move |Json(rows): Json<Vec<wealthfolio_core::external_api::ExchangeRateRow>>| async move {
+                Json(wealthfolio_core::external_api::upsert_exchange_rates_handler(service.as_ref(), rows).await)
+            }
+        }), max_body_bytes))
+        .route_if(features.writes, "/api/exchange-rates/refresh", write_route(post({
             let service = service_clone.clone();
             move || async move {
-                Json(wealthfolio_core::external_api::exchange_rates_handler(service.as_ref()).await)
+                Json(wealthfolio_core::external_api::refresh_exchange_rates_handler(service.as_ref()).await)
+            }
+        }), max_body_bytes))
+        .route("/api/fx/path", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::FxPathQuery>| async move {
+                Json(wealthfolio_core::external_api::fx_path_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/fx/to-base", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::RatesToBaseQuery>| async move {
+                Json(wealthfolio_core::external_api::rates_to_base_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/exchange-rates/{from}/{to}", get({
+            let service = service_clone.clone();
+            move |Path((from, to)): Path<(String, String)>| async move {
+                Json(wealthfolio_core::external_api::exchange_rate_handler(service.as_ref(), &from, &to).await)
+            }
+        }))
+        .route("/api/fx/convert", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::FxConvertQuery>| async move {
+                Json(wealthfolio_core::external_api::convert_amount_handler(service.as_ref(), query).await)
             }
         }))
         .route("/api/settings/base-currency", get({
@@ -51,25 +175,123 @@ pub fn create_external_api_router(config: ExternalApiConfig) -> Router {
                 Json(wealthfolio_core::external_api::base_currency_handler(service.as_ref()).await)
             }
         }))
+        .route("/api/settings/base-currency/history", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::base_currency_history_handler(service.as_ref()).await)
+            }
+        }))
+        .route("/api/config/status", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::config_status_handler(service.as_ref()).await)
+            }
+        }))
+        .route("/api/settings/default-account", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::default_account_handler(service.as_ref()).await)
+            }
+        }))
+        .route_if(features.writes, "/api/settings/default-account", write_route(put({
+            let service = service_clone.clone();
+            move |Json(request): Json<wealthfolio_core::external_api::DefaultAccountRequest>| async move {
+                Json(wealthfolio_core::external_api::set_default_account_handler(service.as_ref(), request).await)
+            }
+        }), max_body_bytes))
+        .route_if(features.exports, "/api/export", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::ExportQuery>| async move {
+                Json(wealthfolio_core::external_api::export_handler(service.as_ref(), query).await)
+            }
+        }))
         // Market data routes
-        .route("/api/market-data/search", get({
+        .route_if(features.market_data, "/api/market-data/search", get({
             let service = service_clone.clone();
             move |Query(query): Query<wealthfolio_core::external_api::MarketDataSearchQuery>| async move {
                 Json(wealthfolio_core::external_api::market_data_search_handler(service.as_ref(), query).await)
             }
         }))
-        .route("/api/market-data/quotes/{symbol}", get({
+        .route_if(features.market_data, "/api/market-data/resolve", get({
             let service = service_clone.clone();
-            move |Path(symbol): Path<String>| async move {
-                Json(wealthfolio_core::external_api::quote_handler(service.as_ref(), &symbol).await)
+            move |Query(query): Query<wealthfolio_core::external_api::MarketDataSearchQuery>| async move {
+                Json(wealthfolio_core::external_api::resolve_symbol_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route_if(features.market_data, "/api/market-data/sync-status", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::sync_status_handler(service.as_ref()).await)
+            }
+        }))
+        .route_if(features.market_data, "/api/market-data/history", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::BulkHistoryQuery>| async move {
+                Json(wealthfolio_core::external_api::bulk_history_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route_if(features.market_data, "/api/market-data/missing", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::missing_quotes_handler(service.as_ref()).await)
+            }
+        }))
+        .route_if(features.market_data, "/api/market-data/quotes/{symbol}", get({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Query(query): Query<wealthfolio_core::external_api::QuoteQuery>| async move {
+                let body = wealthfolio_core::external_api::quote_handler(service.as_ref(), &symbol, query).await;
+                let data_source = body["dataSource"].as_str().map(str::to_string);
+                with_refresh_hint(Json(body).into_response(), data_source.as_deref())
+            }
+        }))
+        .route_if(features.market_data, "/api/market-data/historical/{symbol}", get({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Query(query): Query<wealthfolio_core::external_api::QuoteQuery>| async move {
+                Json(wealthfolio_core::external_api::historical_quotes_handler(service.as_ref(), &symbol, query).await)
+            }
+        }))
+        .route_if(features.market_data, "/api/market-data/{symbol}/gaps", get({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Query(query): Query<wealthfolio_core::external_api::QuoteGapsQuery>| async move {
+                Json(wealthfolio_core::external_api::quote_gaps_handler(service.as_ref(), &symbol, query).await)
             }
         }))
-        .route("/api/market-data/historical/{symbol}", get({
+        .route_if(features.market_data, "/api/market-data/{symbol}/profile/freshness", get({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Query(query): Query<wealthfolio_core::external_api::ProfileFreshnessQuery>| async move {
+                Json(wealthfolio_core::external_api::profile_freshness_handler(service.as_ref(), &symbol, query).await)
+            }
+        }))
+        .route_if(features.market_data, "/api/market-data/{symbol}/stats", get({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Query(query): Query<wealthfolio_core::external_api::QuoteStatsQuery>| async move {
+                Json(wealthfolio_core::external_api::quote_stats_handler(service.as_ref(), &symbol, query).await)
+            }
+        }))
+        .route_if(features.writes && features.market_data, "/api/market-data/{symbol}/ensure", write_route(post({
             let service = service_clone.clone();
             move |Path(symbol): Path<String>| async move {
-                Json(wealthfolio_core::external_api::historical_quotes_handler(service.as_ref(), &symbol).await)
+                Json(wealthfolio_core::external_api::ensure_symbol_handler(service.as_ref(), &symbol).await)
+            }
+        }), max_body_bytes))
+        .route_if(features.writes && features.market_data, "/api/market-data/{symbol}/quote", write_route(post({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Json(request): Json<wealthfolio_core::external_api::ManualQuoteRequest>| async move {
+                Json(wealthfolio_core::external_api::manual_quote_handler(service.as_ref(), &symbol, request).await)
+            }
+        }), max_body_bytes))
+        .route_if(features.market_data, "/api/market-data/{symbol}/sources", get({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>| async move {
+                Json(wealthfolio_core::external_api::data_sources_handler(service.as_ref(), &symbol).await)
             }
         }))
+        .route_if(features.writes && features.market_data, "/api/market-data/{symbol}/sources", write_route(patch({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Json(request): Json<wealthfolio_core::external_api::DataSourceUpdateRequest>| async move {
+                Json(wealthfolio_core::external_api::set_data_source_handler(service.as_ref(), &symbol, request).await)
+            }
+        }), max_body_bytes))
         // Performance routes
         .route("/api/portfolio/performance/{account_id}", get({
             let service = service_clone.clone();
@@ -77,30 +299,448 @@ pub fn create_external_api_router(config: ExternalApiConfig) -> Router {
                 Json(wealthfolio_core::external_api::account_performance_handler(service.as_ref(), &account_id).await)
             }
         }))
-        .route("/api/portfolio/performance/summary", get({
+        .route("/api/portfolio/positions/{symbol}/lots", get({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Query(query): Query<wealthfolio_core::external_api::LotsQuery>| async move {
+                Json(wealthfolio_core::external_api::lots_handler(service.as_ref(), &symbol, query).await)
+            }
+        }))
+        .route("/api/portfolio/unrealized-by-term", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::UnrealizedByTermQuery>| async move {
+                Json(wealthfolio_core::external_api::unrealized_by_term_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/accounts/{id}/currency-exposure", get({
+            let service = service_clone.clone();
+            move |Path(account_id): Path<String>| async move {
+                Json(wealthfolio_core::external_api::account_currency_exposure_handler(service.as_ref(), &account_id).await)
+            }
+        }))
+        .route("/api/portfolio/accounts/{id}/twr", get({
+            let service = service_clone.clone();
+            move |Path(account_id): Path<String>, Query(query): Query<wealthfolio_core::external_api::TwrQuery>| async move {
+                Json(wealthfolio_core::external_api::twr_series_handler(service.as_ref(), &account_id, query).await)
+            }
+        }))
+        .route("/api/portfolio/compare", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::CompareAccountsQuery>| async move {
+                Json(wealthfolio_core::external_api::compare_accounts_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/accounts/{id}/annualized", get({
+            let service = service_clone.clone();
+            move |Path(account_id): Path<String>| async move {
+                Json(wealthfolio_core::external_api::annualized_returns_handler(service.as_ref(), &account_id).await)
+            }
+        }))
+        .route("/api/portfolio/accounts/{id}/valuations", get({
+            let service = service_clone.clone();
+            move |Path(account_id): Path<String>, Query(query): Query<wealthfolio_core::external_api::ValuationSeriesQuery>| async move {
+                Json(wealthfolio_core::external_api::valuation_series_handler(service.as_ref(), &account_id, query).await)
+            }
+        }))
+        .route("/api/portfolio/beta", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::BetaQuery>| async move {
+                Json(wealthfolio_core::external_api::beta_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/sharpe", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::SharpeQuery>| async move {
+                Json(wealthfolio_core::external_api::sharpe_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/var", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::VarQuery>| async move {
+                Json(wealthfolio_core::external_api::var_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/correlation", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::CorrelationQuery>| async move {
+                Json(wealthfolio_core::external_api::correlation_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/contribution", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::ContributionQuery>| async move {
+                Json(wealthfolio_core::external_api::return_contribution_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/yield", get({
             let service = service_clone.clone();
             move || async move {
-                Json(wealthfolio_core::external_api::portfolio_performance_summary_handler(service.as_ref()).await)
+                Json(wealthfolio_core::external_api::yield_handler(service.as_ref()).await)
+            }
+        }))
+        .route("/api/portfolio/value-changes", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::value_changes_handler(service.as_ref()).await)
+            }
+        }))
+        .route("/api/portfolio/quotes", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::TimeFormatQuery>| async move {
+                with_refresh_hint(
+                    Json(wealthfolio_core::external_api::held_quotes_handler(service.as_ref(), query).await).into_response(),
+                    None,
+                )
             }
         }))
+        .route("/api/portfolio/performance/summary", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::PerformanceSummaryQuery>| async move {
+                Json(wealthfolio_core::external_api::portfolio_performance_summary_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/gains", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::GainsQuery>| async move {
+                Json(wealthfolio_core::external_api::gains_by_period_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/fees", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::FeesQuery>| async move {
+                Json(wealthfolio_core::external_api::fees_summary_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route("/api/portfolio/symbols/{symbol}/activities", get({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Query(query): Query<wealthfolio_core::external_api::SymbolActivitiesQuery>| async move {
+                Json(wealthfolio_core::external_api::symbol_activities_handler(service.as_ref(), &symbol, query).await)
+            }
+        }))
+        .route("/api/portfolio/transfers", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::TransfersQuery>| async move {
+                Json(wealthfolio_core::external_api::transfers_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route_if(features.writes, "/api/portfolio/simulate", write_route(post({
+            let service = service_clone.clone();
+            move |Json(request): Json<wealthfolio_core::external_api::SimulateTradeRequest>| async move {
+                Json(wealthfolio_core::external_api::simulate_trade_handler(service.as_ref(), request).await)
+            }
+        }), max_body_bytes))
+        .route_if(features.writes, "/api/portfolio/rebalance", write_route(post({
+            let service = service_clone.clone();
+            move |Json(request): Json<wealthfolio_core::external_api::RebalanceRequest>| async move {
+                Json(wealthfolio_core::external_api::rebalance_handler(service.as_ref(), request).await)
+            }
+        }), max_body_bytes))
         // Activities routes
+        .route("/api/portfolio/activity-range", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::ActivityRangeQuery>| async move {
+                Json(wealthfolio_core::external_api::activity_date_range_handler(service.as_ref(), query).await)
+            }
+        }))
         .route("/api/portfolio/activities", get({
             let service = service_clone.clone();
-            move |Query(query): Query<wealthfolio_core::external_api::ActivitiesQuery>| async move {
-                Json(wealthfolio_core::external_api::activities_handler(service.as_ref(), query).await)
+            move |uri: Uri, Query(query): Query<wealthfolio_core::external_api::ActivitiesQuery>| async move {
+                paginated_response(&uri, wealthfolio_core::external_api::activities_handler(service.as_ref(), query).await)
             }
         }))
+        .route_if(features.writes, "/api/portfolio/activities", write_route(post({
+            let service = service_clone.clone();
+            move |headers: HeaderMap, Json(payload): Json<serde_json::Value>| async move {
+                let idempotency_key = headers
+                    .get(wealthfolio_core::external_api::IDEMPOTENCY_KEY_HEADER)
+                    .and_then(|value| value.to_str().ok());
+                Json(wealthfolio_core::external_api::create_activity_handler(service.as_ref(), payload, idempotency_key).await)
+            }
+        }), max_body_bytes))
+        .route_if(features.writes, "/api/portfolio/activities/validate", write_route(post({
+            let service = service_clone.clone();
+            move |Json(payload): Json<serde_json::Value>| async move {
+                Json(wealthfolio_core::external_api::validate_activity_handler(service.as_ref(), payload).await)
+            }
+        }), max_body_bytes))
+        // Alert routes
+        .route("/api/alerts", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::list_alerts_handler(service.as_ref()).await)
+            }
+        }))
+        .route_if(features.writes, "/api/alerts", write_route(post({
+            let service = service_clone.clone();
+            move |Json(request): Json<wealthfolio_core::external_api::AlertRequest>| async move {
+                Json(wealthfolio_core::external_api::register_alert_handler(service.as_ref(), request).await)
+            }
+        }), max_body_bytes))
+        .route_if(features.writes, "/api/alerts/{id}", delete({
+            let service = service_clone.clone();
+            move |Path(alert_id): Path<String>| async move {
+                Json(wealthfolio_core::external_api::delete_alert_handler(service.as_ref(), &alert_id).await)
+            }
+        }))
+        // Portfolio group routes
+        .route("/api/portfolios", get({
+            let service = service_clone.clone();
+            move || async move {
+                Json(wealthfolio_core::external_api::portfolio_groups_handler(service.as_ref()).await)
+            }
+        }))
+        .route_if(features.writes, "/api/portfolios", write_route(post({
+            let service = service_clone.clone();
+            move |Json(request): Json<wealthfolio_core::external_api::PortfolioGroupRequest>| async move {
+                Json(wealthfolio_core::external_api::save_portfolio_group_handler(service.as_ref(), request).await)
+            }
+        }), max_body_bytes))
+        .route("/api/portfolios/{id}/summary", get({
+            let service = service_clone.clone();
+            move |Path(group_id): Path<String>| async move {
+                Json(wealthfolio_core::external_api::portfolio_group_summary_handler(service.as_ref(), &group_id).await)
+            }
+        }))
+        // Instrument tag routes
+        .route("/api/instruments/{symbol}/tags", get({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>| async move {
+                Json(wealthfolio_core::external_api::instrument_tags_handler(service.as_ref(), &symbol).await)
+            }
+        }))
+        .route_if(features.writes, "/api/instruments/{symbol}/tags", write_route(put({
+            let service = service_clone.clone();
+            move |Path(symbol): Path<String>, Json(request): Json<wealthfolio_core::external_api::InstrumentTagsRequest>| async move {
+                Json(wealthfolio_core::external_api::set_instrument_tags_handler(service.as_ref(), &symbol, request).await)
+            }
+        }), max_body_bytes))
+        // Watchlist routes
+        .route("/api/watchlist", get({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::WatchlistQuery>| async move {
+                Json(wealthfolio_core::external_api::watchlist_handler(service.as_ref(), query).await)
+            }
+        }))
+        .route_if(features.writes, "/api/watchlist", delete({
+            let service = service_clone.clone();
+            move |Query(query): Query<wealthfolio_core::external_api::WatchlistRemoveQuery>| async move {
+                Json(wealthfolio_core::external_api::remove_from_watchlist_handler(service.as_ref(), query).await)
+            }
+        }).merge(write_route(post({
+            let service = service_clone.clone();
+            move |Json(request): Json<wealthfolio_core::external_api::WatchlistRequest>| async move {
+                Json(wealthfolio_core::external_api::add_to_watchlist_handler(service.as_ref(), request).await)
+            }
+        }), max_body_bytes)))
+        .layer(middleware::from_fn(reject_malformed_query))
+        .layer(middleware::from_fn(include_meta))
+        .layer(middleware::from_fn(request_id_middleware))
+}
+
+/// Serves the accounts list with `Last-Modified`/`ETag`, answering 304 Not Modified
+/// when the client's `If-None-Match` or `If-Modified-Since` shows it is current.
+async fn accounts_response(service: Arc<dyn ExternalApiServiceTrait>, headers: HeaderMap) -> Response {
+    let version = wealthfolio_core::external_api::accounts_version_handler(service.as_ref()).await;
+    let Some(version) = version else {
+        return Json(wealthfolio_core::external_api::portfolio_accounts_handler(service.as_ref()).await)
+            .into_response();
+    };
+
+    let header_str = |name: header::HeaderName| headers.get(name).and_then(|value| value.to_str().ok());
+    let validators = [
+        (header::LAST_MODIFIED, version.last_modified_header()),
+        (header::ETAG, version.etag.clone()),
+    ];
+    if version.is_fresh(header_str(header::IF_NONE_MATCH), header_str(header::IF_MODIFIED_SINCE)) {
+        return (StatusCode::NOT_MODIFIED, validators).into_response();
+    }
+    (
+        validators,
+        Json(wealthfolio_core::external_api::portfolio_accounts_handler(service.as_ref()).await),
+    )
+        .into_response()
+}
+
+/// Serves a paginated list, adding a `Link` header pointing at its neighbouring pages.
+fn paginated_response(uri: &Uri, body: serde_json::Value) -> Response {
+    let link = wealthfolio_core::external_api::pagination_link_header(uri.path(), uri.query(), &body)
+        .and_then(|value| HeaderValue::from_str(&value).ok());
+    let mut response = Json(body).into_response();
+    if let Some(link) = link {
+        response.headers_mut().insert(header::LINK, link);
+    }
+    response
+}
+
+/// Adds a `Refresh-Hint` header with the seconds until fresh prices are expected, so
+/// pollers back off outside market hours.
+fn with_refresh_hint(mut response: Response, data_source: Option<&str>) -> Response {
+    let secs = wealthfolio_core::external_api::refresh_after_secs(chrono::Utc::now(), data_source);
+    response.headers_mut().insert(
+        wealthfolio_core::external_api::REFRESH_HINT_HEADER,
+        HeaderValue::from(secs),
+    );
+    response
+}
+
+/// Wraps a write route: caps the body size, answering 413 Payload Too Large when exceeded,
+/// and rejects non-JSON bodies with 415. Replaces axum's built-in body limit so the
+/// configured limit is the only one that applies.
+fn write_route(route: MethodRouter, max_body_bytes: usize) -> MethodRouter {
+    route
+        .layer(RequestBodyLimitLayer::new(max_body_bytes))
+        .layer(DefaultBodyLimit::disable())
+        .layer(middleware::from_fn(require_json_body))
+}
+
+/// Answers 415 Unsupported Media Type with a structured error when a request carries a
+/// body that is not JSON, instead of surfacing the `Json` extractor's parse failure.
+async fn require_json_body(request: Request, next: Next) -> Response {
+    let headers = request.headers();
+    let has_body = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .map_or_else(|| headers.contains_key(header::TRANSFER_ENCODING), |len| len > 0);
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok());
+    let accepted = wealthfolio_core::external_api::JSON_MEDIA_TYPES;
+    if has_body
+        && !content_type.is_some_and(|value| {
+            wealthfolio_core::external_api::content_type_is_accepted(value, accepted)
+        })
+    {
+        return (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Json(wealthfolio_core::external_api::unsupported_media_type_response(
+                content_type,
+                accepted,
+            )),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+/// Answers 400 with a structured `VALIDATION_ERROR` naming each malformed shared query
+/// parameter and its expected format, instead of the `Query` extractor's generic rejection.
+async fn reject_malformed_query(request: Request, next: Next) -> Response {
+    let uri = request.uri();
+    let details = wealthfolio_core::external_api::validate_query_params(uri.path(), uri.query());
+    if !details.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(wealthfolio_core::external_api::query_validation_response(&details)),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
+const INCLUDE_META_HEADER: &str = "x-include-meta";
+
+/// Adds a `meta` block with the server time and API version to JSON object responses
+/// when the client sends `X-Include-Meta: true`. Other clients get the payload unchanged.
+async fn include_meta(request: Request, next: Next) -> Response {
+    let wanted = request
+        .headers()
+        .get(INCLUDE_META_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.trim().eq_ignore_ascii_case("true"));
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            wealthfolio_core::external_api::content_type_is_accepted(
+                value,
+                wealthfolio_core::external_api::JSON_MEDIA_TYPES,
+            )
+        });
+    if !wanted || !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("Failed to read response body for meta: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let Ok(mut value) = serde_json::from_slice::<serde_json::Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    wealthfolio_core::external_api::add_response_meta(&mut value);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(value.to_string()))
+}
+
+const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Tags each request with the caller's `X-Request-ID` (or a fresh UUID), logs it so
+/// core service logs can be correlated, and echoes it back.
+async fn request_id_middleware(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    log::debug!(
+        "External API request {} {} [request_id={}]",
+        request.method(),
+        request.uri().path(),
+        request_id
+    );
+    let mut response = next.run(request).await;
+    log::debug!(
+        "External API response {} [request_id={}]",
+        response.status(),
+        request_id
+    );
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
 }
 
 /// Starts the external API server
 pub async fn start_external_api(config: ExternalApiConfig) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let app = create_external_api_router(config.clone());
 
-    let addr: SocketAddr = format!("{}:{}", config.host, config.port).parse()?;
-    println!("🚀 External API Server ready at http://{}:{}", config.host, config.port);
-    println!("📊 Health endpoint: http://{}:{}/api/health", config.host, config.port);
+    // Periodically evaluate registered portfolio value alerts
+    let alert_service = config.service.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(
+            wealthfolio_core::external_api::ALERT_EVALUATION_INTERVAL_SECS,
+        ));
+        loop {
+            interval.tick().await;
+            if let Err(e) = alert_service.evaluate_alerts().await {
+                log::warn!("Failed to evaluate alerts: {}", e);
+            }
+        }
+    });
 
+    let addr: SocketAddr = format!("{}:{}", config.host, config.port).parse()?;
     let listener = tokio::net::TcpListener::bind(&addr).await?;
+    let bound = listener.local_addr()?;
+    log::info!(
+        "External API server listening on {} (health: http://{}/api/health)",
+        bound,
+        bound
+    );
+    // Plain HTTP only: TLS termination is configured on the web server (src-server);
+    // desktop users who need HTTPS put a TLS reverse proxy in front of this port.
+    // With axum's `http2` feature the connection builder detects the HTTP/2 preface, so
+    // HTTP/1.1 clients and HTTP/2 prior-knowledge (h2c) clients share the same port
     axum::serve(listener, app).await?;
 
     Ok(())
@@ -120,11 +760,17 @@ pub fn create_external_api_config(
         context.market_data_service(),
         context.performance_service(),
         context.activity_service(),
-    ));
+        context.asset_service(),
+        context.valuation_service(),
+        context.snapshot_service(),
+    )
+    .with_secret_store(shared_secret_store()));
 
     ExternalApiConfig {
         port,
         host,
         service,
+        max_body_bytes: wealthfolio_core::external_api::DEFAULT_MAX_BODY_BYTES,
+        features: ExternalApiFeatures::default(),
     }
 }
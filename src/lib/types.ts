@@ -226,6 +226,7 @@ export interface Instrument {
   assetSubclass?: string | null;
   countries?: Country[] | null;
   sectors?: Sector[] | null;
+  profileUpdatedAt?: string | null;
 }
 
 export interface MonetaryValue {
@@ -277,6 +278,7 @@ export interface Holding {
   marketValue: MonetaryValue;
   costBasis?: MonetaryValue | null;
   price?: number | null;
+  quoteDate?: string | null;
   unrealizedGain?: MonetaryValue | null;
   unrealizedGainPct?: number | null;
   realizedGain?: MonetaryValue | null;